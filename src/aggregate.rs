@@ -0,0 +1,349 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use crate::collation::{CollatedKey, Collation, KeyOrder};
+use crate::constant::{AGGREGATE_MEMORY_BUDGET, AGGREGATE_SPILL_DEPTH, AGGREGATE_SPILL_PARTITIONS};
+use crate::expr::Expr;
+use crate::function::{AggregateState, Registration};
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::*;
+use crate::temp_file::TempFile;
+use crate::value::Value;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum AggregateFunc {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    GroupConcat,
+    /// One registered by `Connection::create_aggregate_function`, by its lowercased name, with
+    /// the registration the call is bound to.
+    Custom(String, Registration),
+}
+
+impl AggregateFunc {
+    /// The aggregate a call to `name` makes, bound to `registered` if the connection registered
+    /// a function under the name.
+    pub fn from_call(name: &str, registered: Option<&Registration>) -> Option<AggregateFunc> {
+        match name.to_lowercase().as_str() {
+            "count" => Some(AggregateFunc::Count),
+            "sum" => Some(AggregateFunc::Sum),
+            "avg" => Some(AggregateFunc::Avg),
+            "min" => Some(AggregateFunc::Min),
+            "max" => Some(AggregateFunc::Max),
+            "group_concat" => Some(AggregateFunc::GroupConcat),
+            name => registered
+                .filter(|registration| registration.aggregate().is_some())
+                .map(|registration| AggregateFunc::Custom(String::from(name), registration.clone())),
+        }
+    }
+
+    /// The argument counts the function accepts.
+    pub(crate) fn arity(&self) -> (usize, usize) {
+        match self {
+            AggregateFunc::Count => (0, 1),
+            AggregateFunc::GroupConcat => (1, 2),
+            AggregateFunc::Custom(_, registration) => registration.aggregate().unwrap().1,
+            _ => (1, 1),
+        }
+    }
+}
+
+/// An aggregate call of a query, such as `count(DISTINCT name)`; `count(*)` has no arguments.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Aggregate {
+    pub func: AggregateFunc,
+    pub args: Vec<Expr>,
+    pub distinct: bool,
+    /// The collation of the first argument, under which DISTINCT tells values apart and MIN
+    /// and MAX compare them.
+    pub collation: Collation,
+}
+
+impl Aggregate {
+    /// The aggregate a function call makes, or None if the call is not to an aggregate.
+    /// `collation` gives the collation of a column by name.
+    pub fn from_expr(expr: &Expr, collation: &dyn Fn(&str) -> Option<Collation>) -> Option<Result<Aggregate, ExecuteResult>> {
+        let (name, args, distinct, registered) = match expr {
+            Expr::Function { name, args, distinct, registered } => (name, args, *distinct, registered),
+            _ => return None,
+        };
+        let func = AggregateFunc::from_call(name, registered.as_ref())?;
+        let (min_args, max_args) = func.arity();
+        if args.len() < min_args || args.len() > max_args || (distinct && args.len() != 1) {
+            return Some(Err(ExecuteWrongArgumentCount(name.clone())));
+        }
+        if args.iter().any(|arg| arg.contains_aggregate()) {
+            return Some(Err(ExecuteMisuseOfAggregate(name.clone())));
+        }
+        let collation = args.first().map(|arg| arg.collation(collation)).unwrap_or_default();
+        Some(Ok(Aggregate { func, args: args.clone(), distinct, collation }))
+    }
+
+    /// Whether this is `count(*)`, which the row count of the table answers.
+    pub fn is_count_star(&self) -> bool {
+        self.func == AggregateFunc::Count && self.args.is_empty()
+    }
+}
+
+/// Running state of one aggregate over one group, or over the frame of a window.
+pub(crate) enum Accumulator {
+    Count(i64),
+    /// An integer while every value summed is one, else a real.
+    Sum(Option<Value>),
+    Avg { sum: f64, count: i64 },
+    Min(Option<Value>, Collation),
+    Max(Option<Value>, Collation),
+    GroupConcat(Option<String>),
+    Custom(Box<dyn AggregateState>),
+}
+
+impl Accumulator {
+    /// An accumulator of the function, whose MIN or MAX compares values under `collation`.
+    pub(crate) fn new(func: &AggregateFunc, collation: &Collation) -> Accumulator {
+        match func {
+            AggregateFunc::Count => Accumulator::Count(0),
+            AggregateFunc::Sum => Accumulator::Sum(None),
+            AggregateFunc::Avg => Accumulator::Avg { sum: 0.0, count: 0 },
+            AggregateFunc::Min => Accumulator::Min(None, collation.clone()),
+            AggregateFunc::Max => Accumulator::Max(None, collation.clone()),
+            AggregateFunc::GroupConcat => Accumulator::GroupConcat(None),
+            AggregateFunc::Custom(_, registration) => Accumulator::Custom(registration.aggregate().unwrap().0()),
+        }
+    }
+
+    /// Fold in one row's arguments, skipping NULLs as SQL does (except for `count(*)`); a
+    /// registered aggregate sees them all. Returns whether a MIN or MAX took a new value and
+    /// how many bytes the state grew.
+    pub(crate) fn step(&mut self, args: &[Value]) -> Result<(bool, usize), ExecuteResult> {
+        if let Accumulator::Custom(state) = self {
+            state.step(args).map_err(ExecuteFunctionError)?;
+            return Ok((false, 0));
+        }
+        if args.first().is_some_and(|arg| arg.is_null()) {
+            return Ok((false, 0));
+        }
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Sum(sum) => {
+                let value = args[0].to_numeric().unwrap();
+                *sum = Some(match (sum.take().unwrap_or(Value::Integer(0)), value) {
+                    (Value::Integer(a), Value::Integer(b)) => Value::Integer(a.checked_add(b).ok_or(ExecuteIntegerOverflow)?),
+                    (a, b) => Value::Real(a.to_real().unwrap() + b.to_real().unwrap()),
+                });
+            }
+            Accumulator::Avg { sum, count } => {
+                *sum += args[0].to_real().unwrap();
+                *count += 1;
+            }
+            Accumulator::Min(min, collation) => {
+                if min.as_ref().is_none_or(|min| collation.compare(&args[0], min) == Ordering::Less) {
+                    *min = Some(args[0].clone());
+                    return Ok((true, 0));
+                }
+            }
+            Accumulator::Max(max, collation) => {
+                if max.as_ref().is_none_or(|max| collation.compare(&args[0], max) == Ordering::Greater) {
+                    *max = Some(args[0].clone());
+                    return Ok((true, 0));
+                }
+            }
+            Accumulator::GroupConcat(text) => {
+                let value = args[0].to_string();
+                let added = match text {
+                    Some(text) => {
+                        let separator = match args.get(1) {
+                            Some(separator) => separator.to_string(),
+                            None => String::from(","),
+                        };
+                        text.push_str(&separator);
+                        text.push_str(&value);
+                        separator.len() + value.len()
+                    }
+                    None => {
+                        *text = Some(value.clone());
+                        value.len()
+                    }
+                };
+                return Ok((false, added));
+            }
+            Accumulator::Custom(_) => unreachable!(),
+        }
+        Ok((false, 0))
+    }
+
+    /// The aggregate of the rows folded in so far.
+    pub(crate) fn value(&self) -> Result<Value, ExecuteResult> {
+        Ok(match self {
+            Accumulator::Count(count) => Value::Integer(*count),
+            Accumulator::Sum(sum) => sum.clone().unwrap_or(Value::Null),
+            Accumulator::Avg { count: 0, .. } => Value::Null,
+            Accumulator::Avg { sum, count } => Value::from_real(*sum / *count as f64),
+            Accumulator::Min(value, _) | Accumulator::Max(value, _) => value.clone().unwrap_or(Value::Null),
+            Accumulator::GroupConcat(text) => text.clone().map_or(Value::Null, Value::Text),
+            Accumulator::Custom(state) => return state.finalize().map_err(ExecuteFunctionError),
+        })
+    }
+}
+
+/// Bytes a group's values take when serialized, which stands in for their size in memory.
+fn values_size(values: &[Value]) -> usize {
+    let mut buf = Vec::new();
+    values.iter().for_each(|value| value.serialize(&mut buf));
+    buf.len()
+}
+
+struct Group {
+    /// The first row of the group, or the row holding the extreme of a lone MIN or MAX.
+    row: Vec<Value>,
+    accumulators: Vec<Accumulator>,
+    /// Arguments already seen by each DISTINCT aggregate, told apart by its collation.
+    seen: Vec<Option<HashSet<CollatedKey>>>,
+}
+
+/// A group once every row is in: its GROUP BY values, the row its bare columns read from
+/// (None for the group of an empty table), and the value of every aggregate.
+pub struct FinishedGroup {
+    pub key: Vec<Value>,
+    pub row: Option<Vec<Value>>,
+    pub values: Vec<Value>,
+}
+
+/// Hash aggregation for GROUP BY. Groups live in a hash table until it passes
+/// `AGGREGATE_MEMORY_BUDGET` bytes; after that, rows of groups already in memory are still
+/// folded in, while rows of new groups are written to partition files by the hash of their
+/// key. Each partition is aggregated on its own once the rows are in, partitioning again
+/// with another hash if it is still too large, up to `AGGREGATE_SPILL_DEPTH` times. Keys are
+/// the same group when they are equal under the collations of `order`.
+pub struct Aggregator<'a> {
+    group_by: &'a [Expr],
+    order: Rc<KeyOrder>,
+    aggregates: &'a [Aggregate],
+    /// The order each DISTINCT aggregate tells its arguments apart by.
+    distinct_orders: Vec<Rc<KeyOrder>>,
+    column: &'a dyn Fn(&[Value], &str) -> Option<Value>,
+    /// The lone MIN or MAX whose extreme row the bare columns read from, as in SQLite.
+    extreme: Option<usize>,
+    groups: HashMap<CollatedKey, Group>,
+    groups_size: usize,
+    partitions: Vec<TempFile>,
+    depth: u64,
+}
+
+impl<'a> Aggregator<'a> {
+    /// `column` looks up a column of a row by name, and `order` gives the collation of each
+    /// GROUP BY term.
+    pub fn new(group_by: &'a [Expr], order: KeyOrder, aggregates: &'a [Aggregate],
+               column: &'a dyn Fn(&[Value], &str) -> Option<Value>) -> Aggregator<'a> {
+        let extremes: Vec<usize> = aggregates.iter()
+            .enumerate()
+            .filter(|(_, aggregate)| matches!(aggregate.func, AggregateFunc::Min | AggregateFunc::Max))
+            .map(|(i, _)| i)
+            .collect();
+        let distinct_orders = aggregates.iter()
+            .map(|aggregate| Rc::new(KeyOrder { descending: Vec::new(), collations: vec![aggregate.collation.clone()] }))
+            .collect();
+        Aggregator {
+            group_by,
+            order: Rc::new(order),
+            aggregates,
+            distinct_orders,
+            column,
+            extreme: if extremes.len() == 1 { Some(extremes[0]) } else { None },
+            groups: HashMap::new(),
+            groups_size: 0,
+            partitions: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    fn partition(&self, key: &CollatedKey) -> usize {
+        let mut hasher = DefaultHasher::new();
+        (self.depth, key).hash(&mut hasher);
+        (hasher.finish() % AGGREGATE_SPILL_PARTITIONS as u64) as usize
+    }
+
+    pub fn add_row(&mut self, row: Vec<Value>) -> Result<(), ExecuteResult> {
+        let column = |name: &str| (self.column)(&row, name);
+        let key = self.group_by.iter().map(|expr| expr.evaluate(&column)).collect::<Result<Vec<Value>, _>>()?;
+        let key = CollatedKey { order: self.order.clone(), key };
+        let mut args = Vec::new();
+        for aggregate in self.aggregates {
+            args.push(aggregate.args.iter().map(|arg| arg.evaluate(&column)).collect::<Result<Vec<Value>, _>>()?);
+        }
+        if !self.groups.contains_key(&key) {
+            if !self.partitions.is_empty() {
+                let partition = self.partition(&key);
+                return self.partitions[partition].write_record(&row);
+            }
+            let group = Group {
+                row: row.clone(),
+                accumulators: self.aggregates.iter().map(|aggregate| Accumulator::new(&aggregate.func, &aggregate.collation)).collect(),
+                seen: self.aggregates.iter().map(|aggregate| aggregate.distinct.then(HashSet::new)).collect(),
+            };
+            self.groups_size += values_size(&key.key) + values_size(&row) + 32 * self.aggregates.len();
+            self.groups.insert(key.clone(), group);
+        }
+        let group = self.groups.get_mut(&key).unwrap();
+        let mut extreme_changed = false;
+        for (i, args) in args.iter().enumerate() {
+            if let Some(seen) = &mut group.seen[i] {
+                if args[0].is_null() || !seen.insert(CollatedKey { order: self.distinct_orders[i].clone(), key: args[..1].to_vec() }) {
+                    continue;
+                }
+                self.groups_size += values_size(&args[..1]);
+            }
+            let (changed, added) = group.accumulators[i].step(args)?;
+            extreme_changed |= changed && self.extreme == Some(i);
+            self.groups_size += added;
+        }
+        if extreme_changed {
+            group.row = row;
+        }
+        // Text under a custom collation all hashes alike, so past a few passes partitioning
+        // again would split nothing off.
+        let spill = self.groups_size > AGGREGATE_MEMORY_BUDGET && self.depth < AGGREGATE_SPILL_DEPTH;
+        if spill && self.partitions.is_empty() {
+            self.partitions = (0..AGGREGATE_SPILL_PARTITIONS).map(|_| TempFile::create()).collect::<Result<_, _>>()?;
+        }
+        Ok(())
+    }
+
+    /// Finish every group and hand it to `visit`. Without GROUP BY there is exactly one group,
+    /// even when there were no rows.
+    pub fn finish(self, visit: &mut dyn FnMut(FinishedGroup) -> Result<(), ExecuteResult>) -> Result<(), ExecuteResult> {
+        if self.group_by.is_empty() && self.groups.is_empty() && self.depth == 0 {
+            let values = self.aggregates.iter()
+                .map(|aggregate| Accumulator::new(&aggregate.func, &aggregate.collation).value())
+                .collect::<Result<_, _>>()?;
+            return visit(FinishedGroup { key: Vec::new(), row: None, values });
+        }
+        for (key, group) in self.groups {
+            let values = group.accumulators.iter().map(Accumulator::value).collect::<Result<_, _>>()?;
+            visit(FinishedGroup { key: key.key, row: Some(group.row), values })?;
+        }
+        for partition in self.partitions {
+            let mut aggregator = Aggregator {
+                group_by: self.group_by,
+                order: self.order.clone(),
+                aggregates: self.aggregates,
+                distinct_orders: self.distinct_orders.clone(),
+                column: self.column,
+                extreme: self.extreme,
+                groups: HashMap::new(),
+                groups_size: 0,
+                partitions: Vec::new(),
+                depth: self.depth + 1,
+            };
+            for row in partition.into_reader()? {
+                aggregator.add_row(row?)?;
+            }
+            aggregator.finish(visit)?;
+        }
+        Ok(())
+    }
+}
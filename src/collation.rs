@@ -0,0 +1,178 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::rc::Rc;
+use crate::value::Value;
+
+/// A comparison function for text, registered under a name by `Connection::create_collation`.
+pub type CollationFunc = Rc<dyn Fn(&str, &str) -> Ordering>;
+
+/// The collations registered on a connection by `Connection::create_collation`, by
+/// lowercased name.
+#[derive(Default)]
+pub struct Collations {
+    registered: HashMap<String, CollationFunc>,
+}
+
+impl Collations {
+    /// Make `compare` the collation `name` stands for, in place of any registered before under
+    /// it. The built-in collations cannot be replaced.
+    pub fn register(&mut self, name: &str, compare: CollationFunc) {
+        self.registered.insert(name.to_lowercase(), compare);
+    }
+
+    /// Bind a custom collation to the comparison registered under its name, if there is one.
+    pub fn bind(&self, collation: &mut Collation) {
+        if let Collation::Custom(name, compare) = collation {
+            *compare = self.registered.get(&name.to_lowercase()).cloned();
+        }
+    }
+}
+
+/// How text is compared, as a `COLLATE` clause names it. Values of other types, and text
+/// against any other type, compare as they always do.
+#[derive(Clone, Default)]
+pub enum Collation {
+    /// Byte by byte.
+    #[default]
+    Binary,
+    /// As BINARY, but with ASCII letters folded to lower case.
+    NoCase,
+    /// As BINARY, but ignoring trailing spaces.
+    RTrim,
+    /// One registered by name, which is kept as written, with the comparison registered under
+    /// it on the connection once a statement is bound to it.
+    Custom(String, Option<CollationFunc>),
+}
+
+impl Collation {
+    pub fn from_name(name: &str) -> Collation {
+        match name.to_lowercase().as_str() {
+            "binary" => Collation::Binary,
+            "nocase" => Collation::NoCase,
+            "rtrim" => Collation::RTrim,
+            _ => Collation::Custom(String::from(name), None),
+        }
+    }
+
+    /// Whether a statement may use the collation: the built-in ones always, a custom one once
+    /// it is bound to one registered on the connection.
+    pub fn is_known(&self) -> bool {
+        match self {
+            Collation::Custom(_, compare) => compare.is_some(),
+            _ => true,
+        }
+    }
+
+    pub fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        let (a, b) = match (a, b) {
+            (Value::Text(a), Value::Text(b)) => (a, b),
+            _ => return a.cmp(b),
+        };
+        match self {
+            Collation::Binary => a.cmp(b),
+            Collation::NoCase => a.bytes().map(|byte| byte.to_ascii_lowercase())
+                .cmp(b.bytes().map(|byte| byte.to_ascii_lowercase())),
+            Collation::RTrim => a.trim_end_matches(' ').cmp(b.trim_end_matches(' ')),
+            // A statement fails on an unknown collation before it compares anything under it.
+            Collation::Custom(name, compare) => match compare {
+                Some(compare) => compare(a, b),
+                None => panic!("collation {} compared before it was bound", name),
+            },
+        }
+    }
+
+    /// Feed a value to the hasher so that values equal under the collation hash alike. The
+    /// engine cannot see how a custom collation compares, so all text under one hashes alike.
+    pub fn hash_value<H: Hasher>(&self, value: &Value, state: &mut H) {
+        let text = match value {
+            Value::Text(text) => text,
+            value => return value.hash(state),
+        };
+        match self {
+            Collation::Binary => value.hash(state),
+            Collation::NoCase => text.bytes().for_each(|byte| byte.to_ascii_lowercase().hash(state)),
+            Collation::RTrim => text.trim_end_matches(' ').hash(state),
+            Collation::Custom(..) => {}
+        }
+    }
+}
+
+impl fmt::Display for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Collation::Binary => write!(f, "BINARY"),
+            Collation::NoCase => write!(f, "NOCASE"),
+            Collation::RTrim => write!(f, "RTRIM"),
+            Collation::Custom(name, _) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl fmt::Debug for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Collations are the same if they have the same name, bound or not.
+impl PartialEq for Collation {
+    fn eq(&self, other: &Collation) -> bool {
+        match (self, other) {
+            (Collation::Custom(a, _), Collation::Custom(b, _)) => a.eq_ignore_ascii_case(b),
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+/// How the records of a tree, or the keys of a sort, are ordered: value by value under each
+/// column's collation, ascending unless `descending` says otherwise. Columns without an entry
+/// are ascending and BINARY. Only the values both records have are compared, so a key cut
+/// short matches every record that starts with it.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct KeyOrder {
+    pub descending: Vec<bool>,
+    pub collations: Vec<Collation>,
+}
+
+impl KeyOrder {
+    pub fn compare(&self, a: &[Value], b: &[Value]) -> Ordering {
+        for (i, (a, b)) in a.iter().zip(b).enumerate() {
+            let ordering = match self.collations.get(i) {
+                Some(collation) => collation.compare(a, b),
+                None => a.cmp(b),
+            };
+            let ordering = if self.descending.get(i) == Some(&true) { ordering.reverse() } else { ordering };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+/// A key of a hash table that holds keys equal under a `KeyOrder` as one, as GROUP BY and
+/// DISTINCT do.
+#[derive(Clone, Debug)]
+pub struct CollatedKey {
+    pub order: Rc<KeyOrder>,
+    pub key: Vec<Value>,
+}
+
+impl Hash for CollatedKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for (i, value) in self.key.iter().enumerate() {
+            self.order.collations.get(i).unwrap_or(&Collation::Binary).hash_value(value, state);
+        }
+    }
+}
+
+impl PartialEq for CollatedKey {
+    fn eq(&self, other: &CollatedKey) -> bool {
+        self.order.compare(&self.key, &other.key) == Ordering::Equal
+    }
+}
+
+impl Eq for CollatedKey {}
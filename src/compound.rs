@@ -0,0 +1,205 @@
+use std::cmp::Ordering;
+use crate::collation::KeyOrder;
+use crate::expr::Expr;
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::*;
+use crate::schema::TableDef;
+use crate::sorter::Sorter;
+use crate::statement::{limit_value, order_by_terms, result_collations, result_names, select_rows, sort_order, Statement};
+use crate::table::Table;
+use crate::value::Value;
+
+/// The operator joining a SELECT of a compound SELECT onto the ones before it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CompoundOp {
+    Union,
+    UnionAll,
+    Intersect,
+    Except,
+}
+
+impl CompoundOp {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            CompoundOp::Union => "UNION",
+            CompoundOp::UnionAll => "UNION ALL",
+            CompoundOp::Intersect => "INTERSECT",
+            CompoundOp::Except => "EXCEPT",
+        }
+    }
+}
+
+/// Which side of an operator a row of a compound came from.
+const LEFT: i64 = 0;
+const RIGHT: i64 = 1;
+
+/// The rows of the SELECTs of a compound seen so far, up to its last operator that removes
+/// duplicates. They go into a sorter by their values, under the collations of the result
+/// columns, which spills them to temporary files as they grow, and are read back a group of
+/// equal rows at a time. Every operator in that stretch leaves the rows distinct once it is
+/// done, so duplicates are only dropped as the rows are read back.
+struct CompoundRows {
+    order: KeyOrder,
+    sorter: Sorter,
+}
+
+impl CompoundRows {
+    fn new(order: &KeyOrder) -> CompoundRows {
+        CompoundRows { order: order.clone(), sorter: Sorter::new(order.clone()) }
+    }
+
+    fn insert(&mut self, row: Vec<Value>, side: i64) -> Result<(), ExecuteResult> {
+        self.sorter.insert(row, vec![Value::Integer(side)])
+    }
+
+    /// Add the rows of a SELECT, marked with the side of the operator they came from.
+    fn insert_select(&mut self, select: &Statement, table: &mut Table, side: i64) -> Result<(), ExecuteResult> {
+        let mut result = Ok(());
+        let select_result = select_rows(select, table, false, &mut |row| {
+            if result.is_ok() {
+                result = self.insert(row.to_vec(), side);
+            }
+        });
+        if select_result != ExecuteSuccess {
+            return Err(select_result);
+        }
+        result
+    }
+
+    /// Hand `visit` a row of every group of equal rows, in order, with whether the group holds
+    /// rows from the left and from the right of the operator. As in SQLite, the row is the
+    /// last from the left, which only matters for rows that a collation finds equal.
+    fn groups(self, visit: &mut dyn FnMut(Vec<Value>, [bool; 2]) -> Result<(), ExecuteResult>) -> Result<(), ExecuteResult> {
+        let mut rows = self.sorter.finish()?;
+        let mut group: Option<(Vec<Value>, [bool; 2])> = None;
+        while let Some(record) = rows.next_keyed() {
+            let (row, side) = record?;
+            let side = usize::from(side[0] == Value::Integer(RIGHT));
+            match &mut group {
+                Some((kept, sides)) if self.order.compare(kept, &row) == Ordering::Equal => {
+                    if side == 0 {
+                        *kept = row;
+                    }
+                    sides[side] = true;
+                }
+                _ => {
+                    let mut sides = [false; 2];
+                    sides[side] = true;
+                    if let Some((first, sides)) = group.replace((row, sides)) {
+                        visit(first, sides)?;
+                    }
+                }
+            }
+        }
+        match group {
+            Some((first, sides)) => visit(first, sides),
+            None => Ok(()),
+        }
+    }
+
+    /// Fold in the rows of the next SELECT, as the operator before it says.
+    fn combine(mut self, op: CompoundOp, select: &Statement, table: &mut Table) -> Result<CompoundRows, ExecuteResult> {
+        if matches!(op, CompoundOp::Union | CompoundOp::UnionAll) {
+            self.insert_select(select, table, LEFT)?;
+            return Ok(self);
+        }
+        self.insert_select(select, table, RIGHT)?;
+        let mut combined = CompoundRows::new(&self.order);
+        self.groups(&mut |row, [left, right]| {
+            let keep = if op == CompoundOp::Intersect { left && right } else { left && !right };
+            if keep { combined.insert(row, LEFT) } else { Ok(()) }
+        })?;
+        Ok(combined)
+    }
+}
+
+/// Run a compound SELECT, handing each result row to `output`, after a row of the column
+/// names if `headers` is set. The columns are named after the first SELECT's, and ORDER BY,
+/// LIMIT and OFFSET apply to the rows of the whole compound.
+pub fn select_compound(statement: &Statement, table: &mut Table, headers: bool,
+                       output: &mut dyn FnMut(&[Value])) -> ExecuteResult {
+    match compound_rows(statement, table, headers, output) {
+        Ok(()) => ExecuteSuccess,
+        Err(err) => err,
+    }
+}
+
+fn compound_rows(statement: &Statement, table: &mut Table, headers: bool,
+                 output: &mut dyn FnMut(&[Value])) -> Result<(), ExecuteResult> {
+    let mut first = statement.clone();
+    let selects = std::mem::take(&mut first.compound);
+    first.order_by.clear();
+    first.limit = None;
+    first.offset = None;
+    let names = result_names(&first, &table.schema)?;
+    // ORDER BY names the compound's result columns, by name or position. They compare as the
+    // first SELECT's do, for ORDER BY and for the operators alike.
+    let collations = result_collations(&first, &table.schema)?;
+    let mut table_def = TableDef::in_memory("", names.clone(), Vec::new());
+    for (column, collation) in table_def.columns.iter_mut().zip(&collations) {
+        column.collation = collation.clone();
+    }
+    let order = KeyOrder { descending: vec![false; names.len()], collations };
+    let columns: Vec<(String, Expr)> = names.iter().map(|name| (name.clone(), Expr::Column(name.clone()))).collect();
+    let order_by = order_by_terms(&statement.order_by, &columns, Some(&table_def))?;
+    let limit = limit_value(statement.limit.as_ref())?.unwrap_or(usize::MAX);
+    let offset = limit_value(statement.offset.as_ref())?.unwrap_or(0);
+    if headers {
+        let names: Vec<Value> = names.into_iter().map(Value::Text).collect();
+        output(&names);
+    }
+
+    let mut sorter = if order_by.is_empty() {
+        None
+    } else {
+        Some(Sorter::new(sort_order(&order_by, Some(&table_def))))
+    };
+    let (mut skipped, mut output_rows) = (0, 0);
+    let mut emit = |row: &[Value]| -> Result<(), ExecuteResult> {
+        match &mut sorter {
+            Some(sorter) => {
+                let column = |name: &str| table_def.column_value(row, name);
+                let key = order_by.iter().map(|(expr, _)| expr.evaluate(&column)).collect::<Result<Vec<Value>, ExecuteResult>>()?;
+                sorter.insert(key, row.to_vec())?;
+            }
+            None if skipped < offset => skipped += 1,
+            None if output_rows < limit => {
+                output(row);
+                output_rows += 1;
+            }
+            None => {}
+        }
+        Ok(())
+    };
+
+    // The SELECTs up to the last operator that removes duplicates fold into a sorter; the UNION
+    // ALL ones after it stream their rows straight out.
+    let distinct_end = selects.iter().rposition(|(op, _)| *op != CompoundOp::UnionAll).map_or(0, |i| i + 1);
+    let streamed: Vec<&Statement> = if distinct_end == 0 {
+        std::iter::once(&first).chain(selects.iter().map(|(_, select)| select)).collect()
+    } else {
+        let mut rows = CompoundRows::new(&order);
+        rows.insert_select(&first, table, LEFT)?;
+        for (op, select) in &selects[..distinct_end] {
+            rows = rows.combine(*op, select, table)?;
+        }
+        rows.groups(&mut |row, _| emit(&row))?;
+        selects[distinct_end..].iter().map(|(_, select)| select).collect()
+    };
+    for select in streamed {
+        let mut result = Ok(());
+        let select_result = select_rows(select, table, false, &mut |row| {
+            if result.is_ok() {
+                result = emit(row);
+            }
+        });
+        if select_result != ExecuteSuccess {
+            return Err(select_result);
+        }
+        result?;
+    }
+    if let Some(sorter) = sorter {
+        sorter.finish()?.output(offset, limit, |row| output(row))?;
+    }
+    Ok(())
+}
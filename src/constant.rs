@@ -1,16 +1,67 @@
 use crate::node::NodeType;
 
-pub const ID_SIZE: usize = std::mem::size_of::<u32>();
+pub const TABLE_NAME: &str = "users";
+pub const COLUMN_NAMES: [&str; 3] = ["id", "username", "email"];
+/// The table of the largest rowid each AUTOINCREMENT table has been given, named as in SQLite.
+pub const SEQUENCE_TABLE_NAME: &str = "sqlite_sequence";
+
 pub const USERNAME_SIZE: usize = 32;
 pub const EMAIL_SIZE: usize = 255;
-pub const ID_OFFSET: usize = 0;
-pub const USERNAME_OFFSET: usize = ID_OFFSET + ID_SIZE;
-pub const EMAIL_OFFSET: usize = USERNAME_OFFSET + USERNAME_SIZE;
-pub const ROW_SIZE: usize = ID_SIZE + USERNAME_SIZE + EMAIL_SIZE;
 pub const PAGE_SIZE: usize = 4096;
-pub const TABLE_MAX_PAGES: usize = 100;
-pub const ROWS_PER_PAGE: usize = PAGE_SIZE / ROW_SIZE;
-pub const TABLE_MAX_ROWS: usize = TABLE_MAX_PAGES * ROWS_PER_PAGE;
+/// The most pages a database file may grow to unless `PRAGMA max_page_count` says otherwise.
+/// A write past it fails with ExecuteDatabaseFull.
+pub const TABLE_MAX_PAGES: usize = 1 << 20;
+/// Pages of a SQLite file kept in memory at once; the cache is emptied when it fills.
+pub const SQLITE_CACHE_PAGES: usize = 256;
+
+/// File Header Layout, at the start of page 0:
+/// MAGIC|FORMAT VERSION|CATALOG SIZE|NEXT CATALOG PAGE|FIRST FREE PAGE
+/// followed by the schema catalog, which continues on a chain of pages of
+/// NEXT CATALOG PAGE|CATALOG BYTES when it does not fit. Every field is a big-endian u32.
+/// Pages given back, once overflow pages, are kept on a list of NEXT FREE PAGE for reuse.
+pub const HEADER_PAGE_NUM: usize = 0;
+pub const FILE_MAGIC: &[u8; 16] = b"sqlite-rs format";
+/// Bumped whenever the layout of pages, cells or the catalog changes; a file of any other
+/// version is refused rather than misread. A file from before the header, which has none, is
+/// upgraded when opened; see `upgrade`. Versions 1 and 2 only ever came from builds on the way
+/// to this layout: a version 2 build can `.save` its file as a SQLite file, which this build
+/// reads, while a version 1 build has no `.save` and its rows can only be selected out.
+pub const FILE_FORMAT_VERSION: u32 = 3;
+pub const FORMAT_VERSION_OFFSET: usize = FILE_MAGIC.len();
+pub const CATALOG_SIZE_OFFSET: usize = FORMAT_VERSION_OFFSET + 4;
+pub const CATALOG_NEXT_PAGE_OFFSET: usize = CATALOG_SIZE_OFFSET + 4;
+pub const FREELIST_HEAD_OFFSET: usize = CATALOG_NEXT_PAGE_OFFSET + 4;
+pub const FILE_HEADER_SIZE: usize = FREELIST_HEAD_OFFSET + 4;
+/// Bytes of the catalog a page of its chain holds after the number of the next one.
+pub const CATALOG_PAGE_SPACE: usize = PAGE_SIZE - 4;
+
+/// Payload Slot Layout, for the record of a table or index cell:
+/// PAYLOAD SIZE|LOCAL PAYLOAD|FIRST OVERFLOW PAGE
+/// A slot keeps as much of its record as `MAX_LOCAL_PAYLOAD_SIZE` allows; a longer record
+/// continues on a chain of overflow pages of NEXT OVERFLOW PAGE|PAYLOAD BYTES. Both fields are
+/// big-endian u32s.
+pub const PAYLOAD_SIZE_SIZE: usize = 4;
+pub const OVERFLOW_POINTER_SIZE: usize = 4;
+pub const OVERFLOW_PAGE_SPACE: usize = PAGE_SIZE - 4;
+/// The most of a record a slot keeps on its page: as much as lets four of the largest cells,
+/// with their pointers, share a page, so that a page split in two always leaves halves that fit.
+pub const MAX_LOCAL_PAYLOAD_SIZE: usize = LEAF_NODE_SPACE_FOR_CELLS / 4 - CELL_POINTER_SIZE - LEAF_NODE_KEY_SIZE
+    - PAYLOAD_SIZE_SIZE - OVERFLOW_POINTER_SIZE;
+
+/// The root of the built-in users table, right after the header page.
+pub const USERS_ROOT_PAGE_NUM: usize = 1;
+/// Bytes of rows an ORDER BY keeps in memory before spilling a sorted run to a temporary file.
+pub const SORTER_MEMORY_BUDGET: usize = 16 * PAGE_SIZE;
+/// Runs a sorter merges at once, each of which holds a file open while it is read.
+pub const SORTER_MERGE_FAN_IN: usize = 16;
+/// Bytes of groups a GROUP BY keeps in memory before spilling rows of new groups to partitions.
+pub const AGGREGATE_MEMORY_BUDGET: usize = 16 * PAGE_SIZE;
+pub const AGGREGATE_SPILL_PARTITIONS: usize = 8;
+/// Times a GROUP BY partitions spilled rows again, each with another hash, when a partition is
+/// still too large.
+pub const AGGREGATE_SPILL_DEPTH: u64 = 4;
+/// The largest width or precision of a printf conversion; larger ones are clamped to it.
+pub const PRINTF_WIDTH_LIMIT: usize = 1_000_000;
 
 /// Common Node Header Layout:
 /// NODE TYPE|IS ROOT|PARENT POINTER
@@ -31,16 +82,12 @@ pub const LEAF_NODE_NEXT_LEAF_OFFSET: usize = LEAF_NODE_NUM_CELLS_OFFSET + LEAF_
 pub const LEAF_NODE_HEADER_SIZE: usize = COMMON_NODE_HEADER_SIZE + LEAF_NODE_NUM_CELLS_SIZE + LEAF_NODE_NEXT_LEAF_SIZE;
 
 /// Leaf Node Body Layout:
-/// [Leaf Node Key|Leaf Node Value]
-pub const LEAF_NODE_KEY_SIZE: usize = std::mem::size_of::<u32>();
-pub const LEAF_NODE_KEY_OFFSET: usize = 0;
-pub const LEAF_NODE_VALUE_SIZE: usize = ROW_SIZE;
-pub const LEAF_NODE_VALUE_OFFSET: usize = LEAF_NODE_KEY_OFFSET + LEAF_NODE_KEY_SIZE;
-pub const LEAF_NODE_CELL_SIZE: usize = LEAF_NODE_KEY_SIZE + LEAF_NODE_VALUE_SIZE;
+/// [Cell Pointer]...free space...[Leaf Node Key|Payload Slot]
+/// Each cell pointer is the big-endian u16 offset of its cell, in key order; the cells are
+/// packed against the end of the page, and a page is laid out again whenever it changes.
+pub const CELL_POINTER_SIZE: usize = 2;
+pub const LEAF_NODE_KEY_SIZE: usize = std::mem::size_of::<i64>();
 pub const LEAF_NODE_SPACE_FOR_CELLS: usize = PAGE_SIZE - LEAF_NODE_HEADER_SIZE;
-pub const LEAF_NODE_MAX_CELLS: usize = LEAF_NODE_SPACE_FOR_CELLS / LEAF_NODE_CELL_SIZE;
-pub const LEAF_NODE_RIGHT_SPLIT_COUNT: usize = (LEAF_NODE_MAX_CELLS + 1) / 2;
-pub const LEAF_NODE_LEFT_SPLIT_COUNT: usize = (LEAF_NODE_MAX_CELLS + 1) - LEAF_NODE_RIGHT_SPLIT_COUNT;
 
 /// Internal Node Header Layout
 pub const INTERNAL_NODE_NUM_KEYS_SIZE: usize = std::mem::size_of::<usize>();
@@ -50,21 +97,23 @@ pub const INTERNAL_NODE_RIGHT_CHILD_OFFSET: usize = INTERNAL_NODE_NUM_KEYS_OFFSE
 pub const INTERNAL_NODE_HEADER_SIZE: usize = COMMON_NODE_HEADER_SIZE + INTERNAL_NODE_NUM_KEYS_SIZE + INTERNAL_NODE_RIGHT_CHILD_SIZE;
 
 /// Internal Node Body Layout
-pub const INTERNAL_NODE_KEY_SIZE: usize = std::mem::size_of::<u32>();
+pub const INTERNAL_NODE_KEY_SIZE: usize = std::mem::size_of::<i64>();
 pub const INTERNAL_NODE_CHILD_SIZE: usize = std::mem::size_of::<usize>();
 pub const INTERNAL_NODE_CELL_SIZE: usize = INTERNAL_NODE_KEY_SIZE + INTERNAL_NODE_CHILD_SIZE;
 
 pub const INTERNAL_NODE_MAX_CELLS: usize = 100;
 
+// Index Node Body Layout:
+// Leaf: [Cell Pointer]...[Index Key], Internal: [Cell Pointer]...[Child Pointer|Index Key]
+// laid out as a table leaf is. An index key is a payload slot holding a record of a column
+// value and the rowid it points at, going on to overflow pages when long, as a row does.
 
 pub fn print_constants() {
-    println!("ROW_SIZE: {}", ROW_SIZE);
+    println!("MAX_LOCAL_PAYLOAD_SIZE: {}", MAX_LOCAL_PAYLOAD_SIZE);
     println!("COMMON_NODE_HEADER_SIZE: {}", COMMON_NODE_HEADER_SIZE);
     println!();
     println!("LEAF_NODE_HEADER_SIZE: {}", LEAF_NODE_HEADER_SIZE);
-    println!("LEAF_NODE_CELL_SIZE: {}", LEAF_NODE_CELL_SIZE);
     println!("LEAF_NODE_SPACE_FOR_CELLS: {}", LEAF_NODE_SPACE_FOR_CELLS);
-    println!("LEAF_NODE_MAX_CELLS: {}", LEAF_NODE_MAX_CELLS);
     println!();
     println!("INTERNAL_NODE_HEADER_SIZE: {}", INTERNAL_NODE_HEADER_SIZE);
     println!("INTERNAL_NODE_KEY_SIZE: {}", INTERNAL_NODE_KEY_SIZE);
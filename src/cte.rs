@@ -0,0 +1,252 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::rc::Rc;
+use crate::collation::KeyOrder;
+use crate::expr::Expr;
+use crate::join::{resolve_columns, JoinScope};
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::*;
+use crate::schema::{Schema, TableDef};
+use crate::statement::{limit_value, order_by_terms, query_rows, result_names, sort_order, OrderingTerm, Statement};
+use crate::subquery::materialize_subqueries;
+use crate::table::Table;
+use crate::value::Value;
+
+/// A table named by a WITH clause: `name [(column, ...)] AS (select)`, where a recursive table's
+/// SELECT is `initial UNION [ALL] recursive`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cte {
+    pub name: String,
+    /// The names the WITH clause gives the columns; without them the columns are named after
+    /// the result columns of the initial SELECT.
+    pub columns: Vec<String>,
+    /// The SELECT that computes the table, or its initial rows if the table is recursive.
+    pub initial: Statement,
+    /// The SELECT after the last UNION, if it reads the table itself: it runs again for every
+    /// row the table gets, with that row standing in for the whole table.
+    pub recursive: Option<Statement>,
+    pub union_all: bool,
+    /// The order rows wait in to go through the recursive SELECT; first in, first out without it.
+    pub order_by: Vec<OrderingTerm>,
+    /// The most rows the table gets, which stops the recursion once reached.
+    pub limit: Option<Expr>,
+    /// How many rows go through the recursive SELECT before the table keeps any.
+    pub offset: Option<Expr>,
+}
+
+/// Resolve a SELECT of a WITH clause against the tables it reads, returning the names of its
+/// result columns.
+fn resolve_select(select: &mut Statement, schema: &Schema) -> Result<Vec<String>, ExecuteResult> {
+    let scope = JoinScope::new(select, schema)?;
+    resolve_columns(select, &scope, schema)?;
+    result_names(select, schema)
+}
+
+/// Run a SELECT of a WITH clause, its uncorrelated subqueries first.
+fn run_select(select: &Statement, table: &mut Table) -> Result<Vec<Vec<Value>>, ExecuteResult> {
+    let mut select = select.clone();
+    materialize_subqueries(&mut select, table)?;
+    query_rows(&select, table)
+}
+
+/// Resolve every table of a WITH clause in turn and add it to the schema, where the statement
+/// and the tables after it find it. Unless `run` is unset, as for EXPLAIN QUERY PLAN, each
+/// table's rows are computed before the next table is resolved, or for a recursive table, started.
+pub fn materialize_ctes(ctes: &mut [Cte], run: bool, table: &mut Table) -> Result<(), ExecuteResult> {
+    for cte in ctes {
+        if table.schema.ctes.iter().any(|other| other.name.eq_ignore_ascii_case(&cte.name)) {
+            return Err(ExecuteDuplicateWithTable(cte.name.clone()));
+        }
+        let names = resolve_select(&mut cte.initial, &table.schema)?;
+        if !cte.columns.is_empty() && cte.columns.len() != names.len() {
+            return Err(ExecuteWithColumns(cte.name.clone(), names.len(), cte.columns.len()));
+        }
+        let names = if cte.columns.is_empty() { names } else { cte.columns.clone() };
+        // The table is in the schema before its recursive SELECT resolves, so that it can read it.
+        table.schema.ctes.push(TableDef::in_memory(&cte.name, names, Vec::new()));
+        if let Some(recursive) = &mut cte.recursive {
+            if resolve_select(recursive, &table.schema)?.len() != table.schema.ctes.last().unwrap().columns.len() {
+                return Err(ExecuteCompoundColumns(if cte.union_all { "UNION ALL" } else { "UNION" }));
+            }
+        }
+        if run {
+            match &cte.recursive {
+                Some(recursive) => {
+                    let rows = recursive_rows(cte, recursive, table)?;
+                    table.schema.ctes.last_mut().unwrap().recursive = Some(Rc::new(RefCell::new(rows)));
+                }
+                None => {
+                    let rows = run_select(&cte.initial, table)?;
+                    table.schema.ctes.last_mut().unwrap().rows = Some(Rc::new(rows));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A row waiting to go through the recursive SELECT. Rows leave a `BinaryHeap` of them in
+/// ORDER BY order if the table has one, and rows of equal keys in the order they came.
+struct Queued {
+    order: Rc<KeyOrder>,
+    key: Vec<Value>,
+    seq: u64,
+    row: Vec<Value>,
+}
+
+impl Ord for Queued {
+    // Reversed, so that the heap's greatest row is the least key queued first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.order.compare(&other.key, &self.key).then(other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+/// The rows of a recursive WITH table, computed the way SQLite computes them: the rows of the
+/// initial SELECT go into a queue, and each row taken from the queue is added to the table, then
+/// run through the recursive SELECT as the only row of the table, whose rows join the queue.
+/// Under UNION, a row already queued once is not queued again. Rows are only computed as far as
+/// the scans of the table reach, so a LIMIT of the statement reading it stops the recursion.
+pub struct RecursiveRows {
+    recursive: Rc<Statement>,
+    union_all: bool,
+    /// The table as the recursive SELECT reads it, without rows of its own.
+    working: TableDef,
+    order_by: Vec<(Expr, bool)>,
+    order: Rc<KeyOrder>,
+    queue: BinaryHeap<Queued>,
+    queued: HashSet<Vec<Value>>,
+    next_seq: u64,
+    /// The rows the table has so far.
+    rows: Vec<Vec<Value>>,
+    limit: usize,
+    offset: usize,
+}
+
+impl PartialEq for RecursiveRows {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for RecursiveRows {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RecursiveRows({}, {} rows)", self.working.name, self.rows.len())
+    }
+}
+
+impl RecursiveRows {
+    fn enqueue(&mut self, rows: Vec<Vec<Value>>) -> Result<(), ExecuteResult> {
+        for row in rows {
+            if !self.union_all && !self.queued.insert(row.clone()) {
+                continue;
+            }
+            let key = self.order_by.iter()
+                .map(|(expr, _)| expr.evaluate(&|name| self.working.column_value(&row, name)))
+                .collect::<Result<Vec<Value>, ExecuteResult>>()?;
+            self.queue.push(Queued { order: Rc::clone(&self.order), key, seq: self.next_seq, row });
+            self.next_seq += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Start the rows of a recursive WITH table, the last one in the schema, with the rows of its
+/// initial SELECT queued.
+fn recursive_rows(cte: &Cte, recursive: &Statement, table: &mut Table) -> Result<RecursiveRows, ExecuteResult> {
+    let working = table.schema.ctes.last().unwrap().clone();
+    let columns: Vec<(String, Expr)> = working.columns.iter()
+        .map(|column| (column.name.clone(), Expr::Column(column.name.clone())))
+        .collect();
+    let order_by = order_by_terms(&cte.order_by, &columns, Some(&working))?;
+    let order = Rc::new(sort_order(&order_by, Some(&working)));
+    let mut rows = RecursiveRows {
+        recursive: Rc::new(recursive.clone()),
+        union_all: cte.union_all,
+        working,
+        order_by,
+        order,
+        queue: BinaryHeap::new(),
+        queued: HashSet::new(),
+        next_seq: 0,
+        rows: Vec::new(),
+        limit: limit_value(cte.limit.as_ref())?.unwrap_or(usize::MAX),
+        offset: limit_value(cte.offset.as_ref())?.unwrap_or(0),
+    };
+    rows.enqueue(run_select(&cte.initial, table)?)?;
+    Ok(rows)
+}
+
+/// Take rows off the queue until one is added to the table, running each through the recursive
+/// SELECT. Returns false once the queue is empty or the table has its LIMIT of rows.
+fn next_recursive_row(state: &RefCell<RecursiveRows>, table: &mut Table) -> Result<bool, ExecuteResult> {
+    loop {
+        let (row, added, recursive, working) = {
+            let mut state = state.borrow_mut();
+            if state.rows.len() >= state.limit {
+                return Ok(false);
+            }
+            let row = match state.queue.pop() {
+                Some(queued) => queued.row,
+                None => return Ok(false),
+            };
+            let added = if state.offset > 0 {
+                state.offset -= 1;
+                false
+            } else {
+                state.rows.push(row.clone());
+                true
+            };
+            if state.rows.len() == state.limit {
+                return Ok(true);
+            }
+            (row, added, Rc::clone(&state.recursive), state.working.clone())
+        };
+        // The recursive SELECT reads the row as the whole table, in place of the table as the
+        // statement sees it.
+        let index = table.schema.ctes.iter().rposition(|def| def.name.eq_ignore_ascii_case(&working.name)).unwrap();
+        let saved = mem::replace(&mut table.schema.ctes[index], TableDef { rows: Some(Rc::new(vec![row])), ..working });
+        let result = run_select(&recursive, table);
+        table.schema.ctes[index] = saved;
+        state.borrow_mut().enqueue(result?)?;
+        if added {
+            return Ok(true);
+        }
+    }
+}
+
+/// Visit the rows of a recursive WITH table in order, computing more of them whenever the rows
+/// so far run out, until there are no more or `visit` returns false.
+pub fn for_each_recursive_row<F: FnMut(&mut Table, &[Value]) -> bool>(table: &mut Table, state: &RefCell<RecursiveRows>,
+                                                                      mut visit: F) -> Result<(), ExecuteResult> {
+    let mut next = 0;
+    loop {
+        let row = state.borrow().rows.get(next).cloned();
+        let row = match row {
+            Some(row) => row,
+            None if next_recursive_row(state, table)? => continue,
+            None => return Ok(()),
+        };
+        next += 1;
+        if !visit(table, &row) {
+            return Ok(());
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use crate::constant::*;
-use crate::page::Page;
-use crate::row::Row;
+use crate::page::{cells_fit, leaf_cell_size, split_cell, Page};
+use crate::schema::TableDef;
 use crate::table::Table;
-use crate::utils::{copy_page_data, serialize_row};
+use crate::value::Value;
 
 #[warn(unused_assignments)]
 pub struct Cursor<'a> {
@@ -13,18 +13,33 @@ pub struct Cursor<'a> {
 }
 
 impl<'a> Cursor<'a> {
-    pub fn table_start(table: &'a mut Table) -> Self {
-        let root_page_num = table.root_page_num;
-
+    pub fn table_start(table: &'a mut Table, root_page_num: usize) -> Self {
         let leaf_page_num = table.pager.get_leftmost_leaf_page_num(root_page_num);
-        let leaf_node = table.pager.get_page_view(leaf_page_num).unwrap();
-        let num_cells = leaf_node.leaf_node_num_cells();
 
-        Cursor {
+        let mut cursor = Cursor {
             table,
             cell_num: 0,
             page_num: leaf_page_num,
-            end_of_table: num_cells == 0,
+            end_of_table: false,
+        };
+        cursor.skip_empty_leaves();
+        cursor
+    }
+
+    /// Deleting rows can leave leaves without cells; move on to the next leaf holding one.
+    fn skip_empty_leaves(&mut self) {
+        loop {
+            let page = self.table.pager.get_page_view(self.page_num).unwrap();
+            if self.cell_num < page.leaf_node_num_cells() {
+                return;
+            }
+            let next_page_num = page.get_leaf_node_next_leaf();
+            if next_page_num == 0 {
+                self.end_of_table = true;
+                return;
+            }
+            self.page_num = next_page_num;
+            self.cell_num = 0;
         }
     }
 
@@ -37,88 +52,90 @@ impl<'a> Cursor<'a> {
     }
 
     pub fn advance(&mut self) {
-        let page = self.table.pager.get_page_view(self.page_num).unwrap();
         self.cell_num += 1;
-        if self.cell_num >= page.leaf_node_num_cells() {
-            let next_page_num = page.get_leaf_node_next_leaf();
-            if next_page_num == 0 {
-                self.end_of_table = true;
-            } else {
-                self.page_num = next_page_num;
-                self.cell_num = 0;
-            }
-        }
+        self.skip_empty_leaves();
     }
 
-    pub fn cursor_value(&mut self) -> Box<Row> {
+    pub fn cursor_value(&mut self, table_def: &TableDef) -> Vec<Value> {
         let cell_num = self.cell_num;
         let page = self.get_page_view().unwrap();
-        unsafe { page.row_mut_slot(cell_num) }
+        table_def.decode_row(&self.table.pager, page, cell_num)
     }
 
-    pub unsafe fn leaf_node_insert(&mut self, key: u32, value: &Row) {
-        let cell_num = self.cell_num;
-        let page = self.get_page();
-        let num_cells = page.leaf_node_num_cells();
-        if num_cells >= LEAF_NODE_MAX_CELLS {
-            self.leaf_node_split_and_insert(value.id, value);
+    /// The payload slot of the cell under the cursor.
+    fn value_slot(&self) -> Vec<u8> {
+        self.get_page_view().unwrap().leaf_node_slot(self.cell_num).to_vec()
+    }
+
+    /// Overwrite the value of the cell under the cursor with an encoded row, splitting the leaf
+    /// when the row has grown past the room left on it.
+    pub fn update_value(&mut self, value: &[u8]) {
+        let old_slot = self.value_slot();
+        self.table.pager.free_payload(&old_slot);
+        let slot = self.table.pager.write_payload(value);
+        let mut cells = self.get_page_view().unwrap().leaf_node_cells();
+        cells[self.cell_num].1 = slot;
+        self.set_cells(cells);
+    }
+
+    /// Remove the cell under the cursor. Leaves are never merged, so a leaf may end up empty.
+    pub fn leaf_node_delete(&mut self) {
+        let slot = self.value_slot();
+        self.table.pager.free_payload(&slot);
+        let mut cells = self.get_page_view().unwrap().leaf_node_cells();
+        cells.remove(self.cell_num);
+        self.get_page().set_leaf_node_cells(&cells);
+    }
+
+    pub fn leaf_node_insert(&mut self, key: i64, value: &[u8]) {
+        let slot = self.table.pager.write_payload(value);
+        let mut cells = self.get_page_view().unwrap().leaf_node_cells();
+        cells.insert(self.cell_num, (key, slot));
+        self.set_cells(cells);
+    }
+
+    /// Write the cells to the leaf under the cursor, splitting it in two when they do not fit.
+    fn set_cells(&mut self, cells: Vec<(i64, Vec<u8>)>) {
+        if cells_fit(cells.iter().map(|(_, slot)| leaf_cell_size(slot))) {
+            self.get_page().set_leaf_node_cells(&cells);
             return;
         }
-        if cell_num < num_cells {
-            for i in (cell_num + 1..=num_cells).rev() {
-                std::ptr::copy_nonoverlapping(page.leaf_node_cell(i - 1),
-                                              page.leaf_node_cell(i) as *mut u8,
-                                              LEAF_NODE_CELL_SIZE);
-            }
-        }
-        page.set_leaf_node_num_cells(num_cells + 1);
-        page.set_leaf_node_key(cell_num, key);
-
-        let cell = page.leaf_node_value(cell_num);
-        serialize_row(cell, value);
+        self.leaf_node_split(cells);
     }
 
-    fn leaf_node_split_and_insert(&mut self, key: u32, value: &Row) {
-        let value_cell_num = self.cell_num;
-        let new_page_num = self.table.pager.get_unused_page_num();
-        let old_max;
+    fn leaf_node_split(&mut self, mut cells: Vec<(i64, Vec<u8>)>) {
+        let sizes: Vec<usize> = cells.iter().map(|(_, slot)| leaf_cell_size(slot)).collect();
+        let right_cells = cells.split_off(split_cell(&sizes) + 1);
+        let new_page_num = self.table.pager.allocate_page();
+        let old_node = self.get_page_view().unwrap();
+        let old_next_page_num = old_node.get_leaf_node_next_leaf();
+        let old_node_parent_num = old_node.get_node_parent();
         {
-            let old_node = self.get_page_view().unwrap();
-            old_max = old_node.get_node_max_key();
-            let old_next_page_num = old_node.get_leaf_node_next_leaf();
-            let old_node_parent_num = old_node.get_node_parent();
-            let old_node_ptr = old_node as *const Page;
             let new_node = self.table.pager.get_page(new_page_num);
             new_node.initialize_leaf_node();
             new_node.set_node_parent(old_node_parent_num);
             new_node.set_leaf_node_next_leaf(old_next_page_num);
-            copy_page_data((LEAF_NODE_LEFT_SPLIT_COUNT..LEAF_NODE_MAX_CELLS + 1).rev(), old_node_ptr, new_node, key, value, value_cell_num);
-            new_node.set_leaf_node_num_cells(LEAF_NODE_RIGHT_SPLIT_COUNT);
+            new_node.set_leaf_node_cells(&right_cells);
         }
 
-        let mut is_node_root = false;
+        let is_node_root;
         {
             let old_node = self.get_page();
             is_node_root = old_node.is_node_root();
-            copy_page_data((0..LEAF_NODE_LEFT_SPLIT_COUNT).rev(), old_node as *const Page, old_node, key, value, value_cell_num);
-            old_node.set_leaf_node_num_cells(LEAF_NODE_LEFT_SPLIT_COUNT);
+            old_node.set_leaf_node_cells(&cells);
             old_node.set_leaf_node_next_leaf(new_page_num);
         }
 
         if is_node_root {
             self.create_new_node(new_page_num);
         } else {
-            let old_node = self.get_page();
-            let parent_page_num = old_node.get_node_parent();
-            let new_max = old_node.get_node_max_key();
-            let parent = self.table.pager.get_page(parent_page_num);
-            parent.update_internal_node_key(old_max, new_max);
-            self.table.internal_node_insert(parent_page_num, new_page_num);
+            let separator = cells.last().unwrap().0;
+            self.table.internal_node_split_child(old_node_parent_num, self.page_num, separator, new_page_num);
         }
     }
 
     fn create_new_node(&mut self, right_child_page_num: usize) {
-        let left_child_page_num = self.table.pager.get_unused_page_num();
+        let left_child_page_num = self.table.pager.allocate_page();
         let node_max_key;
         {
             let old_node = self.get_page_view().unwrap();
@@ -139,7 +156,8 @@ impl<'a> Cursor<'a> {
         old_node.set_internal_node_key(0, node_max_key);
         old_node.set_internal_node_right_child(right_child_page_num);
 
-        let root_page_num = self.table.root_page_num;
+        // The cursor sits on the leaf that was the root, whose page now holds the new root.
+        let root_page_num = self.page_num;
         {
             let left_child = self.table.pager.get_page(left_child_page_num);
             left_child.set_node_parent(root_page_num);
@@ -0,0 +1,372 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::printf;
+use crate::value::{parse_numeric, Value};
+
+/// Moments are counted, as in SQLite, in milliseconds of the Julian day number, which starts
+/// at noon on 24 November 4714 BC in the proleptic Gregorian calendar.
+const DAY_MS: i64 = 86_400_000;
+/// The Julian day of 1970-01-01 00:00:00, in milliseconds.
+const UNIX_EPOCH_MS: i64 = 210_866_760_000_000;
+/// The last millisecond of 9999-12-31, the latest moment the functions answer for.
+const MAX_MS: i64 = 464_269_060_799_999;
+
+/// A moment broken down into the fields of the calendar and the clock.
+struct Civil {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    /// Milliseconds into the minute.
+    millis: i64,
+}
+
+impl Civil {
+    /// The moment of a date and time, where days past the end of the month roll into the
+    /// next, as '2021-02-30' is 2 March. None if the moment does not fit in an i64.
+    fn to_moment(&self) -> Option<i64> {
+        let (mut year, mut month) = (self.year, self.month);
+        if month <= 2 {
+            year -= 1;
+            month += 12;
+        }
+        let century = year / 100;
+        let leap_correction = 2 - century + century / 4;
+        let days = 36525i64.checked_mul(year.checked_add(4716)?)? / 100 + 306001 * (month + 1) / 10000 + self.day + leap_correction;
+        // The day number counts from noon, so midnight is half a day before it.
+        (days - 1524).checked_mul(DAY_MS)?
+            .checked_add(self.hour * 3_600_000 + self.minute * 60_000 + self.millis - DAY_MS / 2)
+    }
+
+    fn from_moment(moment: i64) -> Civil {
+        let z = (moment + DAY_MS / 2) / DAY_MS;
+        let alpha = ((z as f64 - 1867216.25) / 36524.25) as i64;
+        let a = z + 1 + alpha - alpha / 4;
+        let b = a + 1524;
+        let c = ((b as f64 - 122.1) / 365.25) as i64;
+        let d = 36525 * (c & 32767) / 100;
+        let e = ((b - d) as f64 / 30.6001) as i64;
+        let month = if e < 14 { e - 1 } else { e - 13 };
+        let in_day = (moment + DAY_MS / 2) % DAY_MS;
+        Civil {
+            year: if month > 2 { c - 4716 } else { c - 4715 },
+            month,
+            day: b - d - (30.6001 * e as f64) as i64,
+            hour: in_day / 3_600_000,
+            minute: in_day / 60_000 % 60,
+            millis: in_day % 60_000,
+        }
+    }
+}
+
+fn now() -> i64 {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    UNIX_EPOCH_MS + since_epoch.as_millis() as i64
+}
+
+/// Reads the fixed-width fields of a time string.
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn accept(&mut self, byte: u8) -> bool {
+        let found = self.bytes.get(self.pos) == Some(&byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// Exactly `count` digits, read as a number no greater than `max`.
+    fn number(&mut self, count: usize, max: i64) -> Option<i64> {
+        let digits = self.bytes.get(self.pos..self.pos + count)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        self.pos += count;
+        let number = digits.iter().fold(0, |n, digit| n * 10 + (digit - b'0') as i64);
+        if number > max { None } else { Some(number) }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.accept(b' ') {}
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+
+    /// YYYY-MM-DD
+    fn date(&mut self) -> Option<(i64, i64, i64)> {
+        let year = self.number(4, 9999)?;
+        self.accept(b'-').then_some(())?;
+        let month = self.number(2, 12)?;
+        self.accept(b'-').then_some(())?;
+        let day = self.number(2, 31)?;
+        if month == 0 || day == 0 { None } else { Some((year, month, day)) }
+    }
+
+    /// HH:MM[:SS[.SSS]], as hours, minutes and milliseconds into the minute.
+    fn time(&mut self) -> Option<(i64, i64, i64)> {
+        let hour = self.number(2, 23)?;
+        self.accept(b':').then_some(())?;
+        let minute = self.number(2, 59)?;
+        let mut millis = 0;
+        if self.accept(b':') {
+            millis = self.number(2, 59)? * 1000;
+            if self.accept(b'.') {
+                let start = self.pos;
+                while self.bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+                    self.pos += 1;
+                }
+                let fraction = std::str::from_utf8(&self.bytes[start - 1..self.pos]).ok()?;
+                millis += (fraction.parse::<f64>().ok()? * 1000.0).round() as i64;
+            }
+        }
+        Some((hour, minute, millis))
+    }
+
+    /// [+-]HH:MM or Z after a time, as the milliseconds it is ahead of UTC.
+    fn zone(&mut self) -> Option<i64> {
+        self.skip_spaces();
+        if self.accept(b'Z') || self.accept(b'z') {
+            return Some(0);
+        }
+        let sign = if self.accept(b'+') {
+            1
+        } else if self.accept(b'-') {
+            -1
+        } else {
+            return Some(0);
+        };
+        let hours = self.number(2, 14)?;
+        self.accept(b':').then_some(())?;
+        let minutes = self.number(2, 59)?;
+        Some(sign * (hours * 3_600_000 + minutes * 60_000))
+    }
+}
+
+/// A time string: YYYY-MM-DD, optionally followed by a space or T and a time, or a time alone on
+/// 2000-01-01. A time may end in a zone, which is taken off to give UTC.
+fn parse_time_string(text: &str) -> Option<i64> {
+    let mut scanner = Scanner { bytes: text.as_bytes(), pos: 0 };
+    let mut civil = Civil { year: 2000, month: 1, day: 1, hour: 0, minute: 0, millis: 0 };
+    if text.as_bytes().get(2) != Some(&b':') {
+        (civil.year, civil.month, civil.day) = scanner.date()?;
+        if scanner.at_end() {
+            return civil.to_moment();
+        }
+        if !scanner.accept(b'T') {
+            scanner.accept(b' ').then_some(())?;
+            scanner.skip_spaces();
+        }
+    }
+    (civil.hour, civil.minute, civil.millis) = scanner.time()?;
+    let zone = scanner.zone()?;
+    scanner.skip_spaces();
+    scanner.at_end().then_some(())?;
+    civil.to_moment()?.checked_sub(zone)
+}
+
+/// The moment a time value and its modifiers name, as far as it has been worked out. A number
+/// is kept until the first modifier, which may say it counts something other than days.
+struct Moment {
+    moment: i64,
+    number: Option<f64>,
+}
+
+impl Moment {
+    /// A time value: a time string, 'now', or a number of Julian days.
+    fn parse(value: &Value) -> Option<Moment> {
+        let number = match value {
+            Value::Null => return None,
+            Value::Integer(_) | Value::Real(_) => value.to_real(),
+            Value::Text(text) => parse_numeric(text).and_then(|number| number.to_real()),
+            Value::Blob(_) => None,
+        };
+        if let Some(number) = number {
+            let moment = if (0.0..5_373_484.5).contains(&number) { (number * DAY_MS as f64 + 0.5) as i64 } else { -1 };
+            return Some(Moment { moment, number: Some(number) });
+        }
+        let text = value.to_string();
+        let text = text.trim();
+        if text.eq_ignore_ascii_case("now") {
+            return Some(Moment { moment: now(), number: None });
+        }
+        parse_time_string(text).map(|moment| Moment { moment, number: None })
+    }
+
+    fn civil(&self) -> Civil {
+        Civil::from_moment(self.moment)
+    }
+
+    /// Apply one modifier, or return None if it is not one.
+    fn modify(&mut self, modifier: &str) -> Option<()> {
+        let modifier = modifier.trim().to_lowercase();
+        let number = self.number.take();
+        match modifier.as_str() {
+            "unixepoch" => self.moment = UNIX_EPOCH_MS.checked_add((number? * 1000.0).round() as i64)?,
+            "julianday" => {
+                number?;
+            }
+            "auto" => {
+                let number = number?;
+                if !(0.0..5_373_484.5).contains(&number) {
+                    self.moment = UNIX_EPOCH_MS.checked_add((number * 1000.0).round() as i64)?;
+                }
+            }
+            // There is no time zone database to hand, so local time is taken to be UTC.
+            "localtime" | "utc" => {}
+            "start of day" => self.moment -= (self.moment + DAY_MS / 2).rem_euclid(DAY_MS),
+            "start of month" | "start of year" => {
+                let civil = self.civil();
+                let month = if modifier == "start of year" { 1 } else { civil.month };
+                self.moment = Civil { month, day: 1, hour: 0, minute: 0, millis: 0, ..civil }.to_moment()?;
+            }
+            _ if modifier.starts_with("weekday ") => {
+                let weekday = parse_numeric(&modifier["weekday ".len()..])?.to_real()?;
+                if weekday.fract() != 0.0 || !(0.0..7.0).contains(&weekday) {
+                    return None;
+                }
+                let days = (weekday as i64 - weekday_of(self.moment)).rem_euclid(7);
+                self.moment += days * DAY_MS;
+            }
+            _ if modifier.starts_with(['+', '-']) && modifier.as_bytes().get(3) == Some(&b':') => {
+                let mut scanner = Scanner { bytes: &modifier.as_bytes()[1..], pos: 0 };
+                let (hour, minute, millis) = scanner.time()?;
+                if !scanner.at_end() {
+                    return None;
+                }
+                let sign = if modifier.starts_with('-') { -1 } else { 1 };
+                self.moment += sign * (hour * 3_600_000 + minute * 60_000 + millis);
+            }
+            _ => {
+                let (amount, unit) = modifier.split_once(' ')?;
+                let amount = parse_numeric(amount)?.to_real()?;
+                let unit = unit.trim_start();
+                let unit = unit.strip_suffix('s').unwrap_or(unit);
+                // Casts saturate, and the sums are checked, so an amount too large for any
+                // moment gives None rather than overflowing.
+                let shift = |moment: i64, ms: f64| moment.checked_add((amount * ms).round() as i64);
+                match unit {
+                    "day" => self.moment = shift(self.moment, DAY_MS as f64)?,
+                    "hour" => self.moment = shift(self.moment, 3_600_000.0)?,
+                    "minute" => self.moment = shift(self.moment, 60_000.0)?,
+                    "second" => self.moment = shift(self.moment, 1000.0)?,
+                    // Whole months and years move the calendar, and what is left over is
+                    // counted as 30 and 365 days.
+                    "month" | "year" => {
+                        let civil = self.civil();
+                        let months = if unit == "month" { amount.trunc() as i64 } else { (amount.trunc() as i64).checked_mul(12)? };
+                        let month = (civil.year * 12 + civil.month - 1).checked_add(months)?;
+                        self.moment = Civil { year: month.div_euclid(12), month: month.rem_euclid(12) + 1, ..civil }.to_moment()?;
+                        let days_per_unit = if unit == "month" { 30.0 } else { 365.0 };
+                        self.moment = self.moment.checked_add((amount.fract() * days_per_unit * DAY_MS as f64).round() as i64)?;
+                    }
+                    _ => return None,
+                }
+            }
+        }
+        Some(())
+    }
+}
+
+/// The day of the week, from 0 for Sunday.
+fn weekday_of(moment: i64) -> i64 {
+    (moment + DAY_MS / 2 + DAY_MS).div_euclid(DAY_MS) % 7
+}
+
+/// The moment named by a time value and modifiers, 'now' if there are none, or None if any of
+/// them is NULL or invalid or the moment falls outside the Julian days up to 9999, as SQLite
+/// also checks after every modifier.
+fn moment(args: &[Value]) -> Option<i64> {
+    let mut moment = match args.first() {
+        Some(value) => Moment::parse(value)?,
+        None => Moment { moment: now(), number: None },
+    };
+    for modifier in args.iter().skip(1) {
+        if modifier.is_null() {
+            return None;
+        }
+        moment.modify(&modifier.to_string())?;
+        (0..=MAX_MS).contains(&moment.moment).then_some(())?;
+    }
+    (0..=MAX_MS).contains(&moment.moment).then_some(moment.moment)
+}
+
+/// Formats a moment as `strftime` does: %d day of month, %f seconds with milliseconds, %H hour,
+/// %j day of year, %J Julian day, %m month, %M minute, %s Unix time, %S seconds, %u and %w day of
+/// week from Monday as 1 and from Sunday as 0, %U and %W week of year from the first Sunday and
+/// from the first Monday, %Y year,
+/// %F and %T the ISO date and time, %R hour and minute, and %% a percent sign. Any other
+/// specifier gives None.
+fn format(format: &str, moment: i64) -> Option<String> {
+    let civil = Civil::from_moment(moment);
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let day_of_year = || {
+            let first = Civil { month: 1, day: 1, ..Civil::from_moment(moment) }.to_moment()?;
+            Some((moment - first + DAY_MS / 2) / DAY_MS)
+        };
+        match chars.next()? {
+            'd' => out.push_str(&format!("{:02}", civil.day)),
+            'f' => out.push_str(&format!("{:06.3}", civil.millis.min(59_999) as f64 / 1000.0)),
+            'H' => out.push_str(&format!("{:02}", civil.hour)),
+            'j' => out.push_str(&format!("{:03}", day_of_year()? + 1)),
+            'J' => out.push_str(&printf::format("%.16g", &[Value::Real(moment as f64 / DAY_MS as f64)])),
+            'm' => out.push_str(&format!("{:02}", civil.month)),
+            'M' => out.push_str(&format!("{:02}", civil.minute)),
+            's' => out.push_str(&(moment - UNIX_EPOCH_MS).div_euclid(1000).to_string()),
+            'S' => out.push_str(&format!("{:02}", civil.millis / 1000)),
+            'u' => out.push_str(&match weekday_of(moment) { 0 => 7, weekday => weekday }.to_string()),
+            'w' => out.push_str(&weekday_of(moment).to_string()),
+            'U' => out.push_str(&format!("{:02}", (day_of_year()? + 7 - weekday_of(moment)) / 7)),
+            'W' => {
+                let monday_weekday = (weekday_of(moment) + 6) % 7;
+                out.push_str(&format!("{:02}", (day_of_year()? + 7 - monday_weekday) / 7));
+            }
+            'Y' => out.push_str(&format!("{:04}", civil.year)),
+            'F' => out.push_str(&format!("{:04}-{:02}-{:02}", civil.year, civil.month, civil.day)),
+            'T' => out.push_str(&format!("{:02}:{:02}:{:02}", civil.hour, civil.minute, civil.millis / 1000)),
+            'R' => out.push_str(&format!("{:02}:{:02}", civil.hour, civil.minute)),
+            '%' => out.push('%'),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+/// SQL `date(time value, modifiers...)`: YYYY-MM-DD.
+pub fn date(args: &[Value]) -> Value {
+    moment(args).and_then(|moment| format("%F", moment)).map_or(Value::Null, Value::Text)
+}
+
+/// SQL `time(time value, modifiers...)`: HH:MM:SS.
+pub fn time(args: &[Value]) -> Value {
+    moment(args).and_then(|moment| format("%T", moment)).map_or(Value::Null, Value::Text)
+}
+
+/// SQL `datetime(time value, modifiers...)`: YYYY-MM-DD HH:MM:SS.
+pub fn datetime(args: &[Value]) -> Value {
+    moment(args).and_then(|moment| format("%F %T", moment)).map_or(Value::Null, Value::Text)
+}
+
+/// SQL `julianday(time value, modifiers...)`: the days since noon on 24 November 4714 BC.
+pub fn julianday(args: &[Value]) -> Value {
+    moment(args).map_or(Value::Null, |moment| Value::Real(moment as f64 / DAY_MS as f64))
+}
+
+/// SQL `strftime(format, time value, modifiers...)`.
+pub fn strftime(args: &[Value]) -> Value {
+    if args[0].is_null() {
+        return Value::Null;
+    }
+    moment(&args[1..]).and_then(|moment| format(&args[0].to_string(), moment)).map_or(Value::Null, Value::Text)
+}
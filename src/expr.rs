@@ -0,0 +1,824 @@
+use std::cmp::Ordering;
+use std::fmt;
+use crate::aggregate::AggregateFunc;
+use crate::collation::{Collation, Collations};
+use crate::function::{self, Functions, Registration};
+use crate::json;
+use crate::pattern;
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::*;
+use crate::schema::ColumnType;
+use crate::statement::OrderingTerm;
+use crate::subquery::Subquery;
+use crate::value::Value;
+use crate::window::{Window, WindowFunc};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    /// The operator that gives the same answer with its operands swapped.
+    pub fn flip(&self) -> CompareOp {
+        match self {
+            CompareOp::Lt => CompareOp::Gt,
+            CompareOp::Le => CompareOp::Ge,
+            CompareOp::Gt => CompareOp::Lt,
+            CompareOp::Ge => CompareOp::Le,
+            op => *op,
+        }
+    }
+
+    pub fn compare(&self, collation: &Collation, left: &Value, right: &Value) -> bool {
+        let ordering = collation.compare(left, right);
+        match self {
+            CompareOp::Eq => ordering == Ordering::Equal,
+            CompareOp::Ne => ordering != Ordering::Equal,
+            CompareOp::Lt => ordering == Ordering::Less,
+            CompareOp::Le => ordering != Ordering::Greater,
+            CompareOp::Gt => ordering == Ordering::Greater,
+            CompareOp::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl fmt::Display for CompareOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            CompareOp::Eq => "=",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// One `column op literal` term of a WHERE clause's top-level AND, which the planner can
+/// answer with a key seek or an index range.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Predicate {
+    pub column: String,
+    pub op: CompareOp,
+    pub value: Value,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Compare(CompareOp),
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    /// `json -> path`: the element the path leads to, as JSON.
+    JsonExtract,
+    /// `json ->> path`: the element the path leads to, as a SQL value.
+    JsonExtractValue,
+    /// `column MATCH query`, which only a full-text table's index can answer.
+    Match,
+}
+
+impl BinaryOp {
+    /// Binding strength, loosest first, matching the order `Parser::parse_expr` descends in.
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Compare(_) | BinaryOp::Match => 4,
+            BinaryOp::Add | BinaryOp::Subtract => 5,
+            BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Remainder => 6,
+            BinaryOp::JsonExtract | BinaryOp::JsonExtractValue => 7,
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryOp::Or => write!(f, "OR"),
+            BinaryOp::And => write!(f, "AND"),
+            BinaryOp::Compare(op) => write!(f, "{}", op),
+            BinaryOp::Add => write!(f, "+"),
+            BinaryOp::Subtract => write!(f, "-"),
+            BinaryOp::Multiply => write!(f, "*"),
+            BinaryOp::Divide => write!(f, "/"),
+            BinaryOp::Remainder => write!(f, "%"),
+            BinaryOp::JsonExtract => write!(f, "->"),
+            BinaryOp::JsonExtractValue => write!(f, "->>"),
+            BinaryOp::Match => write!(f, "MATCH"),
+        }
+    }
+}
+
+/// A scalar expression, as written in WHERE, DEFAULT, CHECK, VALUES and SET.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expr {
+    Literal(Value),
+    Column(String),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// `left IS [NOT] right`: equality under which NULL equals NULL.
+    Is {
+        left: Box<Expr>,
+        right: Box<Expr>,
+        negated: bool,
+    },
+    /// `operand [NOT] IN (list)`
+    InList {
+        operand: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    /// `operand [NOT] LIKE pattern [ESCAPE escape]`, or GLOB when `glob` is set.
+    Like {
+        operand: Box<Expr>,
+        pattern: Box<Expr>,
+        escape: Option<Box<Expr>>,
+        glob: bool,
+        negated: bool,
+    },
+    /// `operand [NOT] BETWEEN low AND high`
+    Between {
+        operand: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool,
+    },
+    /// `name([DISTINCT] args)`, with the name lowercased; `count(*)` has no arguments. A call
+    /// to a function registered on the connection is bound to it before the statement runs.
+    Function {
+        name: String,
+        args: Vec<Expr>,
+        distinct: bool,
+        registered: Option<Registration>,
+    },
+    /// `(select ...)`: the first column of the first row, or NULL when there are no rows.
+    Subquery(Box<Subquery>),
+    /// `EXISTS (select ...)`
+    Exists(Box<Subquery>),
+    /// `operand [NOT] IN (select ...)`
+    InSelect {
+        operand: Box<Expr>,
+        subquery: Box<Subquery>,
+        negated: bool,
+    },
+    /// `operand COLLATE name`: the operand's value, compared as the collation says. The
+    /// resolver also wraps a column that declares a collation in one, `declared` then set,
+    /// where the column is an operand of a comparison.
+    Collate {
+        operand: Box<Expr>,
+        collation: Collation,
+        declared: bool,
+    },
+    /// A column of a query a correlated subquery is nested in, `depth` levels out: 1 for the
+    /// query directly around it. Bound to that query's current row before the subquery runs.
+    OuterColumn { name: String, depth: usize },
+    /// `name(args) OVER (window)`, with the name lowercased: a value computed over the rows
+    /// of the current row's partition. A registered aggregate is bound as a function call is.
+    Window {
+        name: String,
+        args: Vec<Expr>,
+        window: Box<Window>,
+        registered: Option<Registration>,
+    },
+}
+
+fn bool_value(b: Option<bool>) -> Value {
+    match b {
+        Some(b) => Value::Integer(b as i64),
+        None => Value::Null,
+    }
+}
+
+fn compare(op: CompareOp, collation: &Collation, left: &Value, right: &Value) -> Option<bool> {
+    if left.is_null() || right.is_null() {
+        None
+    } else {
+        Some(op.compare(collation, left, right))
+    }
+}
+
+/// The collation a comparison of two operands uses, as SQLite picks it: an explicit COLLATE
+/// on the left, then on the right, then a declared one on the left, then on the right, else
+/// BINARY.
+fn comparison_collation(left: &Expr, right: &Expr) -> Collation {
+    let find = |explicit: bool| [left, right].into_iter().find_map(|operand| match operand {
+        Expr::Collate { collation, declared, .. } if *declared != explicit => Some(collation.clone()),
+        _ => None,
+    });
+    find(true).or_else(|| find(false)).unwrap_or_default()
+}
+
+/// Arithmetic on two numbers as SQLite does it: integers give an integer unless it would
+/// overflow, which gives a real instead, and a real on either side gives a real. Dividing by
+/// zero gives NULL rather than an error, and a remainder is always of the integer parts.
+fn arithmetic(op: BinaryOp, left: Value, right: Value) -> Value {
+    if let (Value::Integer(a), Value::Integer(b)) = (&left, &right) {
+        let (a, b) = (*a, *b);
+        let result = match op {
+            BinaryOp::Divide | BinaryOp::Remainder if b == 0 => return Value::Null,
+            BinaryOp::Add => a.checked_add(b),
+            BinaryOp::Subtract => a.checked_sub(b),
+            BinaryOp::Multiply => a.checked_mul(b),
+            BinaryOp::Divide => a.checked_div(b),
+            _ => Some(a.wrapping_rem(b)),
+        };
+        if let Some(result) = result {
+            return Value::Integer(result);
+        }
+    }
+    let (a, b) = (left.to_real().unwrap(), right.to_real().unwrap());
+    match op {
+        BinaryOp::Add => Value::from_real(a + b),
+        BinaryOp::Subtract => Value::from_real(a - b),
+        BinaryOp::Multiply => Value::from_real(a * b),
+        BinaryOp::Divide if b == 0.0 => Value::Null,
+        BinaryOp::Divide => Value::from_real(a / b),
+        _ => match (a as i64, b as i64) {
+            (_, 0) => Value::Null,
+            (a, b) => Value::Real(a.wrapping_rem(b) as f64),
+        },
+    }
+}
+
+/// Three-valued AND: false wins over NULL, NULL wins over true.
+fn and(left: Option<bool>, right: Option<bool>) -> Option<bool> {
+    match (left, right) {
+        (Some(false), _) | (_, Some(false)) => Some(false),
+        (Some(true), Some(true)) => Some(true),
+        _ => None,
+    }
+}
+
+/// Three-valued OR: true wins over NULL, NULL wins over false.
+fn or(left: Option<bool>, right: Option<bool>) -> Option<bool> {
+    match (left, right) {
+        (Some(true), _) | (_, Some(true)) => Some(true),
+        (Some(false), Some(false)) => Some(false),
+        _ => None,
+    }
+}
+
+fn negate_if(b: Option<bool>, negated: bool) -> Option<bool> {
+    b.map(|b| b != negated)
+}
+
+impl Expr {
+    /// Evaluate against one row, `column` looking up a column value by name.
+    /// NULL propagates through every operator except where AND and OR can decide without it.
+    pub fn evaluate(&self, column: &dyn Fn(&str) -> Option<Value>) -> Result<Value, ExecuteResult> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Column(name) => column(name).ok_or_else(|| ExecuteNoSuchColumn(name.clone())),
+            Expr::Negate(operand) => Ok(match operand.evaluate(column)?.to_numeric() {
+                Some(Value::Integer(i)) => i.checked_neg().map_or(Value::Real(-(i as f64)), Value::Integer),
+                Some(Value::Real(r)) => Value::Real(-r),
+                _ => Value::Null,
+            }),
+            Expr::Not(operand) => Ok(bool_value(operand.evaluate(column)?.to_bool().map(|b| !b))),
+            Expr::Binary { op, left: left_expr, right: right_expr } => {
+                let left = left_expr.evaluate(column)?;
+                let right = right_expr.evaluate(column)?;
+                Ok(match op {
+                    BinaryOp::Or => bool_value(or(left.to_bool(), right.to_bool())),
+                    BinaryOp::And => bool_value(and(left.to_bool(), right.to_bool())),
+                    BinaryOp::Compare(op) => {
+                        bool_value(compare(*op, &comparison_collation(left_expr, right_expr), &left, &right))
+                    }
+                    BinaryOp::JsonExtract | BinaryOp::JsonExtractValue => {
+                        json::extract_operator(&left, &right, *op == BinaryOp::JsonExtract)?
+                    }
+                    BinaryOp::Match => return Err(ExecuteFullTextError(String::from("unable to use function MATCH in the requested context"))),
+                    _ => match (left.to_numeric(), right.to_numeric()) {
+                        (Some(left), Some(right)) => arithmetic(*op, left, right),
+                        _ => Value::Null,
+                    },
+                })
+            }
+            Expr::Is { left, right, negated } => {
+                let collation = comparison_collation(left, right);
+                let equal = collation.compare(&left.evaluate(column)?, &right.evaluate(column)?) == Ordering::Equal;
+                Ok(bool_value(Some(equal != *negated)))
+            }
+            Expr::InList { operand: operand_expr, list, negated } => {
+                // Without a match, a NULL in the list makes the answer unknown rather than false.
+                let operand = operand_expr.evaluate(column)?;
+                let mut found = Some(false);
+                for item in list {
+                    let collation = comparison_collation(operand_expr, item);
+                    found = or(found, compare(CompareOp::Eq, &collation, &operand, &item.evaluate(column)?));
+                    if found == Some(true) {
+                        break;
+                    }
+                }
+                Ok(bool_value(negate_if(found, *negated)))
+            }
+            // A correlated subquery has no rows of its own; its value for the current row is
+            // looked up like a column, under the subquery's SQL.
+            Expr::Subquery(subquery) | Expr::Exists(subquery) | Expr::InSelect { subquery, .. } if subquery.rows.is_none() =>
+                column(&self.to_string()).ok_or_else(|| ExecuteNoSuchColumn(self.to_string())),
+            Expr::Subquery(subquery) => {
+                let rows = subquery.rows.as_ref().unwrap();
+                Ok(rows.first().map_or(Value::Null, |row| row[0].clone()))
+            }
+            Expr::Exists(subquery) => Ok(bool_value(Some(!subquery.rows.as_ref().unwrap().is_empty()))),
+            Expr::InSelect { operand: operand_expr, subquery, negated } => {
+                let operand = operand_expr.evaluate(column)?;
+                // A correlated subquery runs for one row at a time, too rarely to look its
+                // rows up rather than compare with each.
+                let found = match &subquery.values {
+                    Some(values) => values.contains(&operand),
+                    None => {
+                        let collation = operand_expr.collation(&|_| None);
+                        let mut found = Some(false);
+                        for row in subquery.rows.as_ref().unwrap().iter() {
+                            found = or(found, compare(CompareOp::Eq, &collation, &operand, &row[0]));
+                            if found == Some(true) {
+                                break;
+                            }
+                        }
+                        found
+                    }
+                };
+                Ok(bool_value(negate_if(found, *negated)))
+            }
+            Expr::Collate { operand, collation, .. } if collation.is_known() => operand.evaluate(column),
+            Expr::Collate { collation, .. } => Err(ExecuteNoSuchCollation(collation.to_string())),
+            // Only a subquery reads outer columns, and it runs with them bound.
+            Expr::OuterColumn { name, .. } => Err(ExecuteNoSuchColumn(name.clone())),
+            Expr::Like { operand, pattern, escape, glob, negated } => {
+                let operand = operand.evaluate(column)?;
+                let pattern = pattern.evaluate(column)?;
+                let escape = match escape {
+                    Some(escape) => match escape.evaluate(column)? {
+                        Value::Null => return Ok(Value::Null),
+                        escape => escape.to_string().chars().next(),
+                    },
+                    None => None,
+                };
+                if operand.is_null() || pattern.is_null() {
+                    return Ok(Value::Null);
+                }
+                let (pattern, text) = (pattern.to_string(), operand.to_string());
+                let matched = if *glob {
+                    pattern::glob(&pattern, &text)
+                } else {
+                    pattern::like(&pattern, &text, escape)
+                };
+                Ok(bool_value(Some(matched != *negated)))
+            }
+            Expr::Between { operand: operand_expr, low, high, negated } => {
+                let operand = operand_expr.evaluate(column)?;
+                let above = compare(CompareOp::Ge, &comparison_collation(operand_expr, low), &operand, &low.evaluate(column)?);
+                let below = compare(CompareOp::Le, &comparison_collation(operand_expr, high), &operand, &high.evaluate(column)?);
+                Ok(bool_value(negate_if(and(above, below), *negated)))
+            }
+            // Aggregates are computed per group before the expression is evaluated, so any
+            // left here were used where no group exists.
+            Expr::Function { name, registered, .. } if AggregateFunc::from_call(name, registered.as_ref()).is_some() =>
+                Err(ExecuteMisuseOfAggregate(name.clone())),
+            Expr::Function { name, .. } if WindowFunc::from_name(name).is_some() =>
+                Err(ExecuteMisuseOfWindowFunction(name.clone())),
+            Expr::Function { name, args, registered, .. } => {
+                let values = args.iter().map(|arg| arg.evaluate(column)).collect::<Result<Vec<Value>, ExecuteResult>>()?;
+                let json_args: Vec<bool> = args.iter().map(Expr::is_json).collect();
+                function::call_scalar(name, registered.as_ref(), &values, &json_args)
+            }
+            // Likewise window functions are computed over the partition beforehand.
+            Expr::Window { name, .. } => Err(ExecuteMisuseOfWindowFunction(name.clone())),
+        }
+    }
+
+    /// Evaluate an expression that may not refer to any column.
+    pub fn evaluate_constant(&self) -> Result<Value, ExecuteResult> {
+        self.evaluate(&|_| None)
+    }
+
+    /// Whether a row passes this expression as a WHERE clause: only true does, never NULL.
+    pub fn is_true(&self, column: &dyn Fn(&str) -> Option<Value>) -> Result<bool, ExecuteResult> {
+        Ok(self.evaluate(column)?.to_bool() == Some(true))
+    }
+
+    /// Whether the expression gives JSON, as the JSON functions and `->` do, which another JSON
+    /// function then takes for JSON rather than for text.
+    pub fn is_json(&self) -> bool {
+        match self {
+            Expr::Function { name, .. } => matches!(name.as_str(), "json" | "json_array" | "json_object" | "json_set"),
+            Expr::Binary { op, .. } => *op == BinaryOp::JsonExtract,
+            _ => false,
+        }
+    }
+
+    /// Every column the expression refers to, in the order written.
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.collect_columns(&mut columns);
+        columns
+    }
+
+    fn collect_columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        match self {
+            Expr::Column(name) => columns.push(name),
+            _ => self.children().into_iter().for_each(|child| child.collect_columns(columns)),
+        }
+    }
+
+    /// Every column of an enclosing query the expression refers to; columns read inside its
+    /// subqueries are not searched.
+    pub fn outer_columns(&self) -> Vec<&str> {
+        match self {
+            Expr::OuterColumn { name, .. } => vec![name],
+            _ => self.children().into_iter().flat_map(Expr::outer_columns).collect(),
+        }
+    }
+
+    /// How many levels out the furthest query the expression reads a column of is, counting
+    /// the columns its subqueries read from beyond it; 0 when it reads none.
+    pub fn outer_depth(&self) -> usize {
+        match self {
+            Expr::OuterColumn { depth, .. } => *depth,
+            Expr::Subquery(subquery) | Expr::Exists(subquery) => subquery.correlation_depth().saturating_sub(1),
+            Expr::InSelect { operand, subquery, .. } => operand.outer_depth().max(subquery.correlation_depth().saturating_sub(1)),
+            _ => self.children().into_iter().map(Expr::outer_depth).max().unwrap_or(0),
+        }
+    }
+
+    /// Every subquery in the expression, each after the subqueries of its operand; the
+    /// subqueries nested inside them are not searched.
+    pub fn subqueries(&self) -> Vec<&Expr> {
+        let mut subqueries: Vec<&Expr> = self.children().into_iter().flat_map(Expr::subqueries).collect();
+        if matches!(self, Expr::Subquery(_) | Expr::Exists(_) | Expr::InSelect { .. }) {
+            subqueries.push(self);
+        }
+        subqueries
+    }
+
+    /// The operands of the expression, in the order written. A subquery's SELECT is not an operand.
+    fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Literal(_) | Expr::Column(_) | Expr::OuterColumn { .. } | Expr::Subquery(_) | Expr::Exists(_) => vec![],
+            Expr::Negate(operand) | Expr::Not(operand) | Expr::InSelect { operand, .. } | Expr::Collate { operand, .. } => vec![operand],
+            Expr::Binary { left, right, .. } | Expr::Is { left, right, .. } => vec![left, right],
+            Expr::InList { operand, list, .. } => std::iter::once(&**operand).chain(list).collect(),
+            Expr::Like { operand, pattern, escape, .. } => {
+                let mut children = vec![&**operand, &**pattern];
+                children.extend(escape.as_deref());
+                children
+            }
+            Expr::Between { operand, low, high, .. } => vec![operand, low, high],
+            Expr::Function { args, .. } => args.iter().collect(),
+            Expr::Window { args, window, .. } => args.iter()
+                .chain(&window.partition_by)
+                .chain(window.order_by.iter().map(|term| &term.expr))
+                .collect(),
+        }
+    }
+
+    /// A copy of the expression with every subexpression `replace` gives a replacement for
+    /// swapped out; the replacement is not itself searched.
+    pub fn replace(&self, replace: &dyn Fn(&Expr) -> Option<Expr>) -> Expr {
+        self.try_replace(&mut |expr| Ok(replace(expr))).unwrap()
+    }
+
+    /// As `replace`, for a replacement that can fail or that keeps state between calls.
+    pub fn try_replace(&self, replace: &mut dyn FnMut(&Expr) -> Result<Option<Expr>, ExecuteResult>) -> Result<Expr, ExecuteResult> {
+        if let Some(replacement) = replace(self)? {
+            return Ok(replacement);
+        }
+        let mut operand = |operand: &Expr| -> Result<Box<Expr>, ExecuteResult> { Ok(Box::new(operand.try_replace(replace)?)) };
+        Ok(match self {
+            Expr::Literal(_) | Expr::Column(_) | Expr::OuterColumn { .. } | Expr::Subquery(_) | Expr::Exists(_) => self.clone(),
+            Expr::Negate(operand_expr) => Expr::Negate(operand(operand_expr)?),
+            Expr::Not(operand_expr) => Expr::Not(operand(operand_expr)?),
+            Expr::Binary { op, left, right } => Expr::Binary { op: *op, left: operand(left)?, right: operand(right)? },
+            Expr::Is { left, right, negated } =>
+                Expr::Is { left: operand(left)?, right: operand(right)?, negated: *negated },
+            Expr::InList { operand: operand_expr, list, negated } => Expr::InList {
+                operand: operand(operand_expr)?,
+                list: list.iter().map(|item| Ok(*operand(item)?)).collect::<Result<_, ExecuteResult>>()?,
+                negated: *negated,
+            },
+            Expr::Like { operand: operand_expr, pattern, escape, glob, negated } => Expr::Like {
+                operand: operand(operand_expr)?,
+                pattern: operand(pattern)?,
+                escape: escape.as_deref().map(&mut operand).transpose()?,
+                glob: *glob,
+                negated: *negated,
+            },
+            Expr::Between { operand: operand_expr, low, high, negated } => Expr::Between {
+                operand: operand(operand_expr)?,
+                low: operand(low)?,
+                high: operand(high)?,
+                negated: *negated,
+            },
+            Expr::Function { name, args, distinct, registered } => Expr::Function {
+                name: name.clone(),
+                args: args.iter().map(|arg| Ok(*operand(arg)?)).collect::<Result<_, ExecuteResult>>()?,
+                distinct: *distinct,
+                registered: registered.clone(),
+            },
+            Expr::InSelect { operand: operand_expr, subquery, negated } => Expr::InSelect {
+                operand: operand(operand_expr)?,
+                subquery: subquery.clone(),
+                negated: *negated,
+            },
+            Expr::Collate { operand: operand_expr, collation, declared } => Expr::Collate {
+                operand: operand(operand_expr)?,
+                collation: collation.clone(),
+                declared: *declared,
+            },
+            Expr::Window { name, args, window, registered } => Expr::Window {
+                name: name.clone(),
+                registered: registered.clone(),
+                args: args.iter().map(|arg| Ok(*operand(arg)?)).collect::<Result<_, ExecuteResult>>()?,
+                window: Box::new(Window {
+                    partition_by: window.partition_by.iter().map(|expr| Ok(*operand(expr)?)).collect::<Result<_, ExecuteResult>>()?,
+                    order_by: window.order_by.iter()
+                        .map(|term| Ok(OrderingTerm { expr: *operand(&term.expr)?, descending: term.descending }))
+                        .collect::<Result<_, ExecuteResult>>()?,
+                    frame: window.frame.clone(),
+                }),
+            },
+        })
+    }
+
+    /// A copy of the expression with each custom collation and each call to a registered
+    /// function bound to what the connection registered under its name, subqueries included.
+    /// A collation none is registered under fails the statement; a call to a function none is
+    /// registered under fails when it is evaluated.
+    pub fn bind(&self, collations: &Collations, functions: &Functions) -> Result<Expr, ExecuteResult> {
+        let bind_all = |exprs: &[Expr]| exprs.iter().map(|expr| expr.bind(collations, functions)).collect::<Result<Vec<Expr>, ExecuteResult>>();
+        self.try_replace(&mut |expr| Ok(match expr {
+            Expr::Collate { operand, collation, declared } => {
+                let mut collation = collation.clone();
+                collations.bind(&mut collation);
+                if !collation.is_known() {
+                    return Err(ExecuteNoSuchCollation(collation.to_string()));
+                }
+                Some(Expr::Collate { operand: Box::new(operand.bind(collations, functions)?), collation, declared: *declared })
+            }
+            Expr::Function { name, args, distinct, .. } => Some(Expr::Function {
+                name: name.clone(),
+                args: bind_all(args)?,
+                distinct: *distinct,
+                registered: functions.find(name),
+            }),
+            Expr::Window { name, args, window, .. } => Some(Expr::Window {
+                name: name.clone(),
+                args: bind_all(args)?,
+                window: Box::new(Window {
+                    partition_by: bind_all(&window.partition_by)?,
+                    order_by: window.order_by.iter()
+                        .map(|term| Ok(OrderingTerm { expr: term.expr.bind(collations, functions)?, descending: term.descending }))
+                        .collect::<Result<_, ExecuteResult>>()?,
+                    frame: window.frame.clone(),
+                }),
+                registered: functions.find(name),
+            }),
+            Expr::Subquery(subquery) => Some(Expr::Subquery(Box::new(subquery.bind(collations, functions)?))),
+            Expr::Exists(subquery) => Some(Expr::Exists(Box::new(subquery.bind(collations, functions)?))),
+            Expr::InSelect { operand, subquery, negated } => Some(Expr::InSelect {
+                operand: Box::new(operand.bind(collations, functions)?),
+                subquery: Box::new(subquery.bind(collations, functions)?),
+                negated: *negated,
+            }),
+            _ => None,
+        }))
+    }
+
+    /// A copy of the expression in which every literal compared with a column takes the
+    /// column's affinity, `affinity` giving it by name, the way SQLite converts the operand of
+    /// a comparison that has no affinity of its own.
+    pub fn with_comparison_affinity(&self, affinity: &dyn Fn(&str) -> Option<ColumnType>) -> Expr {
+        let convert = |column: &Expr, operand: &Expr| -> Box<Expr> {
+            Box::new(match (column, operand) {
+                (Expr::Column(name), Expr::Literal(value)) => match affinity(name) {
+                    Some(column_type) => Expr::Literal(column_type.apply_affinity(value.clone())),
+                    None => operand.clone(),
+                },
+                _ => operand.with_comparison_affinity(affinity),
+            })
+        };
+        self.replace(&|expr| match expr {
+            Expr::Binary { op: op @ BinaryOp::Compare(_), left, right } =>
+                Some(Expr::Binary { op: *op, left: convert(right, left), right: convert(left, right) }),
+            Expr::Is { left, right, negated } =>
+                Some(Expr::Is { left: convert(right, left), right: convert(left, right), negated: *negated }),
+            Expr::InList { operand, list, negated } => Some(Expr::InList {
+                operand: Box::new(operand.with_comparison_affinity(affinity)),
+                list: list.iter().map(|item| *convert(operand, item)).collect(),
+                negated: *negated,
+            }),
+            Expr::Between { operand, low, high, negated } => Some(Expr::Between {
+                operand: Box::new(operand.with_comparison_affinity(affinity)),
+                low: convert(operand, low),
+                high: convert(operand, high),
+                negated: *negated,
+            }),
+            _ => None,
+        })
+    }
+
+    /// A copy of the expression in which every column compared with something, of this query
+    /// or an outer one, is wrapped in the collation it declares, `collation` giving it for the
+    /// column, so that the comparison uses it. A declared BINARY counts as much as any other
+    /// against a collation the other operand declares.
+    pub fn with_declared_collations(&self, collation: &dyn Fn(&Expr) -> Option<Collation>) -> Expr {
+        let wrap = |operand: &Expr| -> Box<Expr> {
+            Box::new(match operand {
+                Expr::Column(_) | Expr::OuterColumn { .. } => match collation(operand) {
+                    Some(declared) => Expr::Collate { operand: Box::new(operand.clone()), collation: declared, declared: true },
+                    None => operand.clone(),
+                },
+                _ => operand.with_declared_collations(collation),
+            })
+        };
+        self.replace(&|expr| match expr {
+            Expr::Binary { op: op @ BinaryOp::Compare(_), left, right } =>
+                Some(Expr::Binary { op: *op, left: wrap(left), right: wrap(right) }),
+            Expr::Is { left, right, negated } => Some(Expr::Is { left: wrap(left), right: wrap(right), negated: *negated }),
+            Expr::InList { operand, list, negated } => Some(Expr::InList {
+                operand: wrap(operand),
+                list: list.iter().map(|item| *wrap(item)).collect(),
+                negated: *negated,
+            }),
+            Expr::Between { operand, low, high, negated } =>
+                Some(Expr::Between { operand: wrap(operand), low: wrap(low), high: wrap(high), negated: *negated }),
+            Expr::InSelect { operand, subquery, negated } =>
+                Some(Expr::InSelect { operand: wrap(operand), subquery: subquery.clone(), negated: *negated }),
+            _ => None,
+        })
+    }
+
+    /// The expression under any collation the resolver wrapped it in.
+    pub fn without_declared_collation(&self) -> &Expr {
+        match self {
+            Expr::Collate { operand, declared: true, .. } => operand,
+            _ => self,
+        }
+    }
+
+    /// The expression under a declared BINARY collation, which compares as a bare value does.
+    pub fn without_declared_binary(&self) -> &Expr {
+        match self {
+            Expr::Collate { operand, collation: Collation::Binary, declared: true } => operand,
+            _ => self,
+        }
+    }
+
+    /// The collation the expression sorts by: an explicit COLLATE, else that of the column it
+    /// names, `collation` giving it by name, else BINARY.
+    pub fn collation(&self, collation: &dyn Fn(&str) -> Option<Collation>) -> Collation {
+        match self {
+            Expr::Collate { collation, .. } => collation.clone(),
+            Expr::Column(name) => collation(name).unwrap_or_default(),
+            _ => Collation::Binary,
+        }
+    }
+
+    /// Every aggregate call in the expression, outermost first; their arguments are not searched.
+    pub fn aggregates(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Function { name, registered, .. } if AggregateFunc::from_call(name, registered.as_ref()).is_some() => vec![self],
+            _ => self.children().into_iter().flat_map(Expr::aggregates).collect(),
+        }
+    }
+
+    pub fn contains_aggregate(&self) -> bool {
+        !self.aggregates().is_empty()
+    }
+
+    /// Every window call in the expression, but those nested in another.
+    pub fn windows(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Window { .. } => vec![self],
+            _ => self.children().into_iter().flat_map(Expr::windows).collect(),
+        }
+    }
+
+    /// Every MATCH in the expression, outermost first.
+    pub fn matches(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Binary { op: BinaryOp::Match, .. } => vec![self],
+            _ => self.children().into_iter().flat_map(Expr::matches).collect(),
+        }
+    }
+
+    /// The terms of the top-level AND, or the expression itself when it is not an AND.
+    pub fn conjuncts(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Binary { op: BinaryOp::And, left, right } => {
+                let mut conjuncts = left.conjuncts();
+                conjuncts.extend(right.conjuncts());
+                conjuncts
+            }
+            _ => vec![self],
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary { op, .. } => op.precedence(),
+            Expr::Not(_) => 3,
+            Expr::Is { .. } | Expr::InList { .. } | Expr::Like { .. } | Expr::Between { .. } | Expr::InSelect { .. } => 4,
+            _ => u8::MAX,
+        }
+    }
+}
+
+fn not_keyword(negated: bool) -> &'static str {
+    if negated { "NOT " } else { "" }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(Value::Null) => write!(f, "NULL"),
+            Expr::Literal(Value::Text(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expr::Literal(Value::Blob(b)) => {
+                let hex: String = b.iter().map(|byte| format!("{:02X}", byte)).collect();
+                write!(f, "X'{}'", hex)
+            }
+            Expr::Literal(value) => write!(f, "{}", value),
+            Expr::Column(name) | Expr::OuterColumn { name, .. } => write!(f, "{}", name),
+            Expr::Collate { operand, declared: true, .. } => write!(f, "{}", operand),
+            Expr::Collate { operand, collation, .. } => write!(f, "{} COLLATE {}", Parenthesized(operand, u8::MAX), collation),
+            Expr::Negate(operand) => write!(f, "-{}", Parenthesized(operand, u8::MAX)),
+            Expr::Not(operand) => write!(f, "NOT {}", Parenthesized(operand, self.precedence())),
+            Expr::Binary { op, left, right } => {
+                // The right operand binds one level tighter, since operators associate left.
+                write!(f, "{} {} {}", Parenthesized(left, op.precedence()), op,
+                       Parenthesized(right, op.precedence() + 1))
+            }
+            Expr::Is { left, right, negated } => {
+                write!(f, "{} IS {}{}", Parenthesized(left, 4), not_keyword(*negated), Parenthesized(right, 5))
+            }
+            Expr::InList { operand, list, negated } => {
+                let list: Vec<String> = list.iter().map(|item| item.to_string()).collect();
+                write!(f, "{} {}IN ({})", Parenthesized(operand, 4), not_keyword(*negated), list.join(", "))
+            }
+            Expr::Like { operand, pattern, escape, glob, negated } => {
+                let keyword = if *glob { "GLOB" } else { "LIKE" };
+                write!(f, "{} {}{} {}", Parenthesized(operand, 4), not_keyword(*negated), keyword,
+                       Parenthesized(pattern, 5))?;
+                if let Some(escape) = escape {
+                    write!(f, " ESCAPE {}", Parenthesized(escape, 5))?;
+                }
+                Ok(())
+            }
+            Expr::Between { operand, low, high, negated } => {
+                write!(f, "{} {}BETWEEN {} AND {}", Parenthesized(operand, 4), not_keyword(*negated),
+                       Parenthesized(low, 5), Parenthesized(high, 5))
+            }
+            Expr::Function { name, args, registered, .. } if args.is_empty() && AggregateFunc::from_call(name, registered.as_ref()).is_some() => {
+                write!(f, "{}(*)", name)
+            }
+            Expr::Function { name, args, distinct, .. } => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({}{})", name, if *distinct { "DISTINCT " } else { "" }, args.join(", "))
+            }
+            Expr::Subquery(subquery) => write!(f, "({})", subquery.sql),
+            Expr::Exists(subquery) => write!(f, "EXISTS ({})", subquery.sql),
+            Expr::InSelect { operand, subquery, negated } => {
+                write!(f, "{} {}IN ({})", Parenthesized(operand, 4), not_keyword(*negated), subquery.sql)
+            }
+            Expr::Window { name, args, window, registered } if args.is_empty() => {
+                let star = if AggregateFunc::from_call(name, registered.as_ref()).is_some() { "*" } else { "" };
+                write!(f, "{}({}) OVER ({})", name, star, window)
+            }
+            Expr::Window { name, args, window, .. } => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({}) OVER ({})", name, args.join(", "), window)
+            }
+        }
+    }
+}
+
+/// Writes an operand, wrapped in parentheses when it binds looser than its context requires.
+struct Parenthesized<'a>(&'a Expr, u8);
+
+impl fmt::Display for Parenthesized<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.precedence() < self.1 {
+            write!(f, "({})", self.0)
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
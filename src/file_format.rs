@@ -0,0 +1,1171 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Bound;
+use std::rc::Rc;
+use crate::collation::{Collation, KeyOrder};
+use crate::index::IndexTree;
+use crate::pager::Pager;
+use crate::plan::QueryPlan;
+use crate::record::{encode_record, put_varint, read_record, read_varint};
+use crate::result::ExecuteResult::ExecuteSuccess;
+use crate::schema::{ColumnDef, ColumnType, Index, IndexDef, KeyColumn, TableDef};
+use crate::statement::for_each_row;
+use crate::table::Table;
+use crate::value::Value;
+
+/// The first 16 bytes of every SQLite 3 database file.
+pub const SQLITE_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+pub const HEADER_SIZE: usize = 100;
+/// The page size `.save` writes.
+const SAVE_PAGE_SIZE: usize = 4096;
+/// The library version `.save` records as the last to write the file.
+const SQLITE_VERSION_NUMBER: u32 = 3045000;
+/// B-trees deeper than this are taken to loop back on themselves.
+const MAX_BTREE_DEPTH: usize = 64;
+
+const INTERIOR_INDEX_PAGE: u8 = 0x02;
+const INTERIOR_TABLE_PAGE: u8 = 0x05;
+const LEAF_INDEX_PAGE: u8 = 0x0a;
+const LEAF_TABLE_PAGE: u8 = 0x0d;
+
+/// The 100-byte header at the start of a SQLite database file. Every field is big-endian.
+#[derive(Clone, Debug)]
+pub struct FileHeader {
+    pub page_size: usize,
+    pub write_version: u8,
+    pub read_version: u8,
+    /// Bytes at the end of every page that the B-trees leave unused.
+    pub reserved: usize,
+    pub change_counter: u32,
+    pub page_count: u32,
+    pub freelist_trunk: u32,
+    pub freelist_count: u32,
+    pub schema_cookie: u32,
+    pub schema_format: u32,
+    pub default_cache_size: u32,
+    pub autovacuum_top_root: u32,
+    /// 1 for UTF-8, 2 for UTF-16le, 3 for UTF-16be.
+    pub text_encoding: u32,
+    pub user_version: u32,
+    pub incremental_vacuum: u32,
+    pub application_id: u32,
+    pub version_valid_for: u32,
+    pub sqlite_version: u32,
+}
+
+fn malformed(what: impl fmt::Display) -> String {
+    format!("database disk image is malformed: {}", what)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, String> {
+    match bytes.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes(bytes.try_into().unwrap())),
+        None => Err(malformed(format!("read past the end of a page at {}", offset))),
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    match bytes.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_be_bytes(bytes.try_into().unwrap())),
+        None => Err(malformed(format!("read past the end of a page at {}", offset))),
+    }
+}
+
+impl FileHeader {
+    /// Parse the header at the start of a file of `file_len` bytes. The page count is only
+    /// trusted if the file was last written by a version that keeps it, else it follows from
+    /// the file's length.
+    pub fn parse(bytes: &[u8], file_len: u64) -> Result<FileHeader, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[..16] != SQLITE_MAGIC {
+            return Err(String::from("file is not a database"));
+        }
+        let page_size = match read_u16(bytes, 16)? {
+            1 => 65536,
+            size if size >= 512 && size.is_power_of_two() => size as usize,
+            _ => return Err(String::from("file is not a database")),
+        };
+        let mut header = FileHeader {
+            page_size,
+            write_version: bytes[18],
+            read_version: bytes[19],
+            reserved: bytes[20] as usize,
+            change_counter: read_u32(bytes, 24)?,
+            page_count: read_u32(bytes, 28)?,
+            freelist_trunk: read_u32(bytes, 32)?,
+            freelist_count: read_u32(bytes, 36)?,
+            schema_cookie: read_u32(bytes, 40)?,
+            schema_format: read_u32(bytes, 44)?,
+            default_cache_size: read_u32(bytes, 48)?,
+            autovacuum_top_root: read_u32(bytes, 52)?,
+            text_encoding: read_u32(bytes, 56)?,
+            user_version: read_u32(bytes, 60)?,
+            incremental_vacuum: read_u32(bytes, 64)?,
+            application_id: read_u32(bytes, 68)?,
+            version_valid_for: read_u32(bytes, 92)?,
+            sqlite_version: read_u32(bytes, 96)?,
+        };
+        if header.page_count == 0 || header.change_counter != header.version_valid_for {
+            header.page_count = (file_len / page_size as u64).min(u32::MAX as u64) as u32;
+        }
+        // SQLite needs 480 usable bytes a page to fit four cells.
+        if bytes[21] != 64 || bytes[22] != 32 || bytes[23] != 32 || header.usable_size() < 480 {
+            return Err(String::from("file is not a database"));
+        }
+        if header.text_encoding > 1 {
+            return Err(String::from("unsupported text encoding: only UTF-8 databases can be read"));
+        }
+        Ok(header)
+    }
+
+    fn write(&self, bytes: &mut [u8]) {
+        bytes[..16].copy_from_slice(SQLITE_MAGIC);
+        let page_size = if self.page_size == 65536 { 1 } else { self.page_size as u16 };
+        bytes[16..18].copy_from_slice(&page_size.to_be_bytes());
+        bytes[18] = self.write_version;
+        bytes[19] = self.read_version;
+        bytes[20] = self.reserved as u8;
+        // The payload fractions, which SQLite fixes at these values.
+        bytes[21..24].copy_from_slice(&[64, 32, 32]);
+        let fields = [
+            (24, self.change_counter), (28, self.page_count), (32, self.freelist_trunk),
+            (36, self.freelist_count), (40, self.schema_cookie), (44, self.schema_format),
+            (48, self.default_cache_size), (52, self.autovacuum_top_root), (56, self.text_encoding),
+            (60, self.user_version), (64, self.incremental_vacuum), (68, self.application_id),
+            (92, self.version_valid_for), (96, self.sqlite_version),
+        ];
+        for (offset, value) in fields {
+            bytes[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    fn usable_size(&self) -> usize {
+        self.page_size - self.reserved
+    }
+}
+
+/// How much of a cell's payload of `payload_len` bytes stays on its B-tree page, the rest
+/// going to a chain of overflow pages. Table leaves keep more of it than index pages.
+fn local_payload_len(usable_size: usize, payload_len: usize, table_leaf: bool) -> usize {
+    let max_local = if table_leaf { usable_size - 35 } else { (usable_size - 12) * 64 / 255 - 23 };
+    if payload_len <= max_local {
+        return payload_len;
+    }
+    let min_local = (usable_size - 12) * 32 / 255 - 23;
+    let local = min_local + (payload_len - min_local) % (usable_size - 4);
+    if local <= max_local { local } else { min_local }
+}
+
+/// The whole payload of `payload_len` bytes of a cell whose payload starts at `offset` on
+/// `page`, read on through its overflow chain if it has one.
+fn read_payload(pager: &Pager, page: &[u8], offset: usize, payload_len: usize, table_leaf: bool) -> Result<Vec<u8>, String> {
+    let header = &pager.sqlite.as_ref().unwrap().header;
+    let usable_size = header.usable_size();
+    let local_len = local_payload_len(usable_size, payload_len, table_leaf);
+    let spills = local_len < payload_len;
+    let end = offset + local_len + if spills { 4 } else { 0 };
+    let local = page.get(offset..end).ok_or_else(|| malformed("cell runs off its page"))?;
+    let mut payload = local[..local_len].to_vec();
+    if spills {
+        let mut overflow = read_u32(local, local_len)?;
+        let mut pages = 0;
+        while payload.len() < payload_len {
+            pages += 1;
+            if pages > header.page_count {
+                return Err(malformed("overflow chain loops"));
+            }
+            let page = pager.sqlite_page(overflow)?;
+            let take = (payload_len - payload.len()).min(usable_size - 4);
+            payload.extend_from_slice(&page[4..4 + take]);
+            overflow = read_u32(&page, 0)?;
+        }
+    }
+    Ok(payload)
+}
+
+/// A page of a B-tree of the file, with the offsets of its cells from its cell pointer array.
+struct BtreePage {
+    page_num: u32,
+    data: Rc<[u8]>,
+    flag: u8,
+    cells: Vec<usize>,
+    right_child: u32,
+}
+
+impl BtreePage {
+    fn read(pager: &Pager, page_num: u32) -> Result<BtreePage, String> {
+        let data = pager.sqlite_page(page_num)?;
+        // The B-tree page header starts after the file header on page 1.
+        let header = if page_num == 1 { HEADER_SIZE } else { 0 };
+        let flag = data[header];
+        let interior = match flag {
+            INTERIOR_INDEX_PAGE | INTERIOR_TABLE_PAGE => true,
+            LEAF_INDEX_PAGE | LEAF_TABLE_PAGE => false,
+            _ => return Err(malformed(format!("page {} is no B-tree page ({:#04x})", page_num, flag))),
+        };
+        let num_cells = read_u16(&data, header + 3)? as usize;
+        let pointers = header + if interior { 12 } else { 8 };
+        let right_child = if interior { read_u32(&data, header + 8)? } else { 0 };
+        let cells = (0..num_cells)
+            .map(|i| read_u16(&data, pointers + 2 * i).map(usize::from))
+            .collect::<Result<Vec<usize>, String>>()?;
+        Ok(BtreePage { page_num, data, flag, cells, right_child })
+    }
+
+    fn is_leaf(&self) -> bool {
+        matches!(self.flag, LEAF_INDEX_PAGE | LEAF_TABLE_PAGE)
+    }
+
+    fn is_index(&self) -> bool {
+        matches!(self.flag, INTERIOR_INDEX_PAGE | LEAF_INDEX_PAGE)
+    }
+
+    /// The child left of cell `i` of an interior page, or its right child past the last cell.
+    fn child(&self, i: usize) -> Result<u32, String> {
+        match self.cells.get(i) {
+            Some(offset) => read_u32(&self.data, *offset),
+            None => Ok(self.right_child),
+        }
+    }
+
+    fn varint(&self, offset: usize) -> Result<(u64, usize), String> {
+        self.data.get(offset..).and_then(read_varint)
+            .ok_or_else(|| malformed(format!("cell runs off page {}", self.page_num)))
+    }
+
+    /// The rowid of cell `i` of a table page: after the payload's size on a leaf, after the
+    /// child on an interior page.
+    fn rowid(&self, i: usize) -> Result<i64, String> {
+        let offset = self.cells[i];
+        let offset = if self.is_leaf() { offset + self.varint(offset)?.1 } else { offset + 4 };
+        Ok(self.varint(offset)?.0 as i64)
+    }
+
+    /// The record of cell `i` of a table leaf or an index page.
+    fn record(&self, pager: &Pager, i: usize) -> Result<Vec<Value>, String> {
+        let mut offset = self.cells[i];
+        if self.flag == INTERIOR_INDEX_PAGE {
+            offset += 4;
+        }
+        let (payload_len, len) = self.varint(offset)?;
+        offset += len;
+        let table_leaf = self.flag == LEAF_TABLE_PAGE;
+        if table_leaf {
+            offset += self.varint(offset)?.1;
+        }
+        let payload_len = usize::try_from(payload_len).map_err(|_| malformed("payload too large"))?;
+        let payload = read_payload(pager, &self.data, offset, payload_len, table_leaf)?;
+        read_record(&payload).ok_or_else(|| malformed(format!("bad record on page {}", self.page_num)))
+    }
+}
+
+/// A walk over the entries of a B-tree of the file in key order: the rows of a table by
+/// rowid, or the records of an index. It holds the pages from the root down to the entry it
+/// is at, each with the step it takes next, and reads a page only once the walk reaches it.
+/// On a leaf a step is a cell; on an interior table page it is a child; on an interior index
+/// page every child is followed by the entry of the cell it hangs from.
+pub struct BtreeCursor {
+    index: bool,
+    path: Vec<(BtreePage, usize)>,
+}
+
+impl BtreeCursor {
+    /// A cursor before the first row of the table rooted at `root`.
+    pub fn table(pager: &Pager, root: u32) -> Result<BtreeCursor, String> {
+        let mut cursor = BtreeCursor { index: false, path: Vec::new() };
+        cursor.descend(pager, root)?;
+        Ok(cursor)
+    }
+
+    /// A cursor before the first record of the index rooted at `root`.
+    pub fn index(pager: &Pager, root: u32) -> Result<BtreeCursor, String> {
+        let mut cursor = BtreeCursor { index: true, path: Vec::new() };
+        cursor.descend(pager, root)?;
+        Ok(cursor)
+    }
+
+    /// A cursor before the first row of the table whose rowid is at least `rowid`.
+    pub fn seek_rowid(pager: &Pager, root: u32, rowid: i64) -> Result<BtreeCursor, String> {
+        let mut cursor = BtreeCursor { index: false, path: Vec::new() };
+        cursor.seek(pager, root, |page, cell| Ok(page.rowid(cell)? < rowid))?;
+        Ok(cursor)
+    }
+
+    /// A cursor before the first record of the index that `order` does not put before `key`.
+    pub fn seek_index(pager: &Pager, root: u32, order: &KeyOrder, key: &[Value]) -> Result<BtreeCursor, String> {
+        let mut cursor = BtreeCursor { index: true, path: Vec::new() };
+        cursor.seek(pager, root, |page, cell| Ok(order.compare(&page.record(pager, cell)?, key) == Ordering::Less))?;
+        Ok(cursor)
+    }
+
+    fn descend(&mut self, pager: &Pager, page_num: u32) -> Result<(), String> {
+        if self.path.len() >= MAX_BTREE_DEPTH {
+            return Err(malformed("B-tree loops"));
+        }
+        let page = BtreePage::read(pager, page_num)?;
+        if page.is_index() != self.index {
+            return Err(malformed(format!("page {} is no {} page", page_num, if self.index { "index" } else { "table" })));
+        }
+        self.path.push((page, 0));
+        Ok(())
+    }
+
+    /// Descend from `root` to the first entry `before` does not hold for, leaving every page
+    /// on the way at the step that comes back to it. The cells of a page are in order, so
+    /// those before the key come first.
+    fn seek<F: Fn(&BtreePage, usize) -> Result<bool, String>>(&mut self, pager: &Pager, root: u32, before: F) -> Result<(), String> {
+        let mut page_num = root;
+        loop {
+            self.descend(pager, page_num)?;
+            let index = self.index;
+            let (page, step) = self.path.last_mut().unwrap();
+            let (mut low, mut high) = (0, page.cells.len());
+            while low < high {
+                let mid = (low + high) / 2;
+                if before(page, mid)? {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            if page.is_leaf() {
+                *step = low;
+                return Ok(());
+            }
+            *step = if index { 2 * low + 1 } else { low + 1 };
+            page_num = page.child(low)?;
+        }
+    }
+
+    /// Move to the next entry, returning the depth of its page and its cell, or None past
+    /// the last.
+    fn advance(&mut self, pager: &Pager) -> Result<Option<(usize, usize)>, String> {
+        loop {
+            let depth = match self.path.len() {
+                0 => return Ok(None),
+                len => len - 1,
+            };
+            let index = self.index;
+            let (page, step) = &mut self.path[depth];
+            let num_cells = page.cells.len();
+            let num_steps = match (page.is_leaf(), index) {
+                (true, _) => num_cells,
+                (false, true) => 2 * num_cells + 1,
+                (false, false) => num_cells + 1,
+            };
+            if *step == num_steps {
+                self.path.pop();
+                continue;
+            }
+            let this = *step;
+            *step += 1;
+            if page.is_leaf() {
+                return Ok(Some((depth, this)));
+            }
+            if index && this % 2 == 1 {
+                return Ok(Some((depth, this / 2)));
+            }
+            let child = page.child(if index { this / 2 } else { this })?;
+            self.descend(pager, child)?;
+        }
+    }
+
+    /// The rowid and record of the next row of a table.
+    pub fn next_row(&mut self, pager: &Pager) -> Result<Option<(i64, Vec<Value>)>, String> {
+        match self.advance(pager)? {
+            Some((depth, cell)) => {
+                let page = &self.path[depth].0;
+                Ok(Some((page.rowid(cell)?, page.record(pager, cell)?)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The next record of an index.
+    pub fn next_record(&mut self, pager: &Pager) -> Result<Option<Vec<Value>>, String> {
+        match self.advance(pager)? {
+            Some((depth, cell)) => Ok(Some(self.path[depth].0.record(pager, cell)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A row of a table as the engine sees it, from the rowid and record the file holds. A
+/// column declared INTEGER PRIMARY KEY is the rowid, and its record holds NULL; a table
+/// without one has the rowid as a hidden column.
+pub fn table_row(table_def: &TableDef, rowid: i64, mut row: Vec<Value>) -> Vec<Value> {
+    row.resize(table_def.columns.len(), Value::Null);
+    real_values(table_def, &mut row);
+    if let Some(column) = table_def.primary_key() {
+        row[column] = Value::Integer(rowid);
+    }
+    row
+}
+
+/// A row of a WITHOUT ROWID table as the engine sees it, from the record the file holds,
+/// which starts with the key.
+pub fn keyed_row(table_def: &TableDef, record: Vec<Value>) -> Vec<Value> {
+    let mut row = table_def.record_row(record);
+    real_values(table_def, &mut row);
+    row
+}
+
+/// SQLite stores a real with an integer value in a REAL column as an integer.
+fn real_values(table_def: &TableDef, row: &mut [Value]) {
+    for (value, column) in row.iter_mut().zip(&table_def.columns) {
+        if column.column_type == ColumnType::Real && matches!(value, Value::Integer(_)) {
+            *value = column.column_type.apply_affinity(value.clone());
+        }
+    }
+}
+
+/// The row of the table with the rowid, if it has one.
+pub fn find_row(pager: &Pager, table_def: &TableDef, rowid: i64) -> Result<Option<Vec<Value>>, String> {
+    let mut cursor = BtreeCursor::seek_rowid(pager, table_def.root_page_num as u32, rowid)?;
+    Ok(cursor.next_row(pager)?
+        .filter(|(found, _)| *found == rowid)
+        .map(|(_, record)| table_row(table_def, rowid, record)))
+}
+
+/// Every record of the index between the bounds, in index order, as `IndexTree::records`
+/// gives them for the engine's own indexes. A record ends with the rowid of its row.
+pub fn index_records(pager: &Pager, tree: &IndexTree, lower: Bound<&[Value]>, upper: Bound<&[Value]>) -> Result<Vec<Vec<Value>>, String> {
+    let root = tree.root_page_num as u32;
+    let mut cursor = match lower {
+        Bound::Included(key) | Bound::Excluded(key) => BtreeCursor::seek_index(pager, root, &tree.order, key)?,
+        Bound::Unbounded => BtreeCursor::index(pager, root)?,
+    };
+    let mut records = Vec::new();
+    while let Some(record) = cursor.next_record(pager)? {
+        if let Bound::Excluded(key) = lower {
+            if tree.compare(&record, key) == Ordering::Equal {
+                continue;
+            }
+        }
+        let past_upper = match upper {
+            Bound::Included(key) => tree.compare(&record, key) == Ordering::Greater,
+            Bound::Excluded(key) => tree.compare(&record, key) != Ordering::Less,
+            Bound::Unbounded => false,
+        };
+        if past_upper {
+            break;
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// The number of rows of the table rooted at `root`, from the cell counts of its leaves
+/// alone, or of the WITHOUT ROWID table, whose interior pages hold rows too.
+pub fn count_rows(pager: &Pager, root: u32) -> Result<usize, String> {
+    let page_count = pager.sqlite.as_ref().unwrap().header.page_count;
+    let mut count = 0;
+    let mut pages = vec![root];
+    let mut visited = 0;
+    while let Some(page_num) = pages.pop() {
+        visited += 1;
+        if visited > page_count {
+            return Err(malformed("B-tree loops"));
+        }
+        let page = BtreePage::read(pager, page_num)?;
+        match page.flag {
+            LEAF_TABLE_PAGE | LEAF_INDEX_PAGE => count += page.cells.len(),
+            INTERIOR_TABLE_PAGE | INTERIOR_INDEX_PAGE => {
+                if page.flag == INTERIOR_INDEX_PAGE {
+                    count += page.cells.len();
+                }
+                for i in 0..=page.cells.len() {
+                    pages.push(page.child(i)?);
+                }
+            }
+            _ => return Err(malformed(format!("page {} is no B-tree page", page_num))),
+        }
+    }
+    Ok(count)
+}
+
+/// A row of `sqlite_schema`, the table on page 1 that lists every other table and index.
+#[derive(Clone, Debug)]
+pub struct SchemaEntry {
+    pub entry_type: String,
+    pub name: String,
+    pub table_name: String,
+    pub root_page: u32,
+    pub sql: Option<String>,
+}
+
+const SCHEMA_COLUMNS: [&str; 5] = ["type", "name", "tbl_name", "rootpage", "sql"];
+
+/// A genuine SQLite 3 database file. The engine reads its pages through the pager as it
+/// needs them, but never writes to it.
+pub struct SqliteFile {
+    pub header: FileHeader,
+}
+
+impl SqliteFile {
+    pub fn open(header: &[u8], file_len: u64) -> Result<SqliteFile, String> {
+        Ok(SqliteFile { header: FileHeader::parse(header, file_len)? })
+    }
+
+    /// Every page on the freelist: each trunk page, then the leaf pages it lists.
+    pub fn freelist_pages(&self, pager: &Pager) -> Result<Vec<u32>, String> {
+        let mut pages = Vec::new();
+        let mut trunk = self.header.freelist_trunk;
+        while trunk != 0 {
+            if pages.len() > self.header.page_count as usize {
+                return Err(malformed("freelist loops"));
+            }
+            let page = pager.sqlite_page(trunk)?;
+            pages.push(trunk);
+            let num_leaves = read_u32(&page, 4)? as usize;
+            for i in 0..num_leaves {
+                pages.push(read_u32(&page, 8 + 4 * i)?);
+            }
+            trunk = read_u32(&page, 0)?;
+        }
+        Ok(pages)
+    }
+
+    /// The rows of `sqlite_schema`, which is the table rooted at page 1.
+    pub fn schema(&self, pager: &Pager) -> Result<Vec<SchemaEntry>, String> {
+        let text = |value: &Value| match value {
+            Value::Null => String::new(),
+            value => value.to_string(),
+        };
+        let mut entries = Vec::new();
+        let mut cursor = BtreeCursor::table(pager, 1)?;
+        while let Some((_, row)) = cursor.next_row(pager)? {
+            match row.as_slice() {
+                [entry_type, name, table_name, root_page, sql] => entries.push(SchemaEntry {
+                    entry_type: text(entry_type),
+                    name: text(name),
+                    table_name: text(table_name),
+                    root_page: root_page.to_integer().unwrap_or(0) as u32,
+                    sql: if sql.is_null() { None } else { Some(text(sql)) },
+                }),
+                _ => return Err(String::from("malformed database schema")),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The tables and indexes of the file. The tables are `sqlite_schema` itself, also known
+    /// as `sqlite_master`, held in memory, then every ordinary and WITHOUT ROWID table, whose
+    /// rows stay in the file; virtual tables are left out. An index is kept if it can serve the
+    /// engine as an index on its first column. The engine has no views, so a file with one is
+    /// refused.
+    pub fn schema_objects(&self, pager: &Pager) -> Result<(Vec<TableDef>, Vec<Index>), String> {
+        let entries = self.schema(pager)?;
+        if let Some(view) = entries.iter().find(|entry| entry.entry_type == "view") {
+            return Err(format!("views are not supported: {}", view.name));
+        }
+        let schema_rows: Vec<Vec<Value>> = entries.iter()
+            .map(|entry| vec![
+                Value::Text(entry.entry_type.clone()),
+                Value::Text(entry.name.clone()),
+                Value::Text(entry.table_name.clone()),
+                Value::Integer(entry.root_page as i64),
+                entry.sql.clone().map_or(Value::Null, Value::Text),
+            ])
+            .collect();
+        let columns: Vec<String> = SCHEMA_COLUMNS.iter().map(|name| String::from(*name)).collect();
+        let mut table_defs = vec![
+            TableDef::in_memory("sqlite_schema", columns.clone(), schema_rows.clone()),
+            TableDef::in_memory("sqlite_master", columns, schema_rows),
+        ];
+        let mut table_keys = Vec::new();
+        for entry in &entries {
+            let sql = match &entry.sql {
+                Some(sql) if entry.entry_type == "table" && entry.root_page != 0 => sql,
+                _ => continue,
+            };
+            let (columns, keys, primary_key) = parse_table_columns(sql);
+            if columns.is_empty() {
+                continue;
+            }
+            let without_rowid = match primary_key {
+                Some(primary_key) => {
+                    let key = primary_key.iter()
+                        .map(|(name, descending)| {
+                            let column = columns.iter().position(|column| column.name.eq_ignore_ascii_case(name));
+                            column.map(|column| KeyColumn { column, descending: *descending })
+                        })
+                        .collect::<Option<Vec<KeyColumn>>>();
+                    match key {
+                        Some(key) if !key.is_empty() => Some(key),
+                        _ => return Err(format!("malformed database schema ({}): no PRIMARY KEY", entry.name)),
+                    }
+                }
+                None => None,
+            };
+            table_defs.push(TableDef {
+                name: entry.name.clone(),
+                columns,
+                checks: Vec::new(),
+                foreign_keys: Vec::new(),
+                root_page_num: entry.root_page as usize,
+                sql: sql.clone(),
+                autoincrement: false,
+                without_rowid,
+                rows: None,
+                recursive: None,
+                fts: None,
+            });
+            table_keys.push((entry.name.clone(), keys));
+        }
+        let mut indexes = Vec::new();
+        for entry in entries.iter().filter(|entry| entry.entry_type == "index" && entry.root_page != 0) {
+            let table_def = match table_defs.iter().find(|table_def| table_def.name.eq_ignore_ascii_case(&entry.table_name)) {
+                // The entries of an index on a WITHOUT ROWID table end with its key, not a rowid.
+                Some(table_def) if table_def.rows.is_none() && table_def.without_rowid.is_none() => table_def,
+                _ => continue,
+            };
+            let (column, unique) = match &entry.sql {
+                Some(sql) => match parse_index_column(sql) {
+                    Some(found) => found,
+                    None => continue,
+                },
+                // SQLite makes the indexes of PRIMARY KEY and UNIQUE constraints itself, numbered
+                // in the order the table declares them, and lists them without SQL.
+                None => {
+                    let keys = &table_keys.iter().find(|(name, _)| name == &table_def.name).unwrap().1;
+                    let key = entry.name.rsplit('_').next()
+                        .and_then(|n| n.parse::<usize>().ok())
+                        .and_then(|n| keys.get(n.wrapping_sub(1)));
+                    match key {
+                        Some(key) => (key[0].clone(), key.len() == 1),
+                        None => continue,
+                    }
+                }
+            };
+            let column = match table_def.column_index(&column) {
+                Some(column) if table_def.primary_key() != Some(column) => column,
+                _ => continue,
+            };
+            indexes.push(Index {
+                def: IndexDef {
+                    name: entry.name.clone(),
+                    table_name: table_def.name.clone(),
+                    column: table_def.columns[column].name.clone(),
+                    expr: None,
+                    unique,
+                },
+                tree: IndexTree { root_page_num: entry.root_page as usize, order: table_def.index_order(column), key_len: None },
+            });
+        }
+        Ok((table_defs, indexes))
+    }
+
+    /// Print the header and a count of the schema's objects, as the sqlite3 shell's `.dbinfo` does.
+    pub fn print_info(&self, pager: &Pager) -> Result<(), String> {
+        let header = &self.header;
+        let entries = self.schema(pager)?;
+        let count = |entry_type: &str| entries.iter().filter(|entry| entry.entry_type == entry_type).count();
+        let encoding = match header.text_encoding {
+            1 => "1 (utf8)",
+            _ => "0",
+        };
+        let lines = [
+            ("database page size", header.page_size.to_string()),
+            ("write format", header.write_version.to_string()),
+            ("read format", header.read_version.to_string()),
+            ("reserved bytes", header.reserved.to_string()),
+            ("file change counter", header.change_counter.to_string()),
+            ("database page count", header.page_count.to_string()),
+            ("freelist page count", self.freelist_pages(pager)?.len().to_string()),
+            ("schema cookie", header.schema_cookie.to_string()),
+            ("schema format", header.schema_format.to_string()),
+            ("default cache size", header.default_cache_size.to_string()),
+            ("autovacuum top root", header.autovacuum_top_root.to_string()),
+            ("incremental vacuum", header.incremental_vacuum.to_string()),
+            ("text encoding", String::from(encoding)),
+            ("user version", header.user_version.to_string()),
+            ("application id", header.application_id.to_string()),
+            ("software version", header.sqlite_version.to_string()),
+            ("number of tables", count("table").to_string()),
+            ("number of indexes", count("index").to_string()),
+            ("number of triggers", count("trigger").to_string()),
+            ("number of views", count("view").to_string()),
+        ];
+        for (name, value) in lines {
+            println!("{:<21}{}", format!("{}:", name), value);
+        }
+        Ok(())
+    }
+}
+
+/// Split `text` at the commas outside parentheses and quotes.
+fn split_top_level(text: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut depth = 0;
+    let mut quote = None;
+    for c in text.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        parts.last_mut().unwrap().push(c);
+    }
+    parts
+}
+
+/// The words of a column definition: bare words, quoted names without their quotes, and
+/// every parenthesized group as a single word.
+fn definition_words(definition: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let chars: Vec<char> = definition.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if matches!(c, '"' | '`' | '[' | '\'') {
+            let close = if c == '[' { ']' } else { c };
+            let end = chars[i + 1..].iter().position(|d| *d == close).map_or(chars.len(), |len| i + 1 + len);
+            words.push(chars[i + 1..end].iter().collect());
+            i = end + 1;
+        } else if c == '(' {
+            let mut depth = 0;
+            let start = i;
+            while i < chars.len() {
+                depth += match chars[i] { '(' => 1, ')' => -1, _ => 0 };
+                i += 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            words.push(chars[start..i].iter().collect());
+        } else {
+            let start = i;
+            while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | '"' | '`' | '[' | '\'') {
+                i += 1;
+            }
+            words.push(chars[start..i].iter().collect());
+        }
+    }
+    words
+}
+
+/// The columns of a primary key, each named, with whether it is descending.
+type PrimaryKey = Vec<(String, bool)>;
+
+/// The columns a CREATE TABLE statement of any SQLite dialect declares, with their affinity
+/// and which is the INTEGER PRIMARY KEY, the columns of each PRIMARY KEY or UNIQUE constraint
+/// SQLite keeps an index for, in the order it numbers those indexes, and for a WITHOUT ROWID
+/// table its primary key. The engine's own parser knows only part of the syntax SQLite files
+/// hold, so this reads just the names, types and keys. A table without an INTEGER PRIMARY KEY
+/// gets the hidden rowid column the engine's own such tables have.
+fn parse_table_columns(sql: &str) -> (Vec<ColumnDef>, Vec<Vec<String>>, Option<PrimaryKey>) {
+    const CONSTRAINTS: [&str; 5] = ["constraint", "primary", "unique", "check", "foreign"];
+    const COLUMN_CONSTRAINTS: [&str; 10] = ["constraint", "primary", "unique", "check", "not", "null", "default", "collate", "references", "generated"];
+    let (open, close) = match (sql.find('('), sql.rfind(')')) {
+        (Some(open), Some(close)) if open < close => (open, close),
+        _ => return (Vec::new(), Vec::new(), None),
+    };
+    let without_rowid = sql[close + 1..].to_lowercase().split_whitespace().collect::<Vec<_>>().join(" ").contains("without rowid");
+    let mut columns: Vec<ColumnDef> = Vec::new();
+    let mut type_names = Vec::new();
+    let mut table_primary_key = None;
+    let mut primary_key_columns = Vec::new();
+    // Each key, and whether it is the primary key, which has no index if it is the rowid.
+    let mut keys: Vec<(Vec<String>, bool)> = Vec::new();
+    for definition in split_top_level(&sql[open + 1..close]) {
+        let words = definition_words(&definition);
+        let lower: Vec<String> = words.iter().map(|word| word.to_lowercase()).collect();
+        let first = match lower.first() {
+            Some(first) => first,
+            None => continue,
+        };
+        if CONSTRAINTS.contains(&first.as_str()) {
+            let at = match lower.iter().position(|word| word == "primary" || word == "unique") {
+                Some(at) => at,
+                None => continue,
+            };
+            let primary = lower[at] == "primary";
+            let key_columns = match words.get(at + if primary { 2 } else { 1 }) {
+                Some(key_columns) if key_columns.starts_with('(') => key_columns,
+                _ => continue,
+            };
+            let key_words: Vec<Vec<String>> = split_top_level(key_columns.trim_start_matches('(').trim_end_matches(')')).iter()
+                .map(|column| definition_words(column))
+                .filter(|words| !words.is_empty())
+                .collect();
+            let key_columns: Vec<String> = key_words.iter().map(|words| words[0].clone()).collect();
+            if primary {
+                primary_key_columns = key_words.iter()
+                    .map(|words| (words[0].clone(), words.iter().any(|word| word.eq_ignore_ascii_case("desc"))))
+                    .collect();
+            }
+            // `PRIMARY KEY (column)` makes that column the rowid, as a column constraint would.
+            if primary && key_columns.len() == 1 {
+                table_primary_key = Some(key_columns[0].clone());
+            }
+            keys.push((key_columns, primary));
+            continue;
+        }
+        let type_end = lower.iter().skip(1).position(|word| COLUMN_CONSTRAINTS.contains(&word.as_str())).map_or(words.len(), |i| i + 1);
+        let type_name: Vec<&str> = words[1..type_end].iter().filter(|word| !word.starts_with('(')).map(String::as_str).collect();
+        let type_name = type_name.join(" ");
+        let primary_key = lower[type_end..].windows(2).any(|pair| pair[0] == "primary" && pair[1] == "key")
+            && type_name.eq_ignore_ascii_case("integer")
+            && !lower[type_end..].iter().any(|word| word == "desc");
+        let collation = lower[type_end..].iter().position(|word| word == "collate")
+            .and_then(|at| words.get(type_end + at + 1))
+            .map_or(Collation::Binary, |name| Collation::from_name(name));
+        for (at, word) in lower.iter().enumerate().skip(type_end) {
+            let primary = word == "primary" && lower.get(at + 1).is_some_and(|next| next == "key");
+            if primary {
+                primary_key_columns = vec![(words[0].clone(), lower.get(at + 2).is_some_and(|next| next == "desc"))];
+            }
+            if primary || word == "unique" {
+                keys.push((vec![words[0].clone()], primary));
+            }
+        }
+        columns.push(ColumnDef {
+            name: words[0].clone(),
+            column_type: ColumnType::from_declared(&type_name),
+            primary_key,
+            unique: false,
+            not_null: false,
+            default: None,
+            collation,
+            hidden: false,
+        });
+        type_names.push(type_name);
+    }
+    // Only a column declared exactly INTEGER is the rowid; `INT PRIMARY KEY` is a key of its own.
+    if let Some(name) = table_primary_key {
+        if let Some(column) = columns.iter().position(|column| column.name.eq_ignore_ascii_case(&name)) {
+            columns[column].primary_key = type_names[column].eq_ignore_ascii_case("integer");
+        }
+    }
+    // The table of a WITHOUT ROWID table is the index of its primary key.
+    let has_rowid_column = columns.iter().any(|column| column.primary_key);
+    let keys = keys.into_iter()
+        .filter(|(_, primary)| !(*primary && (has_rowid_column || without_rowid)))
+        .map(|(key, _)| key)
+        .collect();
+    if !without_rowid && !has_rowid_column && !columns.is_empty() && columns.iter().all(|column| !column.name.eq_ignore_ascii_case("rowid")) {
+        columns.push(ColumnDef {
+            name: String::from("rowid"),
+            column_type: ColumnType::Integer,
+            primary_key: true,
+            unique: false,
+            not_null: false,
+            default: None,
+            collation: Collation::Binary,
+            hidden: true,
+        });
+    }
+    (columns, keys, without_rowid.then_some(primary_key_columns))
+}
+
+/// The first column a CREATE INDEX statement indexes, and whether the index keeps that column
+/// unique, if the index can serve the engine as an index on it: one that is not partial, on a
+/// column rather than an expression, in ascending order under the column's own collation.
+fn parse_index_column(sql: &str) -> Option<(String, bool)> {
+    let words = definition_words(sql);
+    let lower: Vec<String> = words.iter().map(|word| word.to_lowercase()).collect();
+    let unique = lower.get(1).is_some_and(|word| word == "unique");
+    let at = lower.iter().position(|word| word.starts_with('('))?;
+    if lower[at + 1..].iter().any(|word| word == "where") {
+        return None;
+    }
+    let columns = split_top_level(words[at].trim_start_matches('(').trim_end_matches(')'));
+    if columns[0].trim_start().starts_with('(') {
+        return None;
+    }
+    match definition_words(&columns[0]).as_slice() {
+        [column] => Some((column.clone(), unique && columns.len() == 1)),
+        [column, order] if order.eq_ignore_ascii_case("asc") => Some((column.clone(), unique && columns.len() == 1)),
+        _ => None,
+    }
+}
+
+
+/// The pages of a SQLite file being written, numbered from 1.
+struct FileWriter {
+    pages: Vec<Vec<u8>>,
+}
+
+impl FileWriter {
+    fn allocate(&mut self) -> u32 {
+        self.pages.push(vec![0; SAVE_PAGE_SIZE]);
+        self.pages.len() as u32
+    }
+
+    /// The bytes free for cells on a page, each of which also takes a 2-byte pointer.
+    fn capacity(page_num: Option<u32>, leaf: bool) -> usize {
+        let header = if page_num == Some(1) { HEADER_SIZE } else { 0 };
+        SAVE_PAGE_SIZE - header - if leaf { 8 } else { 12 }
+    }
+
+    fn fits(cells: &[Vec<u8>], capacity: usize) -> bool {
+        cells.iter().map(|cell| cell.len() + 2).sum::<usize>() <= capacity
+    }
+
+    /// The cells at which a level of B-tree pages breaks when packed in order: each is the
+    /// first that no longer fits on the page before it.
+    fn breaks(cells: &[Vec<u8>], capacity: usize) -> Vec<usize> {
+        let mut breaks = Vec::new();
+        let mut used = 0;
+        for (i, cell) in cells.iter().enumerate() {
+            if used + cell.len() + 2 > capacity {
+                breaks.push(i);
+                used = 0;
+            }
+            used += cell.len() + 2;
+        }
+        breaks
+    }
+
+    /// Lay out a B-tree page: its header, the cell pointer array after it, and the cells
+    /// packed against the end of the page in order.
+    fn write_page(&mut self, page_num: u32, flag: u8, cells: &[Vec<u8>], right_child: Option<u32>) {
+        let page = &mut self.pages[page_num as usize - 1];
+        // The B-tree page header starts after the file header on page 1.
+        let header = if page_num == 1 { HEADER_SIZE } else { 0 };
+        let pointers = header + if right_child.is_some() { 12 } else { 8 };
+        let mut content = SAVE_PAGE_SIZE;
+        for (i, cell) in cells.iter().enumerate() {
+            content -= cell.len();
+            page[content..content + cell.len()].copy_from_slice(cell);
+            page[pointers + 2 * i..pointers + 2 * i + 2].copy_from_slice(&(content as u16).to_be_bytes());
+        }
+        page[header] = flag;
+        page[header + 3..header + 5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+        page[header + 5..header + 7].copy_from_slice(&(content as u16).to_be_bytes());
+        if let Some(right_child) = right_child {
+            page[header + 8..header + 12].copy_from_slice(&right_child.to_be_bytes());
+        }
+    }
+
+    /// Append a payload to a cell after its size: the part that stays on the page, then the
+    /// number of the first overflow page if the rest spills over.
+    fn push_payload(&mut self, cell: &mut Vec<u8>, payload: &[u8], table_leaf: bool) {
+        let local = local_payload_len(SAVE_PAGE_SIZE, payload.len(), table_leaf);
+        cell.extend_from_slice(&payload[..local]);
+        if local < payload.len() {
+            let chunks: Vec<&[u8]> = payload[local..].chunks(SAVE_PAGE_SIZE - 4).collect();
+            let first = self.pages.len() as u32 + 1;
+            for (i, chunk) in chunks.iter().enumerate() {
+                let page_num = self.allocate();
+                let next = if i + 1 < chunks.len() { page_num + 1 } else { 0 };
+                let page = &mut self.pages[page_num as usize - 1];
+                page[..4].copy_from_slice(&next.to_be_bytes());
+                page[4..4 + chunk.len()].copy_from_slice(chunk);
+            }
+            cell.extend_from_slice(&first.to_be_bytes());
+        }
+    }
+
+    /// Write a page at `root`, or at a new page without one, returning its number.
+    fn write_root(&mut self, root: Option<u32>, flag: u8, cells: &[Vec<u8>], right_child: Option<u32>) -> u32 {
+        let root = root.unwrap_or_else(|| self.allocate());
+        self.write_page(root, flag, cells, right_child);
+        root
+    }
+
+    /// Build a table B-tree of records in rowid order, bottom up, at `root` if given: leaves
+    /// packed full, with interior pages over them that bound each leaf by its last rowid.
+    fn build_table(&mut self, rows: Vec<(i64, Vec<u8>)>, root: Option<u32>) -> u32 {
+        let mut cells = Vec::new();
+        for (rowid, record) in &rows {
+            // The rowid follows the payload's size, ahead of the payload.
+            let mut cell = Vec::new();
+            put_varint(&mut cell, record.len() as u64);
+            put_varint(&mut cell, *rowid as u64);
+            self.push_payload(&mut cell, record, true);
+            cells.push(cell);
+        }
+        if Self::fits(&cells, Self::capacity(root, true)) {
+            return self.write_root(root, LEAF_TABLE_PAGE, &cells, None);
+        }
+        let mut leaves = Vec::new();
+        let mut bounds = Vec::new();
+        let mut start = 0;
+        for end in Self::breaks(&cells, Self::capacity(None, true)).into_iter().chain([cells.len()]) {
+            let page_num = self.allocate();
+            self.write_page(page_num, LEAF_TABLE_PAGE, &cells[start..end], None);
+            leaves.push(page_num);
+            let mut bound = Vec::new();
+            put_varint(&mut bound, rows[end - 1].0 as u64);
+            bounds.push(bound);
+            start = end;
+        }
+        bounds.pop();
+        self.build_interior(leaves, bounds, INTERIOR_TABLE_PAGE, root)
+    }
+
+    /// Build an index B-tree of records in order, bottom up. An index keeps each entry once,
+    /// so the entry that ends a leaf moves up to the level above, between its neighbours.
+    fn build_index(&mut self, records: Vec<Vec<u8>>) -> u32 {
+        let mut cells = Vec::new();
+        for record in &records {
+            let mut cell = Vec::new();
+            put_varint(&mut cell, record.len() as u64);
+            self.push_payload(&mut cell, record, false);
+            cells.push(cell);
+        }
+        if Self::fits(&cells, Self::capacity(None, true)) {
+            return self.write_root(None, LEAF_INDEX_PAGE, &cells, None);
+        }
+        let (leaves, dividers) = self.split_level(&cells, LEAF_INDEX_PAGE, &[], 0);
+        self.build_interior(leaves, dividers, INTERIOR_INDEX_PAGE, None)
+    }
+
+    /// Build the interior levels of a B-tree over `children`, bottom up, until one page at
+    /// `root` holds them all. A cell is a child's number followed by the body that bounds the
+    /// keys under it; every child but the last has one, the last being the right child.
+    fn build_interior(&mut self, mut children: Vec<u32>, mut bodies: Vec<Vec<u8>>, flag: u8, root: Option<u32>) -> u32 {
+        loop {
+            let right_child = children.pop().unwrap();
+            let cells: Vec<Vec<u8>> = children.iter().zip(&bodies)
+                .map(|(child, body)| [&child.to_be_bytes()[..], body].concat())
+                .collect();
+            if Self::fits(&cells, Self::capacity(root, false)) {
+                return self.write_root(root, flag, &cells, Some(right_child));
+            }
+            (children, bodies) = self.split_level(&cells, flag, &children, right_child);
+            bodies.iter_mut().for_each(|body| { body.drain(..4); });
+        }
+    }
+
+    /// Pack a level of pages, breaking wherever a cell no longer fits. That cell leaves the
+    /// level: it moves up as the divider between the pages either side, and on an interior
+    /// level its child becomes the right child of the page before. Returns the pages and the
+    /// cells between them.
+    fn split_level(&mut self, cells: &[Vec<u8>], flag: u8, children: &[u32], right_child: u32) -> (Vec<u32>, Vec<Vec<u8>>) {
+        let leaf = flag == LEAF_INDEX_PAGE;
+        let mut breaks = Self::breaks(cells, Self::capacity(None, leaf));
+        // The last page must keep a cell, so a break at the last cell moves back by one.
+        if breaks.last() == Some(&(cells.len() - 1)) {
+            *breaks.last_mut().unwrap() -= 1;
+        }
+        let (mut pages, mut dividers) = (Vec::new(), Vec::new());
+        let mut start = 0;
+        for end in breaks.iter().copied().chain([cells.len()]) {
+            let right = if leaf { None } else if end < cells.len() { Some(children[end]) } else { Some(right_child) };
+            let page_num = self.allocate();
+            self.write_page(page_num, flag, &cells[start..end], right);
+            pages.push(page_num);
+            if end < cells.len() {
+                dividers.push(cells[end].clone());
+            }
+            start = end + 1;
+        }
+        (pages, dividers)
+    }
+}
+
+/// Write the database to `path` as a SQLite 3 file that SQLite itself can open: every table,
+/// the built-in users table among them, and every index, listed in `sqlite_schema`.
+pub fn save_database(table: &mut Table, path: &str) -> Result<(), String> {
+    let mut writer = FileWriter { pages: vec![vec![0; SAVE_PAGE_SIZE]] };
+    let mut entries = Vec::new();
+    let mut table_rows = Vec::new();
+    for table_def in table.schema.tables.clone() {
+        let mut rows = Vec::new();
+        let result = for_each_row(table, &table_def, &QueryPlan::FullScan, None, |_, row| {
+            rows.push(row.to_vec());
+            true
+        });
+        if result != ExecuteSuccess {
+            return Err(format!("cannot read table {}", table_def.name));
+        }
+        // The INTEGER PRIMARY KEY is the rowid, and SQLite stores NULL in its place; the hidden
+        // rowid of a table without one is no column of the file's table. The rows of
+        // sqlite_sequence, which has none, are numbered in order. A WITHOUT ROWID table is an
+        // index of its records, which the scan has already put in key order.
+        let key = table_def.primary_key();
+        let root_page = if table_def.without_rowid.is_some() {
+            writer.build_index(rows.iter().map(|row| encode_record(&table_def.key_record(row))).collect())
+        } else if let Some(fts) = &table_def.fts {
+            // SQLite could only search a full-text table through an FTS5 index of its own
+            // making, so it gets the plain table of the declared columns, under the same rowids.
+            let records = rows.iter()
+                .map(|row| (row[0].to_integer().unwrap(), encode_record(&row[1..=fts.unindexed.len()])))
+                .collect();
+            writer.build_table(records, None)
+        } else {
+            let records = rows.iter().enumerate()
+                .map(|(i, row)| {
+                    let mut row = row.clone();
+                    match key {
+                        Some(key) if table_def.columns[key].hidden => (row.remove(key).to_integer().unwrap(), encode_record(&row)),
+                        Some(key) => (std::mem::replace(&mut row[key], Value::Null).to_integer().unwrap(), encode_record(&row)),
+                        None => (i as i64 + 1, encode_record(&row)),
+                    }
+                })
+                .collect();
+            writer.build_table(records, None)
+        };
+        entries.push(vec![
+            Value::Text(String::from("table")),
+            Value::Text(table_def.name.clone()),
+            Value::Text(table_def.name.clone()),
+            Value::Integer(root_page as i64),
+            Value::Text(match &table_def.fts {
+                Some(fts) => {
+                    let columns: Vec<&str> = table_def.columns[1..=fts.unindexed.len()].iter().map(|column| column.name.as_str()).collect();
+                    format!("CREATE TABLE {}({})", table_def.name, columns.join(", "))
+                }
+                None => table_def.sql.clone(),
+            }),
+        ]);
+        table_rows.push((table_def, key, rows));
+    }
+    for index in &table.schema.indexes {
+        let (table_def, key, rows) = table_rows.iter().find(|(table_def, _, _)| table_def.name.eq_ignore_ascii_case(&index.def.table_name)).unwrap();
+        let key = key.unwrap();
+        let mut keys = Vec::new();
+        for row in rows {
+            let value = index.def.key_value(table_def, row).map_err(|_| format!("cannot compute the entries of index {}", index.def.name))?;
+            keys.push(vec![value, row[key].clone()]);
+        }
+        keys.sort_by(|a, b| index.tree.compare(a, b));
+        let root_page = writer.build_index(keys.iter().map(|key| encode_record(key)).collect());
+        // SQLite makes the indexes of UNIQUE constraints itself, and lists them without SQL.
+        let sql = if index.def.name.starts_with("sqlite_autoindex_") { Value::Null } else { Value::Text(index.def.sql()) };
+        entries.push(vec![
+            Value::Text(String::from("index")),
+            Value::Text(index.def.name.clone()),
+            Value::Text(index.def.table_name.clone()),
+            Value::Integer(root_page as i64),
+            sql,
+        ]);
+    }
+    let schema_rows = entries.iter().enumerate().map(|(i, entry)| (i as i64 + 1, encode_record(entry))).collect();
+    writer.build_table(schema_rows, Some(1));
+
+    let header = FileHeader {
+        page_size: SAVE_PAGE_SIZE,
+        write_version: 1,
+        read_version: 1,
+        reserved: 0,
+        change_counter: 1,
+        page_count: writer.pages.len() as u32,
+        freelist_trunk: 0,
+        freelist_count: 0,
+        schema_cookie: 1,
+        schema_format: 4,
+        default_cache_size: 0,
+        autovacuum_top_root: 0,
+        text_encoding: 1,
+        user_version: 0,
+        incremental_vacuum: 0,
+        application_id: 0,
+        version_valid_for: 1,
+        sqlite_version: SQLITE_VERSION_NUMBER,
+    };
+    header.write(&mut writer.pages[0]);
+    std::fs::write(path, writer.pages.concat()).map_err(|err| err.to_string())
+}
@@ -0,0 +1,915 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+use crate::collation::Collation;
+use crate::expr::{BinaryOp, Expr};
+use crate::index::IndexTree;
+use crate::pager::Pager;
+use crate::plan::QueryPlan;
+use crate::record::{get_varint, put_varint};
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::*;
+use crate::schema::{ColumnDef, ColumnType, Schema, TableDef};
+use crate::table::Table;
+use crate::value::Value;
+
+/// BM25's term frequency saturation and document length normalization, as SQLite sets them.
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+/// What `CREATE VIRTUAL TABLE name USING fts5(...)` gives besides the names of its columns.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FtsOptions {
+    /// The words of `tokenize = '...'`: the tokenizer, then its arguments.
+    pub tokenize: Vec<String>,
+    /// For each declared column, whether it is UNINDEXED: kept with the row but never searched.
+    pub unindexed: Vec<bool>,
+}
+
+impl FtsOptions {
+    pub fn tokenizer(&self) -> Result<Tokenizer, ExecuteResult> {
+        Tokenizer::new(&self.tokenize)
+    }
+}
+
+/// The columns of a full-text table: a hidden rowid, the declared columns, then a hidden
+/// column named after the table, which MATCH is applied to and which holds what a match found
+/// for `bm25()`, and a hidden `rank`, the bm25() of the row.
+pub fn table_columns(table_name: &str, declared: Vec<String>) -> Vec<ColumnDef> {
+    let column = |name: String, hidden: bool| ColumnDef {
+        name,
+        column_type: ColumnType::Blob,
+        primary_key: false,
+        unique: false,
+        not_null: false,
+        default: None,
+        collation: Collation::Binary,
+        hidden,
+    };
+    let mut columns = vec![ColumnDef { column_type: ColumnType::Integer, primary_key: true, ..column(String::from("rowid"), true) }];
+    columns.extend(declared.into_iter().map(|name| column(name, false)));
+    columns.push(column(String::from(table_name), true));
+    columns.push(column(String::from("rank"), true));
+    columns
+}
+
+/// The CREATE TABLE statements of the shadow tables a full-text table keeps its index in: one
+/// with the positions of each term in each column of each row, and one with the number of
+/// tokens in each column of each row.
+pub fn shadow_tables(table_name: &str) -> [String; 2] {
+    [
+        format!("create table {}_idx(term, id, col, pos, primary key (term, id, col)) without rowid", table_name),
+        format!("create table {}_docsize(id integer primary key, sz) without rowid", table_name),
+    ]
+}
+
+/// The trees of a full-text table's shadow tables: that of its terms, then that of its token counts.
+fn shadow_trees(schema: &Schema, table_name: &str) -> Result<(IndexTree, IndexTree), ExecuteResult> {
+    let tree = |suffix: &str| {
+        let name = format!("{}_{}", table_name, suffix);
+        schema.find_table(&name).and_then(TableDef::key_tree).ok_or(ExecuteNoSuchTable(name))
+    };
+    Ok((tree("idx")?, tree("docsize")?))
+}
+
+/// The values of a blob of varints.
+fn varints(mut bytes: &[u8]) -> Vec<u64> {
+    let mut values = Vec::new();
+    while !bytes.is_empty() {
+        let (value, len) = get_varint(bytes);
+        values.push(value);
+        bytes = &bytes[len..];
+    }
+    values
+}
+
+fn varint_blob(values: impl IntoIterator<Item = u64>) -> Value {
+    let mut blob = Vec::new();
+    for value in values {
+        put_varint(&mut blob, value);
+    }
+    Value::Blob(blob)
+}
+
+/// How text is split into the terms that are indexed and searched for.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Tokenizer {
+    /// Runs of ASCII letters and digits and of any non-ASCII character; only ASCII is folded
+    /// to lower case.
+    Ascii,
+    /// Runs of Unicode letters and digits, folded to lower case and, unless told otherwise,
+    /// stripped of their diacritics.
+    Unicode61 { remove_diacritics: bool },
+    /// The terms of another tokenizer, each reduced to its stem by the Porter algorithm.
+    Porter(Box<Tokenizer>),
+}
+
+impl Default for Tokenizer {
+    fn default() -> Tokenizer {
+        Tokenizer::Unicode61 { remove_diacritics: true }
+    }
+}
+
+impl Tokenizer {
+    /// The tokenizer the words of a `tokenize` option name, unicode61 when there are none.
+    fn new(words: &[String]) -> Result<Tokenizer, ExecuteResult> {
+        let constructor_error = || ExecuteFullTextError(String::from("error in tokenizer constructor"));
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (name.to_lowercase(), args),
+            None => return Ok(Tokenizer::default()),
+        };
+        match name.as_str() {
+            "ascii" if args.is_empty() => Ok(Tokenizer::Ascii),
+            "unicode61" => {
+                // Options come in pairs of name and value.
+                let mut remove_diacritics = true;
+                for option in args.chunks(2) {
+                    match option {
+                        [name, value] if name.eq_ignore_ascii_case("remove_diacritics") => {
+                            remove_diacritics = match value.as_str() {
+                                "0" => false,
+                                "1" | "2" => true,
+                                _ => return Err(constructor_error()),
+                            };
+                        }
+                        _ => return Err(constructor_error()),
+                    }
+                }
+                Ok(Tokenizer::Unicode61 { remove_diacritics })
+            }
+            "porter" => Ok(Tokenizer::Porter(Box::new(Tokenizer::new(args)?))),
+            "ascii" => Err(constructor_error()),
+            _ => Err(ExecuteFullTextError(format!("no such tokenizer: {}", words[0]))),
+        }
+    }
+
+    /// The terms of the text, in order, so that a term's position is its index.
+    fn terms(&self, text: &str) -> Vec<String> {
+        match self {
+            Tokenizer::Ascii => text.split(|c: char| c.is_ascii() && !c.is_ascii_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(str::to_ascii_lowercase)
+                .collect(),
+            Tokenizer::Unicode61 { remove_diacritics } => text.split(|c: char| !(c.is_alphanumeric() || is_combining_diacritic(c)))
+                .map(|word| {
+                    word.chars()
+                        .filter(|c| !(*remove_diacritics && is_combining_diacritic(*c)))
+                        .flat_map(char::to_lowercase)
+                        .map(|c| if *remove_diacritics { without_diacritic(c) } else { c })
+                        .collect::<String>()
+                })
+                .filter(|term| !term.is_empty())
+                .collect(),
+            Tokenizer::Porter(tokenizer) => tokenizer.terms(text).iter().map(|term| porter_stem(term)).collect(),
+        }
+    }
+}
+
+/// Whether the character is one of the combining diacritical marks, which unicode61 counts as
+/// part of a term.
+fn is_combining_diacritic(c: char) -> bool {
+    ('\u{300}'..='\u{36F}').contains(&c)
+}
+
+/// The letter a lower-case Latin letter with a diacritic is written with, or the character
+/// itself. Letters whose stroke or hook is part of them, like ø and ł, are letters of their own.
+fn without_diacritic(c: char) -> char {
+    match c {
+        'à'..='å' | 'ā' | 'ă' | 'ą' => 'a',
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => 'c',
+        'ď' => 'd',
+        'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => 'e',
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => 'g',
+        'ĥ' => 'h',
+        'ì'..='ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' => 'i',
+        'ĵ' => 'j',
+        'ķ' => 'k',
+        'ĺ' | 'ļ' | 'ľ' => 'l',
+        'ñ' | 'ń' | 'ņ' | 'ň' => 'n',
+        'ò'..='ö' | 'ō' | 'ŏ' | 'ő' => 'o',
+        'ŕ' | 'ŗ' | 'ř' => 'r',
+        'ś' | 'ŝ' | 'ş' | 'š' => 's',
+        'ţ' | 'ť' => 't',
+        'ù'..='ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => 'u',
+        'ŵ' => 'w',
+        'ý' | 'ÿ' | 'ŷ' => 'y',
+        'ź' | 'ż' | 'ž' => 'z',
+        _ => c,
+    }
+}
+
+/// Whether the letter at `i` counts as a consonant: any but a, e, i, o and u, and y after a
+/// vowel or at the start.
+fn is_consonant(word: &[u8], i: usize) -> bool {
+    match word[i] {
+        b'a' | b'e' | b'i' | b'o' | b'u' => false,
+        b'y' => i == 0 || !is_consonant(word, i - 1),
+        _ => true,
+    }
+}
+
+/// Porter's m: how many times a run of vowels is followed by a run of consonants.
+fn measure(stem: &[u8]) -> usize {
+    let mut i = 0;
+    while i < stem.len() && is_consonant(stem, i) {
+        i += 1;
+    }
+    let mut m = 0;
+    loop {
+        while i < stem.len() && !is_consonant(stem, i) {
+            i += 1;
+        }
+        if i == stem.len() {
+            return m;
+        }
+        while i < stem.len() && is_consonant(stem, i) {
+            i += 1;
+        }
+        m += 1;
+    }
+}
+
+fn has_vowel(stem: &[u8]) -> bool {
+    (0..stem.len()).any(|i| !is_consonant(stem, i))
+}
+
+fn ends_with_double_consonant(word: &[u8]) -> bool {
+    let len = word.len();
+    len >= 2 && word[len - 1] == word[len - 2] && is_consonant(word, len - 1)
+}
+
+/// Whether the stem ends consonant, vowel, consonant, the last not w, x or y, as in hop.
+fn ends_with_cvc(stem: &[u8]) -> bool {
+    let len = stem.len();
+    len >= 3 && is_consonant(stem, len - 3) && !is_consonant(stem, len - 2) && is_consonant(stem, len - 1)
+        && !matches!(stem[len - 1], b'w' | b'x' | b'y')
+}
+
+/// Replace the first of the suffixes the word ends with, if the stem before it passes the test.
+fn replace_suffix(word: &mut Vec<u8>, suffixes: &[(&str, &str)], test: impl Fn(&[u8], &str) -> bool) {
+    for (suffix, replacement) in suffixes {
+        if word.ends_with(suffix.as_bytes()) {
+            let stem_len = word.len() - suffix.len();
+            if test(&word[..stem_len], suffix) {
+                word.truncate(stem_len);
+                word.extend_from_slice(replacement.as_bytes());
+            }
+            return;
+        }
+    }
+}
+
+/// The stem of a lower-case term, as Martin Porter's reference implementation finds it. Terms
+/// of fewer than three bytes or more than 64 are their own stems.
+fn porter_stem(term: &str) -> String {
+    const STEP2: [(&str, &str); 21] = [
+        ("ational", "ate"), ("tional", "tion"), ("enci", "ence"), ("anci", "ance"), ("izer", "ize"),
+        ("bli", "ble"), ("alli", "al"), ("entli", "ent"), ("eli", "e"), ("ousli", "ous"),
+        ("ization", "ize"), ("ation", "ate"), ("ator", "ate"), ("alism", "al"), ("iveness", "ive"),
+        ("fulness", "ful"), ("ousness", "ous"), ("aliti", "al"), ("iviti", "ive"), ("biliti", "ble"),
+        ("logi", "log"),
+    ];
+    const STEP3: [(&str, &str); 7] = [
+        ("icate", "ic"), ("ative", ""), ("alize", "al"), ("iciti", "ic"), ("ical", "ic"), ("ful", ""), ("ness", ""),
+    ];
+    const STEP4: [(&str, &str); 19] = [
+        ("al", ""), ("ance", ""), ("ence", ""), ("er", ""), ("ic", ""), ("able", ""), ("ible", ""), ("ant", ""),
+        ("ement", ""), ("ment", ""), ("ent", ""), ("ion", ""), ("ou", ""), ("ism", ""), ("ate", ""), ("iti", ""),
+        ("ous", ""), ("ive", ""), ("ize", ""),
+    ];
+    if term.len() < 3 || term.len() > 64 {
+        return String::from(term);
+    }
+    let mut word = term.as_bytes().to_vec();
+
+    // Step 1a: plurals.
+    if word.ends_with(b"sses") || word.ends_with(b"ies") {
+        word.truncate(word.len() - 2);
+    } else if !word.ends_with(b"ss") && word.ends_with(b"s") {
+        word.pop();
+    }
+    // Step 1b: -eed, -ed and -ing.
+    if word.ends_with(b"eed") {
+        if measure(&word[..word.len() - 3]) > 0 {
+            word.pop();
+        }
+    } else if let Some(suffix) = [&b"ed"[..], b"ing"].into_iter().find(|suffix| word.ends_with(suffix)) {
+        let stem_len = word.len() - suffix.len();
+        if has_vowel(&word[..stem_len]) {
+            word.truncate(stem_len);
+            if word.ends_with(b"at") || word.ends_with(b"bl") || word.ends_with(b"iz") {
+                word.push(b'e');
+            } else if ends_with_double_consonant(&word) && !matches!(word[word.len() - 1], b'l' | b's' | b'z') {
+                word.pop();
+            } else if measure(&word) == 1 && ends_with_cvc(&word) {
+                word.push(b'e');
+            }
+        }
+    }
+    // Step 1c: a final y after a vowel becomes i.
+    if word.ends_with(b"y") && has_vowel(&word[..word.len() - 1]) {
+        let last = word.len() - 1;
+        word[last] = b'i';
+    }
+    // Steps 2 to 4: ever shorter suffixes, each off a longer stem.
+    replace_suffix(&mut word, &STEP2, |stem, _| measure(stem) > 0);
+    replace_suffix(&mut word, &STEP3, |stem, _| measure(stem) > 0);
+    let step4: Vec<(&str, &str)> = STEP4.iter()
+        .copied()
+        .filter(|(suffix, _)| *suffix != "ion" || word.ends_with(b"sion") || word.ends_with(b"tion"))
+        .collect();
+    replace_suffix(&mut word, &step4, |stem, _| measure(stem) > 1);
+    // Step 5: a final e, and a double l.
+    if word.ends_with(b"e") {
+        let stem = &word[..word.len() - 1];
+        let m = measure(stem);
+        if m > 1 || (m == 1 && !ends_with_cvc(stem)) {
+            word.pop();
+        }
+    }
+    if word.ends_with(b"ll") && measure(&word) > 1 {
+        word.pop();
+    }
+    String::from_utf8(word).unwrap_or_else(|_| String::from(term))
+}
+
+/// The terms in each declared column of a row, with the positions each is at, and the number
+/// of tokens in the column. An UNINDEXED column has none.
+fn row_terms(table_def: &TableDef, row: &[Value]) -> Vec<(u64, BTreeMap<String, Vec<u64>>)> {
+    let fts = table_def.fts.as_ref().unwrap();
+    let tokenizer = fts.tokenizer().unwrap_or_default();
+    fts.unindexed.iter()
+        .enumerate()
+        .map(|(column, unindexed)| {
+            let value = &row[column + 1];
+            let mut terms: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+            if *unindexed || value.is_null() {
+                return (0, terms);
+            }
+            let tokens = tokenizer.terms(&value.to_string());
+            for (position, term) in tokens.iter().enumerate() {
+                terms.entry(term.clone()).or_default().push(position as u64);
+            }
+            (tokens.len() as u64, terms)
+        })
+        .collect()
+}
+
+/// Add a row of a full-text table to its index: the positions of its terms in each column,
+/// and the number of tokens in each.
+pub fn index_row(table: &mut Table, table_def: &TableDef, rowid: i64, row: &[Value]) {
+    let (terms_tree, sizes_tree) = match shadow_trees(&table.schema, &table_def.name) {
+        Ok(trees) => trees,
+        Err(_) => return,
+    };
+    let mut sizes = Vec::new();
+    for (column, (size, terms)) in row_terms(table_def, row).into_iter().enumerate() {
+        sizes.push(size);
+        for (term, positions) in terms {
+            let record = vec![Value::Text(term), Value::Integer(rowid), Value::Integer(column as i64), varint_blob(positions)];
+            terms_tree.insert_record(&mut table.pager, record);
+        }
+    }
+    sizes_tree.insert_record(&mut table.pager, vec![Value::Integer(rowid), varint_blob(sizes)]);
+}
+
+/// Take a row of a full-text table out of its index, as `index_row` put it in.
+pub fn unindex_row(table: &mut Table, table_def: &TableDef, rowid: i64, row: &[Value]) -> Result<(), ExecuteResult> {
+    let (terms_tree, sizes_tree) = shadow_trees(&table.schema, &table_def.name)?;
+    let missing = |suffix: &str| ExecuteMissingEntry(format!("{}_{}", table_def.name, suffix));
+    for (column, (_, terms)) in row_terms(table_def, row).into_iter().enumerate() {
+        for term in terms.into_keys() {
+            let key = [Value::Text(term), Value::Integer(rowid), Value::Integer(column as i64)];
+            if !terms_tree.delete_record(&mut table.pager, &key) {
+                return Err(missing("idx"));
+            }
+        }
+    }
+    if !sizes_tree.delete_record(&mut table.pager, &[Value::Integer(rowid)]) {
+        return Err(missing("docsize"));
+    }
+    Ok(())
+}
+
+/// A phrase of a full-text query: terms that must follow one another, each a whole term of the
+/// index or, marked with `*`, any term that starts with it, in one of the columns given or any.
+struct Phrase {
+    terms: Vec<(String, bool)>,
+    columns: Option<Vec<usize>>,
+}
+
+/// How a full-text query combines its phrases, by their number.
+enum Query {
+    Phrase(usize),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>, Box<Query>),
+}
+
+impl Query {
+    /// The rows the query matches, given the rows each phrase is found in.
+    fn rows(&self, hits: &[BTreeMap<i64, Vec<u64>>]) -> BTreeSet<i64> {
+        match self {
+            Query::Phrase(phrase) => hits[*phrase].keys().copied().collect(),
+            Query::And(left, right) => left.rows(hits).intersection(&right.rows(hits)).copied().collect(),
+            Query::Or(left, right) => left.rows(hits).union(&right.rows(hits)).copied().collect(),
+            Query::Not(left, right) => left.rows(hits).difference(&right.rows(hits)).copied().collect(),
+        }
+    }
+}
+
+/// Join two parts of a query. A phrase with no terms in it drops out, leaving the other part.
+fn combine(op: fn(Box<Query>, Box<Query>) -> Query, left: Option<Query>, right: Option<Query>) -> Option<Query> {
+    match (left, right) {
+        (Some(left), Some(right)) => Some(op(Box::new(left), Box::new(right))),
+        (left, right) => left.or(right),
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum QueryToken {
+    Word(String),
+    /// A "quoted string", with its doubled quotes made single.
+    Str(String),
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Colon,
+    Plus,
+    Star,
+    Minus,
+    End,
+}
+
+fn syntax_error(near: &str) -> ExecuteResult {
+    ExecuteFullTextError(format!("fts5: syntax error near \"{}\"", near))
+}
+
+/// Whether the character may be part of a bareword: an ASCII letter or digit, `_`, any
+/// non-ASCII character, or the substitute character.
+fn is_bareword(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || !c.is_ascii() || c == '\x1A'
+}
+
+/// Split a full-text query into tokens, each with the text it was read from.
+fn lex_query(query: &str) -> Result<Vec<(QueryToken, String)>, ExecuteResult> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let token = match c {
+            ' ' | '\t' | '\n' | '\r' | '\x0B' | '\x0C' => {
+                i += 1;
+                continue;
+            }
+            '(' => QueryToken::LParen,
+            ')' => QueryToken::RParen,
+            '{' => QueryToken::LBrace,
+            '}' => QueryToken::RBrace,
+            ':' => QueryToken::Colon,
+            '+' => QueryToken::Plus,
+            '*' => QueryToken::Star,
+            '-' => QueryToken::Minus,
+            '"' => {
+                let start = i;
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(ExecuteFullTextError(String::from("unterminated string"))),
+                        Some('"') if chars.get(i + 1) == Some(&'"') => {
+                            text.push('"');
+                            i += 2;
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            text.push(*c);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+                tokens.push((QueryToken::Str(text), chars[start..i].iter().collect()));
+                continue;
+            }
+            c if is_bareword(c) => {
+                let start = i;
+                while i < chars.len() && is_bareword(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push((QueryToken::Word(word.clone()), word));
+                continue;
+            }
+            c => return Err(syntax_error(&c.to_string())),
+        };
+        tokens.push((token, c.to_string()));
+        i += 1;
+    }
+    tokens.push((QueryToken::End, String::new()));
+    Ok(tokens)
+}
+
+/// Reads a full-text query. Phrases side by side must all be found, binding tighter than NOT,
+/// then AND, then OR, which are only keywords in upper case. Parentheses group, and `column:`
+/// or `{column ...}:` before a phrase or a group keeps it to those columns.
+struct QueryParser<'a> {
+    tokens: Vec<(QueryToken, String)>,
+    pos: usize,
+    tokenizer: &'a Tokenizer,
+    /// The names of the table's declared columns, which column filters name.
+    columns: &'a [String],
+    /// The phrases read so far, which `Query::Phrase` numbers.
+    phrases: &'a mut Vec<Phrase>,
+}
+
+impl QueryParser<'_> {
+    fn peek(&self) -> &QueryToken {
+        &self.tokens[self.pos].0
+    }
+
+    fn accept(&mut self, token: &QueryToken) -> bool {
+        if self.peek() == token {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        self.accept(&QueryToken::Word(String::from(keyword)))
+    }
+
+    fn expect(&mut self, token: &QueryToken) -> Result<(), ExecuteResult> {
+        if self.accept(token) {
+            return Ok(());
+        }
+        Err(self.error())
+    }
+
+    fn error(&self) -> ExecuteResult {
+        syntax_error(&self.tokens[self.pos].1)
+    }
+
+    /// Whether a phrase, or the column filter before one, starts here.
+    fn at_phrase(&self) -> bool {
+        match self.peek() {
+            QueryToken::Word(word) => !matches!(word.as_str(), "AND" | "OR" | "NOT"),
+            QueryToken::Str(_) | QueryToken::LBrace | QueryToken::Minus => true,
+            _ => false,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Option<Query>, ExecuteResult> {
+        let mut left = self.parse_and()?;
+        while self.accept_keyword("OR") {
+            let right = self.parse_and()?;
+            left = combine(Query::Or, left, right);
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Option<Query>, ExecuteResult> {
+        let mut left = self.parse_not()?;
+        while self.accept_keyword("AND") {
+            let right = self.parse_not()?;
+            left = combine(Query::And, left, right);
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Option<Query>, ExecuteResult> {
+        let mut left = self.parse_group()?;
+        while self.accept_keyword("NOT") {
+            let right = self.parse_group()?;
+            left = combine(Query::Not, left, right);
+        }
+        Ok(left)
+    }
+
+    /// (query), colset : (query), or phrases side by side, each after its own column filter.
+    fn parse_group(&mut self) -> Result<Option<Query>, ExecuteResult> {
+        if self.accept(&QueryToken::LParen) {
+            let query = self.parse_or()?;
+            self.expect(&QueryToken::RParen)?;
+            return Ok(query);
+        }
+        let columns = self.parse_colset()?;
+        if let Some(columns) = &columns {
+            if self.accept(&QueryToken::LParen) {
+                let first = self.phrases.len();
+                let query = self.parse_or()?;
+                self.expect(&QueryToken::RParen)?;
+                // A filter inside the group keeps only the columns both name.
+                for phrase in &mut self.phrases[first..] {
+                    phrase.columns = Some(match &phrase.columns {
+                        Some(inner) => inner.iter().copied().filter(|column| columns.contains(column)).collect(),
+                        None => columns.clone(),
+                    });
+                }
+                return Ok(query);
+            }
+        }
+        let mut query = self.parse_phrase(columns)?;
+        while self.at_phrase() {
+            let columns = self.parse_colset()?;
+            let right = self.parse_phrase(columns)?;
+            query = combine(Query::And, query, right);
+        }
+        Ok(query)
+    }
+
+    /// [-] column : or [-] {column ...} :, the columns to search, or after `-` those not to.
+    fn parse_colset(&mut self) -> Result<Option<Vec<usize>>, ExecuteResult> {
+        let negated = self.accept(&QueryToken::Minus);
+        let names = match self.peek().clone() {
+            QueryToken::LBrace => {
+                self.pos += 1;
+                let mut names = Vec::new();
+                loop {
+                    match self.peek().clone() {
+                        QueryToken::Word(name) | QueryToken::Str(name) => names.push(name),
+                        QueryToken::RBrace => break,
+                        _ => return Err(self.error()),
+                    }
+                    self.pos += 1;
+                }
+                self.pos += 1;
+                names
+            }
+            QueryToken::Word(name) | QueryToken::Str(name) if self.tokens[self.pos + 1].0 == QueryToken::Colon => {
+                self.pos += 1;
+                vec![name]
+            }
+            _ if negated => return Err(self.error()),
+            _ => return Ok(None),
+        };
+        self.expect(&QueryToken::Colon)?;
+        let mut columns = Vec::new();
+        for name in names {
+            match self.columns.iter().position(|column| column.eq_ignore_ascii_case(&name)) {
+                Some(column) => columns.push(column),
+                None => return Err(ExecuteNoSuchColumn(name)),
+            }
+        }
+        if negated {
+            columns = (0..self.columns.len()).filter(|column| !columns.contains(column)).collect();
+        }
+        Ok(Some(columns))
+    }
+
+    /// string [*] [+ string [*] ...]: the terms of the strings, one after another, the last of
+    /// each a prefix if `*` follows it. A phrase with no terms is None.
+    fn parse_phrase(&mut self, columns: Option<Vec<usize>>) -> Result<Option<Query>, ExecuteResult> {
+        let mut terms = Vec::new();
+        loop {
+            let text = match self.peek() {
+                QueryToken::Word(_) if !self.at_phrase() => return Err(self.error()),
+                QueryToken::Word(text) | QueryToken::Str(text) => text.clone(),
+                _ => return Err(self.error()),
+            };
+            self.pos += 1;
+            let mut string_terms: Vec<(String, bool)> = self.tokenizer.terms(&text).into_iter().map(|term| (term, false)).collect();
+            if self.accept(&QueryToken::Star) {
+                if let Some(last) = string_terms.last_mut() {
+                    last.1 = true;
+                }
+            }
+            terms.extend(string_terms);
+            if !self.accept(&QueryToken::Plus) {
+                break;
+            }
+        }
+        if terms.is_empty() {
+            return Ok(None);
+        }
+        self.phrases.push(Phrase { terms, columns });
+        Ok(Some(Query::Phrase(self.phrases.len() - 1)))
+    }
+}
+
+/// The MATCH conjunct, with its column unqualified, if the index of the full-text table can
+/// answer it: it is made against the table-named column, to search every column, or a
+/// declared one, to search just that, with a query that reads no row.
+fn answerable(conjunct: &Expr, table_def: &TableDef) -> Option<Expr> {
+    let fts = table_def.fts.as_ref()?;
+    let (left, right) = match conjunct {
+        Expr::Binary { op: BinaryOp::Match, left, right } => (left, right),
+        _ => return None,
+    };
+    let name = match left.without_declared_collation() {
+        Expr::Column(name) => name.split_once('.').map_or(name.as_str(), |(_, column)| column),
+        _ => return None,
+    };
+    let column = table_def.column_index(name)?;
+    if column == 0 || column > fts.unindexed.len() + 1 || !right.columns().is_empty() || !right.subqueries().is_empty() {
+        return None;
+    }
+    Some(Expr::Binary { op: BinaryOp::Match, left: Box::new(Expr::Column(String::from(name))), right: right.clone() })
+}
+
+/// The terms of the WHERE clause's top-level AND the index of a full-text table answers.
+pub fn match_terms(where_clause: Option<&Expr>, table_def: &TableDef) -> Vec<Expr> {
+    match where_clause {
+        Some(where_clause) => where_clause.conjuncts().into_iter().filter_map(|conjunct| answerable(conjunct, table_def)).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// The WHERE clause with its MATCH terms answered from the index of the full-text table: those
+/// the plan took hold for every row it reaches, and any other the table's index can answer is
+/// looked up in it ahead of the scan, to hold for the rows whose rowid, in `rowid_column`, it
+/// found. Any MATCH left is an error, since nothing else can answer it.
+pub fn answered_where(table: &Table, where_clause: Option<&Expr>, table_def: &TableDef, rowid_column: &str,
+                      plan: &QueryPlan) -> Result<Option<Expr>, ExecuteResult> {
+    let where_clause = match where_clause {
+        Some(where_clause) => where_clause,
+        None => return Ok(None),
+    };
+    let answered: &[Expr] = match plan {
+        QueryPlan::FullTextMatch(matches) => {
+            parse_matches(table_def, matches)?;
+            matches
+        }
+        _ => &[],
+    };
+    let where_clause = where_clause.try_replace(&mut |expr| {
+        let term = match answerable(expr, table_def) {
+            Some(term) => term,
+            None => return Ok(None),
+        };
+        if answered.contains(&term) {
+            return Ok(Some(Expr::Literal(Value::Integer(1))));
+        }
+        let found = search(&table.pager, &table.schema, table_def, &[term])?;
+        Ok(Some(Expr::InList {
+            operand: Box::new(Expr::Column(String::from(rowid_column))),
+            list: found.into_iter().map(|(rowid, _)| Expr::Literal(Value::Integer(rowid))).collect(),
+            negated: false,
+        }))
+    })?;
+    if !where_clause.matches().is_empty() {
+        return Err(ExecuteFullTextError(String::from("unable to use function MATCH in the requested context")));
+    }
+    Ok(Some(where_clause))
+}
+
+/// The query all the MATCH terms the plan took make together, and its phrases.
+fn parse_matches(table_def: &TableDef, matches: &[Expr]) -> Result<(Option<Query>, Vec<Phrase>), ExecuteResult> {
+    let fts = table_def.fts.as_ref().unwrap();
+    let tokenizer = fts.tokenizer()?;
+    let columns: Vec<String> = table_def.columns[1..=fts.unindexed.len()].iter().map(|column| column.name.clone()).collect();
+    let mut phrases = Vec::new();
+    let mut query = None;
+    for term in matches {
+        let (left, right) = match term {
+            Expr::Binary { left, right, .. } => (left, right),
+            _ => continue,
+        };
+        let text = match right.evaluate(&|_| None)? {
+            Value::Null => String::new(),
+            value => value.to_string(),
+        };
+        let first = phrases.len();
+        let mut parser = QueryParser { tokens: lex_query(&text)?, pos: 0, tokenizer: &tokenizer, columns: &columns, phrases: &mut phrases };
+        let term_query = parser.parse_or()?;
+        if *parser.peek() != QueryToken::End {
+            return Err(parser.error());
+        }
+        // Made against a declared column, the query searches only it.
+        if let Expr::Column(name) = &**left {
+            if let Some(column) = columns.iter().position(|column| column.eq_ignore_ascii_case(name)) {
+                for phrase in &mut phrases[first..] {
+                    phrase.columns = Some(phrase.columns.take().map_or(vec![column], |columns| columns.into_iter().filter(|c| *c == column).collect()));
+                }
+            }
+        }
+        query = combine(Query::And, query, term_query);
+    }
+    Ok((query, phrases))
+}
+
+/// The rows the phrase is found in, with the number of times it is in each column.
+fn phrase_hits(pager: &Pager, tree: &IndexTree, phrase: &Phrase, num_columns: usize) -> BTreeMap<i64, Vec<u64>> {
+    // The positions of each term of the phrase, by row and column.
+    let positions: Vec<BTreeMap<(i64, usize), BTreeSet<u64>>> = phrase.terms.iter()
+        .map(|(term, prefix)| {
+            let lower = [Value::Text(term.clone())];
+            let upper = [Value::Text(if *prefix { format!("{}\u{10FFFF}", term) } else { term.clone() })];
+            let mut positions: BTreeMap<(i64, usize), BTreeSet<u64>> = BTreeMap::new();
+            for record in tree.records(pager, Bound::Included(&lower), Bound::Included(&upper)) {
+                let key = (record[1].to_integer().unwrap_or(0), record[2].to_integer().unwrap_or(0) as usize);
+                if let Value::Blob(blob) = &record[3] {
+                    positions.entry(key).or_default().extend(varints(blob));
+                }
+            }
+            positions
+        })
+        .collect();
+    let mut hits = BTreeMap::new();
+    for (&(rowid, column), starts) in &positions[0] {
+        if column >= num_columns || phrase.columns.as_ref().is_some_and(|columns| !columns.contains(&column)) {
+            continue;
+        }
+        let found = starts.iter()
+            .filter(|start| {
+                positions[1..].iter().zip(1..).all(|(term, offset)| {
+                    term.get(&(rowid, column)).is_some_and(|positions| positions.contains(&(*start + offset)))
+                })
+            })
+            .count();
+        if found > 0 {
+            hits.entry(rowid).or_insert_with(|| vec![0; num_columns])[column] += found as u64;
+        }
+    }
+    hits
+}
+
+/// The rows of a full-text table that match every MATCH term the plan took, in rowid order,
+/// each with the values of its hidden table-named and rank columns. The first is a blob of
+/// varints holding what bm25() scores from: the number of phrases, of columns and of rows,
+/// the tokens in each column of all rows, then of this row, then for each phrase the number
+/// of rows it is in and the number of times it is in each column of this row.
+pub fn search(pager: &Pager, schema: &Schema, table_def: &TableDef, matches: &[Expr]) -> Result<Vec<(i64, Vec<Value>)>, ExecuteResult> {
+    let (query, phrases) = parse_matches(table_def, matches)?;
+    let (terms_tree, sizes_tree) = shadow_trees(schema, &table_def.name)?;
+    let num_columns = table_def.fts.as_ref().unwrap().unindexed.len();
+    let hits: Vec<BTreeMap<i64, Vec<u64>>> = phrases.iter().map(|phrase| phrase_hits(pager, &terms_tree, phrase, num_columns)).collect();
+    let rowids = query.map(|query| query.rows(&hits)).unwrap_or_default();
+
+    let sizes: BTreeMap<i64, Vec<u64>> = sizes_tree.records(pager, Bound::Unbounded, Bound::Unbounded).into_iter()
+        .map(|record| {
+            let sizes = match &record[1] {
+                Value::Blob(blob) => varints(blob),
+                _ => Vec::new(),
+            };
+            (record[0].to_integer().unwrap_or(0), sizes)
+        })
+        .collect();
+    let mut totals = vec![0; num_columns];
+    for row_sizes in sizes.values() {
+        for (total, size) in totals.iter_mut().zip(row_sizes) {
+            *total += size;
+        }
+    }
+    Ok(rowids.into_iter()
+        .map(|rowid| {
+            let mut info = vec![phrases.len() as u64, num_columns as u64, sizes.len() as u64];
+            info.extend(&totals);
+            let row_sizes = sizes.get(&rowid).cloned().unwrap_or_default();
+            info.extend((0..num_columns).map(|column| row_sizes.get(column).copied().unwrap_or(0)));
+            for phrase_hits in &hits {
+                info.push(phrase_hits.len() as u64);
+                let row_hits = phrase_hits.get(&rowid);
+                info.extend((0..num_columns).map(|column| row_hits.map_or(0, |hits| hits[column])));
+            }
+            let rank = bm25_score(&info, &[]).unwrap_or(0.0);
+            (rowid, vec![varint_blob(info), Value::Real(rank)])
+        })
+        .collect())
+}
+
+/// The bm25() of a row from what the search found, or None if that is not what it holds.
+fn bm25_score(info: &[u64], weights: &[f64]) -> Option<f64> {
+    let (num_phrases, num_columns, num_rows) = match info {
+        [num_phrases, num_columns, num_rows, ..] => (*num_phrases as usize, *num_columns as usize, *num_rows as f64),
+        _ => return None,
+    };
+    if info.len() != 3 + 2 * num_columns + num_phrases * (num_columns + 1) {
+        return None;
+    }
+    let total: u64 = info[3..3 + num_columns].iter().sum();
+    let size: u64 = info[3 + num_columns..3 + 2 * num_columns].iter().sum();
+    let average_size = total as f64 / num_rows;
+    let mut score = 0.0;
+    for phrase in info[3 + 2 * num_columns..].chunks(num_columns + 1) {
+        let rows_hit = phrase[0] as f64;
+        // A phrase in over half the rows tells little, but never counts against a row.
+        let mut idf = ((num_rows - rows_hit + 0.5) / (rows_hit + 0.5)).ln();
+        if idf <= 0.0 {
+            idf = 1e-6;
+        }
+        let frequency: f64 = phrase[1..].iter()
+            .enumerate()
+            .map(|(column, hits)| weights.get(column).copied().unwrap_or(1.0) * *hits as f64)
+            .sum();
+        score += idf * frequency * (BM25_K1 + 1.0)
+            / (frequency + BM25_K1 * (1.0 - BM25_B + BM25_B * size as f64 / average_size));
+    }
+    Some(-score)
+}
+
+/// `bm25(table [, weight ...])`: how well the row matches the full-text query, by Okapi BM25
+/// with the weights given to its columns in order, 1.0 for any left out. Better matches score
+/// lower, so they sort first. A row no query was run for scores 0.
+pub fn bm25(args: &[Value]) -> Result<Value, ExecuteResult> {
+    let weights: Vec<f64> = args[1..].iter().map(|weight| weight.to_real().unwrap_or(0.0)).collect();
+    let score = match &args[0] {
+        Value::Null => Some(0.0),
+        Value::Blob(info) => bm25_score(&varints(info), &weights),
+        _ => None,
+    };
+    score.map(Value::Real)
+        .ok_or_else(|| ExecuteFullTextError(String::from("unable to use function bm25 in the requested context")))
+}
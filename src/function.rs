@@ -0,0 +1,337 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::datetime;
+use crate::fts;
+use crate::json;
+use crate::printf;
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::*;
+use crate::value::Value;
+
+/// A scalar function registered by `Connection::create_scalar_function`. An error it returns
+/// fails the statement with its message.
+pub type ScalarFunc = Rc<dyn Fn(&[Value]) -> Result<Value, String>>;
+
+/// The running state of a registered aggregate over one group, or over the frame of a window.
+pub trait AggregateState {
+    /// Fold in the arguments of one row, NULLs included.
+    fn step(&mut self, args: &[Value]) -> Result<(), String>;
+    /// The aggregate of the rows folded in so far. A window asks for it after every row.
+    fn finalize(&self) -> Result<Value, String>;
+}
+
+/// The state of an aggregate registered as a pair of closures, over a state of type `T`.
+struct ClosureAggregate<T, S, F> {
+    state: T,
+    step: Rc<S>,
+    finalize: Rc<F>,
+}
+
+impl<T, S, F> AggregateState for ClosureAggregate<T, S, F>
+where
+    S: Fn(&mut T, &[Value]) -> Result<(), String>,
+    F: Fn(&T) -> Result<Value, String>,
+{
+    fn step(&mut self, args: &[Value]) -> Result<(), String> {
+        (self.step)(&mut self.state, args)
+    }
+
+    fn finalize(&self) -> Result<Value, String> {
+        (self.finalize)(&self.state)
+    }
+}
+
+/// Makes a fresh state for each group a registered aggregate runs over.
+pub type AggregateFactory = Rc<dyn Fn() -> Box<dyn AggregateState>>;
+
+/// The factory of an aggregate whose state starts as `T::default()`, is folded by `step` and
+/// read by `finalize`.
+pub fn aggregate_factory<T, S, F>(step: S, finalize: F) -> AggregateFactory
+where
+    T: Default + 'static,
+    S: Fn(&mut T, &[Value]) -> Result<(), String> + 'static,
+    F: Fn(&T) -> Result<Value, String> + 'static,
+{
+    let (step, finalize) = (Rc::new(step), Rc::new(finalize));
+    Rc::new(move || Box::new(ClosureAggregate { state: T::default(), step: step.clone(), finalize: finalize.clone() }))
+}
+
+#[derive(Clone)]
+enum UserFunction {
+    Scalar(ScalarFunc),
+    Aggregate(AggregateFactory),
+}
+
+/// A function registered on a connection, with the number of arguments it takes, or None for
+/// any number. A call to it is bound to it before the statement runs.
+#[derive(Clone)]
+pub struct Registration {
+    num_args: Option<usize>,
+    function: UserFunction,
+}
+
+impl Registration {
+    /// The factory and argument counts of a registered aggregate.
+    pub fn aggregate(&self) -> Option<(AggregateFactory, (usize, usize))> {
+        let arity = self.num_args.map_or((0, usize::MAX), |num_args| (num_args, num_args));
+        match &self.function {
+            UserFunction::Aggregate(factory) => Some((factory.clone(), arity)),
+            UserFunction::Scalar(_) => None,
+        }
+    }
+}
+
+/// Registrations are the same if they are of the same closure.
+impl PartialEq for Registration {
+    fn eq(&self, other: &Registration) -> bool {
+        match (&self.function, &other.function) {
+            (UserFunction::Scalar(a), UserFunction::Scalar(b)) => Rc::ptr_eq(a, b),
+            (UserFunction::Aggregate(a), UserFunction::Aggregate(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.function {
+            UserFunction::Scalar(_) => "scalar",
+            UserFunction::Aggregate(_) => "aggregate",
+        };
+        write!(f, "Registration({}, {:?})", kind, self.num_args)
+    }
+}
+
+/// The functions registered on a connection by `Connection::create_scalar_function` and
+/// `Connection::create_aggregate_function`, by lowercased name, and the generator behind its
+/// `random()`.
+pub struct Functions {
+    registered: HashMap<String, Registration>,
+    random: Registration,
+}
+
+impl Default for Functions {
+    fn default() -> Functions {
+        // Seeded from the clock on first use.
+        let state = Cell::new(0);
+        let generator = move |_: &[Value]| Ok(Value::Integer(random(&state)));
+        Functions {
+            registered: HashMap::new(),
+            random: Registration { num_args: Some(0), function: UserFunction::Scalar(Rc::new(generator)) },
+        }
+    }
+}
+
+impl Functions {
+    /// Register a function under `name`, in place of any scalar or aggregate registered before
+    /// under it. A negative `num_args` takes any number of arguments.
+    fn register(&mut self, name: &str, num_args: i32, function: UserFunction) {
+        let registration = Registration { num_args: usize::try_from(num_args).ok(), function };
+        self.registered.insert(name.to_lowercase(), registration);
+    }
+
+    pub fn register_scalar(&mut self, name: &str, num_args: i32, function: ScalarFunc) {
+        self.register(name, num_args, UserFunction::Scalar(function));
+    }
+
+    pub fn register_aggregate(&mut self, name: &str, num_args: i32, factory: AggregateFactory) {
+        self.register(name, num_args, UserFunction::Aggregate(factory));
+    }
+
+    /// What a call to `name` is bound to: the function registered under it, unless a built-in
+    /// function has the name, or for `random()` the connection's generator.
+    pub fn find(&self, name: &str) -> Option<Registration> {
+        match name.to_lowercase().as_str() {
+            "random" => Some(self.random.clone()),
+            name if builtin_arity(name).is_some() => None,
+            name => self.registered.get(name).cloned(),
+        }
+    }
+}
+
+/// The least and most arguments of each built-in scalar function.
+fn builtin_arity(name: &str) -> Option<(usize, usize)> {
+    Some(match name.to_lowercase().as_str() {
+        "length" | "lower" | "upper" | "typeof" | "hex" | "abs" | "json" => (1, 1),
+        "substr" | "substring" => (2, 3),
+        "trim" | "ltrim" | "rtrim" | "round" => (1, 2),
+        "instr" | "ifnull" | "nullif" => (2, 2),
+        "replace" => (3, 3),
+        "coalesce" => (2, usize::MAX),
+        "random" => (0, 0),
+        "printf" | "format" | "date" | "time" | "datetime" | "julianday" | "json_array" | "json_object" => (0, usize::MAX),
+        "strftime" | "json_extract" | "json_set" | "bm25" => (1, usize::MAX),
+        _ => return None,
+    })
+}
+
+/// The next number of a xorshift64* generator of that state: not fit for secrets, as SQLite's
+/// is not either.
+fn random(state: &Cell<u64>) -> i64 {
+    let mut x = state.get();
+    if x == 0 {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        x = (since_epoch.as_nanos() as u64) | 1;
+    }
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    state.set(x);
+    x.wrapping_mul(0x2545_f491_4f6c_dd1d) as i64
+}
+
+/// `substr(value, start, length)`, over characters, or bytes for a blob: `start` counts from 1,
+/// or back from the end when negative, and a negative `length` takes that many before `start`.
+fn substr<T: Clone>(items: &[T], start: i64, length: Option<i64>) -> Vec<T> {
+    let len = items.len() as i64;
+    let (mut start, mut length) = (start, length.unwrap_or(i64::MAX));
+    let before = length < 0;
+    if before {
+        length = length.saturating_neg();
+    }
+    if start < 0 {
+        start += len;
+        if start < 0 {
+            length = length.saturating_add(start).max(0);
+            start = 0;
+        }
+    } else if start > 0 {
+        start -= 1;
+    } else if length > 0 {
+        // Position 0 is just before the first, so it takes one fewer.
+        length -= 1;
+    }
+    if before {
+        start -= length;
+        if start < 0 {
+            length += start;
+            start = 0;
+        }
+    }
+    let end = start.saturating_add(length).min(len);
+    if start >= end { Vec::new() } else { items[start as usize..end as usize].to_vec() }
+}
+
+/// `trim(text, characters)` and its one-sided forms, taking off spaces if no characters are
+/// given.
+fn trim(name: &str, args: &[Value]) -> Value {
+    let text = args[0].to_string();
+    let characters: Vec<char> = args.get(1).map_or(vec![' '], |characters| characters.to_string().chars().collect());
+    let trimmed = match name {
+        "ltrim" => text.trim_start_matches(characters.as_slice()),
+        "rtrim" => text.trim_end_matches(characters.as_slice()),
+        _ => text.trim_matches(characters.as_slice()),
+    };
+    Value::Text(String::from(trimmed))
+}
+
+/// `round(number, digits)`: a real, rounded half away from zero to between 0 and 30 digits.
+fn round(args: &[Value]) -> Value {
+    let real = args[0].to_real().unwrap_or(0.0);
+    let digits = args.get(1).and_then(Value::to_integer).unwrap_or(0).clamp(0, 30) as usize;
+    // Past 2^52 a real has no fraction left to round.
+    if !real.is_finite() || real.abs() >= 4503599627370496.0 {
+        return Value::Real(real);
+    }
+    let rounded: f64 = printf::fixed(real.abs(), digits, false).parse().unwrap_or(0.0);
+    Value::Real(if real < 0.0 { -rounded } else { rounded })
+}
+
+/// Call a built-in scalar function, or return None if there is none of that name.
+fn call_builtin(name: &str, args: &[Value], json_args: &[bool]) -> Option<Result<Value, ExecuteResult>> {
+    let (min_args, max_args) = builtin_arity(name)?;
+    if args.len() < min_args || args.len() > max_args {
+        return Some(Err(ExecuteWrongArgumentCount(String::from(name))));
+    }
+    let name = name.to_lowercase();
+    if let Some(result) = json::call(&name, args, json_args) {
+        return Some(result);
+    }
+    if name == "bm25" {
+        return Some(fts::bm25(args));
+    }
+    let value = match (name.as_str(), args) {
+        ("typeof", [value]) => Value::Text(String::from(match value {
+            Value::Null => "null",
+            Value::Integer(_) => "integer",
+            Value::Real(_) => "real",
+            Value::Text(_) => "text",
+            Value::Blob(_) => "blob",
+        })),
+        ("coalesce" | "ifnull", args) => args.iter().find(|arg| !arg.is_null()).cloned().unwrap_or(Value::Null),
+        ("nullif", [a, b]) => if a == b { Value::Null } else { a.clone() },
+        // Only a call bound to the connection's generator has one to draw from.
+        ("random", []) => return None,
+        ("hex", [value]) => {
+            let bytes = match value {
+                Value::Blob(bytes) => bytes.clone(),
+                value => value.to_string().into_bytes(),
+            };
+            Value::Text(bytes.iter().map(|byte| format!("{:02X}", byte)).collect())
+        }
+        ("printf" | "format", []) | ("printf" | "format", [Value::Null, ..]) => Value::Null,
+        ("printf" | "format", [format, args @ ..]) => Value::Text(printf::format(&format.to_string(), args)),
+        ("date", args) => datetime::date(args),
+        ("time", args) => datetime::time(args),
+        ("datetime", args) => datetime::datetime(args),
+        ("julianday", args) => datetime::julianday(args),
+        ("strftime", args) => datetime::strftime(args),
+        // The rest give NULL for any NULL argument.
+        _ if args.iter().any(Value::is_null) => Value::Null,
+        ("length", [Value::Blob(bytes)]) => Value::Integer(bytes.len() as i64),
+        ("length", [value]) => Value::Integer(value.to_string().chars().count() as i64),
+        ("lower", [value]) => Value::Text(value.to_string().to_ascii_lowercase()),
+        ("upper", [value]) => Value::Text(value.to_string().to_ascii_uppercase()),
+        ("abs", [Value::Integer(i)]) => match i.checked_abs() {
+            Some(i) => Value::Integer(i),
+            None => return Some(Err(ExecuteIntegerOverflow)),
+        },
+        ("abs", [value]) => Value::Real(value.to_real().unwrap_or(0.0).abs()),
+        ("round", args) => round(args),
+        ("trim" | "ltrim" | "rtrim", args) => trim(&name, args),
+        ("substr" | "substring", [value, start, length @ ..]) => {
+            let start = start.to_integer().unwrap_or(0);
+            let length = length.first().and_then(Value::to_integer);
+            match value {
+                Value::Blob(bytes) => Value::Blob(substr(bytes, start, length)),
+                value => Value::Text(substr(&value.to_string().chars().collect::<Vec<char>>(), start, length).into_iter().collect()),
+            }
+        }
+        ("instr", [haystack, needle]) => match (haystack, needle) {
+            (Value::Blob(haystack), Value::Blob(needle)) => {
+                let found = if needle.is_empty() { Some(0) } else { haystack.windows(needle.len()).position(|window| window == needle) };
+                Value::Integer(found.map_or(0, |i| i as i64 + 1))
+            }
+            (haystack, needle) => {
+                let haystack = haystack.to_string();
+                let found = haystack.find(&needle.to_string());
+                Value::Integer(found.map_or(0, |i| haystack[..i].chars().count() as i64 + 1))
+            }
+        },
+        ("replace", [text, from, to]) => {
+            if from.to_string().is_empty() {
+                text.clone()
+            } else {
+                Value::Text(text.to_string().replace(&from.to_string(), &to.to_string()))
+            }
+        }
+        _ => unreachable!(),
+    };
+    Some(Ok(value))
+}
+
+/// Call the scalar function of that name, which must take that many arguments: the one the
+/// call is bound to, else a built-in one. `json_args` flags the arguments that came from JSON
+/// functions, which the JSON functions take for JSON.
+pub fn call_scalar(name: &str, registered: Option<&Registration>, args: &[Value], json_args: &[bool]) -> Result<Value, ExecuteResult> {
+    match registered {
+        Some(Registration { num_args: Some(num_args), function: UserFunction::Scalar(_) }) if *num_args != args.len() => {
+            Err(ExecuteWrongArgumentCount(String::from(name)))
+        }
+        Some(Registration { function: UserFunction::Scalar(function), .. }) => function(args).map_err(ExecuteFunctionError),
+        _ => call_builtin(name, args, json_args).unwrap_or_else(|| Err(ExecuteNoSuchFunction(String::from(name)))),
+    }
+}
@@ -0,0 +1,253 @@
+use std::cmp::Ordering;
+use std::ops::Bound;
+use crate::collation::KeyOrder;
+use crate::constant::*;
+use crate::page::{cells_fit, split_cell};
+use crate::pager::Pager;
+use crate::record::{decode_record, encode_record};
+use crate::value::Value;
+
+/// Key of an index cell: the indexed column value, then the rowid of the table row. A cell
+/// holds it as a record of the two, as SQLite stores index entries.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct IndexKey {
+    pub value: Value,
+    pub rowid: i64,
+}
+
+impl IndexKey {
+    fn record(&self) -> Vec<Value> {
+        vec![self.value.clone(), Value::Integer(self.rowid)]
+    }
+}
+
+/// The key slot of a cell holding the record, spilling onto overflow pages when it is long.
+fn key_slot(pager: &mut Pager, record: &[Value]) -> Vec<u8> {
+    pager.write_payload(&encode_record(record))
+}
+
+/// The record a cell's key slot holds.
+fn slot_record(pager: &Pager, slot: &[u8]) -> Vec<Value> {
+    decode_record(&pager.read_payload(slot))
+}
+
+/// A B-tree of records, sharing the node headers of the table tree, in the tree's order.
+/// A secondary index holds `IndexKey`s; a WITHOUT ROWID table holds its rows, primary key first.
+/// Leaf cells hold just the record; each internal key is the max record of the child to its left,
+/// kept as a copy with overflow pages of its own.
+#[derive(Clone)]
+pub struct IndexTree {
+    pub root_page_num: usize,
+    pub order: KeyOrder,
+    /// How many leading values of a record are its key, which alone order and find it, or None
+    /// if all are. The rest of a WITHOUT ROWID row can change without the record moving, which
+    /// leaves the copies internal nodes keep of it stale past the key.
+    pub key_len: Option<usize>,
+}
+
+impl IndexTree {
+    pub fn create(pager: &mut Pager, order: KeyOrder) -> IndexTree {
+        let root_page_num = pager.allocate_page();
+        let root = pager.get_page(root_page_num);
+        root.initialize_leaf_node();
+        root.set_node_root(true);
+        IndexTree { root_page_num, order, key_len: None }
+    }
+
+    /// Compare the keys of two records, or of a record and a key or the start of one.
+    pub fn compare(&self, a: &[Value], b: &[Value]) -> Ordering {
+        let key = |record: &[Value]| self.key_len.map_or(record.len(), |len| len.min(record.len()));
+        self.order.compare(&a[..key(a)], &b[..key(b)])
+    }
+
+    /// Descend to the leaf that should hold the key, remembering (page, child index) on the way.
+    fn find_leaf(&self, pager: &Pager, key: &[Value]) -> (usize, Vec<(usize, usize)>) {
+        let mut path = Vec::new();
+        let mut page_num = self.root_page_num;
+        loop {
+            let page = pager.get_page_view(page_num).unwrap();
+            if page.is_leaf_node() {
+                return (page_num, path);
+            }
+            let cells = page.index_internal_node_cells();
+            let child_index = cells.partition_point(|(_, slot)| self.compare(&slot_record(pager, slot), key) == Ordering::Less);
+            path.push((page_num, child_index));
+            page_num = if child_index == cells.len() {
+                page.get_internal_node_right_child()
+            } else {
+                cells[child_index].0
+            };
+        }
+    }
+
+    pub fn insert(&self, pager: &mut Pager, key: IndexKey) {
+        self.insert_record(pager, key.record());
+    }
+
+    pub fn insert_record(&self, pager: &mut Pager, record: Vec<Value>) {
+        let (mut page_num, mut path) = self.find_leaf(pager, &record);
+        let mut keys = pager.get_page_view(page_num).unwrap().index_leaf_node_keys();
+        let cell_num = keys.partition_point(|slot| self.compare(&slot_record(pager, slot), &record) == Ordering::Less);
+        let slot = key_slot(pager, &record);
+        keys.insert(cell_num, slot);
+        let sizes: Vec<usize> = keys.iter().map(Vec::len).collect();
+        if cells_fit(sizes.iter().copied()) {
+            pager.get_page(page_num).set_index_leaf_node_keys(&keys);
+            return;
+        }
+
+        if page_num == self.root_page_num {
+            page_num = self.grow_root(pager);
+            path = vec![(self.root_page_num, 0)];
+        }
+        let right_keys = keys.split_off(split_cell(&sizes) + 1);
+        let right_page_num = pager.allocate_page();
+        let old_next_page_num = pager.get_page_view(page_num).unwrap().get_leaf_node_next_leaf();
+        {
+            let right = pager.get_page(right_page_num);
+            right.initialize_leaf_node();
+            right.set_leaf_node_next_leaf(old_next_page_num);
+            right.set_index_leaf_node_keys(&right_keys);
+        }
+        {
+            let left = pager.get_page(page_num);
+            left.set_leaf_node_next_leaf(right_page_num);
+            left.set_index_leaf_node_keys(&keys);
+        }
+        let separator = slot_record(pager, keys.last().unwrap());
+        let separator = key_slot(pager, &separator);
+        self.insert_into_parent(pager, path, page_num, separator, right_page_num);
+    }
+
+    /// Move the root's content into a new child, leaving the root as an internal node with
+    /// only a right child. The root page number of the tree never changes.
+    fn grow_root(&self, pager: &mut Pager) -> usize {
+        let child_page_num = pager.allocate_page();
+        let root_buf = pager.get_page_view(self.root_page_num).unwrap().buf;
+        {
+            let child = pager.get_page(child_page_num);
+            child.buf = root_buf;
+            child.set_node_root(false);
+        }
+        let root = pager.get_page(self.root_page_num);
+        root.initialize_internal_node();
+        root.set_node_root(true);
+        root.set_internal_node_right_child(child_page_num);
+        child_page_num
+    }
+
+    /// Record in the parent that `left_page_num` was split, keys up to `separator` staying on the
+    /// left and the rest moving to `right_page_num`. Splits the parent in turn when it overflows.
+    fn insert_into_parent(&self, pager: &mut Pager, mut path: Vec<(usize, usize)>,
+                          left_page_num: usize, separator: Vec<u8>, right_page_num: usize) {
+        let (mut parent_page_num, child_index) = path.pop().unwrap();
+        let parent = pager.get_page_view(parent_page_num).unwrap();
+        let mut cells = parent.index_internal_node_cells();
+        let mut right_child = parent.get_internal_node_right_child();
+        cells.insert(child_index, (left_page_num, separator));
+        if child_index + 1 == cells.len() {
+            right_child = right_page_num;
+        } else {
+            cells[child_index + 1].0 = right_page_num;
+        }
+        let sizes: Vec<usize> = cells.iter().map(|(_, slot)| INTERNAL_NODE_CHILD_SIZE + slot.len()).collect();
+        if cells_fit(sizes.iter().copied()) {
+            let parent = pager.get_page(parent_page_num);
+            parent.set_index_internal_node_cells(&cells);
+            parent.set_internal_node_right_child(right_child);
+            return;
+        }
+
+        if parent_page_num == self.root_page_num {
+            parent_page_num = self.grow_root(pager);
+            path = vec![(self.root_page_num, 0)];
+        }
+        let right_cells = cells.split_off(split_cell(&sizes) + 1);
+        let (middle_child, promoted) = cells.pop().unwrap();
+        let new_page_num = pager.allocate_page();
+        {
+            let new_node = pager.get_page(new_page_num);
+            new_node.initialize_internal_node();
+            new_node.set_index_internal_node_cells(&right_cells);
+            new_node.set_internal_node_right_child(right_child);
+        }
+        {
+            let parent = pager.get_page(parent_page_num);
+            parent.set_index_internal_node_cells(&cells);
+            parent.set_internal_node_right_child(middle_child);
+        }
+        self.insert_into_parent(pager, path, parent_page_num, promoted, new_page_num);
+    }
+
+    pub fn delete(&self, pager: &mut Pager, key: &IndexKey) -> bool {
+        self.delete_record(pager, &key.record())
+    }
+
+    /// Remove the record the key leads from its leaf. Leaves are never merged, so a leaf may
+    /// end up empty.
+    pub fn delete_record(&self, pager: &mut Pager, key: &[Value]) -> bool {
+        let (page_num, _) = self.find_leaf(pager, key);
+        let mut keys = pager.get_page_view(page_num).unwrap().index_leaf_node_keys();
+        match keys.binary_search_by(|slot| self.compare(&slot_record(pager, slot), key)) {
+            Ok(cell_num) => {
+                let slot = keys.remove(cell_num);
+                pager.free_payload(&slot);
+                pager.get_page(page_num).set_index_leaf_node_keys(&keys);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Return the rowids of every key whose value lies within the bounds, in index order.
+    pub fn scan(&self, pager: &Pager, lower: Bound<&Value>, upper: Bound<&Value>) -> Vec<i64> {
+        self.records(pager, lower.map(std::slice::from_ref), upper.map(std::slice::from_ref)).into_iter()
+            .map(|record| record[1].to_integer().unwrap())
+            .collect()
+    }
+
+    /// Return the record the key leads, if the tree holds one.
+    pub fn find_record(&self, pager: &Pager, key: &[Value]) -> Option<Vec<Value>> {
+        self.records(pager, Bound::Included(key), Bound::Included(key)).into_iter().next()
+    }
+
+    /// Return every record between the bounds, in tree order. A bound compares only the
+    /// values it has, so it takes in or leaves out every record that starts with them.
+    pub fn records(&self, pager: &Pager, lower: Bound<&[Value]>, upper: Bound<&[Value]>) -> Vec<Vec<Value>> {
+        let (mut page_num, mut cell_num) = match lower {
+            Bound::Included(key) | Bound::Excluded(key) => {
+                let (page_num, _) = self.find_leaf(pager, key);
+                let keys = pager.get_page_view(page_num).unwrap().index_leaf_node_keys();
+                (page_num, keys.partition_point(|slot| self.compare(&slot_record(pager, slot), key) == Ordering::Less))
+            }
+            Bound::Unbounded => (pager.get_leftmost_leaf_page_num(self.root_page_num), 0),
+        };
+
+        let mut records = Vec::new();
+        loop {
+            let page = pager.get_page_view(page_num).unwrap();
+            for slot in page.index_leaf_node_keys().into_iter().skip(cell_num) {
+                let record = slot_record(pager, &slot);
+                if let Bound::Excluded(key) = lower {
+                    if self.compare(&record, key) == Ordering::Equal {
+                        continue;
+                    }
+                }
+                let past_upper = match upper {
+                    Bound::Included(key) => self.compare(&record, key) == Ordering::Greater,
+                    Bound::Excluded(key) => self.compare(&record, key) != Ordering::Less,
+                    Bound::Unbounded => false,
+                };
+                if past_upper {
+                    return records;
+                }
+                records.push(record);
+            }
+            page_num = page.get_leaf_node_next_leaf();
+            cell_num = 0;
+            if page_num == 0 {
+                return records;
+            }
+        }
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::iter;
+use std::ops::Bound;
+use crate::expr::Expr;
+use crate::json;
+use crate::plan::{JoinStrategy, QueryPlan};
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::*;
+use crate::schema::{ColumnDef, Schema, TableDef};
+use crate::statement::{for_each_row, index_rowids, read_row, result_columns, row_matches, JoinKind, ResultColumn, Statement};
+use crate::subquery::{correlated_subqueries, subquery_value};
+use crate::table::Table;
+use crate::value::Value;
+
+/// One table of a FROM clause, under the name its columns are qualified by.
+pub struct JoinTable {
+    /// The alias, else the table name.
+    pub name: String,
+    pub def: TableDef,
+    /// How the table joins onto the ones before it; the first table is `Inner`.
+    pub kind: JoinKind,
+    /// The arguments of a table-valued function, whose rows they give for each row of the
+    /// tables before it.
+    pub args: Option<Vec<Expr>>,
+    pub on: Option<Expr>,
+}
+
+/// The table a FROM clause names: a table of the schema, or for a table-valued function a
+/// table of its columns, whose rows `function_rows` gives.
+pub fn from_table(schema: &Schema, name: &str, args: Option<&[Expr]>) -> Result<TableDef, ExecuteResult> {
+    match args {
+        Some(args) => json::table(name, args.len()).ok_or_else(|| ExecuteNoSuchTable(String::from(name)))?,
+        None => {
+            let def = schema.find_table(name).ok_or_else(|| ExecuteNoSuchTable(String::from(name)))?;
+            schema.check_collations(def)?;
+            Ok(def.clone())
+        }
+    }
+}
+
+/// The rows of a table-valued function, `column` looking up the columns its arguments read.
+pub fn function_rows(name: &str, args: &[Expr], column: &dyn Fn(&str) -> Option<Value>) -> Result<Vec<Vec<Value>>, ExecuteResult> {
+    let values = args.iter().map(|arg| arg.evaluate(column)).collect::<Result<Vec<Value>, ExecuteResult>>()?;
+    json::table_rows(name, &values)
+}
+
+/// The tables a statement reads. A joined row holds every table's columns in turn, and `def`
+/// describes it as a single table whose columns are named `table.column`.
+pub struct JoinScope {
+    pub tables: Vec<JoinTable>,
+    pub def: TableDef,
+}
+
+impl JoinScope {
+    /// The scope of the statement's FROM clause; a SELECT without one reads no tables.
+    pub fn new(statement: &Statement, schema: &Schema) -> Result<JoinScope, ExecuteResult> {
+        let first = (JoinKind::Inner, &statement.table_name, &statement.table_alias, &statement.table_args, None);
+        let joined = statement.joins.iter().map(|join| (join.kind, &join.table_name, &join.alias, &join.args, join.on.as_ref()));
+        let mut tables = Vec::new();
+        let mut columns = Vec::new();
+        let from = iter::once(first).filter(|(_, table_name, _, _, _)| !table_name.is_empty()).chain(joined);
+        for (kind, table_name, alias, args, on) in from {
+            let def = from_table(schema, table_name, args.as_deref())?;
+            let name = alias.clone().unwrap_or_else(|| def.name.to_lowercase());
+            columns.extend(def.columns.iter().map(|column| ColumnDef {
+                name: format!("{}.{}", name, column.name),
+                primary_key: false,
+                unique: false,
+                ..column.clone()
+            }));
+            tables.push(JoinTable { name, def, kind, args: args.clone(), on: on.cloned() });
+        }
+        let def = TableDef {
+            name: String::new(),
+            columns,
+            checks: Vec::new(),
+            foreign_keys: Vec::new(),
+            root_page_num: 0,
+            sql: String::new(),
+            autoincrement: false,
+            without_rowid: None,
+            rows: None,
+            recursive: None,
+            fts: None,
+        };
+        Ok(JoinScope { tables, def })
+    }
+
+    /// The definition of the rows the statement reads, as its resolved column names find them:
+    /// a single table's own, the joined row's `def` for a join, and none without FROM.
+    pub fn row_def(&self) -> Option<&TableDef> {
+        match self.tables.as_slice() {
+            [] => None,
+            [table] => Some(&table.def),
+            _ => Some(&self.def),
+        }
+    }
+
+    /// The table and column a column name refers to. A qualified name picks the table by
+    /// name; a bare name must belong to exactly one table.
+    pub fn find_column(&self, name: &str) -> Result<(usize, usize), ExecuteResult> {
+        let found: Vec<(usize, usize)> = match name.split_once('.') {
+            Some((table_name, column)) => self.tables.iter()
+                .enumerate()
+                .filter(|(_, table)| table.name.eq_ignore_ascii_case(table_name))
+                .filter_map(|(i, table)| table.def.column_index(column).map(|column| (i, column)))
+                .collect(),
+            None => self.tables.iter()
+                .enumerate()
+                .filter_map(|(i, table)| table.def.column_index(name).map(|column| (i, column)))
+                .collect(),
+        };
+        match found.as_slice() {
+            [found] => Ok(*found),
+            [] => Err(ExecuteNoSuchColumn(String::from(name))),
+            _ => Err(ExecuteAmbiguousColumn(String::from(name))),
+        }
+    }
+
+    /// The name a column reference resolves to: the bare column of a single table, else the
+    /// column qualified by its table, as named in `def`.
+    fn resolve_name(&self, name: &str) -> Result<String, ExecuteResult> {
+        let (table, column) = self.find_column(name)?;
+        let column = &self.tables[table].def.columns[column].name;
+        if self.tables.len() == 1 {
+            Ok(column.clone())
+        } else {
+            Ok(format!("{}.{}", self.tables[table].name, column))
+        }
+    }
+}
+
+/// Resolves the column names of one statement: against its own tables first, then against
+/// those of each statement it is nested in, innermost first.
+struct Resolver<'a> {
+    scope: &'a JoinScope,
+    outer: &'a [&'a JoinScope],
+    schema: &'a Schema,
+}
+
+impl Resolver<'_> {
+    /// Rewrite every column the expression names to its resolved name, a column of the
+    /// enclosing statement to an outer column, and resolve every subquery in turn. Names of
+    /// `aliases` that are no column are left for the result column they name. Literals
+    /// compared with a column then take its affinity, and the column brings its collation.
+    fn resolve_expr(&self, expr: &Expr, aliases: &[String]) -> Result<Expr, ExecuteResult> {
+        let mut names = HashMap::new();
+        for name in expr.columns() {
+            match self.scope.resolve_name(name) {
+                Ok(resolved) => {
+                    names.insert(String::from(name), Expr::Column(resolved));
+                }
+                Err(ExecuteNoSuchColumn(_)) if aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name)) => {}
+                Err(ExecuteNoSuchColumn(missing)) => {
+                    let outer = self.outer.iter().enumerate()
+                        .find_map(|(i, outer)| Some(Expr::OuterColumn { name: outer.resolve_name(name).ok()?, depth: i + 1 }));
+                    names.insert(String::from(name), outer.ok_or(ExecuteNoSuchColumn(missing))?);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        let resolved = expr.try_replace(&mut |expr| match expr {
+            Expr::Column(name) => Ok(names.get(name).cloned()),
+            Expr::Subquery(subquery) | Expr::Exists(subquery) | Expr::InSelect { subquery, .. } => {
+                let mut subquery = subquery.clone();
+                let scope = JoinScope::new(&subquery.select, self.schema)?;
+                let outer: Vec<&JoinScope> = iter::once(self.scope).chain(self.outer.iter().copied()).collect();
+                resolve_statement(&mut subquery.select, &scope, &outer, self.schema)?;
+                // A scalar subquery and IN compare against a single value per row.
+                let single_column = !matches!(expr, Expr::Exists(_));
+                let num_columns = result_columns(&subquery.select, scope.row_def())?.len();
+                if single_column && num_columns != 1 {
+                    return Err(ExecuteSubqueryColumns(num_columns));
+                }
+                Ok(Some(match expr {
+                    Expr::Subquery(_) => Expr::Subquery(subquery),
+                    Expr::Exists(_) => Expr::Exists(subquery),
+                    Expr::InSelect { operand, negated, .. } => Expr::InSelect {
+                        operand: Box::new(self.resolve_expr(operand, aliases)?),
+                        subquery,
+                        negated: *negated,
+                    },
+                    _ => unreachable!(),
+                }))
+            }
+            _ => Ok(None),
+        })?;
+        let row_def = self.scope.row_def();
+        let resolved = resolved.with_comparison_affinity(&|name| {
+            let def = row_def?;
+            def.column_index(name).map(|column| def.columns[column].column_type)
+        });
+        Ok(resolved.with_declared_collations(&|column| match column {
+            Expr::Column(name) => row_def?.column_collation(name),
+            Expr::OuterColumn { name, depth } => self.outer[depth - 1].row_def()?.column_collation(name),
+            _ => None,
+        }))
+    }
+}
+
+/// Resolve every column a statement names against the tables it reads, so the rest of the
+/// statement can look columns up by name alone: a single table's columns by their bare names,
+/// a join's by `table.column`. Fails on a column no table has, or one more than one table has.
+pub fn resolve_columns(statement: &mut Statement, scope: &JoinScope, schema: &Schema) -> Result<(), ExecuteResult> {
+    resolve_statement(statement, scope, &[], schema)
+}
+
+fn resolve_statement(statement: &mut Statement, scope: &JoinScope, outer: &[&JoinScope],
+                     schema: &Schema) -> Result<(), ExecuteResult> {
+    let resolver = Resolver { scope, outer, schema };
+    // ORDER BY, GROUP BY and HAVING may name result columns as well.
+    let mut aliases = Vec::new();
+    let mut result_names = Vec::new();
+    for column in &statement.result_columns {
+        match column {
+            ResultColumn::Expr { alias: Some(alias), .. } => {
+                aliases.push(alias.clone());
+                result_names.push(alias.clone());
+            }
+            ResultColumn::Expr { expr: Expr::Column(name), .. } => {
+                result_names.push(String::from(name.rsplit('.').next().unwrap()));
+            }
+            _ => {}
+        }
+    }
+    for column in &mut statement.result_columns {
+        match column {
+            ResultColumn::Expr { expr, .. } => *expr = resolver.resolve_expr(expr, &[])?,
+            ResultColumn::TableAll(name) if scope.tables.len() == 1 && scope.tables[0].name.eq_ignore_ascii_case(name) => {
+                *column = ResultColumn::All;
+            }
+            ResultColumn::TableAll(name) if !scope.tables.iter().any(|table| table.name.eq_ignore_ascii_case(name)) => {
+                return Err(ExecuteNoSuchTable(name.clone()));
+            }
+            _ => {}
+        }
+    }
+    if let Some(where_clause) = &statement.where_clause {
+        statement.where_clause = Some(resolver.resolve_expr(where_clause, &[])?);
+    }
+    for join in &mut statement.joins {
+        if let Some(on) = &join.on {
+            join.on = Some(resolver.resolve_expr(on, &[])?);
+        }
+    }
+    let joins_args = statement.joins.iter_mut().flat_map(|join| join.args.iter_mut().flatten());
+    for arg in statement.table_args.iter_mut().flatten().chain(joins_args) {
+        *arg = resolver.resolve_expr(arg, &[])?;
+    }
+    let names_result = |expr: &Expr| matches!(expr, Expr::Column(name) if result_names.iter().any(|result| result.eq_ignore_ascii_case(name)));
+    for term in &mut statement.group_by {
+        if !names_result(term) {
+            *term = resolver.resolve_expr(term, &aliases)?;
+        }
+    }
+    // The ORDER BY of a compound can only name the compound's result columns.
+    let compound = !statement.compound.is_empty();
+    for term in &mut statement.order_by {
+        if !compound && !names_result(&term.expr) {
+            term.expr = resolver.resolve_expr(&term.expr, &aliases)?;
+        }
+    }
+    if let Some(having) = &statement.having {
+        statement.having = Some(resolver.resolve_expr(having, &aliases)?);
+    }
+    for (_, expr) in &mut statement.assignments {
+        *expr = resolver.resolve_expr(expr, &[])?;
+    }
+    // LIMIT, OFFSET and VALUES read no row, but their subqueries still read tables.
+    for expr in statement.limit.iter_mut().chain(&mut statement.offset).chain(statement.values.iter_mut().flatten()) {
+        *expr = resolver.resolve_expr(expr, &[])?;
+    }
+    // Every other SELECT of a compound reads tables of its own, and returns as many columns.
+    if compound {
+        let num_columns = result_columns(statement, scope.row_def())?.len();
+        for (op, select) in &mut statement.compound {
+            let select_scope = JoinScope::new(select, schema)?;
+            resolve_statement(select, &select_scope, outer, schema)?;
+            if result_columns(select, select_scope.row_def())?.len() != num_columns {
+                return Err(ExecuteCompoundColumns(op.keyword()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Walks the joined rows depth first: for every row of a table, the rows of the next table
+/// that go with it.
+struct Join<'a> {
+    scope: &'a JoinScope,
+    strategies: &'a [JoinStrategy],
+    where_clause: Option<&'a Expr>,
+    /// The rows of each hash-joined table by join key, built on first use.
+    hash_tables: Vec<Option<HashMap<Value, Vec<Vec<Value>>>>>,
+    /// The error that stopped the join, if any.
+    result: ExecuteResult,
+}
+
+impl Join<'_> {
+    /// The rows of the table at `depth` that may join the row of the tables before it.
+    fn candidates(&mut self, table: &mut Table, depth: usize, row: &[Value]) -> Result<Vec<Vec<Value>>, ExecuteResult> {
+        let scope = self.scope;
+        let def = &scope.tables[depth].def;
+        // A table-valued function reads the row so far, which gives it rows of its own.
+        if let Some(args) = &scope.tables[depth].args {
+            let column = |name: &str| scope.def.column_index(name).and_then(|column| row.get(column).cloned());
+            return Ok(function_rows(&def.name, args, &column).unwrap_or_default());
+        }
+        // The value the outer row gives the join key, converted to the key column's type.
+        let probe = |outer: &Expr, column: &str| -> Option<Value> {
+            let value = outer.evaluate(&|name| scope.def.column_value(row, name)).ok()?;
+            if value.is_null() {
+                return None;
+            }
+            Some(def.columns[def.column_index(column).unwrap()].column_type.apply_affinity(value))
+        };
+        let scan = |table: &mut Table, plan: &QueryPlan| {
+            let mut rows = Vec::new();
+            match for_each_row(table, def, plan, None, |_, row| {
+                rows.push(row.to_vec());
+                true
+            }) {
+                ExecuteSuccess => Ok(rows),
+                err => Err(err),
+            }
+        };
+        match &self.strategies[depth] {
+            JoinStrategy::Outer(plan) => scan(table, plan),
+            JoinStrategy::NestedLoop => scan(table, &QueryPlan::FullScan),
+            JoinStrategy::PrimaryKey(outer) => {
+                let primary_key = &def.columns[def.primary_key().unwrap()].name;
+                match probe(outer, primary_key) {
+                    Some(Value::Integer(key)) => Ok(read_row(table, def, key)?.into_iter().collect()),
+                    _ => Ok(Vec::new()),
+                }
+            }
+            JoinStrategy::Index { index_name, column, outer } => match probe(outer, column) {
+                Some(value) => {
+                    let index = table.schema.find_index(index_name).unwrap();
+                    let mut rows = Vec::new();
+                    for rowid in index_rowids(table, index, Bound::Included(&value), Bound::Included(&value))? {
+                        rows.extend(read_row(table, def, rowid)?);
+                    }
+                    Ok(rows)
+                }
+                None => Ok(Vec::new()),
+            },
+            JoinStrategy::Hash { column, outer } => {
+                if self.hash_tables[depth].is_none() {
+                    let key_column = def.column_index(column).unwrap();
+                    let mut hash_table: HashMap<Value, Vec<Vec<Value>>> = HashMap::new();
+                    // NULL keys equal nothing, so they are left out.
+                    for row in scan(table, &QueryPlan::FullScan)?.into_iter().filter(|row| !row[key_column].is_null()) {
+                        hash_table.entry(row[key_column].clone()).or_default().push(row);
+                    }
+                    self.hash_tables[depth] = Some(hash_table);
+                }
+                let hash_table = self.hash_tables[depth].as_ref().unwrap();
+                Ok(probe(outer, column)
+                    .and_then(|value| hash_table.get(&value).cloned())
+                    .unwrap_or_default())
+            }
+        }
+    }
+
+    /// Whether the joined row so far satisfies the ON clause of the table at `depth`. A
+    /// correlated subquery in it runs for the row first.
+    fn on_matches(&self, table: &mut Table, depth: usize, row: &[Value]) -> Result<bool, ExecuteResult> {
+        let def = &self.scope.def;
+        let on = match &self.scope.tables[depth].on {
+            Some(on) => on,
+            None => return Ok(true),
+        };
+        let subqueries = correlated_subqueries(&[on]);
+        if subqueries.is_empty() {
+            return row_matches(def, row, Some(on));
+        }
+        let mut values: Vec<(String, Value)> = Vec::new();
+        let column = |values: &[(String, Value)], name: &str| {
+            def.column_index(name).and_then(|column| row.get(column).cloned())
+                .or_else(|| values.iter().find(|(subquery, _)| subquery == name).map(|(_, value)| value.clone()))
+        };
+        for subquery in subqueries {
+            let value = subquery_value(subquery, &|name| column(&values, name), table)?;
+            values.push((subquery.to_string(), value));
+        }
+        on.is_true(&|name| column(&values, name))
+    }
+
+    /// Extend the row with every matching row of the table at `depth` and the tables after it.
+    /// Returns false once `visit` has asked to stop.
+    fn join(&mut self, table: &mut Table, depth: usize, row: &mut Vec<Value>,
+            visit: &mut dyn FnMut(&mut Table, &[Value]) -> bool) -> bool {
+        let scope = self.scope;
+        if depth == scope.tables.len() {
+            return match row_matches(&scope.def, row, self.where_clause) {
+                Ok(matches) => !matches || visit(table, row),
+                Err(err) => {
+                    self.result = err;
+                    false
+                }
+            };
+        }
+        let join_table = &scope.tables[depth];
+        let offset = row.len();
+        let mut matched = false;
+        let candidates = match self.candidates(table, depth, row) {
+            Ok(candidates) => candidates,
+            Err(err) => {
+                self.result = err;
+                return false;
+            }
+        };
+        for candidate in candidates {
+            row.extend(candidate);
+            match self.on_matches(table, depth, row) {
+                Ok(true) => {
+                    matched = true;
+                    if !self.join(table, depth + 1, row, visit) {
+                        return false;
+                    }
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    self.result = err;
+                    return false;
+                }
+            }
+            row.truncate(offset);
+        }
+        if !matched && join_table.kind == JoinKind::Left {
+            row.extend(iter::repeat_n(Value::Null, join_table.def.columns.len()));
+            if !self.join(table, depth + 1, row, visit) {
+                return false;
+            }
+            row.truncate(offset);
+        }
+        true
+    }
+}
+
+/// Visit every joined row that satisfies the ON clauses and the WHERE clause, reaching each
+/// table the way its strategy says, stopping early once `visit` returns false.
+pub fn for_each_joined_row<F: FnMut(&mut Table, &[Value]) -> bool>(table: &mut Table, scope: &JoinScope, strategies: &[JoinStrategy],
+                                                                   where_clause: Option<&Expr>, mut visit: F) -> ExecuteResult {
+    let mut join = Join {
+        scope,
+        strategies,
+        where_clause,
+        hash_tables: scope.tables.iter().map(|_| None).collect(),
+        result: ExecuteSuccess,
+    };
+    join.join(table, 0, &mut Vec::new(), &mut visit);
+    join.result
+}
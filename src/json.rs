@@ -0,0 +1,682 @@
+use std::fmt;
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::*;
+use crate::schema::TableDef;
+use crate::value::Value;
+
+/// A JSON value. Numbers keep the text they were written in and strings and labels their
+/// escapes, so that `json()` gives back what it was given, only minified.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+    Null,
+    True,
+    False,
+    Number(String),
+    /// The text between the quotes, escapes and all.
+    String(String),
+    Array(Vec<Json>),
+    /// The members in the order written; of two with the same label, the first is the one found.
+    Object(Vec<(String, Json)>),
+}
+
+/// Reads JSON text, as RFC 8259 has it.
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn accept(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), ExecuteResult> {
+        if self.accept(byte) { Ok(()) } else { Err(ExecuteMalformedJson) }
+    }
+
+    fn skip_digits(&mut self) -> usize {
+        let start = self.pos;
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn parse_value(&mut self) -> Result<Json, ExecuteResult> {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        for (word, value) in [("true", Json::True), ("false", Json::False), ("null", Json::Null)] {
+            if rest.starts_with(word) {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if self.accept(b'}') {
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let label = self.parse_string()?;
+                    self.expect(b':')?;
+                    members.push((label, self.parse_value()?));
+                    if !self.accept(b',') {
+                        self.expect(b'}')?;
+                        return Ok(Json::Object(members));
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.accept(b']') {
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.parse_value()?);
+                    if !self.accept(b',') {
+                        self.expect(b']')?;
+                        return Ok(Json::Array(items));
+                    }
+                }
+            }
+            Some(b'"') => Ok(Json::String(self.parse_string()?)),
+            Some(b'-' | b'0'..=b'9') => {
+                // -? int [. digits] [e [+|-] digits], where int has no leading zero.
+                let start = self.pos;
+                if self.peek() == Some(b'-') {
+                    self.pos += 1;
+                }
+                if self.peek() == Some(b'0') {
+                    self.pos += 1;
+                } else if self.skip_digits() == 0 {
+                    return Err(ExecuteMalformedJson);
+                }
+                if self.peek() == Some(b'.') {
+                    self.pos += 1;
+                    if self.skip_digits() == 0 {
+                        return Err(ExecuteMalformedJson);
+                    }
+                }
+                if matches!(self.peek(), Some(b'e' | b'E')) {
+                    self.pos += 1;
+                    if matches!(self.peek(), Some(b'+' | b'-')) {
+                        self.pos += 1;
+                    }
+                    if self.skip_digits() == 0 {
+                        return Err(ExecuteMalformedJson);
+                    }
+                }
+                Ok(Json::Number(String::from(&self.text[start..self.pos])))
+            }
+            _ => Err(ExecuteMalformedJson),
+        }
+    }
+
+    /// A string, returned as the text between its quotes once its escapes are checked.
+    fn parse_string(&mut self) -> Result<String, ExecuteResult> {
+        if self.peek() != Some(b'"') {
+            return Err(ExecuteMalformedJson);
+        }
+        self.pos += 1;
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => self.pos += 1,
+                        Some(b'u') => {
+                            let hex = self.text.get(self.pos + 1..self.pos + 5).ok_or(ExecuteMalformedJson)?;
+                            if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                                return Err(ExecuteMalformedJson);
+                            }
+                            self.pos += 5;
+                        }
+                        _ => return Err(ExecuteMalformedJson),
+                    }
+                }
+                Some(byte) if byte >= 0x20 => self.pos += 1,
+                _ => return Err(ExecuteMalformedJson),
+            }
+        }
+        let string = String::from(&self.text[start..self.pos]);
+        self.pos += 1;
+        Ok(string)
+    }
+}
+
+/// Text escaped to stand between the quotes of a JSON string.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The text of a JSON string's escaped content. A `\u` escape of half a surrogate pair takes
+/// the other half from the escape after it.
+fn unescape(escaped: &str) -> String {
+    let mut text = String::new();
+    let mut chars = escaped.chars();
+    let hex = |chars: &mut std::str::Chars| u32::from_str_radix(&chars.by_ref().take(4).collect::<String>(), 16).unwrap_or(0);
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('b') => text.push('\u{8}'),
+            Some('f') => text.push('\u{c}'),
+            Some('n') => text.push('\n'),
+            Some('r') => text.push('\r'),
+            Some('t') => text.push('\t'),
+            Some('u') => {
+                let mut code = hex(&mut chars);
+                if (0xd800..0xdc00).contains(&code) && chars.as_str().starts_with("\\u") {
+                    chars.nth(1);
+                    let low = hex(&mut chars);
+                    code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                }
+                text.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+            }
+            Some(c) => text.push(c),
+            None => {}
+        }
+    }
+    text
+}
+
+/// Whether a number is written as a real, with a fraction or an exponent.
+fn is_real(number: &str) -> bool {
+    number.contains(['.', 'e', 'E'])
+}
+
+impl Json {
+    /// Parse JSON text, which may have whitespace around it but nothing else.
+    pub fn parse(text: &str) -> Result<Json, ExecuteResult> {
+        let mut parser = Parser { text, pos: 0 };
+        let json = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.pos != text.len() {
+            return Err(ExecuteMalformedJson);
+        }
+        Ok(json)
+    }
+
+    /// A SQL value as JSON: text as a string, unless `is_json` says it came from a JSON
+    /// function and is JSON already. A blob has no JSON to be.
+    pub fn from_value(value: &Value, is_json: bool) -> Result<Json, ExecuteResult> {
+        Ok(match value {
+            Value::Null => Json::Null,
+            Value::Integer(i) => Json::Number(i.to_string()),
+            // JSON has no infinity, so it is written as a number too large to read back finite.
+            Value::Real(r) if r.is_infinite() => Json::Number(String::from(if *r > 0.0 { "9.0e+999" } else { "-9.0e+999" })),
+            Value::Real(_) => Json::Number(value.to_string()),
+            Value::Text(text) if is_json => Json::parse(text)?,
+            Value::Text(text) => Json::String(escape(text)),
+            Value::Blob(_) => return Err(ExecuteFunctionError(String::from("JSON cannot hold BLOB values"))),
+        })
+    }
+
+    /// The JSON a function's first argument holds, or None for NULL.
+    fn from_document(value: &Value) -> Result<Option<Json>, ExecuteResult> {
+        match value {
+            Value::Null => Ok(None),
+            Value::Blob(bytes) => Json::parse(&String::from_utf8_lossy(bytes)).map(Some),
+            value => Json::parse(&value.to_string()).map(Some),
+        }
+    }
+
+    /// The value as SQL sees it: true and false as 1 and 0, numbers as integers or reals as
+    /// written, strings as their text and arrays and objects as their JSON text.
+    pub fn to_value(&self) -> Value {
+        match self {
+            Json::Null => Value::Null,
+            Json::True => Value::Integer(1),
+            Json::False => Value::Integer(0),
+            Json::Number(number) => match number.parse::<i64>() {
+                Ok(i) if !is_real(number) => Value::Integer(i),
+                _ => Value::Real(number.parse().unwrap_or(0.0)),
+            },
+            Json::String(escaped) => Value::Text(unescape(escaped)),
+            Json::Array(_) | Json::Object(_) => Value::Text(self.to_string()),
+        }
+    }
+
+    /// The name `json_each()` gives the value's type.
+    fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::True => "true",
+            Json::False => "false",
+            Json::Number(number) if is_real(number) => "real",
+            Json::Number(_) => "integer",
+            Json::String(_) => "text",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    fn is_container(&self) -> bool {
+        matches!(self, Json::Array(_) | Json::Object(_))
+    }
+
+    /// The number of elements of an array or members of an object.
+    fn len(&self) -> Option<usize> {
+        match self {
+            Json::Array(items) => Some(items.len()),
+            Json::Object(members) => Some(members.len()),
+            _ => None,
+        }
+    }
+
+    /// The elements of an array or the values of an object, each with the step to it.
+    fn children(&self) -> Vec<(Step, &Json)> {
+        match self {
+            Json::Array(items) => items.iter().enumerate().map(|(i, item)| (Step::Index(i), item)).collect(),
+            Json::Object(members) => members.iter().map(|(label, value)| (Step::Label(unescape(label)), value)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The number of elements in the value, itself included.
+    fn size(&self) -> i64 {
+        1 + self.children().into_iter().map(|(_, child)| child.size()).sum::<i64>()
+    }
+
+    /// The element a path leads to, if there is one.
+    fn lookup(&self, steps: &[Step]) -> Option<&Json> {
+        let (step, rest) = match steps.split_first() {
+            Some(split) => split,
+            None => return Some(self),
+        };
+        match (self, step) {
+            (Json::Object(members), Step::Label(label)) => members.iter()
+                .find(|(member, _)| unescape(member) == *label)
+                .and_then(|(_, value)| value.lookup(rest)),
+            (Json::Array(items), step) => items.get(step.index(items.len())?)?.lookup(rest),
+            _ => None,
+        }
+    }
+
+    /// Set the element a path leads to, as `json_set()` does: an element that is there is
+    /// replaced, and one that is missing is added if its object or array is there, creating the
+    /// objects and arrays between. An array only grows by one at its end.
+    fn set(&mut self, steps: &[Step], value: Json) {
+        let (step, rest) = match steps.split_first() {
+            Some(split) => split,
+            None => {
+                *self = value;
+                return;
+            }
+        };
+        match (self, step) {
+            (Json::Object(members), Step::Label(label)) => {
+                match members.iter_mut().find(|(member, _)| unescape(member) == *label) {
+                    Some((_, member)) => member.set(rest, value),
+                    None => if let Some(created) = Json::created(rest, value) {
+                        members.push((escape(label), created));
+                    },
+                }
+            }
+            (Json::Array(items), step) => match step.index(items.len()) {
+                Some(i) if i < items.len() => items[i].set(rest, value),
+                Some(i) if i == items.len() => items.extend(Json::created(rest, value)),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// The element a path leads to from nothing: objects for labels and one-element arrays for
+    /// the first index, or None if the path needs any other index.
+    fn created(steps: &[Step], value: Json) -> Option<Json> {
+        let (step, rest) = match steps.split_first() {
+            Some(split) => split,
+            None => return Some(value),
+        };
+        let created = Json::created(rest, value)?;
+        match step {
+            Step::Label(label) => Some(Json::Object(vec![(escape(label), created)])),
+            Step::Index(0) | Step::FromEnd(0) => Some(Json::Array(vec![created])),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::True => write!(f, "true"),
+            Json::False => write!(f, "false"),
+            Json::Number(number) => write!(f, "{}", number),
+            Json::String(escaped) => write!(f, "\"{}\"", escaped),
+            Json::Array(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "[{}]", items.join(","))
+            }
+            Json::Object(members) => {
+                let members: Vec<String> = members.iter().map(|(label, value)| format!("\"{}\":{}", label, value)).collect();
+                write!(f, "{{{}}}", members.join(","))
+            }
+        }
+    }
+}
+
+/// One step of a path: an object's member by label, or an array's element counted from its
+/// start, or back from its end with `[#-N]`, `[#]` being just past the last.
+#[derive(Clone, PartialEq, Debug)]
+enum Step {
+    Label(String),
+    Index(usize),
+    FromEnd(usize),
+}
+
+impl Step {
+    /// The position the step picks in an array of `len` elements, if it is one.
+    fn index(&self, len: usize) -> Option<usize> {
+        match self {
+            Step::Index(i) => Some(*i),
+            Step::FromEnd(n) => len.checked_sub(*n),
+            Step::Label(_) => None,
+        }
+    }
+
+    /// The key `json_each()` gives the element the step leads to.
+    fn key(&self) -> Value {
+        match self {
+            Step::Label(label) => Value::Text(label.clone()),
+            Step::Index(i) | Step::FromEnd(i) => Value::Integer(*i as i64),
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Label(label) if !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+                write!(f, ".{}", label)
+            }
+            Step::Label(label) => write!(f, ".\"{}\"", label),
+            Step::Index(i) => write!(f, "[{}]", i),
+            Step::FromEnd(0) => write!(f, "[#]"),
+            Step::FromEnd(n) => write!(f, "[#-{}]", n),
+        }
+    }
+}
+
+/// The steps of a path: `$` then any of `.label`, `."label"`, `[N]`, `[#-N]` and `[#]`, each
+/// with the offset it starts at.
+fn parse_path_steps(path: &str) -> Result<Vec<(usize, Step)>, ExecuteResult> {
+    let bad_path = || ExecuteBadJsonPath(String::from(path));
+    let mut rest = path.strip_prefix('$').ok_or_else(bad_path)?;
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        let start = path.len() - rest.len();
+        if let Some(quoted) = rest.strip_prefix(".\"") {
+            let end = quoted.find('"').ok_or_else(bad_path)?;
+            steps.push((start, Step::Label(String::from(&quoted[..end]))));
+            rest = &quoted[end + 1..];
+        } else if let Some(label) = rest.strip_prefix('.') {
+            let end = label.find(['.', '[']).unwrap_or(label.len());
+            if end == 0 {
+                return Err(bad_path());
+            }
+            steps.push((start, Step::Label(String::from(&label[..end]))));
+            rest = &label[end..];
+        } else if let Some(index) = rest.strip_prefix('[') {
+            let end = index.find(']').ok_or_else(bad_path)?;
+            let step = match index[..end].strip_prefix('#') {
+                Some("") => Step::FromEnd(0),
+                Some(back) => Step::FromEnd(back.strip_prefix('-').and_then(|n| n.parse().ok()).ok_or_else(bad_path)?),
+                None => Step::Index(index[..end].parse().map_err(|_| bad_path())?),
+            };
+            steps.push((start, step));
+            rest = &index[end + 1..];
+        } else {
+            return Err(bad_path());
+        }
+    }
+    Ok(steps)
+}
+
+fn parse_path(path: &str) -> Result<Vec<Step>, ExecuteResult> {
+    Ok(parse_path_steps(path)?.into_iter().map(|(_, step)| step).collect())
+}
+
+/// The path a `->` or `->>` operator's right operand names: a path if it starts with `$`, an
+/// object label for any other text, and an array index for an integer, counted back from the
+/// end if it is negative. None for any other value.
+fn operator_path(operand: &Value) -> Result<Option<Vec<Step>>, ExecuteResult> {
+    Ok(match operand {
+        Value::Text(text) if text.starts_with('$') => Some(parse_path(text)?),
+        Value::Text(label) => Some(vec![Step::Label(label.clone())]),
+        Value::Integer(i) if *i < 0 => Some(vec![Step::FromEnd(i.unsigned_abs() as usize)]),
+        Value::Integer(i) => Some(vec![Step::Index(*i as usize)]),
+        _ => None,
+    })
+}
+
+/// `json -> path`, the element as JSON text, or `json ->> path` when `as_json` is unset, the
+/// element as a SQL value; NULL if there is no such element.
+pub fn extract_operator(json: &Value, path: &Value, as_json: bool) -> Result<Value, ExecuteResult> {
+    let json = match Json::from_document(json)? {
+        Some(json) => json,
+        None => return Ok(Value::Null),
+    };
+    let element = match operator_path(path)? {
+        Some(steps) => json.lookup(&steps),
+        None => None,
+    };
+    Ok(match element {
+        Some(element) if as_json => Value::Text(element.to_string()),
+        Some(element) => element.to_value(),
+        None => Value::Null,
+    })
+}
+
+/// Call the JSON function of that name, given the arguments it takes, or return None if it is
+/// no JSON function. `json_args` flags each argument that came from a JSON function, and so
+/// is taken for JSON rather than for text.
+pub fn call(name: &str, args: &[Value], json_args: &[bool]) -> Option<Result<Value, ExecuteResult>> {
+    let is_json = |i: usize| json_args.get(i).copied().unwrap_or(false);
+    let result = match name {
+        "json" => Json::from_document(&args[0]).map(|json| json.map_or(Value::Null, |json| Value::Text(json.to_string()))),
+        "json_array" => args.iter()
+            .enumerate()
+            .map(|(i, arg)| Json::from_value(arg, is_json(i)))
+            .collect::<Result<Vec<Json>, ExecuteResult>>()
+            .map(|items| Value::Text(Json::Array(items).to_string())),
+        "json_object" => json_object(args, &is_json),
+        "json_extract" => json_extract(args),
+        "json_set" => json_set(args, &is_json),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn json_object(args: &[Value], is_json: &dyn Fn(usize) -> bool) -> Result<Value, ExecuteResult> {
+    if !args.len().is_multiple_of(2) {
+        return Err(ExecuteFunctionError(String::from("json_object() requires an even number of arguments")));
+    }
+    let mut members = Vec::new();
+    for (i, pair) in args.chunks(2).enumerate() {
+        let label = match &pair[0] {
+            Value::Text(label) => escape(label),
+            _ => return Err(ExecuteFunctionError(String::from("json_object() labels must be TEXT"))),
+        };
+        members.push((label, Json::from_value(&pair[1], is_json(2 * i + 1))?));
+    }
+    Ok(Value::Text(Json::Object(members).to_string()))
+}
+
+/// `json_extract(json, path, ...)`: the element one path leads to as a SQL value, or for
+/// several paths a JSON array of the elements, null where there is none.
+fn json_extract(args: &[Value]) -> Result<Value, ExecuteResult> {
+    let json = match Json::from_document(&args[0])? {
+        Some(json) => json,
+        None => return Ok(Value::Null),
+    };
+    let mut elements = Vec::new();
+    for path in &args[1..] {
+        if path.is_null() {
+            return Ok(Value::Null);
+        }
+        elements.push(json.lookup(&parse_path(&path.to_string())?));
+    }
+    Ok(match elements.as_slice() {
+        [] => Value::Null,
+        [element] => element.map_or(Value::Null, Json::to_value),
+        elements => {
+            let items = elements.iter().map(|element| element.cloned().unwrap_or(Json::Null)).collect();
+            Value::Text(Json::Array(items).to_string())
+        }
+    })
+}
+
+/// `json_set(json, path, value, ...)`: the JSON with each path in turn set to its value. A
+/// NULL path sets nothing.
+fn json_set(args: &[Value], is_json: &dyn Fn(usize) -> bool) -> Result<Value, ExecuteResult> {
+    if args.len().is_multiple_of(2) {
+        return Err(ExecuteFunctionError(String::from("json_set() needs an odd number of arguments")));
+    }
+    let mut json = match Json::from_document(&args[0])? {
+        Some(json) => json,
+        None => return Ok(Value::Null),
+    };
+    for (i, pair) in args[1..].chunks(2).enumerate() {
+        if pair[0].is_null() {
+            continue;
+        }
+        let steps = parse_path(&pair[0].to_string())?;
+        json.set(&steps, Json::from_value(&pair[1], is_json(2 * i + 2))?);
+    }
+    Ok(Value::Text(json.to_string()))
+}
+
+/// The columns of the rows of `json_each()` and `json_tree()`.
+const TABLE_COLUMNS: [&str; 8] = ["key", "value", "type", "atom", "id", "parent", "fullkey", "path"];
+
+/// The table `json_each(json [, path])` or `json_tree(json [, path])` gives, its columns only,
+/// or None if `name` is neither.
+pub fn table(name: &str, num_args: usize) -> Option<Result<TableDef, ExecuteResult>> {
+    if !name.eq_ignore_ascii_case("json_each") && !name.eq_ignore_ascii_case("json_tree") {
+        return None;
+    }
+    if !(1..=2).contains(&num_args) {
+        return Some(Err(ExecuteWrongArgumentCount(name.to_lowercase())));
+    }
+    let columns = TABLE_COLUMNS.iter().map(|column| String::from(*column)).collect();
+    Some(Ok(TableDef::in_memory(&name.to_lowercase(), columns, Vec::new())))
+}
+
+/// Makes the rows of the elements of a document, numbering every element in the order it
+/// is written.
+struct Walk {
+    rows: Vec<Vec<Value>>,
+    next_id: i64,
+}
+
+impl Walk {
+    /// Add the row of an element, then if `recursive` the rows of every element under it, each
+    /// with the id of the one it is in as its parent.
+    fn visit(&mut self, element: &Json, key: Value, parent: Value, fullkey: &str, path: &str, recursive: bool) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let atom = if element.is_container() { Value::Null } else { element.to_value() };
+        self.rows.push(vec![
+            key,
+            element.to_value(),
+            Value::Text(String::from(element.type_name())),
+            atom,
+            Value::Integer(id),
+            parent,
+            Value::Text(String::from(fullkey)),
+            Value::Text(String::from(path)),
+        ]);
+        if recursive {
+            for (step, child) in element.children() {
+                self.visit(child, step.key(), Value::Integer(id), &format!("{}{}", fullkey, step), fullkey, true);
+            }
+        } else {
+            self.next_id += element.size() - 1;
+        }
+    }
+}
+
+/// The rows of `json_each()` or `json_tree()`: none for a NULL argument or a path that leads
+/// nowhere. `json_each()` gives a row for each child of the element the path leads to, or
+/// for the element itself if it has none, and `json_tree()` a row for it and for every element
+/// under it.
+pub fn table_rows(name: &str, args: &[Value]) -> Result<Vec<Vec<Value>>, ExecuteResult> {
+    let json = match Json::from_document(&args[0])? {
+        Some(json) => json,
+        None => return Ok(Vec::new()),
+    };
+    let (fullkey, steps) = match args.get(1) {
+        Some(Value::Null) => return Ok(Vec::new()),
+        Some(path) => (path.to_string(), parse_path_steps(&path.to_string())?),
+        None => (String::from("$"), Vec::new()),
+    };
+    let (last_start, last_step) = steps.last().cloned().unwrap_or((fullkey.len(), Step::Index(0)));
+    let steps: Vec<Step> = steps.into_iter().map(|(_, step)| step).collect();
+    let element = match json.lookup(&steps) {
+        Some(element) => element,
+        None => return Ok(Vec::new()),
+    };
+    let mut walk = Walk { rows: Vec::new(), next_id: 0 };
+    if name.eq_ignore_ascii_case("json_tree") {
+        // The top element is keyed by the last step of the path, and its path is that of the
+        // element it is in.
+        let key = match &last_step {
+            _ if steps.is_empty() => Value::Null,
+            Step::Label(label) => Value::Text(label.clone()),
+            step => {
+                let container = json.lookup(&steps[..steps.len() - 1]).and_then(Json::len).unwrap_or(0);
+                Value::Integer(step.index(container).unwrap_or(0) as i64)
+            }
+        };
+        let path = if steps.is_empty() { fullkey.clone() } else { String::from(&fullkey[..last_start]) };
+        walk.visit(element, key, Value::Null, &fullkey, &path, true);
+    } else if element.is_container() {
+        walk.next_id += 1;
+        for (step, child) in element.children() {
+            walk.visit(child, step.key(), Value::Null, &format!("{}{}", fullkey, step), &fullkey, false);
+        }
+    } else {
+        walk.visit(element, Value::Null, Value::Null, &fullkey, &fullkey, false);
+    }
+    Ok(walk.rows)
+}
@@ -0,0 +1,34 @@
+mod constant;
+mod page;
+mod pager;
+pub mod table;
+mod record;
+pub mod statement;
+pub mod result;
+mod node;
+mod plan;
+mod cursor;
+pub mod utils;
+pub mod value;
+mod collation;
+mod function;
+mod printf;
+mod datetime;
+mod json;
+mod fts;
+mod tokenizer;
+mod expr;
+mod pattern;
+mod parser;
+mod index;
+mod schema;
+mod sorter;
+mod aggregate;
+mod temp_file;
+mod join;
+mod subquery;
+mod cte;
+mod compound;
+mod window;
+mod file_format;
+mod upgrade;
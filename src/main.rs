@@ -1,22 +1,10 @@
-#[warn(unused_assignments)]
 use std::{env, process};
-use crate::result::{get_meta_result, MetaCommandResult};
-use crate::result::ExecuteResult::*;
-use crate::result::PrepareResult::*;
-use crate::statement::{execute_statement, prepare_statement};
-use crate::table::{db_open};
-use crate::utils::{print_prompt, read_line};
-
-mod constant;
-mod page;
-mod pager;
-mod table;
-mod row;
-mod statement;
-mod result;
-mod node;
-mod cursor;
-mod utils;
+use sqlite_rust::result::{get_meta_result, MetaCommandResult};
+use sqlite_rust::result::ExecuteResult::*;
+use sqlite_rust::result::PrepareResult::*;
+use sqlite_rust::statement::{execute_statement, prepare_statement};
+use sqlite_rust::table::{db_open};
+use sqlite_rust::utils::{print_prompt, read_line};
 
 
 fn main() {
@@ -45,8 +33,48 @@ fn main() {
                 match execute_statement(stmt, &mut table) {
                     ExecuteSuccess => println!("Executed."),
                     ExecuteDuplicateKey => println!("Error: Duplicate key."),
-                    ExecuteTableFull => println!("Error: Table full."),
-                    _ => println!("Error: execute failed")
+                    ExecuteStringTooLong => println!("Error: String is too long."),
+                    ExecuteNoSuchTable(name) => println!("Error: no such table: {}", name),
+                    ExecuteNoSuchColumn(name) => println!("Error: no such column: {}", name),
+                    ExecuteAmbiguousColumn(name) => println!("Error: ambiguous column name: {}", name),
+                    ExecuteSubqueryColumns(count) => println!("Error: sub-select returns {} columns - expected 1", count),
+                    ExecuteCompoundColumns(operator) =>
+                        println!("Error: SELECTs to the left and right of {} do not have the same number of result columns", operator),
+                    ExecuteDuplicateWithTable(name) => println!("Error: duplicate WITH table name: {}", name),
+                    ExecuteWithColumns(name, values, columns) => println!("Error: table {} has {} values for {} columns", name, values, columns),
+                    ExecuteIndexExists(name) => println!("Error: index {} already exists", name),
+                    ExecuteTableExists(name) => println!("Error: table {} already exists", name),
+                    ExecuteDuplicateColumn(name) => println!("Error: duplicate column name: {}", name),
+                    ExecuteCompositeRowidKey(name) => println!("Error: table {} needs WITHOUT ROWID for a PRIMARY KEY of several columns", name),
+                    ExecutePrimaryKeyMissing(name) => println!("Error: PRIMARY KEY missing on table {}", name),
+                    ExecuteWithoutRowidIndex(name) => println!("Error: WITHOUT ROWID table {} cannot have secondary or UNIQUE indexes", name),
+                    ExecuteValueCount(name, columns, values) =>
+                        println!("Error: table {} has {} columns but {} values were supplied", name, columns, values),
+                    ExecuteUniqueViolation(constraint) => println!("Error: UNIQUE constraint failed: {}", constraint),
+                    ExecuteNotNullViolation(column) => println!("Error: NOT NULL constraint failed: {}", column),
+                    ExecuteCheckViolation(constraint) => println!("Error: CHECK constraint failed: {}", constraint),
+                    ExecuteForeignKeyViolation(constraint) => println!("Error: FOREIGN KEY constraint failed: {}", constraint),
+                    ExecuteTermOutOfRange(clause, num_columns) =>
+                        println!("Error: {} term out of range - should be between 1 and {}", clause, num_columns),
+                    ExecuteDatatypeMismatch => println!("Error: datatype mismatch"),
+                    ExecuteNoSuchFunction(name) => println!("Error: no such function: {}", name),
+                    ExecuteNoSuchCollation(name) => println!("Error: no such collation sequence: {}", name),
+                    ExecuteFunctionError(message) => println!("Error: {}", message),
+                    ExecuteMisuseOfAggregate(name) => println!("Error: misuse of aggregate function {}()", name),
+                    ExecuteMisuseOfWindowFunction(name) => println!("Error: misuse of window function {}()", name),
+                    ExecuteWrongArgumentCount(name) => println!("Error: wrong number of arguments to function {}()", name),
+                    ExecuteIntegerOverflow => println!("Error: integer overflow"),
+                    ExecuteMalformedJson => println!("Error: malformed JSON"),
+                    ExecuteBadJsonPath(path) => println!("Error: bad JSON path: '{}'", path),
+                    ExecuteFullTextError(message) => println!("Error: {}", message),
+                    ExecuteVirtualTableIndex => println!("Error: virtual tables may not be indexed"),
+                    ExecuteReadOnly => println!("Error: attempt to write a readonly database"),
+                    ExecuteCorruptFile(message) => println!("Error: {}", message),
+                    ExecuteTempFileError(message) => println!("Error: {}", message),
+                    ExecuteTableReadOnly(name) => println!("Error: table {} may not be modified", name),
+                    ExecuteReservedName(name) => println!("Error: object name reserved for internal use: {}", name),
+                    ExecuteDatabaseFull => println!("Error: database or disk is full"),
+                    ExecuteMissingEntry(name) => println!("Error: database disk image is malformed: row missing from {}", name),
                 }
             }
             Err(prepare_result) => {
@@ -58,8 +86,7 @@ fn main() {
                     PrepareStringTooLong =>
                         println!("String is too long."),
                     PrepareInvalidId =>
-                        println!("ID must be positive."),
-                    _ => {}
+                        println!("ID must be an integer."),
                 };
                 continue;
             }
@@ -1,8 +1,6 @@
-use std::io::Read;
 use std::process;
 use crate::node::NodeType;
 use crate::node::NodeType::{NodeInternal, NodeLeaf};
-use crate::row::Row;
 use crate::constant::*;
 
 pub struct Page {
@@ -16,56 +14,17 @@ impl Page {
         }
     }
 
-    pub(crate) unsafe fn row_mut_slot(&self, cell_num: usize) -> Box<Row> {
-        fn read_end_idx(bytes: &[u8]) -> usize {
-            for i in (0..bytes.len()).rev() {
-                if bytes[i] != 0 {
-                    return i;
-                }
-            }
-            0
-        }
-        let cell = self.leaf_node_value(cell_num);
-
-        let id = std::ptr::read(cell as *const u32);
-        let username_bytes = std::ptr::read((cell as usize + USERNAME_OFFSET) as *const [u8; USERNAME_SIZE]);
-        let email_bytes = std::ptr::read((cell as usize + EMAIL_OFFSET) as *const [u8; EMAIL_SIZE]);
-
-        Box::new(Row {
-            id,
-            username: String::from_utf8_unchecked(Vec::from(&username_bytes[0..=read_end_idx(&username_bytes)])),
-            email: String::from_utf8_unchecked(Vec::from(&email_bytes[0..=read_end_idx(&email_bytes)])),
-        })
-    }
-
-    fn load(&mut self, bytes: &[u8]) {
-        let mut idx = 0;
-        while idx + ROW_SIZE <= bytes.len() {
-            let mut reader = std::io::Cursor::new(&bytes[idx..idx + ROW_SIZE]);
-            let mut id_bytes = [0; ID_SIZE];
-            reader.read_exact(&mut id_bytes)
-                .expect("Page load error");
-            let mut username_bytes = [0; USERNAME_SIZE];
-            reader.read_exact(&mut username_bytes)
-                .expect("Page load error");
-            let mut email_bytes = [0; EMAIL_SIZE];
-            reader.read_exact(&mut email_bytes)
-                .expect("Page load error");
-            idx += ROW_SIZE;
-        }
-    }
-
     unsafe fn leaf_node_mut_num_cells(&self) -> *mut usize {
         self.index(LEAF_NODE_NUM_CELLS_OFFSET) as *mut usize
     }
 
     pub(crate) fn leaf_node_num_cells(&self) -> usize {
-        unsafe { *self.leaf_node_mut_num_cells() }
+        unsafe { std::ptr::read_unaligned(self.leaf_node_mut_num_cells()) }
     }
 
     pub(crate) fn set_leaf_node_num_cells(&mut self, num_cells: usize) {
         unsafe {
-            *self.leaf_node_mut_num_cells() = num_cells
+            std::ptr::write_unaligned(self.leaf_node_mut_num_cells(), num_cells)
         }
     }
 
@@ -74,20 +33,54 @@ impl Page {
         (ptr as isize).checked_add(offset as isize).unwrap()
     }
 
-    pub(crate) fn leaf_node_cell(&self, cell_num: usize) -> *const u8 {
-        (self.index(LEAF_NODE_HEADER_SIZE + cell_num * LEAF_NODE_CELL_SIZE)) as *const u8
+    /// The offset of a cell of a leaf or index node, from its pointer after the `header_size`
+    /// bytes of header.
+    fn cell_offset(&self, header_size: usize, cell_num: usize) -> usize {
+        let pointer = header_size + cell_num * CELL_POINTER_SIZE;
+        u16::from_be_bytes(self.buf[pointer..pointer + CELL_POINTER_SIZE].try_into().unwrap()) as usize
+    }
+
+    /// The bytes of a cell made of `prefix_size` bytes, its key or child pointer, and a slot.
+    fn cell(&self, header_size: usize, cell_num: usize, prefix_size: usize) -> &[u8] {
+        let offset = self.cell_offset(header_size, cell_num);
+        let size = prefix_size + slot_size(&self.buf[offset + prefix_size..]);
+        &self.buf[offset..offset + size]
+    }
+
+    /// Lay out the cells in order, their pointers after the header and the cells themselves
+    /// packed against the end of the page, zeroing the space left between.
+    fn set_cells<'a>(&mut self, header_size: usize, cells: impl Iterator<Item = &'a [u8]>) {
+        let (mut pointer, mut end) = (header_size, PAGE_SIZE);
+        for cell in cells {
+            end -= cell.len();
+            self.buf[end..end + cell.len()].copy_from_slice(cell);
+            self.buf[pointer..pointer + CELL_POINTER_SIZE].copy_from_slice(&(end as u16).to_be_bytes());
+            pointer += CELL_POINTER_SIZE;
+        }
+        self.buf[pointer..end].fill(0);
+    }
+
+    pub(crate) fn leaf_node_key(&self, cell_num: usize) -> i64 {
+        let offset = self.cell_offset(LEAF_NODE_HEADER_SIZE, cell_num);
+        i64::from_ne_bytes(self.buf[offset..offset + LEAF_NODE_KEY_SIZE].try_into().unwrap())
     }
 
-    pub(crate) fn leaf_node_key(&self, cell_num: usize) -> u32 {
-        unsafe { *(self.leaf_node_cell(cell_num) as *mut u32) }
+    /// The payload slot of a table leaf cell.
+    pub(crate) fn leaf_node_slot(&self, cell_num: usize) -> &[u8] {
+        &self.cell(LEAF_NODE_HEADER_SIZE, cell_num, LEAF_NODE_KEY_SIZE)[LEAF_NODE_KEY_SIZE..]
     }
 
-    pub(crate) fn set_leaf_node_key(&self, cell_num: usize, key: u32) {
-        unsafe { *(self.leaf_node_cell(cell_num) as *mut u32) = key }
+    /// Return the (key, payload slot) cells of a table leaf node.
+    pub(crate) fn leaf_node_cells(&self) -> Vec<(i64, Vec<u8>)> {
+        (0..self.leaf_node_num_cells())
+            .map(|i| (self.leaf_node_key(i), self.leaf_node_slot(i).to_vec()))
+            .collect()
     }
 
-    pub(crate) fn leaf_node_value(&self, cell_num: usize) -> *mut u8 {
-        self.index(LEAF_NODE_HEADER_SIZE + cell_num * LEAF_NODE_CELL_SIZE + LEAF_NODE_KEY_SIZE) as *mut u8
+    pub(crate) fn set_leaf_node_cells(&mut self, cells: &[(i64, Vec<u8>)]) {
+        let cells: Vec<Vec<u8>> = cells.iter().map(|(key, slot)| [&key.to_ne_bytes()[..], slot].concat()).collect();
+        self.set_cells(LEAF_NODE_HEADER_SIZE, cells.iter().map(Vec::as_slice));
+        self.set_leaf_node_num_cells(cells.len());
     }
 
     pub(crate) fn initialize_leaf_node(&mut self) {
@@ -96,7 +89,7 @@ impl Page {
         self.set_leaf_node_next_leaf(0);
         let ptr = self.index(LEAF_NODE_NUM_CELLS_OFFSET) as *mut usize;
         unsafe {
-            *ptr = 0;
+            std::ptr::write_unaligned(ptr, 0);
         }
     }
 
@@ -105,14 +98,10 @@ impl Page {
         self.set_node_root(false);
         let ptr = self.index(INTERNAL_NODE_NUM_KEYS_OFFSET) as *mut usize;
         unsafe {
-            *ptr = 0;
+            std::ptr::write_unaligned(ptr, 0);
         }
     }
 
-    fn is_full(&self) -> bool {
-        self.leaf_node_num_cells() >= LEAF_NODE_MAX_CELLS
-    }
-
     pub(crate) fn is_leaf_node(&self) -> bool {
         *(self.get_node_type()) == NodeType::NodeLeaf
     }
@@ -144,43 +133,38 @@ impl Page {
 
     pub fn set_internal_node_right_child(&mut self, internal_node_right_child: usize) {
         unsafe {
-            *(self.internal_node_right_child() as *mut usize) = internal_node_right_child;
+            std::ptr::write_unaligned(self.internal_node_right_child() as *mut usize, internal_node_right_child);
         }
     }
 
     pub fn get_internal_node_right_child(&self) -> usize {
         unsafe {
-            *(self.internal_node_right_child() as *mut usize)
+            std::ptr::read_unaligned(self.internal_node_right_child() as *const usize)
         }
     }
 
     pub fn set_internal_node_num_keys(&mut self, num_keys: usize) {
         unsafe {
-            *(self.index(INTERNAL_NODE_NUM_KEYS_OFFSET) as *mut usize) = num_keys;
+            std::ptr::write_unaligned(self.index(INTERNAL_NODE_NUM_KEYS_OFFSET) as *mut usize, num_keys);
         }
     }
 
     pub fn get_internal_node_num_keys(&self) -> usize {
         unsafe {
-            *(self.index(INTERNAL_NODE_NUM_KEYS_OFFSET) as *const usize)
+            std::ptr::read_unaligned(self.index(INTERNAL_NODE_NUM_KEYS_OFFSET) as *const usize)
         }
     }
 
-    pub fn increase_internal_node_num_keys(&mut self, incr: usize) {
-        let origin_num_keys = self.get_internal_node_num_keys();
-        self.set_internal_node_num_keys(origin_num_keys + incr);
-    }
-
     pub fn internal_node_cell(&self, cell_num: usize) -> isize {
         self.index(INTERNAL_NODE_HEADER_SIZE + cell_num * INTERNAL_NODE_CELL_SIZE)
     }
 
     fn set_internal_node_cell(&mut self, cell_num: usize, page_num: usize) {
-        unsafe { *(self.internal_node_cell(cell_num) as *mut usize) = page_num }
+        unsafe { std::ptr::write_unaligned(self.internal_node_cell(cell_num) as *mut usize, page_num) }
     }
 
     fn get_internal_node_cell(&self, cell_num: usize) -> usize {
-        unsafe { *(self.internal_node_cell(cell_num) as *const usize) }
+        unsafe { std::ptr::read_unaligned(self.internal_node_cell(cell_num) as *const usize) }
     }
 
     pub fn set_internal_node_child(&mut self, child_num: usize, child_page_num: usize) {
@@ -207,19 +191,19 @@ impl Page {
         }
     }
 
-    pub fn set_internal_node_key(&mut self, key_num: usize, key_val: u32) {
+    pub fn set_internal_node_key(&mut self, key_num: usize, key_val: i64) {
         unsafe {
-            *((self.internal_node_cell(key_num) + INTERNAL_NODE_CHILD_SIZE as isize) as *mut u32) = key_val;
+            std::ptr::write_unaligned((self.internal_node_cell(key_num) + INTERNAL_NODE_CHILD_SIZE as isize) as *mut i64, key_val);
         }
     }
 
-    pub(crate) fn get_internal_node_key(&self, cell_num: usize) -> u32 {
+    pub(crate) fn get_internal_node_key(&self, cell_num: usize) -> i64 {
         unsafe {
-            *((self.internal_node_cell(cell_num) + INTERNAL_NODE_CHILD_SIZE as isize) as *const u32)
+            std::ptr::read_unaligned((self.internal_node_cell(cell_num) + INTERNAL_NODE_CHILD_SIZE as isize) as *const i64)
         }
     }
 
-    pub fn get_node_max_key(&self) -> u32 {
+    pub fn get_node_max_key(&self) -> i64 {
         match self.get_node_type() {
             NodeInternal => self.get_internal_node_key(self.get_internal_node_num_keys() - 1),
             NodeLeaf => self.leaf_node_key(self.leaf_node_num_cells() - 1)
@@ -228,35 +212,30 @@ impl Page {
 
     pub fn get_leaf_node_next_leaf(&self) -> usize {
         unsafe {
-            *(self.index(LEAF_NODE_NEXT_LEAF_OFFSET) as *const usize)
+            std::ptr::read_unaligned(self.index(LEAF_NODE_NEXT_LEAF_OFFSET) as *const usize)
         }
     }
 
     pub fn set_leaf_node_next_leaf(&self, next_leaf: usize) {
         unsafe {
-            *(self.index(LEAF_NODE_NEXT_LEAF_OFFSET) as *mut usize) = next_leaf;
+            std::ptr::write_unaligned(self.index(LEAF_NODE_NEXT_LEAF_OFFSET) as *mut usize, next_leaf);
         }
     }
 
     pub fn get_node_parent(&self) -> usize {
         unsafe {
-            *(self.index(PARENT_POINTER_OFFSET) as *const usize)
+            std::ptr::read_unaligned(self.index(PARENT_POINTER_OFFSET) as *const usize)
         }
     }
 
     pub fn set_node_parent(&self, parent_page_num: usize) {
         unsafe {
-            *(self.index(PARENT_POINTER_OFFSET) as *mut usize) = parent_page_num;
+            std::ptr::write_unaligned(self.index(PARENT_POINTER_OFFSET) as *mut usize, parent_page_num);
         }
     }
 
-    pub fn update_internal_node_key(&mut self, old_key: u32, new_key: u32) {
-        let old_child_index = self.internal_node_find_child(old_key);
-        self.set_internal_node_key(old_child_index, new_key);
-    }
-
     /// Return the index of the child which should contain the given key.
-    pub(crate) fn internal_node_find_child(&self, key: u32) -> usize {
+    pub(crate) fn internal_node_find_child(&self, key: i64) -> usize {
         let num_keys = self.get_internal_node_num_keys();
         // binary search
         let (mut min_cell, mut max_cell) = (0, num_keys);
@@ -272,7 +251,7 @@ impl Page {
         max_cell
     }
 
-    pub(crate) fn leaf_node_find(&self, key: u32) -> usize {
+    pub(crate) fn leaf_node_find(&self, key: i64) -> usize {
         let num_cells = self.leaf_node_num_cells();
         let (mut min_index, mut one_past_max_index) = (0, num_cells);
         while one_past_max_index != min_index {
@@ -288,4 +267,69 @@ impl Page {
         }
         min_index
     }
-}
\ No newline at end of file
+
+    /// Return the key slots of an index leaf node.
+    pub(crate) fn index_leaf_node_keys(&self) -> Vec<Vec<u8>> {
+        (0..self.leaf_node_num_cells())
+            .map(|i| self.cell(LEAF_NODE_HEADER_SIZE, i, 0).to_vec())
+            .collect()
+    }
+
+    pub(crate) fn set_index_leaf_node_keys(&mut self, keys: &[Vec<u8>]) {
+        self.set_cells(LEAF_NODE_HEADER_SIZE, keys.iter().map(Vec::as_slice));
+        self.set_leaf_node_num_cells(keys.len());
+    }
+
+    /// Return the (child page, key slot) cells of an index internal node, without the right child.
+    pub(crate) fn index_internal_node_cells(&self) -> Vec<(usize, Vec<u8>)> {
+        (0..self.get_internal_node_num_keys())
+            .map(|i| {
+                let cell = self.cell(INTERNAL_NODE_HEADER_SIZE, i, INTERNAL_NODE_CHILD_SIZE);
+                let child = usize::from_ne_bytes(cell[..INTERNAL_NODE_CHILD_SIZE].try_into().unwrap());
+                (child, cell[INTERNAL_NODE_CHILD_SIZE..].to_vec())
+            })
+            .collect()
+    }
+
+    pub(crate) fn set_index_internal_node_cells(&mut self, cells: &[(usize, Vec<u8>)]) {
+        let cells: Vec<Vec<u8>> = cells.iter().map(|(child, slot)| [&child.to_ne_bytes()[..], slot].concat()).collect();
+        self.set_cells(INTERNAL_NODE_HEADER_SIZE, cells.iter().map(Vec::as_slice));
+        self.set_internal_node_num_keys(cells.len());
+    }
+}
+
+/// The bytes a payload slot takes, from the payload size it starts with.
+pub(crate) fn slot_size(slot: &[u8]) -> usize {
+    let size = u32::from_be_bytes(slot[..PAYLOAD_SIZE_SIZE].try_into().unwrap()) as usize;
+    PAYLOAD_SIZE_SIZE + size.min(MAX_LOCAL_PAYLOAD_SIZE) + OVERFLOW_POINTER_SIZE
+}
+
+/// The space cells of these sizes take on a node page, their pointers included.
+fn cells_space(sizes: impl IntoIterator<Item = usize>) -> usize {
+    sizes.into_iter().map(|size| size + CELL_POINTER_SIZE).sum()
+}
+
+/// Whether cells of these sizes fit on one leaf or index node page, whose headers are all
+/// `LEAF_NODE_HEADER_SIZE` long.
+pub(crate) fn cells_fit(sizes: impl IntoIterator<Item = usize>) -> bool {
+    cells_space(sizes) <= LEAF_NODE_SPACE_FOR_CELLS
+}
+
+/// The cell at which cells of these sizes, too many for one page, reach half their space.
+/// No cell takes more than a quarter of a page, so the cells before it and those after it
+/// each fit on a page of their own, and neither is empty.
+pub(crate) fn split_cell(sizes: &[usize]) -> usize {
+    let total = cells_space(sizes.iter().copied());
+    let mut space = 0;
+    sizes.iter()
+        .position(|size| {
+            space += size + CELL_POINTER_SIZE;
+            space * 2 >= total
+        })
+        .unwrap()
+}
+
+/// The size of a table leaf cell holding the slot.
+pub(crate) fn leaf_cell_size(slot: &[u8]) -> usize {
+    LEAF_NODE_KEY_SIZE + slot.len()
+}
@@ -1,21 +1,38 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::process;
-use crate::constant::{PAGE_SIZE, TABLE_MAX_PAGES};
-use crate::page::Page;
+use std::rc::Rc;
+use crate::constant::{CATALOG_NEXT_PAGE_OFFSET, CATALOG_PAGE_SPACE, CATALOG_SIZE_OFFSET, FILE_FORMAT_VERSION, FILE_HEADER_SIZE,
+                      FILE_MAGIC, FORMAT_VERSION_OFFSET, FREELIST_HEAD_OFFSET, HEADER_PAGE_NUM, MAX_LOCAL_PAYLOAD_SIZE,
+                      OVERFLOW_PAGE_SPACE, OVERFLOW_POINTER_SIZE, PAGE_SIZE, PAYLOAD_SIZE_SIZE, SQLITE_CACHE_PAGES, TABLE_MAX_PAGES,
+                      USERS_ROOT_PAGE_NUM};
+use crate::file_format::{SqliteFile, HEADER_SIZE as SQLITE_HEADER_SIZE, SQLITE_MAGIC};
+use crate::page::{slot_size, Page};
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::ExecuteDatabaseFull;
 
 pub struct Pager {
     file_descriptor: RefCell<File>,
-    pages: Vec<Option<Box<Page>>>,
+    /// The pages read or written so far, by page number, grown as pages past them are asked for.
+    /// A page once loaded stays boxed where it is until the pager is dropped.
+    pages: RefCell<Vec<Option<Box<Page>>>>,
     pub(crate) num_pages: usize,
+    /// Set by `PRAGMA max_page_count`: writes are refused once the file has this many pages.
+    pub(crate) max_pages: usize,
+    /// Set when the file is a genuine SQLite 3 database rather than one of our own, which is
+    /// then read a page at a time through `sqlite_page` and never written.
+    pub(crate) sqlite: Option<SqliteFile>,
+    /// The pages of a SQLite file read so far, by their SQLite page number.
+    sqlite_pages: RefCell<HashMap<u32, Rc<[u8]>>>,
 }
 
 impl Pager {
     pub(crate) fn new(file: File) -> Self {
         fn num_pages_file(file_length: u64) -> usize {
             let num_page = file_length / PAGE_SIZE as u64;
-            if file_length % PAGE_SIZE as u64 != 0 {
+            if !file_length.is_multiple_of(PAGE_SIZE as u64) {
                 println!("Database file is not a whole number of pages. Corrupt file.");
                 process::exit(0x0100);
             }
@@ -24,64 +41,80 @@ impl Pager {
         Pager {
             num_pages: num_pages_file(file.metadata().unwrap().len()),
             file_descriptor: RefCell::new(file),
-            pages: std::iter::repeat_with(|| None).take(TABLE_MAX_PAGES).collect::<Vec<_>>(),
+            pages: RefCell::new(Vec::new()),
+            max_pages: TABLE_MAX_PAGES,
+            sqlite: None,
+            sqlite_pages: RefCell::new(HashMap::new()),
         }
     }
 
-    pub(crate) fn get_page_view(&self, page_num: usize) -> Option<&Page> {
-        if page_num > TABLE_MAX_PAGES {
-            panic!("Tried to fetch page number out of bounds. {} > {}", page_num, TABLE_MAX_PAGES);
+    /// A pager over a SQLite 3 file, whose pages are read through `sqlite_page` instead.
+    fn new_sqlite(file: File, sqlite: SqliteFile) -> Self {
+        Pager {
+            num_pages: 0,
+            file_descriptor: RefCell::new(file),
+            pages: RefCell::new(Vec::new()),
+            max_pages: TABLE_MAX_PAGES,
+            sqlite: Some(sqlite),
+            sqlite_pages: RefCell::new(HashMap::new()),
         }
+    }
 
-        unsafe {
-            let ptr = self.pages.as_ptr();
-            let page = ptr.offset(page_num as isize);
-            if (*page).is_none() {
-                self.load_page(page_num);
-            }
-            let page = ptr.offset(page_num as isize);
-            Some((*page).as_ref().unwrap().as_ref())
+    /// A page of the SQLite file, numbered from 1 as SQLite numbers them, read from the file
+    /// the first time it is asked for. At most `SQLITE_CACHE_PAGES` are kept at once.
+    pub(crate) fn sqlite_page(&self, page_num: u32) -> Result<Rc<[u8]>, String> {
+        if let Some(page) = self.sqlite_pages.borrow().get(&page_num) {
+            return Ok(page.clone());
+        }
+        let header = &self.sqlite.as_ref().unwrap().header;
+        if page_num == 0 || page_num > header.page_count {
+            return Err(format!("database disk image is malformed: no page {}", page_num));
         }
+        let mut page = vec![0; header.page_size];
+        let mut file = self.file_descriptor.borrow_mut();
+        file.seek(SeekFrom::Start((page_num as u64 - 1) * header.page_size as u64))
+            .and_then(|_| file.read_exact(&mut page))
+            .map_err(|_| format!("database disk image is malformed: no page {}", page_num))?;
+        let page: Rc<[u8]> = Rc::from(page);
+        let mut pages = self.sqlite_pages.borrow_mut();
+        if pages.len() >= SQLITE_CACHE_PAGES {
+            pages.clear();
+        }
+        pages.insert(page_num, page.clone());
+        Ok(page)
     }
 
-    fn load_page(&self, page_num: usize) {
+    pub(crate) fn get_page_view(&self, page_num: usize) -> Option<&Page> {
+        let mut pages = self.pages.borrow_mut();
+        if page_num >= pages.len() {
+            pages.resize_with(page_num + 1, || None);
+        }
+        let page: *const Page = pages[page_num].get_or_insert_with(|| self.load_page(page_num)).as_ref();
+        // The page is boxed and never moved or dropped while the pager lives, so it outlasts the
+        // borrow of the vector holding it.
+        Some(unsafe { &*page })
+    }
+
+    fn load_page(&self, page_num: usize) -> Box<Page> {
         let mut new_page = Page::new();
         if page_num <= self.num_pages {
             self.file_descriptor.borrow_mut().seek(SeekFrom::Start(page_num as u64 * PAGE_SIZE as u64))
                 .expect("Pager load page error");
             let result = self.file_descriptor.borrow_mut().read(&mut new_page.buf);
-            if result.is_err() {
-                println!("Error reading file: {}", result.unwrap());
+            if let Err(err) = result {
+                println!("Error reading file: {}", err);
                 process::exit(0x0100);
             }
         }
-
-        unsafe {
-            let ptr = self.pages.as_ptr();
-            let pages = ptr as *mut Option<Box<Page>>;
-            (*pages.offset(page_num as isize)) = Some(Box::new(new_page));
-        }
+        Box::new(new_page)
     }
 
     pub(crate) fn get_page(&mut self, page_num: usize) -> &mut Page {
-        if page_num > TABLE_MAX_PAGES {
-            panic!("Tried to fetch page number out of bounds. {} > {}", page_num, TABLE_MAX_PAGES);
-        }
-        unsafe {
-            let ptr = self.pages.as_ptr();
-            let page = ptr.offset(page_num as isize);
-            if (*page).is_none() {
-                self.load_page(page_num);
-                if page_num >= self.num_pages {
-                    self.num_pages += 1;
-                }
-            }
-        }
-        let pages = self.pages.as_mut_ptr();
-        unsafe {
-            let page = pages.offset(page_num as isize);
-            (*page).as_mut().unwrap().as_mut()
+        self.get_page_view(page_num);
+        if page_num >= self.num_pages {
+            self.num_pages = page_num + 1;
         }
+        self.pages.get_mut()[page_num].as_mut().unwrap()
     }
 
     pub fn get_leftmost_leaf_page_num(&self, page_num: usize) -> usize {
@@ -94,45 +127,220 @@ impl Pager {
             return page_num;
         }
         let child_page_num = p.get_internal_node_child(0);
-        return self.get_leftmost_leaf_page_num(child_page_num);
+        self.get_leftmost_leaf_page_num(child_page_num)
     }
 
     pub fn pager_flush(&mut self, page_num: usize) {
-        match &self.pages[page_num] {
-            Some(page) => {
-                self.file_descriptor.borrow_mut().seek(SeekFrom::Start(page_num as u64 * PAGE_SIZE as u64))
-                    .expect("Pager flush :seek error");
-                self.file_descriptor.borrow_mut().write(page.buf.as_slice())
-                    .expect("Pager flush : write error");
-                self.file_descriptor.borrow_mut().flush()
-                    .expect("Pager flush : flush error");
-            }
-            None => ()
+        if self.sqlite.is_some() {
+            return;
+        }
+        if let Some(Some(page)) = self.pages.get_mut().get(page_num) {
+            self.file_descriptor.borrow_mut().seek(SeekFrom::Start(page_num as u64 * PAGE_SIZE as u64))
+                .expect("Pager flush :seek error");
+            self.file_descriptor.borrow_mut().write_all(page.buf.as_slice())
+                .expect("Pager flush : write error");
+            self.file_descriptor.borrow_mut().flush()
+                .expect("Pager flush : flush error");
         }
     }
 
-    fn close(&mut self) {
+    pub(crate) fn close(&mut self) {
         self.file_descriptor.borrow_mut().flush().expect("Pager close error");
     }
 
-    pub(crate) fn get_unused_page_num(&self) -> usize {
-        self.num_pages
+    /// Check that the file may grow before a row is written: that it is below `max_pages`, or
+    /// has free pages to give. A row with overflow pages may still take the file a few pages past.
+    pub(crate) fn check_room(&self) -> Result<(), ExecuteResult> {
+        if self.num_pages >= self.max_pages && self.read_u32(HEADER_PAGE_NUM, FREELIST_HEAD_OFFSET) == 0 {
+            return Err(ExecuteDatabaseFull);
+        }
+        Ok(())
+    }
+
+    /// Take a zeroed page for a new node or overflow page: one from the free list, or else a
+    /// page past the end of the file.
+    pub(crate) fn allocate_page(&mut self) -> usize {
+        let page_num = match self.read_u32(HEADER_PAGE_NUM, FREELIST_HEAD_OFFSET) as usize {
+            0 => self.num_pages,
+            free => {
+                let next = self.read_u32(free, 0);
+                self.write_u32(HEADER_PAGE_NUM, FREELIST_HEAD_OFFSET, next);
+                free
+            }
+        };
+        self.get_page(page_num).buf = [0; PAGE_SIZE];
+        page_num
+    }
+
+    /// Put a page no longer used on the free list.
+    fn free_page(&mut self, page_num: usize) {
+        let head = self.read_u32(HEADER_PAGE_NUM, FREELIST_HEAD_OFFSET);
+        self.get_page(page_num).buf = [0; PAGE_SIZE];
+        self.write_u32(page_num, 0, head);
+        self.write_u32(HEADER_PAGE_NUM, FREELIST_HEAD_OFFSET, page_num as u32);
+    }
+
+    /// A payload slot holding `payload`, spilling what is past `MAX_LOCAL_PAYLOAD_SIZE` onto a
+    /// chain of overflow pages.
+    pub(crate) fn write_payload(&mut self, payload: &[u8]) -> Vec<u8> {
+        let local = payload.len().min(MAX_LOCAL_PAYLOAD_SIZE);
+        let mut slot = vec![0; PAYLOAD_SIZE_SIZE + local + OVERFLOW_POINTER_SIZE];
+        slot[..PAYLOAD_SIZE_SIZE].copy_from_slice(&(payload.len() as u32).to_be_bytes());
+        slot[PAYLOAD_SIZE_SIZE..PAYLOAD_SIZE_SIZE + local].copy_from_slice(&payload[..local]);
+        // Written back to front, so each page is given the number of the one after it.
+        let mut next = 0;
+        for chunk in payload[local..].chunks(OVERFLOW_PAGE_SPACE).rev() {
+            let page_num = self.allocate_page();
+            self.write_u32(page_num, 0, next);
+            self.get_page(page_num).buf[4..4 + chunk.len()].copy_from_slice(chunk);
+            next = page_num as u32;
+        }
+        slot[PAYLOAD_SIZE_SIZE + local..].copy_from_slice(&next.to_be_bytes());
+        slot
+    }
+
+    /// The payload a slot holds, read on through its overflow pages.
+    pub(crate) fn read_payload(&self, slot: &[u8]) -> Vec<u8> {
+        let size = u32::from_be_bytes(slot[..PAYLOAD_SIZE_SIZE].try_into().unwrap()) as usize;
+        let slot = &slot[..slot_size(slot)];
+        let mut payload = slot[PAYLOAD_SIZE_SIZE..slot.len() - OVERFLOW_POINTER_SIZE].to_vec();
+        let mut page_num = u32::from_be_bytes(slot[slot.len() - OVERFLOW_POINTER_SIZE..].try_into().unwrap()) as usize;
+        while payload.len() < size && page_num != 0 {
+            let page = self.get_page_view(page_num).unwrap();
+            let take = (size - payload.len()).min(OVERFLOW_PAGE_SPACE);
+            payload.extend_from_slice(&page.buf[4..4 + take]);
+            page_num = self.read_u32(page_num, 0) as usize;
+        }
+        payload
+    }
+
+    /// Give back the overflow pages of a slot whose payload is being removed or replaced.
+    pub(crate) fn free_payload(&mut self, slot: &[u8]) {
+        let slot = &slot[..slot_size(slot)];
+        let mut page_num = u32::from_be_bytes(slot[slot.len() - OVERFLOW_POINTER_SIZE..].try_into().unwrap()) as usize;
+        while page_num != 0 {
+            let next = self.read_u32(page_num, 0) as usize;
+            self.free_page(page_num);
+            page_num = next;
+        }
+    }
+
+    fn read_u32(&self, page_num: usize, offset: usize) -> u32 {
+        let page = self.get_page_view(page_num).unwrap();
+        u32::from_be_bytes(page.buf[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn write_u32(&mut self, page_num: usize, offset: usize, value: u32) {
+        self.get_page(page_num).buf[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    /// Lay out the header page of a new database file.
+    fn initialize_header(&mut self) {
+        let page = self.get_page(HEADER_PAGE_NUM);
+        page.buf[..FILE_MAGIC.len()].copy_from_slice(FILE_MAGIC);
+        self.write_u32(HEADER_PAGE_NUM, FORMAT_VERSION_OFFSET, FILE_FORMAT_VERSION);
+        self.write_u32(HEADER_PAGE_NUM, CATALOG_SIZE_OFFSET, 0);
+        self.write_u32(HEADER_PAGE_NUM, CATALOG_NEXT_PAGE_OFFSET, 0);
+        self.write_u32(HEADER_PAGE_NUM, FREELIST_HEAD_OFFSET, 0);
+    }
+
+    /// Check that the file is a database of this format and version.
+    fn check_header(&self) -> Result<(), String> {
+        let page = self.get_page_view(HEADER_PAGE_NUM).unwrap();
+        if &page.buf[..FILE_MAGIC.len()] != FILE_MAGIC {
+            return Err(String::from("file is not a database of this format"));
+        }
+        let version = self.read_u32(HEADER_PAGE_NUM, FORMAT_VERSION_OFFSET);
+        if version < FILE_FORMAT_VERSION {
+            return Err(format!("file format version {} was written by an older build (this build reads version {}); open it with that build and .save it as a SQLite file, which this build reads",
+                               version, FILE_FORMAT_VERSION));
+        }
+        if version != FILE_FORMAT_VERSION {
+            return Err(format!("unsupported file format version {} (this build reads version {})", version, FILE_FORMAT_VERSION));
+        }
+        Ok(())
+    }
+
+    /// The page after `page_num` on the catalog's chain, which must be one of the file's.
+    fn next_catalog_page(&self, page_num: usize, offset: usize) -> Result<usize, String> {
+        let next = self.read_u32(page_num, offset) as usize;
+        if next == HEADER_PAGE_NUM || next >= self.num_pages {
+            return Err(format!("database disk image is malformed: catalog page {} out of range", next));
+        }
+        Ok(next)
+    }
+
+    /// The bytes of the schema catalog, read from the header page and its chain.
+    pub(crate) fn read_catalog(&self) -> Result<Vec<u8>, String> {
+        let size = self.read_u32(HEADER_PAGE_NUM, CATALOG_SIZE_OFFSET) as usize;
+        let first = size.min(PAGE_SIZE - FILE_HEADER_SIZE);
+        let mut catalog = self.get_page_view(HEADER_PAGE_NUM).unwrap().buf[FILE_HEADER_SIZE..FILE_HEADER_SIZE + first].to_vec();
+        let (mut page_num, mut next_offset) = (HEADER_PAGE_NUM, CATALOG_NEXT_PAGE_OFFSET);
+        while catalog.len() < size {
+            // A chain can be no longer than the file, so a longer one loops.
+            if catalog.len() > self.num_pages * PAGE_SIZE {
+                return Err(String::from("database disk image is malformed: catalog chain loops"));
+            }
+            page_num = self.next_catalog_page(page_num, next_offset)?;
+            next_offset = 0;
+            let take = (size - catalog.len()).min(CATALOG_PAGE_SPACE);
+            catalog.extend_from_slice(&self.get_page_view(page_num).unwrap().buf[4..4 + take]);
+        }
+        Ok(catalog)
+    }
+
+    /// Write the schema catalog to the header page, going on to a chain of pages when it does
+    /// not fit. Pages of the chain already written are used again.
+    pub(crate) fn write_catalog(&mut self, catalog: &[u8]) {
+        self.write_u32(HEADER_PAGE_NUM, CATALOG_SIZE_OFFSET, catalog.len() as u32);
+        let first = catalog.len().min(PAGE_SIZE - FILE_HEADER_SIZE);
+        self.get_page(HEADER_PAGE_NUM).buf[FILE_HEADER_SIZE..FILE_HEADER_SIZE + first].copy_from_slice(&catalog[..first]);
+        let (mut page_num, mut next_offset) = (HEADER_PAGE_NUM, CATALOG_NEXT_PAGE_OFFSET);
+        for chunk in catalog[first..].chunks(CATALOG_PAGE_SPACE) {
+            let next = match self.read_u32(page_num, next_offset) as usize {
+                0 => {
+                    let next = self.allocate_page();
+                    self.write_u32(page_num, next_offset, next as u32);
+                    next
+                }
+                next => next,
+            };
+            self.get_page(next).buf[4..4 + chunk.len()].copy_from_slice(chunk);
+            (page_num, next_offset) = (next, 0);
+        }
     }
 }
 
 pub fn pager_open(file_name: &str) -> Pager {
-    let file = OpenOptions::new()
+    let mut file = OpenOptions::new()
         .write(true)
         .create(true)
+        .truncate(false)
         .read(true)
         .open(file_name)
         .unwrap();
 
+    // Only the header of a SQLite file is read here; its pages are read as they are needed.
+    let mut header = Vec::new();
+    (&mut file).take(SQLITE_HEADER_SIZE as u64).read_to_end(&mut header).expect("Pager open: read error");
+    if header.starts_with(SQLITE_MAGIC) {
+        let file_len = file.metadata().expect("Pager open: read error").len();
+        let sqlite = SqliteFile::open(&header, file_len).unwrap_or_else(|err| {
+            println!("Error: {}", err);
+            process::exit(0x0100);
+        });
+        return Pager::new_sqlite(file, sqlite);
+    }
+
     let mut pager = Pager::new(file);
     if pager.num_pages == 0 {
-        let root_node = pager.get_page(0);
+        pager.initialize_header();
+        let root_node = pager.get_page(USERS_ROOT_PAGE_NUM);
         root_node.initialize_leaf_node();
         root_node.set_node_root(true);
+    } else if let Err(err) = pager.check_header() {
+        println!("Error: {}", err);
+        process::exit(0x0100);
     }
     pager
 }
\ No newline at end of file
@@ -0,0 +1,1109 @@
+use crate::collation::Collation;
+use crate::compound::CompoundOp;
+use crate::cte::Cte;
+use crate::expr::{BinaryOp, CompareOp, Expr};
+use crate::fts::{self, FtsOptions};
+use crate::result::PrepareResult;
+use crate::result::PrepareResult::*;
+use crate::schema::{CheckConstraint, ColumnDef, ColumnType, ForeignKey, ForeignKeyAction, IndexDef, KeyColumn, TableDef};
+use crate::statement::{JoinClause, JoinKind, OrderingTerm, ResultColumn, Statement, StatementType};
+use crate::subquery::Subquery;
+use crate::tokenizer::{tokenize, Token};
+use crate::value::Value;
+use crate::window::{Frame, FrameBound, FrameUnit, Window};
+
+pub struct Parser {
+    command: String,
+    tokens: Vec<Token>,
+    /// Where each token starts in `command`.
+    offsets: Vec<usize>,
+    pos: usize,
+}
+
+impl Parser {
+    pub fn new(command: &str) -> Result<Parser, PrepareResult> {
+        let trimmed = command.trim_start();
+        let leading = command.len() - trimmed.len();
+        let (tokens, offsets) = tokenize(command)?.into_iter().map(|(token, offset)| (token, offset - leading)).unzip();
+        Ok(Parser {
+            command: String::from(trimmed.trim_end().trim_end_matches(';').trim_end()),
+            tokens,
+            offsets,
+            pos: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn accept(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), PrepareResult> {
+        if self.accept(token) {
+            Ok(())
+        } else {
+            Err(PrepareSyntaxErr)
+        }
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek().is_some_and(|token| token.is_keyword(keyword)) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), PrepareResult> {
+        if self.accept_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(PrepareSyntaxErr)
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<String, PrepareResult> {
+        match self.next_token() {
+            Some(Token::Ident(ident)) => Ok(ident.to_lowercase()),
+            _ => Err(PrepareSyntaxErr),
+        }
+    }
+
+    /// Succeed only when every token was consumed, allowing one trailing `;`.
+    fn expect_end(&mut self) -> Result<(), PrepareResult> {
+        self.accept(&Token::Semicolon);
+        if self.peek().is_some() {
+            return Err(PrepareSyntaxErr);
+        }
+        Ok(())
+    }
+
+    /// Accept 9223372036854775808 written as an integer, which the tokenizer reads as a real
+    /// since only a minus in front of it brings it into the range of one.
+    fn accept_min_integer_magnitude(&mut self) -> bool {
+        let digits = i64::MIN.unsigned_abs().to_string();
+        let rest = self.offsets.get(self.pos).and_then(|offset| self.command[*offset..].strip_prefix(digits.as_str()));
+        if matches!(self.peek(), Some(Token::Real(_))) && rest.is_some_and(|rest| !rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '.')) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn parse_literal(&mut self) -> Result<Value, PrepareResult> {
+        match self.next_token() {
+            Some(Token::Integer(i)) => Ok(Value::Integer(i)),
+            Some(Token::Real(r)) => Ok(Value::Real(r)),
+            Some(Token::Minus) if self.accept_min_integer_magnitude() => Ok(Value::Integer(i64::MIN)),
+            Some(Token::Minus) => match self.next_token() {
+                Some(Token::Integer(i)) => Ok(Value::Integer(-i)),
+                Some(Token::Real(r)) => Ok(Value::Real(-r)),
+                _ => Err(PrepareSyntaxErr),
+            },
+            Some(Token::Str(s)) => Ok(Value::Text(s)),
+            Some(Token::Blob(b)) => Ok(Value::Blob(b)),
+            Some(token) if token.is_keyword("null") => Ok(Value::Null),
+            _ => Err(PrepareSyntaxErr),
+        }
+    }
+
+    /// expr := or; operators bind from OR (loosest) through AND, NOT, comparisons,
+    /// + and -, * / % and unary minus, to a postfix COLLATE (tightest).
+    pub fn parse_expr(&mut self) -> Result<Expr, PrepareResult> {
+        let mut left = self.parse_and()?;
+        while self.accept_keyword("or") {
+            let right = self.parse_and()?;
+            left = binary(BinaryOp::Or, left, right);
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, PrepareResult> {
+        let mut left = self.parse_not()?;
+        while self.accept_keyword("and") {
+            let right = self.parse_not()?;
+            left = binary(BinaryOp::And, left, right);
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, PrepareResult> {
+        if self.accept_keyword("not") {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    /// additive [op additive | IS [NOT] additive | MATCH additive | [NOT] IN (expr, ...) | [NOT] IN (select ...)
+    /// | [NOT] LIKE|GLOB additive [ESCAPE additive] | [NOT] BETWEEN additive AND additive ...]
+    fn parse_comparison(&mut self) -> Result<Expr, PrepareResult> {
+        let mut left = self.parse_additive()?;
+        loop {
+            if let Some(op) = self.peek().and_then(compare_op) {
+                self.pos += 1;
+                let right = self.parse_additive()?;
+                left = binary(BinaryOp::Compare(op), left, right);
+                continue;
+            }
+            if self.accept_keyword("is") {
+                let negated = self.accept_keyword("not");
+                let right = self.parse_additive()?;
+                left = Expr::Is { left: Box::new(left), right: Box::new(right), negated };
+                continue;
+            }
+            if self.accept_keyword("match") {
+                let right = self.parse_additive()?;
+                left = binary(BinaryOp::Match, left, right);
+                continue;
+            }
+            // NOT here only negates a following IN, LIKE, GLOB or BETWEEN.
+            let negated = self.peek().is_some_and(|token| token.is_keyword("not"))
+                && self.tokens.get(self.pos + 1).is_some_and(|token| {
+                    ["in", "like", "glob", "between"].iter().any(|keyword| token.is_keyword(keyword))
+                });
+            if negated {
+                self.pos += 1;
+            }
+            let operand = Box::new(left);
+            left = if self.accept_keyword("in") {
+                self.expect(&Token::LParen)?;
+                if self.peek().is_some_and(|token| token.is_keyword("select")) {
+                    let subquery = Box::new(self.parse_subquery()?);
+                    left = Expr::InSelect { operand, subquery, negated };
+                    continue;
+                }
+                let mut list = vec![self.parse_expr()?];
+                while self.accept(&Token::Comma) {
+                    list.push(self.parse_expr()?);
+                }
+                self.expect(&Token::RParen)?;
+                Expr::InList { operand, list, negated }
+            } else if self.peek().is_some_and(|token| token.is_keyword("like") || token.is_keyword("glob")) {
+                let glob = self.accept_keyword("glob");
+                if !glob {
+                    self.expect_keyword("like")?;
+                }
+                let pattern = Box::new(self.parse_additive()?);
+                let escape = if !glob && self.accept_keyword("escape") {
+                    Some(Box::new(self.parse_additive()?))
+                } else {
+                    None
+                };
+                Expr::Like { operand, pattern, escape, glob, negated }
+            } else if self.accept_keyword("between") {
+                // The bounds stop short of AND, which here separates them.
+                let low = Box::new(self.parse_additive()?);
+                self.expect_keyword("and")?;
+                let high = Box::new(self.parse_additive()?);
+                Expr::Between { operand, low, high, negated }
+            } else {
+                return Ok(*operand);
+            };
+        }
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, PrepareResult> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => BinaryOp::Add,
+                Some(Token::Minus) => BinaryOp::Subtract,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.parse_multiplicative()?;
+            left = binary(op, left, right);
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, PrepareResult> {
+        let mut left = self.parse_extract()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => BinaryOp::Multiply,
+                Some(Token::Slash) => BinaryOp::Divide,
+                Some(Token::Percent) => BinaryOp::Remainder,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.parse_extract()?;
+            left = binary(op, left, right);
+        }
+    }
+
+    fn parse_extract(&mut self) -> Result<Expr, PrepareResult> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Arrow) => BinaryOp::JsonExtract,
+                Some(Token::LongArrow) => BinaryOp::JsonExtractValue,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.parse_unary()?;
+            left = binary(op, left, right);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, PrepareResult> {
+        if self.accept(&Token::Minus) {
+            if self.accept_min_integer_magnitude() {
+                return Ok(Expr::Literal(Value::Integer(i64::MIN)));
+            }
+            return Ok(match self.parse_unary()? {
+                Expr::Literal(Value::Integer(i)) => Expr::Literal(Value::Integer(-i)),
+                Expr::Literal(Value::Real(r)) => Expr::Literal(Value::Real(-r)),
+                operand => Expr::Negate(Box::new(operand)),
+            });
+        }
+        if self.accept(&Token::Plus) {
+            return self.parse_unary();
+        }
+        let mut expr = self.parse_primary()?;
+        while self.accept_keyword("collate") {
+            let collation = Collation::from_name(&self.expect_ident()?);
+            expr = Expr::Collate { operand: Box::new(expr), collation, declared: false };
+        }
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, PrepareResult> {
+        match self.next_token() {
+            Some(Token::Integer(i)) => Ok(Expr::Literal(Value::Integer(i))),
+            Some(Token::Real(r)) => Ok(Expr::Literal(Value::Real(r))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(Value::Text(s))),
+            Some(Token::Blob(b)) => Ok(Expr::Literal(Value::Blob(b))),
+            Some(token) if token.is_keyword("null") => Ok(Expr::Literal(Value::Null)),
+            Some(token) if token.is_keyword("exists") && self.accept(&Token::LParen) => {
+                Ok(Expr::Exists(Box::new(self.parse_subquery()?)))
+            }
+            Some(Token::Ident(name)) if self.accept(&Token::LParen) => self.parse_function_args(name.to_lowercase()),
+            Some(Token::Ident(name)) if self.accept(&Token::Dot) => {
+                let column = self.expect_ident()?;
+                Ok(Expr::Column(format!("{}.{}", name, column).to_lowercase()))
+            }
+            Some(Token::Ident(name)) => Ok(Expr::Column(name.to_lowercase())),
+            Some(Token::LParen) if self.peek().is_some_and(|token| token.is_keyword("select")) => {
+                Ok(Expr::Subquery(Box::new(self.parse_subquery()?)))
+            }
+            Some(Token::LParen) => {
+                let expr = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                Ok(expr)
+            }
+            _ => Err(PrepareSyntaxErr),
+        }
+    }
+
+    /// select ...) after an opening parenthesis, keeping the SELECT's text.
+    fn parse_subquery(&mut self) -> Result<Subquery, PrepareResult> {
+        let start = self.offsets[self.pos];
+        let select = self.parse_select_body()?;
+        let end = self.offsets.get(self.pos).copied().unwrap_or(self.command.len());
+        self.expect(&Token::RParen)?;
+        let sql = String::from(self.command[start..end].trim_end());
+        Ok(Subquery { select, sql, rows: None, values: None })
+    }
+
+    /// ([DISTINCT] expr [, ...]) | (*) | () after a function name, the parenthesis already consumed,
+    /// then OVER (window) for a window call
+    fn parse_function_args(&mut self, name: String) -> Result<Expr, PrepareResult> {
+        let mut args = Vec::new();
+        let distinct = self.accept_keyword("distinct");
+        if !distinct && self.accept(&Token::Star) {
+            // `count(*)` counts rows, which an empty argument list stands for.
+        } else if distinct || self.peek() != Some(&Token::RParen) {
+            loop {
+                args.push(self.parse_expr()?);
+                if !self.accept(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        self.expect(&Token::RParen)?;
+        if !self.accept_keyword("over") {
+            return Ok(Expr::Function { name, args, distinct, registered: None });
+        }
+        // A window call takes every row of its frame, so DISTINCT has no place in it.
+        if distinct {
+            return Err(PrepareSyntaxErr);
+        }
+        self.expect(&Token::LParen)?;
+        let window = self.parse_window()?;
+        self.expect(&Token::RParen)?;
+        Ok(Expr::Window { name, args, window: Box::new(window), registered: None })
+    }
+
+    /// [PARTITION BY expr [, ...]] [ORDER BY ...] [frame] inside OVER (...), where frame is
+    /// `{ROWS | RANGE} {start | BETWEEN start AND end}`. A RANGE offset measures the distance from
+    /// the single ORDER BY term.
+    fn parse_window(&mut self) -> Result<Window, PrepareResult> {
+        let mut partition_by = Vec::new();
+        if self.accept_keyword("partition") {
+            self.expect_keyword("by")?;
+            loop {
+                partition_by.push(self.parse_expr()?);
+                if !self.accept(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        let order_by = self.parse_order_by()?;
+        let unit = if self.accept_keyword("rows") {
+            FrameUnit::Rows
+        } else if self.accept_keyword("range") {
+            FrameUnit::Range
+        } else {
+            return Ok(Window { partition_by, order_by, frame: None });
+        };
+        let (start, end) = if self.accept_keyword("between") {
+            let start = self.parse_frame_bound()?;
+            self.expect_keyword("and")?;
+            (start, self.parse_frame_bound()?)
+        } else {
+            (self.parse_frame_bound()?, FrameBound::CurrentRow)
+        };
+        // The frame cannot start after it ends, whatever the current row.
+        let position = |bound: FrameBound| match bound {
+            FrameBound::UnboundedPreceding => 0,
+            FrameBound::Preceding(_) => 1,
+            FrameBound::CurrentRow => 2,
+            FrameBound::Following(_) => 3,
+            FrameBound::UnboundedFollowing => 4,
+        };
+        if start == FrameBound::UnboundedFollowing || end == FrameBound::UnboundedPreceding || position(start) > position(end) {
+            return Err(PrepareSyntaxErr);
+        }
+        let offset = |bound: FrameBound| matches!(bound, FrameBound::Preceding(_) | FrameBound::Following(_));
+        if unit == FrameUnit::Range && (offset(start) || offset(end)) && order_by.len() != 1 {
+            return Err(PrepareSyntaxErr);
+        }
+        Ok(Window { partition_by, order_by, frame: Some(Frame { unit, start, end }) })
+    }
+
+    /// UNBOUNDED {PRECEDING | FOLLOWING} | CURRENT ROW | n {PRECEDING | FOLLOWING}
+    fn parse_frame_bound(&mut self) -> Result<FrameBound, PrepareResult> {
+        if self.accept_keyword("unbounded") {
+            if self.accept_keyword("preceding") {
+                return Ok(FrameBound::UnboundedPreceding);
+            }
+            self.expect_keyword("following")?;
+            return Ok(FrameBound::UnboundedFollowing);
+        }
+        if self.accept_keyword("current") {
+            self.expect_keyword("row")?;
+            return Ok(FrameBound::CurrentRow);
+        }
+        let offset = match self.next_token() {
+            Some(Token::Integer(offset)) => offset,
+            _ => return Err(PrepareSyntaxErr),
+        };
+        if self.accept_keyword("preceding") {
+            return Ok(FrameBound::Preceding(offset));
+        }
+        self.expect_keyword("following")?;
+        Ok(FrameBound::Following(offset))
+    }
+
+    /// [WHERE expr]
+    fn parse_where(&mut self) -> Result<Option<Expr>, PrepareResult> {
+        if !self.accept_keyword("where") {
+            return Ok(None);
+        }
+        Ok(Some(self.parse_expr()?))
+    }
+
+    /// select [result-column, ... [from table [join ...] [where ...]] [group by ... [having ...]]
+    /// [{union [all] | intersect | except} select ...] [order by ...] [limit ...]], where a result column is `*`,
+    /// `table.*` or `expr [[as] alias]`. A bare `select` lists the users table.
+    pub fn parse_select(&mut self) -> Result<Statement, PrepareResult> {
+        if self.tokens.len() == 1 || (self.tokens.len() == 2 && self.tokens[1] == Token::Semicolon) {
+            self.expect_keyword("select")?;
+            let mut stmt = Statement::new(StatementType::StatementSelect);
+            stmt.result_columns.push(ResultColumn::All);
+            self.expect_end()?;
+            return Ok(stmt);
+        }
+        let stmt = self.parse_select_body()?;
+        self.expect_end()?;
+        Ok(stmt)
+    }
+
+    /// with [recursive] name [(column, ...)] as (select ... [union [all] select ...]) [, ...] select ...
+    /// As in SQLite, RECURSIVE is optional: a table whose second SELECT reads it is recursive either way.
+    pub fn parse_with(&mut self) -> Result<Statement, PrepareResult> {
+        self.expect_keyword("with")?;
+        self.accept_keyword("recursive");
+        let mut ctes = Vec::new();
+        loop {
+            ctes.push(self.parse_cte()?);
+            if !self.accept(&Token::Comma) {
+                break;
+            }
+        }
+        let mut stmt = self.parse_select_body()?;
+        self.expect_end()?;
+        stmt.ctes = ctes;
+        Ok(stmt)
+    }
+
+    /// name [(column, ...)] as (select ... [union [all] select ...]) of a WITH clause. After a
+    /// UNION, ORDER BY, LIMIT and OFFSET belong to the whole table rather than the second SELECT.
+    fn parse_cte(&mut self) -> Result<Cte, PrepareResult> {
+        let name = self.expect_ident()?;
+        let columns = if self.accept(&Token::LParen) { self.parse_ident_list()? } else { Vec::new() };
+        self.expect_keyword("as")?;
+        self.expect(&Token::LParen)?;
+        let mut initial = self.parse_select_body()?;
+        self.expect(&Token::RParen)?;
+        // The SELECT after the last UNION recurses if it reads the table being defined; the
+        // ORDER BY and LIMIT after it then order and bound the recursion.
+        let union_all = match initial.compound.last() {
+            Some((CompoundOp::Union, select)) if select.reads_table(&name) => false,
+            Some((CompoundOp::UnionAll, select)) if select.reads_table(&name) => true,
+            _ => return Ok(Cte { name, columns, initial, recursive: None, union_all: false, order_by: Vec::new(), limit: None, offset: None }),
+        };
+        let (_, recursive) = initial.compound.pop().unwrap();
+        Ok(Cte {
+            name,
+            columns,
+            recursive: Some(recursive),
+            union_all,
+            order_by: std::mem::take(&mut initial.order_by),
+            limit: initial.limit.take(),
+            offset: initial.offset.take(),
+            initial,
+        })
+    }
+
+    /// The SELECT of `parse_select`, as a whole statement or nested in an expression: SELECTs
+    /// joined by UNION [ALL], INTERSECT or EXCEPT, then the ORDER BY and LIMIT of them all.
+    fn parse_select_body(&mut self) -> Result<Statement, PrepareResult> {
+        let mut stmt = self.parse_select_core()?;
+        while let Some(op) = self.parse_compound_op() {
+            stmt.compound.push((op, self.parse_select_core()?));
+        }
+        stmt.order_by = self.parse_order_by()?;
+        if self.accept_keyword("limit") {
+            let limit = self.parse_expr()?;
+            // `LIMIT offset, count` is the older spelling of `LIMIT count OFFSET offset`.
+            if self.accept(&Token::Comma) {
+                stmt.offset = Some(limit);
+                stmt.limit = Some(self.parse_expr()?);
+            } else {
+                stmt.limit = Some(limit);
+                if self.accept_keyword("offset") {
+                    stmt.offset = Some(self.parse_expr()?);
+                }
+            }
+        }
+        Ok(stmt)
+    }
+
+    /// UNION [ALL] | INTERSECT | EXCEPT
+    fn parse_compound_op(&mut self) -> Option<CompoundOp> {
+        if self.accept_keyword("union") {
+            Some(if self.accept_keyword("all") { CompoundOp::UnionAll } else { CompoundOp::Union })
+        } else if self.accept_keyword("intersect") {
+            Some(CompoundOp::Intersect)
+        } else if self.accept_keyword("except") {
+            Some(CompoundOp::Except)
+        } else {
+            None
+        }
+    }
+
+    /// One SELECT of a compound, up to its HAVING.
+    fn parse_select_core(&mut self) -> Result<Statement, PrepareResult> {
+        self.expect_keyword("select")?;
+        let mut stmt = Statement::new(StatementType::StatementSelect);
+        loop {
+            stmt.result_columns.push(self.parse_result_column()?);
+            if !self.accept(&Token::Comma) {
+                break;
+            }
+        }
+        if self.accept_keyword("from") {
+            stmt.table_name = self.expect_ident()?;
+            stmt.table_args = self.parse_table_args()?;
+            stmt.table_alias = self.parse_table_alias()?;
+            stmt.joins = self.parse_joins()?;
+            stmt.where_clause = self.parse_where()?;
+        } else if stmt.result_columns.iter().any(|column| !matches!(column, ResultColumn::Expr { .. })) {
+            // `*` needs a table to expand into.
+            return Err(PrepareSyntaxErr);
+        } else {
+            stmt.table_name = String::new();
+        }
+        if self.accept_keyword("group") {
+            self.expect_keyword("by")?;
+            loop {
+                stmt.group_by.push(self.parse_expr()?);
+                if !self.accept(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        if self.accept_keyword("having") {
+            stmt.having = Some(self.parse_expr()?);
+        }
+        Ok(stmt)
+    }
+
+    /// [(expr [, ...])] after a table name, which makes it a table-valued function
+    fn parse_table_args(&mut self) -> Result<Option<Vec<Expr>>, PrepareResult> {
+        if !self.accept(&Token::LParen) {
+            return Ok(None);
+        }
+        let mut args = Vec::new();
+        if !self.accept(&Token::RParen) {
+            loop {
+                args.push(self.parse_expr()?);
+                if !self.accept(&Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(&Token::RParen)?;
+        }
+        Ok(Some(args))
+    }
+
+    /// [[as] alias] after a table name
+    fn parse_table_alias(&mut self) -> Result<Option<String>, PrepareResult> {
+        const KEYWORDS: [&str; 14] = ["where", "group", "having", "order", "limit", "join", "inner", "left",
+            "cross", "on", "outer", "union", "intersect", "except"];
+        let explicit = self.accept_keyword("as");
+        match self.peek() {
+            Some(Token::Ident(ident)) if explicit || !KEYWORDS.iter().any(|keyword| ident.eq_ignore_ascii_case(keyword)) => {
+                let alias = ident.to_lowercase();
+                self.pos += 1;
+                Ok(Some(alias))
+            }
+            _ if explicit => Err(PrepareSyntaxErr),
+            _ => Ok(None),
+        }
+    }
+
+    /// { , table | [inner | cross | left [outer]] join table } [(args)] [[as] alias] [on expr],
+    /// repeated
+    fn parse_joins(&mut self) -> Result<Vec<JoinClause>, PrepareResult> {
+        let mut joins = Vec::new();
+        loop {
+            let kind = if self.accept(&Token::Comma) {
+                JoinKind::Cross
+            } else if self.accept_keyword("join") {
+                JoinKind::Inner
+            } else if self.accept_keyword("inner") {
+                self.expect_keyword("join")?;
+                JoinKind::Inner
+            } else if self.accept_keyword("cross") {
+                self.expect_keyword("join")?;
+                JoinKind::Cross
+            } else if self.accept_keyword("left") {
+                self.accept_keyword("outer");
+                self.expect_keyword("join")?;
+                JoinKind::Left
+            } else {
+                return Ok(joins);
+            };
+            let table_name = self.expect_ident()?;
+            let args = self.parse_table_args()?;
+            let alias = self.parse_table_alias()?;
+            let on = if self.accept_keyword("on") { Some(self.parse_expr()?) } else { None };
+            joins.push(JoinClause { kind, table_name, alias, args, on });
+        }
+    }
+
+    /// [ORDER BY expr [ASC | DESC] [, ...]]
+    fn parse_order_by(&mut self) -> Result<Vec<OrderingTerm>, PrepareResult> {
+        let mut terms = Vec::new();
+        if !self.accept_keyword("order") {
+            return Ok(terms);
+        }
+        self.expect_keyword("by")?;
+        loop {
+            let expr = self.parse_expr()?;
+            let descending = self.accept_keyword("desc");
+            if !descending {
+                self.accept_keyword("asc");
+            }
+            terms.push(OrderingTerm { expr, descending });
+            if !self.accept(&Token::Comma) {
+                return Ok(terms);
+            }
+        }
+    }
+
+    fn parse_result_column(&mut self) -> Result<ResultColumn, PrepareResult> {
+        const CLAUSE_KEYWORDS: [&str; 9] = ["from", "where", "group", "having", "order", "limit", "union", "intersect", "except"];
+        if self.accept(&Token::Star) {
+            return Ok(ResultColumn::All);
+        }
+        if let (Some(Token::Ident(table)), Some(Token::Dot), Some(Token::Star)) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1), self.tokens.get(self.pos + 2)) {
+            let table = table.to_lowercase();
+            self.pos += 3;
+            return Ok(ResultColumn::TableAll(table));
+        }
+        let expr = self.parse_expr()?;
+        // Aliases keep their case, since they name the column in the output.
+        let explicit = self.accept_keyword("as");
+        let alias = match self.peek() {
+            Some(Token::Ident(ident)) if explicit || !CLAUSE_KEYWORDS.iter().any(|keyword| ident.eq_ignore_ascii_case(keyword)) => {
+                Some(ident.clone())
+            }
+            _ if explicit => return Err(PrepareSyntaxErr),
+            _ => None,
+        };
+        if alias.is_some() {
+            self.pos += 1;
+        }
+        Ok(ResultColumn::Expr { expr, alias })
+    }
+
+    /// update table set column = literal [, ...] [where ...]
+    pub fn parse_update(&mut self) -> Result<Statement, PrepareResult> {
+        self.expect_keyword("update")?;
+        let mut stmt = Statement::new(StatementType::StatementUpdate);
+        stmt.table_name = self.expect_ident()?;
+        self.expect_keyword("set")?;
+        loop {
+            let column = self.expect_ident()?;
+            self.expect(&Token::Eq)?;
+            stmt.assignments.push((column, self.parse_expr()?));
+            if !self.accept(&Token::Comma) {
+                break;
+            }
+        }
+        stmt.where_clause = self.parse_where()?;
+        self.expect_end()?;
+        Ok(stmt)
+    }
+
+    /// delete from table [where ...]
+    pub fn parse_delete(&mut self) -> Result<Statement, PrepareResult> {
+        self.expect_keyword("delete")?;
+        self.expect_keyword("from")?;
+        let mut stmt = Statement::new(StatementType::StatementDelete);
+        stmt.table_name = self.expect_ident()?;
+        stmt.where_clause = self.parse_where()?;
+        self.expect_end()?;
+        Ok(stmt)
+    }
+
+    /// insert into table [(column, ...)] values (expr, ...) [, (expr, ...) ...]
+    pub fn parse_insert(&mut self) -> Result<Statement, PrepareResult> {
+        self.expect_keyword("insert")?;
+        self.expect_keyword("into")?;
+        let mut stmt = Statement::new(StatementType::StatementInsert);
+        stmt.table_name = self.expect_ident()?;
+        if self.accept(&Token::LParen) {
+            stmt.columns = self.parse_ident_list()?;
+        }
+        self.expect_keyword("values")?;
+        loop {
+            self.expect(&Token::LParen)?;
+            let mut values = vec![self.parse_expr()?];
+            while self.accept(&Token::Comma) {
+                values.push(self.parse_expr()?);
+            }
+            self.expect(&Token::RParen)?;
+            if !stmt.columns.is_empty() && values.len() != stmt.columns.len() {
+                return Err(PrepareSyntaxErr);
+            }
+            stmt.values.push(values);
+            if !self.accept(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect_end()?;
+        Ok(stmt)
+    }
+
+    /// ident [, ident ...] ) with the opening parenthesis already consumed
+    fn parse_ident_list(&mut self) -> Result<Vec<String>, PrepareResult> {
+        let mut idents = vec![self.expect_ident()?];
+        while self.accept(&Token::Comma) {
+            idents.push(self.expect_ident()?);
+        }
+        self.expect(&Token::RParen)?;
+        Ok(idents)
+    }
+
+    /// pragma name [= value]
+    pub fn parse_pragma(&mut self) -> Result<Statement, PrepareResult> {
+        self.expect_keyword("pragma")?;
+        let mut stmt = Statement::new(StatementType::StatementPragma);
+        stmt.table_name = String::new();
+        let name = self.expect_ident()?;
+        let value = if self.accept(&Token::Eq) {
+            match self.next_token() {
+                Some(Token::Ident(value)) => Some(Value::Text(value.to_lowercase())),
+                Some(Token::Integer(value)) => Some(Value::Integer(value)),
+                _ => return Err(PrepareSyntaxErr),
+            }
+        } else {
+            None
+        };
+        self.expect_end()?;
+        stmt.pragma = Some((name, value));
+        Ok(stmt)
+    }
+
+    /// create table ... | create [unique] index ...
+    pub fn parse_create(&mut self) -> Result<Statement, PrepareResult> {
+        if self.tokens.get(1).is_some_and(|token| token.is_keyword("table")) {
+            self.parse_create_table()
+        } else if self.tokens.get(1).is_some_and(|token| token.is_keyword("virtual")) {
+            self.parse_create_virtual_table()
+        } else {
+            self.parse_create_index()
+        }
+    }
+
+    /// create table name (column-def, ... [, table-constraint, ...]) [without rowid]
+    fn parse_create_table(&mut self) -> Result<Statement, PrepareResult> {
+        self.expect_keyword("create")?;
+        self.expect_keyword("table")?;
+        let name = self.expect_ident()?;
+        self.expect(&Token::LParen)?;
+        let mut columns: Vec<ColumnDef> = Vec::new();
+        let mut checks = Vec::new();
+        let mut foreign_keys = Vec::new();
+        let mut key = Vec::new();
+        let mut autoincrement = false;
+        loop {
+            let constraint_name = self.parse_constraint_name()?;
+            if self.accept_keyword("check") {
+                checks.push(CheckConstraint { name: constraint_name, expr: self.parse_check()? });
+            } else if self.accept_keyword("foreign") {
+                self.expect_keyword("key")?;
+                self.expect(&Token::LParen)?;
+                let column = self.expect_ident()?;
+                self.expect(&Token::RParen)?;
+                self.expect_keyword("references")?;
+                foreign_keys.push(self.parse_references(column)?);
+            } else if self.accept_keyword("primary") {
+                self.expect_keyword("key")?;
+                if !key.is_empty() {
+                    return Err(PrepareSyntaxErr);
+                }
+                self.expect(&Token::LParen)?;
+                loop {
+                    let column_name = self.expect_ident()?;
+                    let column = columns.iter().position(|column| column.name == column_name).ok_or(PrepareSyntaxErr)?;
+                    columns[column].primary_key = true;
+                    key.push(KeyColumn { column, descending: self.parse_descending() });
+                    if !self.accept(&Token::Comma) {
+                        break;
+                    }
+                }
+                self.expect(&Token::RParen)?;
+            } else if self.accept_keyword("unique") {
+                self.expect(&Token::LParen)?;
+                let column_name = self.expect_ident()?;
+                self.expect(&Token::RParen)?;
+                let column = columns.iter_mut()
+                    .find(|column| column.name == column_name)
+                    .ok_or(PrepareSyntaxErr)?;
+                column.unique = true;
+            } else if constraint_name.is_none() {
+                let position = columns.len();
+                columns.push(self.parse_column_def(position, &mut checks, &mut foreign_keys, &mut key, &mut autoincrement)?);
+            } else {
+                return Err(PrepareSyntaxErr);
+            }
+            if !self.accept(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RParen)?;
+        let mut without_rowid = None;
+        if self.accept_keyword("without") {
+            self.expect_keyword("rowid")?;
+            // AUTOINCREMENT hands out rowids, which such a table does not have.
+            if autoincrement {
+                return Err(PrepareSyntaxErr);
+            }
+            without_rowid = Some(key);
+        } else if key.len() <= 1 && !key.first().is_some_and(|key| columns[key.column].column_type == ColumnType::Integer) {
+            // With no INTEGER PRIMARY KEY to be its rowid the table keeps a hidden one, and a
+            // primary key on another column is only a UNIQUE constraint, as in SQLite.
+            if let Some(key) = key.first() {
+                columns[key.column].primary_key = false;
+                columns[key.column].unique = true;
+            }
+            columns.push(ColumnDef {
+                name: String::from("rowid"),
+                column_type: ColumnType::Integer,
+                primary_key: true,
+                unique: false,
+                not_null: false,
+                default: None,
+                collation: Collation::Binary,
+                hidden: true,
+            });
+        }
+        self.expect_end()?;
+        let mut stmt = Statement::new(StatementType::StatementCreateTable);
+        stmt.table_name = name.clone();
+        stmt.table_to_create = Some(TableDef {
+            name,
+            columns,
+            checks,
+            foreign_keys,
+            root_page_num: 0,
+            sql: self.command.clone(),
+            autoincrement,
+            without_rowid,
+            rows: None,
+            recursive: None,
+            fts: None,
+        });
+        Ok(stmt)
+    }
+
+    /// create virtual table name using fts5(column [unindexed], ... [, tokenize = 'tokenizer [args]'])
+    fn parse_create_virtual_table(&mut self) -> Result<Statement, PrepareResult> {
+        self.expect_keyword("create")?;
+        self.expect_keyword("virtual")?;
+        self.expect_keyword("table")?;
+        let name = self.expect_ident()?;
+        self.expect_keyword("using")?;
+        // FTS5 is the only module there is.
+        self.expect_keyword("fts5")?;
+        self.expect(&Token::LParen)?;
+        let mut columns = Vec::new();
+        let mut unindexed = Vec::new();
+        let mut tokenize = Vec::new();
+        loop {
+            let word = self.expect_ident()?;
+            if self.accept(&Token::Eq) {
+                if word != "tokenize" {
+                    return Err(PrepareSyntaxErr);
+                }
+                tokenize = match self.next_token() {
+                    Some(Token::Str(value)) | Some(Token::Ident(value)) => value.split_whitespace().map(String::from).collect(),
+                    _ => return Err(PrepareSyntaxErr),
+                };
+            } else {
+                columns.push(word);
+                unindexed.push(self.accept_keyword("unindexed"));
+            }
+            if !self.accept(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RParen)?;
+        self.expect_end()?;
+        let mut stmt = Statement::new(StatementType::StatementCreateTable);
+        stmt.table_name = name.clone();
+        stmt.table_to_create = Some(TableDef {
+            columns: fts::table_columns(&name, columns),
+            name,
+            checks: Vec::new(),
+            foreign_keys: Vec::new(),
+            root_page_num: 0,
+            sql: self.command.clone(),
+            autoincrement: false,
+            without_rowid: None,
+            rows: None,
+            recursive: None,
+            fts: Some(FtsOptions { tokenize, unindexed }),
+        });
+        Ok(stmt)
+    }
+
+    /// [asc | desc], returning whether it is descending.
+    fn parse_descending(&mut self) -> bool {
+        if self.accept_keyword("desc") {
+            return true;
+        }
+        self.accept_keyword("asc");
+        false
+    }
+
+    /// [constraint name]
+    fn parse_constraint_name(&mut self) -> Result<Option<String>, PrepareResult> {
+        if self.accept_keyword("constraint") {
+            return Ok(Some(self.expect_ident()?));
+        }
+        Ok(None)
+    }
+
+    /// (expr) after CHECK
+    fn parse_check(&mut self) -> Result<Expr, PrepareResult> {
+        self.expect(&Token::LParen)?;
+        let expr = self.parse_expr()?;
+        self.expect(&Token::RParen)?;
+        // A CHECK is evaluated against the row alone, with no table to run a subquery on.
+        if !expr.subqueries().is_empty() {
+            return Err(PrepareSyntaxErr);
+        }
+        Ok(expr)
+    }
+
+    /// parent [(column)] [on delete set null | cascade | restrict | no action],
+    /// after REFERENCES
+    fn parse_references(&mut self, column: String) -> Result<ForeignKey, PrepareResult> {
+        let parent_table = self.expect_ident()?;
+        let mut parent_column = None;
+        if self.accept(&Token::LParen) {
+            parent_column = Some(self.expect_ident()?);
+            self.expect(&Token::RParen)?;
+        }
+        let mut on_delete = ForeignKeyAction::NoAction;
+        if self.accept_keyword("on") {
+            self.expect_keyword("delete")?;
+            on_delete = if self.accept_keyword("cascade") {
+                ForeignKeyAction::Cascade
+            } else if self.accept_keyword("restrict") {
+                ForeignKeyAction::Restrict
+            } else if self.accept_keyword("set") {
+                self.expect_keyword("null")?;
+                ForeignKeyAction::SetNull
+            } else {
+                self.expect_keyword("no")?;
+                self.expect_keyword("action")?;
+                ForeignKeyAction::NoAction
+            };
+        }
+        Ok(ForeignKey { column, parent_table, parent_column, on_delete })
+    }
+
+    /// name [type [(size [, size])]] [[constraint name] primary key [asc | desc] [autoincrement]
+    /// | unique | not null | null | default value | collate name | check (expr)
+    /// | references parent ...], for the column at `position`. Column CHECKs and REFERENCES are
+    /// added to `checks` and `foreign_keys`, a PRIMARY KEY makes `key`, and AUTOINCREMENT sets
+    /// `autoincrement`.
+    fn parse_column_def(&mut self, position: usize, checks: &mut Vec<CheckConstraint>, foreign_keys: &mut Vec<ForeignKey>,
+                        key: &mut Vec<KeyColumn>, autoincrement: &mut bool) -> Result<ColumnDef, PrepareResult> {
+        const CONSTRAINT_KEYWORDS: [&str; 9] = ["constraint", "primary", "unique", "not", "null", "default", "collate", "check", "references"];
+        let name = self.expect_ident()?;
+        // A type name may run over several words, as in `double precision`.
+        let mut type_words = Vec::new();
+        while let Some(Token::Ident(word)) = self.peek() {
+            if CONSTRAINT_KEYWORDS.iter().any(|keyword| word.eq_ignore_ascii_case(keyword)) {
+                break;
+            }
+            type_words.push(word.clone());
+            self.pos += 1;
+        }
+        if !type_words.is_empty() && self.accept(&Token::LParen) {
+            self.parse_literal()?;
+            if self.accept(&Token::Comma) {
+                self.parse_literal()?;
+            }
+            self.expect(&Token::RParen)?;
+        }
+        let column_type = ColumnType::from_declared(&type_words.join(" "));
+        let mut column = ColumnDef {
+            name,
+            column_type,
+            primary_key: false,
+            unique: false,
+            not_null: false,
+            default: None,
+            collation: Collation::Binary,
+            hidden: false,
+        };
+        loop {
+            let constraint_name = self.parse_constraint_name()?;
+            if self.accept_keyword("primary") {
+                self.expect_keyword("key")?;
+                if !key.is_empty() {
+                    return Err(PrepareSyntaxErr);
+                }
+                column.primary_key = true;
+                key.push(KeyColumn { column: position, descending: self.parse_descending() });
+                if self.accept_keyword("autoincrement") {
+                    // Only an INTEGER PRIMARY KEY is a rowid, which AUTOINCREMENT allocates.
+                    if column.column_type != ColumnType::Integer {
+                        return Err(PrepareSyntaxErr);
+                    }
+                    *autoincrement = true;
+                }
+            } else if self.accept_keyword("unique") {
+                column.unique = true;
+            } else if self.accept_keyword("not") {
+                self.expect_keyword("null")?;
+                column.not_null = true;
+            } else if self.accept_keyword("null") {
+                column.not_null = false;
+            } else if self.accept_keyword("default") {
+                // A parenthesized default may be any expression; a bare one is a signed literal.
+                let default = if self.peek() == Some(&Token::LParen) {
+                    self.parse_primary()?
+                } else {
+                    Expr::Literal(self.parse_literal()?)
+                };
+                if !default.subqueries().is_empty() {
+                    return Err(PrepareSyntaxErr);
+                }
+                column.default = Some(default);
+            } else if self.accept_keyword("collate") {
+                column.collation = Collation::from_name(&self.expect_ident()?);
+            } else if self.accept_keyword("check") {
+                checks.push(CheckConstraint { name: constraint_name, expr: self.parse_check()? });
+            } else if self.accept_keyword("references") {
+                foreign_keys.push(self.parse_references(column.name.clone())?);
+            } else if constraint_name.is_none() {
+                return Ok(column);
+            } else {
+                return Err(PrepareSyntaxErr);
+            }
+        }
+    }
+
+    /// create [unique] index name on table (column | expr)
+    pub fn parse_create_index(&mut self) -> Result<Statement, PrepareResult> {
+        self.expect_keyword("create")?;
+        let unique = self.accept_keyword("unique");
+        self.expect_keyword("index")?;
+        let name = self.expect_ident()?;
+        self.expect_keyword("on")?;
+        let table_name = self.expect_ident()?;
+        self.expect(&Token::LParen)?;
+        let (column, expr) = match self.parse_expr()? {
+            Expr::Column(column) => (column, None),
+            // An index is kept up to date from the row alone, with no table to run a subquery on.
+            expr if !expr.subqueries().is_empty() => return Err(PrepareSyntaxErr),
+            expr => (expr.to_string(), Some(expr)),
+        };
+        self.expect(&Token::RParen)?;
+        self.expect_end()?;
+        let mut stmt = Statement::new(StatementType::StatementCreateIndex);
+        stmt.table_name = table_name.clone();
+        stmt.index_to_create = Some(IndexDef { name, table_name, column, expr, unique });
+        Ok(stmt)
+    }
+}
+
+fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+    Expr::Binary { op, left: Box::new(left), right: Box::new(right) }
+}
+
+fn compare_op(token: &Token) -> Option<CompareOp> {
+    match token {
+        Token::Eq => Some(CompareOp::Eq),
+        Token::Ne => Some(CompareOp::Ne),
+        Token::Lt => Some(CompareOp::Lt),
+        Token::Le => Some(CompareOp::Le),
+        Token::Gt => Some(CompareOp::Gt),
+        Token::Ge => Some(CompareOp::Ge),
+        _ => None,
+    }
+}
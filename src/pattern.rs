@@ -0,0 +1,124 @@
+/// One element of a compiled LIKE or GLOB pattern.
+#[derive(PartialEq, Debug)]
+enum PatternToken {
+    /// `%` in LIKE, `*` in GLOB: any run of characters, including none.
+    AnySequence,
+    /// `_` in LIKE, `?` in GLOB: exactly one character.
+    AnyChar,
+    Char(char),
+    /// `[...]` in GLOB: one character in (or, when negated, not in) the ranges.
+    Set {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+}
+
+impl PatternToken {
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        match self {
+            PatternToken::AnySequence | PatternToken::AnyChar => true,
+            PatternToken::Char(p) if ignore_case => p.eq_ignore_ascii_case(&c),
+            PatternToken::Char(p) => *p == c,
+            PatternToken::Set { ranges, negated } => {
+                ranges.iter().any(|(low, high)| *low <= c && c <= *high) != *negated
+            }
+        }
+    }
+}
+
+fn compile_like(pattern: &str, escape: Option<char>) -> Vec<PatternToken> {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            c if Some(c) == escape => PatternToken::Char(chars.next().unwrap_or(c)),
+            '%' => PatternToken::AnySequence,
+            '_' => PatternToken::AnyChar,
+            c => PatternToken::Char(c),
+        });
+    }
+    tokens
+}
+
+/// Compile a GLOB pattern, or None if a `[` is never closed, which matches nothing.
+fn compile_glob(pattern: &str) -> Option<Vec<PatternToken>> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => tokens.push(PatternToken::AnySequence),
+            '?' => tokens.push(PatternToken::AnyChar),
+            '[' => {
+                i += 1;
+                let negated = chars.get(i) == Some(&'^');
+                if negated {
+                    i += 1;
+                }
+                // A `]` straight after the opening bracket is a member, not the end.
+                let mut ranges = Vec::new();
+                let mut first = true;
+                loop {
+                    let c = *chars.get(i)?;
+                    if c == ']' && !first {
+                        break;
+                    }
+                    if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|high| *high != ']') {
+                        ranges.push((c, chars[i + 2]));
+                        i += 3;
+                    } else {
+                        ranges.push((c, c));
+                        i += 1;
+                    }
+                    first = false;
+                }
+                tokens.push(PatternToken::Set { ranges, negated });
+            }
+            c => tokens.push(PatternToken::Char(c)),
+        }
+        i += 1;
+    }
+    Some(tokens)
+}
+
+/// Match the whole text, backtracking only to the most recent wildcard sequence.
+fn matches(pattern: &[PatternToken], text: &str, ignore_case: bool) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(PatternToken::AnySequence) => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some(token) if token.matches(text[t], ignore_case) => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match backtrack {
+            // Let the last sequence wildcard swallow one more character and retry.
+            Some((star, start)) => {
+                p = star + 1;
+                t = start + 1;
+                backtrack = Some((star, start + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|token| *token == PatternToken::AnySequence)
+}
+
+/// SQL LIKE: `%` and `_` wildcards, ASCII letters compared without case, as in SQLite.
+pub fn like(pattern: &str, text: &str, escape: Option<char>) -> bool {
+    matches(&compile_like(pattern, escape), text, true)
+}
+
+/// SQL GLOB: Unix-style `*`, `?` and `[...]` wildcards, compared with case.
+pub fn glob(pattern: &str, text: &str) -> bool {
+    compile_glob(pattern).is_some_and(|pattern| matches(&pattern, text, false))
+}
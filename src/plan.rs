@@ -0,0 +1,1072 @@
+use std::fmt;
+use std::ops::Bound;
+use crate::expr::{BinaryOp, CompareOp, Expr, Predicate};
+use crate::fts;
+use crate::join::{from_table, JoinScope, JoinTable};
+use crate::schema::{RowKey, Schema, TableDef};
+use crate::statement::{assign_rowids, insert_rows, is_aggregate_select, order_by_terms, result_columns, AggregateQuery, JoinKind, Statement, StatementType};
+use crate::table::Table;
+use crate::value::Value;
+use crate::window::{window_calls, window_sorts};
+
+/// Access path a statement uses to reach the rows it touches.
+#[derive(PartialEq, Debug)]
+pub enum QueryPlan {
+    /// Walk every leaf from `Cursor::table_start` along the next leaf chain.
+    FullScan,
+    /// Descend from the root to a single key with `Table::find`.
+    PrimaryKeySeek(i64),
+    /// Walk the rows of a WITHOUT ROWID table whose primary key starts with the values.
+    KeySeek(Vec<Value>),
+    /// Walk a secondary index between the bounds, then fetch each rowid from the table.
+    IndexScan {
+        index_name: String,
+        column: String,
+        lower: Bound<Value>,
+        upper: Bound<Value>,
+    },
+    /// Search a full-text table's index for the rows its MATCH terms find, in rowid order,
+    /// then fetch each from the table.
+    FullTextMatch(Vec<Expr>),
+}
+
+impl QueryPlan {
+    /// The EXPLAIN QUERY PLAN line for the table, under the name the statement gives it.
+    fn describe(&self, table_def: &TableDef, table_name: &str) -> String {
+        // SQLite shows a full-text table as a virtual table, searched by an index string of `=`
+        // for a rowid and `M` and the column number for each MATCH.
+        if table_def.fts.is_some() {
+            let index = match self {
+                QueryPlan::PrimaryKeySeek(_) => String::from("="),
+                QueryPlan::FullTextMatch(matches) => {
+                    let mut columns: Vec<usize> = matches.iter()
+                        .flat_map(|term| term.columns())
+                        .filter_map(|name| table_def.column_index(name))
+                        .map(|column| column - 1)
+                        .collect();
+                    columns.sort();
+                    columns.iter().map(|column| format!("M{}", column)).collect()
+                }
+                _ => String::new(),
+            };
+            return format!("SCAN {} VIRTUAL TABLE INDEX 0:{}", table_name, index);
+        }
+        match self {
+            QueryPlan::FullScan => format!("SCAN {}", table_name),
+            QueryPlan::PrimaryKeySeek(_) => {
+                let column = &table_def.columns[table_def.primary_key().unwrap()].name;
+                format!("SEARCH {} USING PRIMARY KEY ({}=?)", table_name, column)
+            }
+            QueryPlan::KeySeek(key) => {
+                let terms: Vec<String> = table_def.key_columns().iter()
+                    .take(key.len())
+                    .map(|column| format!("{}=?", table_def.columns[*column].name))
+                    .collect();
+                format!("SEARCH {} USING PRIMARY KEY ({})", table_name, terms.join(" AND "))
+            }
+            QueryPlan::IndexScan { index_name, column, lower, upper } => {
+                // Like SQLite, name an index on an expression without spelling the expression out.
+                let column = if table_def.column_index(column).is_some() { column.as_str() } else { "<expr>" };
+                let mut terms = Vec::new();
+                match (lower, upper) {
+                    (Bound::Included(low), Bound::Included(high)) if low == high => terms.push(format!("{}=?", column)),
+                    _ => {
+                        match lower {
+                            Bound::Included(_) => terms.push(format!("{}>=?", column)),
+                            Bound::Excluded(_) => terms.push(format!("{}>?", column)),
+                            Bound::Unbounded => {}
+                        }
+                        match upper {
+                            Bound::Included(_) => terms.push(format!("{}<=?", column)),
+                            Bound::Excluded(_) => terms.push(format!("{}<?", column)),
+                            Bound::Unbounded => {}
+                        }
+                    }
+                }
+                format!("SEARCH {} USING INDEX {} ({})", table_name, index_name, terms.join(" AND "))
+            }
+            QueryPlan::FullTextMatch(_) => format!("SCAN {}", table_name),
+        }
+    }
+}
+
+/// How a join reaches the rows of one of its tables for each row of the tables before it.
+#[derive(PartialEq, Debug)]
+pub enum JoinStrategy {
+    /// The first table, reached by its own access path.
+    Outer(QueryPlan),
+    /// Scan every row of the table for each outer row.
+    NestedLoop,
+    /// Seek the table's primary key to the value of the outer expression.
+    PrimaryKey(Expr),
+    /// Look the value of the outer expression up in an index on the column.
+    Index {
+        index_name: String,
+        column: String,
+        outer: Expr,
+    },
+    /// Hash the table's rows on the column once, then probe with the value of the outer expression.
+    Hash {
+        column: String,
+        outer: Expr,
+    },
+}
+
+impl JoinStrategy {
+    fn describe(&self, table: &JoinTable) -> String {
+        if let Some(args) = &table.args {
+            return describe_function_scan(&table.name, args.len());
+        }
+        match self {
+            JoinStrategy::Outer(plan) => plan.describe(&table.def, &table.name),
+            JoinStrategy::NestedLoop => format!("SCAN {}", table.name),
+            JoinStrategy::PrimaryKey(_) => {
+                let column = &table.def.columns[table.def.primary_key().unwrap()].name;
+                format!("SEARCH {} USING PRIMARY KEY ({}=?)", table.name, column)
+            }
+            JoinStrategy::Index { index_name, column, .. } => {
+                format!("SEARCH {} USING INDEX {} ({}=?)", table.name, index_name, column)
+            }
+            JoinStrategy::Hash { column, .. } => format!("SEARCH {} USING HASH TABLE ({}=?)", table.name, column),
+        }
+    }
+}
+
+/// One step of a compiled statement, as listed by `EXPLAIN`.
+#[derive(PartialEq, Debug)]
+pub enum Operation {
+    /// Open a cursor with `Cursor::table_start` on the tree rooted at the page.
+    TableStart(usize),
+    /// Jump to the address once the cursor is past the last cell.
+    EndOfTable(usize),
+    /// Decode the row under the cursor with `Cursor::cursor_value`.
+    CursorValue,
+    /// Jump to the address unless the row satisfies the WHERE clause.
+    Filter(usize),
+    /// Emit the decoded row.
+    ResultRow,
+    /// Jump to the address, using up one row of the OFFSET, while any of it is left.
+    SkipOffset(usize),
+    /// Count an output row against the LIMIT and jump to the address once it is reached.
+    DecrJumpZero(usize),
+    /// Seek the table's primary key to the key the joined row gives it.
+    SeekKey(String),
+    /// Hash the rows of the table on their join key.
+    HashBuild(String),
+    /// Look the key the joined row gives the table up in its hash table.
+    HashProbe(String),
+    /// Take the next row of the table with the probed key, or jump to the address when none are left.
+    HashNext(usize),
+    /// Jump to the address unless the joined row satisfies the ON clause.
+    JoinFilter(usize),
+    /// Start a LEFT JOIN's search for rows of the table that match.
+    ResetMatch(String),
+    /// Note that a row of the LEFT JOIN's table matched.
+    SetMatch(String),
+    /// Jump to the address if a row of the LEFT JOIN's table matched.
+    IfMatched(usize),
+    /// Fill the table's columns of the joined row with NULLs.
+    NullRow(String),
+    /// Count the rows of the tree rooted at the page from its leaf headers.
+    CountCells(usize),
+    /// Fold the row into the aggregates of its group, spilling new groups once memory is full.
+    AggStep,
+    /// Take the next finished group, or jump to the address once every group is out.
+    AggFinal(usize),
+    /// Jump to the address unless the group satisfies the HAVING clause.
+    Having(usize),
+    /// Add the row to the ORDER BY sorter, spilling a sorted run once memory is full.
+    SorterInsert,
+    /// Sort the rows, merging any spilled runs, or jump to the address if there are none.
+    SorterSort(usize),
+    /// Take the row at the front of the sorter.
+    SorterData,
+    /// Step to the next sorted row and jump back to the address, unless none are left.
+    SorterNext(usize),
+    /// Move the cursor with `Cursor::advance` and jump back to the address.
+    Advance(usize),
+    /// Position a cursor at the cell for the key with `Table::find`.
+    Find(Option<RowKey>),
+    /// Jump to the address if the cell under the cursor does not hold the sought key.
+    NotFound(usize),
+    /// Stop with `ExecuteDuplicateKey` if the cell already holds the key.
+    CheckDuplicateKey(Option<RowKey>),
+    /// Stop with `ExecuteUniqueViolation` if the unique index already holds the value.
+    CheckUnique(String),
+    /// Stop with `ExecuteForeignKeyViolation` unless the referenced row exists in the table.
+    CheckForeignKey(String),
+    /// Stop with `ExecuteForeignKeyViolation` if rows of the table reference a changed value.
+    CheckReferenced(String),
+    /// Apply the ON DELETE action of the table's rows that reference the deleted row.
+    OnDelete(String),
+    /// Write the row with `Cursor::leaf_node_insert`, splitting the leaf when full.
+    LeafNodeInsert(Option<RowKey>),
+    /// Position an index cursor at the lower bound of the scan.
+    IndexSeek(String),
+    /// Jump to the address once the index cursor is past the upper bound.
+    IndexEnd(usize),
+    /// Step the index cursor and jump back to the address.
+    IndexNext(usize),
+    /// Position a table cursor at the rowid taken from the index or row set.
+    SeekRowid,
+    /// Remember the rowid of the current row for a later write pass.
+    RowSetAdd,
+    /// Remember the rowids of the rows of the full-text table rooted at the page that its
+    /// index finds for the MATCH terms.
+    FullTextQuery(usize),
+    /// Take the next remembered rowid, or jump to the address when none are left.
+    RowSetNext(usize),
+    Goto(usize),
+    /// Remove the cell under the cursor.
+    DeleteCell,
+    /// Rewrite the row under the cursor with the SET values, moving it if the id changed.
+    UpdateCell,
+    IndexInsert(String),
+    IndexDelete(String),
+    /// Allocate the root page of a new index tree.
+    CreateIndexTree(String),
+    /// Allocate the root page of a new table tree.
+    CreateTableTree(String),
+    /// Persist the schema to the catalog page.
+    WriteCatalog,
+    Halt,
+}
+
+impl Operation {
+    fn opcode(&self) -> &'static str {
+        match self {
+            Operation::TableStart(_) => "TableStart",
+            Operation::EndOfTable(_) => "EndOfTable",
+            Operation::CursorValue => "CursorValue",
+            Operation::Filter(_) => "Filter",
+            Operation::ResultRow => "ResultRow",
+            Operation::SkipOffset(_) => "SkipOffset",
+            Operation::DecrJumpZero(_) => "DecrJumpZero",
+            Operation::SeekKey(_) => "SeekKey",
+            Operation::HashBuild(_) => "HashBuild",
+            Operation::HashProbe(_) => "HashProbe",
+            Operation::HashNext(_) => "HashNext",
+            Operation::JoinFilter(_) => "JoinFilter",
+            Operation::ResetMatch(_) => "ResetMatch",
+            Operation::SetMatch(_) => "SetMatch",
+            Operation::IfMatched(_) => "IfMatched",
+            Operation::NullRow(_) => "NullRow",
+            Operation::CountCells(_) => "CountCells",
+            Operation::AggStep => "AggStep",
+            Operation::AggFinal(_) => "AggFinal",
+            Operation::Having(_) => "Having",
+            Operation::SorterInsert => "SorterInsert",
+            Operation::SorterSort(_) => "SorterSort",
+            Operation::SorterData => "SorterData",
+            Operation::SorterNext(_) => "SorterNext",
+            Operation::Advance(_) => "Advance",
+            Operation::Find(_) => "Find",
+            Operation::NotFound(_) => "NotFound",
+            Operation::CheckDuplicateKey(_) => "CheckDuplicateKey",
+            Operation::CheckUnique(_) => "CheckUnique",
+            Operation::CheckForeignKey(_) => "CheckForeignKey",
+            Operation::CheckReferenced(_) => "CheckReferenced",
+            Operation::OnDelete(_) => "OnDelete",
+            Operation::LeafNodeInsert(_) => "LeafNodeInsert",
+            Operation::IndexSeek(_) => "IndexSeek",
+            Operation::IndexEnd(_) => "IndexEnd",
+            Operation::IndexNext(_) => "IndexNext",
+            Operation::SeekRowid => "SeekRowid",
+            Operation::RowSetAdd => "RowSetAdd",
+            Operation::FullTextQuery(_) => "FullTextQuery",
+            Operation::RowSetNext(_) => "RowSetNext",
+            Operation::Goto(_) => "Goto",
+            Operation::DeleteCell => "DeleteCell",
+            Operation::UpdateCell => "UpdateCell",
+            Operation::IndexInsert(_) => "IndexInsert",
+            Operation::IndexDelete(_) => "IndexDelete",
+            Operation::CreateIndexTree(_) => "CreateIndexTree",
+            Operation::CreateTableTree(_) => "CreateTableTree",
+            Operation::WriteCatalog => "WriteCatalog",
+            Operation::Halt => "Halt",
+        }
+    }
+
+    fn p1(&self) -> String {
+        match self {
+            Operation::TableStart(page_num) | Operation::CountCells(page_num)
+            | Operation::FullTextQuery(page_num) => page_num.to_string(),
+            Operation::EndOfTable(addr) | Operation::Advance(addr) | Operation::Filter(addr)
+            | Operation::NotFound(addr) | Operation::IndexEnd(addr) | Operation::IndexNext(addr)
+            | Operation::RowSetNext(addr) | Operation::Goto(addr)
+            | Operation::SorterSort(addr) | Operation::SorterNext(addr)
+            | Operation::SkipOffset(addr) | Operation::DecrJumpZero(addr)
+            | Operation::AggFinal(addr) | Operation::Having(addr)
+            | Operation::HashNext(addr) | Operation::JoinFilter(addr) | Operation::IfMatched(addr) => addr.to_string(),
+            Operation::Find(key) | Operation::CheckDuplicateKey(key) | Operation::LeafNodeInsert(key) =>
+                key.as_ref().map_or(String::new(), RowKey::to_string),
+            Operation::IndexSeek(name) | Operation::IndexInsert(name) | Operation::IndexDelete(name)
+            | Operation::CreateIndexTree(name) | Operation::CreateTableTree(name)
+            | Operation::CheckUnique(name) | Operation::CheckForeignKey(name)
+            | Operation::CheckReferenced(name) | Operation::OnDelete(name)
+            | Operation::SeekKey(name) | Operation::HashBuild(name) | Operation::HashProbe(name)
+            | Operation::ResetMatch(name) | Operation::SetMatch(name) | Operation::NullRow(name) => name.clone(),
+            _ => String::new(),
+        }
+    }
+
+    fn comment(&self) -> String {
+        match self {
+            Operation::TableStart(page_num) => format!("leftmost leaf of root page {}", page_num),
+            Operation::EndOfTable(addr) => format!("if end of table goto {}", addr),
+            Operation::CursorValue => String::from("decode row at cursor"),
+            Operation::Filter(addr) => format!("if WHERE is false goto {}", addr),
+            Operation::ResultRow => String::from("output result columns"),
+            Operation::SkipOffset(addr) => format!("if OFFSET left, count it and goto {}", addr),
+            Operation::DecrJumpZero(addr) => format!("count row, if LIMIT reached goto {}", addr),
+            Operation::SeekKey(_) => String::from("seek key from joined row"),
+            Operation::HashBuild(_) => String::from("hash rows on join key"),
+            Operation::HashProbe(_) => String::from("look up key from joined row"),
+            Operation::HashNext(addr) => format!("next row with key, else goto {}", addr),
+            Operation::JoinFilter(addr) => format!("if ON is false goto {}", addr),
+            Operation::ResetMatch(_) => String::from("no row matched yet"),
+            Operation::SetMatch(_) => String::from("a row matched"),
+            Operation::IfMatched(addr) => format!("if a row matched goto {}", addr),
+            Operation::NullRow(_) => String::from("use NULL for every column"),
+            Operation::CountCells(page_num) => format!("count cells of leaves under root page {}", page_num),
+            Operation::AggStep => String::from("fold row into its group"),
+            Operation::AggFinal(addr) => format!("next finished group, else goto {}", addr),
+            Operation::Having(addr) => format!("if HAVING is false goto {}", addr),
+            Operation::SorterInsert => String::from("add row to sorter"),
+            Operation::SorterSort(addr) => format!("sort rows, if none goto {}", addr),
+            Operation::SorterData => String::from("row at front of sorter"),
+            Operation::SorterNext(addr) => format!("next sorted row, goto {}", addr),
+            Operation::Advance(addr) => format!("next cell, goto {}", addr),
+            Operation::Find(key) => format!("seek {}", key_comment(key)),
+            Operation::NotFound(addr) => format!("if key not found goto {}", addr),
+            Operation::CheckDuplicateKey(key) => format!("fail if {} exists", key_comment(key)),
+            Operation::CheckUnique(_) => String::from("fail if value is already indexed"),
+            Operation::CheckForeignKey(_) => String::from("fail unless parent row exists"),
+            Operation::CheckReferenced(_) => String::from("fail if child rows reference old value"),
+            Operation::OnDelete(_) => String::from("cascade, set null or restrict child rows"),
+            Operation::LeafNodeInsert(key) => format!("insert cell {}", key_comment(key)),
+            Operation::IndexSeek(_) => String::from("seek index to lower bound"),
+            Operation::IndexEnd(addr) => format!("if past upper bound goto {}", addr),
+            Operation::IndexNext(addr) => format!("next index entry, goto {}", addr),
+            Operation::SeekRowid => String::from("seek table to rowid"),
+            Operation::RowSetAdd => String::from("remember rowid"),
+            Operation::FullTextQuery(page_num) => format!("remember rowids that MATCH in table of root page {}", page_num),
+            Operation::RowSetNext(addr) => format!("next remembered rowid, else goto {}", addr),
+            Operation::Goto(addr) => format!("goto {}", addr),
+            Operation::DeleteCell => String::from("delete cell"),
+            Operation::UpdateCell => String::from("write SET values"),
+            Operation::IndexInsert(_) => String::from("insert index entry"),
+            Operation::IndexDelete(_) => String::from("delete index entry"),
+            Operation::CreateIndexTree(_) => String::from("allocate index root page"),
+            Operation::CreateTableTree(_) => String::from("allocate table root page"),
+            Operation::WriteCatalog => String::from("save schema"),
+            Operation::Halt => String::new(),
+        }
+    }
+}
+
+/// A key as a comment names it; the key of a row that cannot be made stays unnamed.
+fn key_comment(key: &Option<RowKey>) -> String {
+    key.as_ref().map_or(String::from("key"), |key| format!("key={}", key))
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<18}{:<9} {}", self.opcode(), self.p1(), self.comment())
+    }
+}
+
+/// Keys of the rows an INSERT writes, rowids it would generate included, or None for a row
+/// whose key cannot be made, which fails the INSERT.
+fn insert_keys(statement: &Statement, table_def: &TableDef, table: &Table) -> Vec<Option<RowKey>> {
+    let rows = insert_rows(statement, table_def)
+        .and_then(|mut rows| assign_rowids(table, table_def, &mut rows).map(|_| rows));
+    match rows {
+        Ok(rows) => rows.iter().map(|row| table_def.row_key(row).ok()).collect(),
+        Err(_) => vec![None; statement.values.len()],
+    }
+}
+
+pub fn plan_statement(statement: &Statement, table: &Table) -> QueryPlan {
+    let schema = &table.schema;
+    let table_def = match schema.find_table(&statement.table_name) {
+        Some(table_def) => table_def,
+        None => return QueryPlan::FullScan,
+    };
+    match statement.stmt_type {
+        StatementType::StatementInsert => {
+            match insert_keys(statement, table_def, table).into_iter().next().flatten() {
+                Some(RowKey::Key(key)) => QueryPlan::KeySeek(key),
+                Some(RowKey::Rowid(key)) => QueryPlan::PrimaryKeySeek(key),
+                // The INSERT fails before it seeks anything.
+                None => QueryPlan::FullScan,
+            }
+        }
+        StatementType::StatementCreateIndex | StatementType::StatementCreateTable
+        | StatementType::StatementPragma => QueryPlan::FullScan,
+        StatementType::StatementSelect | StatementType::StatementUpdate | StatementType::StatementDelete =>
+            plan_where(statement.where_clause.as_ref(), table_def, schema),
+    }
+}
+
+/// The `column op literal` and `expr op literal` terms of the WHERE clause's top-level AND, with the literal moved
+/// to the right and BETWEEN split into its two bounds. Every other term is left to the filter.
+fn sargable_terms(where_clause: &Expr) -> Vec<Predicate> {
+    // A column compares under its declared collation, which is the one its index is ordered by;
+    // an explicit COLLATE on either side may not be, so it keeps the term from the index.
+    let term = |column: &Expr, op: CompareOp, value: &Expr| match (column.without_declared_collation(), value) {
+        (Expr::Column(column), Expr::Literal(value)) => Some(Predicate { column: column.clone(), op, value: value.clone() }),
+        // Any other expression of the row's columns can be looked up in an index on it, which
+        // names it by its SQL.
+        (expr, Expr::Literal(value)) if is_indexable(expr) => Some(Predicate { column: expr.to_string(), op, value: value.clone() }),
+        _ => None,
+    };
+    let mut terms = Vec::new();
+    for conjunct in where_clause.conjuncts() {
+        match conjunct {
+            Expr::Binary { op: BinaryOp::Compare(op), left, right } => {
+                terms.extend(term(left, *op, right).or_else(|| term(right, op.flip(), left)));
+            }
+            Expr::Between { operand, low, high, negated: false } => {
+                terms.extend(term(operand, CompareOp::Ge, low));
+                terms.extend(term(operand, CompareOp::Le, high));
+            }
+            _ => {}
+        }
+    }
+    terms
+}
+
+/// Whether an index could hold the expression's value: it reads columns of the row, and
+/// nothing else but literals and functions of them.
+fn is_indexable(expr: &Expr) -> bool {
+    !matches!(expr, Expr::Column(_) | Expr::Literal(_) | Expr::Collate { .. })
+        && !expr.columns().is_empty()
+        && expr.subqueries().is_empty()
+        && expr.outer_columns().is_empty()
+}
+
+/// Pick the access path for a WHERE clause: the MATCH terms of a full-text table, then an
+/// equality on the primary key, or on the leading columns of a WITHOUT ROWID table's, then an
+/// equality on an indexed column, then a range on an indexed column, else a full scan.
+pub fn plan_where(where_clause: Option<&Expr>, table_def: &TableDef, schema: &Schema) -> QueryPlan {
+    let terms = match where_clause {
+        Some(where_clause) => sargable_terms(where_clause),
+        None => Vec::new(),
+    };
+    // A MATCH can only be answered by the index of a full-text table.
+    if table_def.fts.is_some() {
+        let matches = fts::match_terms(where_clause, table_def);
+        if !matches.is_empty() {
+            return QueryPlan::FullTextMatch(matches);
+        }
+    }
+    // A WITH table has no key, and no indexes either.
+    let primary_key = table_def.primary_key().map(|column| &table_def.columns[column].name);
+    for predicate in &terms {
+        if Some(&predicate.column) == primary_key && predicate.op == CompareOp::Eq {
+            if let Value::Integer(id) = predicate.value {
+                return QueryPlan::PrimaryKeySeek(id);
+            }
+        }
+    }
+    if table_def.without_rowid.is_some() {
+        let mut key = Vec::new();
+        for column in table_def.key_columns() {
+            let name = &table_def.columns[column].name;
+            match terms.iter().find(|predicate| predicate.column.eq_ignore_ascii_case(name) && predicate.op == CompareOp::Eq) {
+                Some(predicate) => key.push(predicate.value.clone()),
+                None => break,
+            }
+        }
+        if !key.is_empty() {
+            return QueryPlan::KeySeek(key);
+        }
+    }
+
+    let indexes = schema.table_indexes(&table_def.name);
+    for index in &indexes {
+        for predicate in &terms {
+            if predicate.column == index.def.column && predicate.op == CompareOp::Eq {
+                return QueryPlan::IndexScan {
+                    index_name: index.def.name.clone(),
+                    column: index.def.column.clone(),
+                    lower: Bound::Included(predicate.value.clone()),
+                    upper: Bound::Included(predicate.value.clone()),
+                };
+            }
+        }
+    }
+
+    for index in indexes {
+        let mut lower = Bound::Unbounded;
+        let mut upper = Bound::Unbounded;
+        for predicate in terms.iter().filter(|predicate| predicate.column == index.def.column) {
+            let value = predicate.value.clone();
+            match predicate.op {
+                CompareOp::Gt if lower == Bound::Unbounded => lower = Bound::Excluded(value),
+                CompareOp::Ge if lower == Bound::Unbounded => lower = Bound::Included(value),
+                CompareOp::Lt if upper == Bound::Unbounded => upper = Bound::Excluded(value),
+                CompareOp::Le if upper == Bound::Unbounded => upper = Bound::Included(value),
+                _ => {}
+            }
+        }
+        if lower != Bound::Unbounded || upper != Bound::Unbounded {
+            return QueryPlan::IndexScan {
+                index_name: index.def.name.clone(),
+                column: index.def.column.clone(),
+                lower,
+                upper,
+            };
+        }
+    }
+    QueryPlan::FullScan
+}
+
+/// Pick how a join reaches each of its tables. The first table takes the access path of the
+/// WHERE terms that read only it. Each later table is reached through an equality between one
+/// of its columns and the tables before it: by its primary key, else by an index on the column,
+/// else by a hash table of its rows. Without such an equality it is scanned for every outer
+/// row. A LEFT JOIN only looks at its ON clause, since WHERE applies after its NULL rows are added.
+pub fn plan_joins(scope: &JoinScope, where_clause: Option<&Expr>, schema: &Schema) -> Vec<JoinStrategy> {
+    let where_terms = where_clause.map(|where_clause| where_clause.conjuncts()).unwrap_or_default();
+    // The tables an expression reads, or None if it names a column of no table or holds a
+    // subquery, whose value is not known until the row it belongs to is read.
+    let tables_read = |expr: &Expr| -> Option<Vec<usize>> {
+        if !expr.subqueries().is_empty() {
+            return None;
+        }
+        expr.columns().into_iter().map(|name| scope.find_column(name).ok().map(|(table, _)| table)).collect()
+    };
+
+    let first = &scope.tables[0];
+    let first_terms = where_terms.iter()
+        .filter(|term| tables_read(term).is_some_and(|tables| tables.iter().all(|table| *table == 0)))
+        .map(|term| term.replace(&|expr| match expr {
+            Expr::Column(name) => name.split_once('.').map(|(_, column)| Expr::Column(String::from(column))),
+            _ => None,
+        }))
+        .reduce(|left, right| Expr::Binary { op: BinaryOp::And, left: Box::new(left), right: Box::new(right) });
+    let mut strategies = vec![JoinStrategy::Outer(plan_where(first_terms.as_ref(), &first.def, schema))];
+
+    for (i, table) in scope.tables.iter().enumerate().skip(1) {
+        // A table-valued function makes its rows afresh for each row before it.
+        if table.args.is_some() {
+            strategies.push(JoinStrategy::NestedLoop);
+            continue;
+        }
+        let mut terms = table.on.as_ref().map(|on| on.conjuncts()).unwrap_or_default();
+        if table.kind != JoinKind::Left {
+            terms.extend(where_terms.iter().copied());
+        }
+        let indexes = schema.table_indexes(&table.def.name);
+        // Candidates are ranked primary key, index, hash; the first of the best rank wins.
+        let mut best: Option<(u8, JoinStrategy)> = None;
+        for term in terms {
+            let (left, right) = match term {
+                Expr::Binary { op: BinaryOp::Compare(CompareOp::Eq), left, right } => (left, right),
+                _ => continue,
+            };
+            for (inner, outer) in [(left, right), (right, left)] {
+                let (inner, outer) = (inner.without_declared_binary(), outer.without_declared_binary());
+                let column = match inner {
+                    Expr::Column(name) => match scope.find_column(name) {
+                        Ok((table_num, column)) if table_num == i => &table.def.columns[column],
+                        _ => continue,
+                    },
+                    _ => continue,
+                };
+                if !tables_read(outer).is_some_and(|tables| tables.iter().all(|table_num| *table_num < i)) {
+                    continue;
+                }
+                // A column that declares a collation other than BINARY comes wrapped in it, as
+                // does one of an explicit COLLATE; either compares in a way no key, index or hash
+                // table follows.
+                if matches!(outer, Expr::Collate { .. }) {
+                    continue;
+                }
+                let outer = outer.clone();
+                let candidate = if table.def.primary_key().is_some_and(|key| table.def.columns[key].name == column.name) {
+                    (0, JoinStrategy::PrimaryKey(outer))
+                } else if let Some(index) = indexes.iter().find(|index| index.def.column.eq_ignore_ascii_case(&column.name)) {
+                    (1, JoinStrategy::Index { index_name: index.def.name.clone(), column: column.name.clone(), outer })
+                } else {
+                    (2, JoinStrategy::Hash { column: column.name.clone(), outer })
+                };
+                if best.as_ref().is_none_or(|(rank, _)| candidate.0 < *rank) {
+                    best = Some(candidate);
+                }
+            }
+        }
+        strategies.push(best.map_or(JoinStrategy::NestedLoop, |(_, strategy)| strategy));
+    }
+    strategies
+}
+
+/// Whether the rows the plan reaches must go through the sorter for ORDER BY. They need not
+/// when the only term reads, ascending, the key the plan already walks in order.
+pub fn needs_sort(order_by: &[(Expr, bool)], table_def: &TableDef, plan: &QueryPlan) -> bool {
+    let ordered_column = match (plan, table_def.primary_key(), table_def.without_rowid.as_deref()) {
+        (QueryPlan::IndexScan { column, .. }, _, _) => column,
+        (_, Some(primary_key), _) => &table_def.columns[primary_key].name,
+        (_, None, Some([first, ..])) if !first.descending => &table_def.columns[first.column].name,
+        // The rows of a join come in no order of their own.
+        _ => return !order_by.is_empty(),
+    };
+    match order_by {
+        [] => false,
+        [(Expr::Column(name), false)] => !name.eq_ignore_ascii_case(ordered_column),
+        _ => true,
+    }
+}
+
+/// Whether a SELECT on the table sorts its rows; a statement whose ORDER BY does not resolve
+/// is shown as sorting, since it fails before reading any rows. An aggregate SELECT outputs
+/// groups, and window calls rows in window order, which no access path orders.
+pub fn select_sorts(statement: &Statement, table_def: &TableDef, plan: &QueryPlan) -> bool {
+    if is_aggregate_select(statement) || !window_calls(&statement.row_exprs()).is_empty() {
+        return !statement.order_by.is_empty();
+    }
+    match result_columns(statement, Some(table_def)).and_then(|columns| order_by_terms(&statement.order_by, &columns, Some(table_def))) {
+        Ok(order_by) => needs_sort(&order_by, table_def, plan),
+        Err(_) => !statement.order_by.is_empty(),
+    }
+}
+
+/// Whether an aggregate SELECT on the table answers `count(*)` from the leaf headers.
+fn select_counts_rows(statement: &Statement, table_def: &TableDef) -> bool {
+    let query = result_columns(statement, Some(table_def)).and_then(|columns| {
+        let order_by = order_by_terms(&statement.order_by, &columns, Some(table_def))?;
+        AggregateQuery::resolve(statement, &columns, &order_by, Some(table_def))
+    });
+    query.is_ok_and(|query| query.counts_rows)
+}
+
+/// The temporary trees a SELECT on the table builds, named by the clause they serve.
+pub fn select_temp_trees(statement: &Statement, table_def: &TableDef, plan: &QueryPlan) -> Vec<&'static str> {
+    let mut trees = Vec::new();
+    if !statement.group_by.is_empty() {
+        trees.push("GROUP BY");
+    }
+    trees.extend(std::iter::repeat_n("WINDOW", window_sorts(&statement.row_exprs())));
+    if select_sorts(statement, table_def, plan) {
+        trees.push("ORDER BY");
+    }
+    trees
+}
+
+/// Point the jumps of a loop body at its loop: `SkipOffset` goes on to the next row and
+/// `DecrJumpZero` leaves the loop. The body is built before those addresses are known.
+fn resolve_jumps(body: Vec<Operation>, next: usize, exit: usize) -> Vec<Operation> {
+    body.into_iter()
+        .map(|operation| match operation {
+            Operation::SkipOffset(_) => Operation::SkipOffset(next),
+            Operation::DecrJumpZero(_) => Operation::DecrJumpZero(exit),
+            operation => operation,
+        })
+        .collect()
+}
+
+/// Append the loop that visits every row reached by the plan, running `body` on each one
+/// that passes the WHERE clause.
+fn compile_scan(operations: &mut Vec<Operation>, plan: &QueryPlan, root_page_num: usize,
+                has_filter: bool, body: Vec<Operation>) {
+    let start = operations.len();
+    let filter_len = if has_filter { 1 } else { 0 };
+    match plan {
+        QueryPlan::FullScan => {
+            let next = start + 3 + filter_len + body.len();
+            operations.push(Operation::TableStart(root_page_num));
+            operations.push(Operation::EndOfTable(next + 1));
+            operations.push(Operation::CursorValue);
+            if has_filter {
+                operations.push(Operation::Filter(next));
+            }
+            operations.extend(resolve_jumps(body, next, next + 1));
+            operations.push(Operation::Advance(start + 1));
+        }
+        QueryPlan::PrimaryKeySeek(key) => {
+            let end = start + 3 + filter_len + body.len();
+            operations.push(Operation::Find(Some(RowKey::Rowid(*key))));
+            operations.push(Operation::NotFound(end));
+            operations.push(Operation::CursorValue);
+            if has_filter {
+                operations.push(Operation::Filter(end));
+            }
+            operations.extend(resolve_jumps(body, end, end));
+        }
+        QueryPlan::KeySeek(key) => {
+            let next = start + 3 + filter_len + body.len();
+            operations.push(Operation::Find(Some(RowKey::Key(key.clone()))));
+            operations.push(Operation::IndexEnd(next + 1));
+            operations.push(Operation::CursorValue);
+            if has_filter {
+                operations.push(Operation::Filter(next));
+            }
+            operations.extend(resolve_jumps(body, next, next + 1));
+            operations.push(Operation::IndexNext(start + 1));
+        }
+        QueryPlan::IndexScan { index_name, .. } => {
+            let next = start + 4 + filter_len + body.len();
+            operations.push(Operation::IndexSeek(index_name.clone()));
+            operations.push(Operation::IndexEnd(next + 1));
+            operations.push(Operation::SeekRowid);
+            operations.push(Operation::CursorValue);
+            if has_filter {
+                operations.push(Operation::Filter(next));
+            }
+            operations.extend(resolve_jumps(body, next, next + 1));
+            operations.push(Operation::IndexNext(start + 1));
+        }
+        QueryPlan::FullTextMatch(_) => {
+            let next = start + 4 + filter_len + body.len();
+            operations.push(Operation::FullTextQuery(root_page_num));
+            operations.push(Operation::RowSetNext(next + 1));
+            operations.push(Operation::SeekRowid);
+            operations.push(Operation::CursorValue);
+            if has_filter {
+                operations.push(Operation::Filter(next));
+            }
+            operations.extend(resolve_jumps(body, next, next + 1));
+            operations.push(Operation::Goto(start + 1));
+        }
+    }
+}
+
+/// Point a jump at the address.
+fn set_jump(operation: &mut Operation, addr: usize) {
+    match operation {
+        Operation::EndOfTable(target) | Operation::NotFound(target) | Operation::IndexEnd(target)
+        | Operation::HashNext(target) | Operation::RowSetNext(target) | Operation::Filter(target) | Operation::JoinFilter(target)
+        | Operation::SkipOffset(target) | Operation::DecrJumpZero(target) | Operation::IfMatched(target) => *target = addr,
+        _ => {}
+    }
+}
+
+/// Append the loop over one table of a join and, inside it, the loops over the tables after it.
+/// The innermost loop runs `body` on every joined row that passes the WHERE clause. Returns
+/// the operations that go on to the next row of the enclosing loop, whose address is not
+/// known yet.
+fn compile_join_loop(operations: &mut Vec<Operation>, scope: &JoinScope, strategies: &[JoinStrategy],
+                     depth: usize, has_filter: bool, body: Vec<Operation>) -> Vec<usize> {
+    if depth == scope.tables.len() {
+        let mut next_jumps = Vec::new();
+        if has_filter {
+            next_jumps.push(operations.len());
+            operations.push(Operation::Filter(0));
+        }
+        for operation in body {
+            if matches!(operation, Operation::SkipOffset(_)) {
+                next_jumps.push(operations.len());
+            }
+            operations.push(operation);
+        }
+        return next_jumps;
+    }
+    let table = &scope.tables[depth];
+    let name = table.name.clone();
+    let left = table.kind == JoinKind::Left;
+    if left {
+        operations.push(Operation::ResetMatch(name.clone()));
+    }
+    let full_scan = QueryPlan::FullScan;
+    let plan = match &strategies[depth] {
+        JoinStrategy::Outer(plan) => Some(plan),
+        JoinStrategy::NestedLoop => Some(&full_scan),
+        _ => None,
+    };
+    // The loop's head, the operation that leaves it, and how it steps to the next row.
+    let head = operations.len();
+    let (end_jump, step) = match (plan, &strategies[depth]) {
+        (Some(QueryPlan::FullScan), _) => {
+            operations.push(Operation::TableStart(table.def.root_page_num));
+            operations.push(Operation::EndOfTable(0));
+            operations.push(Operation::CursorValue);
+            (head + 1, Some(Operation::Advance(head + 1)))
+        }
+        (Some(QueryPlan::PrimaryKeySeek(key)), _) => {
+            operations.push(Operation::Find(Some(RowKey::Rowid(*key))));
+            operations.push(Operation::NotFound(0));
+            operations.push(Operation::CursorValue);
+            (head + 1, None)
+        }
+        (Some(QueryPlan::KeySeek(key)), _) => {
+            operations.push(Operation::Find(Some(RowKey::Key(key.clone()))));
+            operations.push(Operation::IndexEnd(0));
+            operations.push(Operation::CursorValue);
+            (head + 1, Some(Operation::IndexNext(head + 1)))
+        }
+        (Some(QueryPlan::IndexScan { index_name, .. }), _) | (None, JoinStrategy::Index { index_name, .. }) => {
+            operations.push(Operation::IndexSeek(index_name.clone()));
+            operations.push(Operation::IndexEnd(0));
+            operations.push(Operation::SeekRowid);
+            operations.push(Operation::CursorValue);
+            (head + 1, Some(Operation::IndexNext(head + 1)))
+        }
+        (Some(QueryPlan::FullTextMatch(_)), _) => {
+            operations.push(Operation::FullTextQuery(table.def.root_page_num));
+            operations.push(Operation::RowSetNext(0));
+            operations.push(Operation::SeekRowid);
+            operations.push(Operation::CursorValue);
+            (head + 1, Some(Operation::Goto(head + 1)))
+        }
+        (None, JoinStrategy::PrimaryKey(_)) => {
+            operations.push(Operation::SeekKey(name.clone()));
+            operations.push(Operation::NotFound(0));
+            operations.push(Operation::CursorValue);
+            (head + 1, None)
+        }
+        (None, _) => {
+            operations.push(Operation::HashProbe(name.clone()));
+            operations.push(Operation::HashNext(0));
+            (head + 1, Some(Operation::Goto(head + 1)))
+        }
+    };
+    let mut next_jumps = Vec::new();
+    if table.on.is_some() {
+        next_jumps.push(operations.len());
+        operations.push(Operation::JoinFilter(0));
+    }
+    let matched = operations.len();
+    if left {
+        operations.push(Operation::SetMatch(name.clone()));
+    }
+    next_jumps.extend(compile_join_loop(operations, scope, strategies, depth + 1, has_filter, body));
+    let next = operations.len();
+    operations.extend(step);
+    let end = operations.len();
+    if left {
+        operations.push(Operation::IfMatched(end + 3));
+        operations.push(Operation::NullRow(name));
+        operations.push(Operation::Goto(matched));
+    }
+    next_jumps.into_iter().for_each(|addr| set_jump(&mut operations[addr], next));
+    set_jump(&mut operations[end_jump], end);
+    // Every jump inside this loop is resolved, so none are left for the enclosing one.
+    Vec::new()
+}
+
+/// Append the nested loops of a join, running `body` on every joined row that passes the ON
+/// clauses and the WHERE clause.
+fn compile_join(operations: &mut Vec<Operation>, scope: &JoinScope, strategies: &[JoinStrategy],
+                has_filter: bool, body: Vec<Operation>) {
+    for (table, strategy) in scope.tables.iter().zip(strategies) {
+        if matches!(strategy, JoinStrategy::Hash { .. }) {
+            operations.push(Operation::HashBuild(table.name.clone()));
+        }
+    }
+    let start = operations.len();
+    compile_join_loop(operations, scope, strategies, 0, has_filter, body);
+    let exit = operations.len();
+    for operation in &mut operations[start..] {
+        if matches!(operation, Operation::DecrJumpZero(_)) {
+            set_jump(operation, exit);
+        }
+    }
+}
+
+pub fn compile_statement(statement: &Statement, table: &Table) -> Vec<Operation> {
+    let mut operations = Vec::new();
+    let source_def = match from_table(&table.schema, &statement.table_name, statement.table_args.as_deref()) {
+        Ok(source_def) => source_def,
+        Err(_) => {
+            // Only CREATE TABLE names a table that does not exist yet, and only a SELECT
+            // without FROM names none.
+            if statement.stmt_type == StatementType::StatementSelect {
+                operations.push(Operation::ResultRow);
+            }
+            if let Some(table_def) = &statement.table_to_create {
+                operations.push(Operation::CreateTableTree(table_def.name.clone()));
+                let num_unique = table_def.columns.iter().filter(|column| column.unique && !column.primary_key).count();
+                for i in 0..num_unique {
+                    operations.push(Operation::CreateIndexTree(format!("sqlite_autoindex_{}_{}", table_def.name, i + 1)));
+                }
+                operations.push(Operation::WriteCatalog);
+            }
+            operations.push(Operation::Halt);
+            return operations;
+        }
+    };
+    let table_def = &source_def;
+    let plan = plan_statement(statement, table);
+    let indexes = table.schema.table_indexes(&table_def.name);
+    let mut index_names: Vec<String> = indexes.iter().map(|index| index.def.name.clone()).collect();
+    // A full-text table's terms are kept in a table of their own, maintained like an index.
+    if table_def.fts.is_some() {
+        index_names.push(format!("{}_idx", table_def.name));
+    }
+    let unique_names: Vec<String> = indexes.iter()
+        .filter(|index| index.def.unique)
+        .map(|index| index.def.name.clone())
+        .collect();
+    // Foreign keys are only enforced while `PRAGMA foreign_keys` is on.
+    let (parent_names, child_names): (Vec<String>, Vec<String>) = if table.foreign_keys {
+        (table_def.foreign_keys.iter().map(|foreign_key| foreign_key.parent_table.clone()).collect(),
+         table.schema.tables.iter()
+             .filter(|child_def| child_def.foreign_keys.iter().any(|foreign_key| foreign_key.parent_table == table_def.name))
+             .map(|child_def| child_def.name.clone())
+             .collect())
+    } else {
+        (Vec::new(), Vec::new())
+    };
+    let root_page_num = table_def.root_page_num;
+    let has_filter = statement.where_clause.is_some();
+    match statement.stmt_type {
+        StatementType::StatementInsert => {
+            for key in insert_keys(statement, table_def, table) {
+                operations.push(Operation::Find(key.clone()));
+                operations.push(Operation::CheckDuplicateKey(key.clone()));
+                operations.extend(unique_names.iter().cloned().map(Operation::CheckUnique));
+                operations.extend(parent_names.iter().cloned().map(Operation::CheckForeignKey));
+                operations.push(Operation::LeafNodeInsert(key));
+                operations.extend(index_names.iter().cloned().map(Operation::IndexInsert));
+            }
+        }
+        StatementType::StatementSelect => {
+            // Rows before the OFFSET are skipped, and the scan stops once LIMIT rows are out.
+            let mut output = Vec::new();
+            if statement.offset.is_some() {
+                output.push(Operation::SkipOffset(0));
+            }
+            output.push(Operation::ResultRow);
+            if statement.limit.is_some() {
+                output.push(Operation::DecrJumpZero(0));
+            }
+            let join = if statement.joins.is_empty() {
+                None
+            } else {
+                JoinScope::new(statement, &table.schema).ok().map(|scope| {
+                    let strategies = plan_joins(&scope, statement.where_clause.as_ref(), &table.schema);
+                    (scope, strategies)
+                })
+            };
+            let source_def = join.as_ref().map_or(table_def, |(scope, _)| &scope.def);
+            let scan = |operations: &mut Vec<Operation>, body: Vec<Operation>| match &join {
+                Some((scope, strategies)) => compile_join(operations, scope, strategies, has_filter, body),
+                None => compile_scan(operations, &plan, root_page_num, has_filter, body),
+            };
+            if is_aggregate_select(statement) {
+                // Groups are only finished once every row is in, and then go through the sorter.
+                if select_counts_rows(statement, table_def) {
+                    operations.push(Operation::CountCells(root_page_num));
+                } else {
+                    scan(&mut operations, vec![Operation::AggStep]);
+                }
+                let start = operations.len();
+                let having_len = if statement.having.is_some() { 1 } else { 0 };
+                operations.push(Operation::AggFinal(start + having_len + 3));
+                if statement.having.is_some() {
+                    operations.push(Operation::Having(start));
+                }
+                operations.push(Operation::SorterInsert);
+                operations.push(Operation::Goto(start));
+                let start = operations.len();
+                let next = start + 2 + output.len();
+                operations.push(Operation::SorterSort(next + 1));
+                operations.push(Operation::SorterData);
+                operations.extend(resolve_jumps(output, next, next + 1));
+                operations.push(Operation::SorterNext(start + 1));
+            } else if select_sorts(statement, source_def, &plan) {
+                scan(&mut operations, vec![Operation::SorterInsert]);
+                let start = operations.len();
+                let next = start + 2 + output.len();
+                operations.push(Operation::SorterSort(next + 1));
+                operations.push(Operation::SorterData);
+                operations.extend(resolve_jumps(output, next, next + 1));
+                operations.push(Operation::SorterNext(start + 1));
+            } else {
+                scan(&mut operations, output);
+            }
+        }
+        StatementType::StatementCreateIndex => {
+            let def = statement.index_to_create.as_ref().unwrap();
+            operations.push(Operation::CreateIndexTree(def.name.clone()));
+            let mut body = Vec::new();
+            if def.unique {
+                body.push(Operation::CheckUnique(def.name.clone()));
+            }
+            body.push(Operation::IndexInsert(def.name.clone()));
+            compile_scan(&mut operations, &plan, root_page_num, false, body);
+            operations.push(Operation::WriteCatalog);
+        }
+        StatementType::StatementCreateTable | StatementType::StatementPragma => {}
+        StatementType::StatementUpdate | StatementType::StatementDelete => {
+            // Collect the rowids first so the write pass never walks cells it has already moved.
+            compile_scan(&mut operations, &plan, root_page_num, has_filter, vec![Operation::RowSetAdd]);
+            let start = operations.len();
+            let mut body = vec![Operation::SeekRowid, Operation::CursorValue];
+            body.extend(index_names.iter().cloned().map(Operation::IndexDelete));
+            if statement.stmt_type == StatementType::StatementDelete {
+                body.extend(child_names.into_iter().map(Operation::OnDelete));
+                body.push(Operation::DeleteCell);
+            } else {
+                body.extend(unique_names.into_iter().map(Operation::CheckUnique));
+                body.extend(parent_names.into_iter().map(Operation::CheckForeignKey));
+                body.extend(child_names.into_iter().map(Operation::CheckReferenced));
+                body.push(Operation::UpdateCell);
+                body.extend(index_names.into_iter().map(Operation::IndexInsert));
+            }
+            operations.push(Operation::RowSetNext(start + body.len() + 2));
+            operations.extend(body);
+            operations.push(Operation::Goto(start));
+        }
+    }
+    operations.push(Operation::Halt);
+    operations
+}
+
+pub fn print_operations(operations: &[Operation]) {
+    println!("{:<6}{:<18}{:<9} comment", "addr", "opcode", "p1");
+    for (addr, operation) in operations.iter().enumerate() {
+        println!("{:<6}{}", addr, operation.to_string().trim_end());
+    }
+}
+
+/// One line of EXPLAIN QUERY PLAN, with the plan of the subquery it introduces nested under it.
+pub struct PlanLine {
+    pub text: String,
+    pub children: Vec<PlanLine>,
+}
+
+impl PlanLine {
+    pub fn new(text: String) -> PlanLine {
+        PlanLine { text, children: Vec::new() }
+    }
+}
+
+/// The line of a SELECT without FROM, which computes its one row without reading any table.
+pub const CONSTANT_ROW: &str = "SCAN CONSTANT ROW";
+
+/// The EXPLAIN QUERY PLAN line of a single-table statement.
+pub fn describe_scan(plan: &QueryPlan, table_def: &TableDef) -> String {
+    plan.describe(table_def, &table_def.name)
+}
+
+/// The EXPLAIN QUERY PLAN line of a table-valued function, which SQLite shows as a virtual
+/// table searched by an index number with a bit set for each argument.
+pub fn describe_function_scan(table_name: &str, num_args: usize) -> String {
+    format!("SCAN {} VIRTUAL TABLE INDEX {}:", table_name, (1 << num_args) - 1)
+}
+
+/// The EXPLAIN QUERY PLAN lines of a join, one per table.
+pub fn describe_joins(scope: &JoinScope, strategies: &[JoinStrategy]) -> Vec<String> {
+    scope.tables.iter().zip(strategies).map(|(table, strategy)| strategy.describe(table)).collect()
+}
+
+/// The EXPLAIN QUERY PLAN lines of the temp B-trees a statement sorts with.
+pub fn describe_temp_trees(temp_trees: &[&str]) -> Vec<String> {
+    temp_trees.iter().map(|clause| format!("USE TEMP B-TREE FOR {}", clause)).collect()
+}
+
+pub fn print_query_plan(lines: &[PlanLine]) {
+    println!("QUERY PLAN");
+    print_plan_lines(lines, "");
+}
+
+fn print_plan_lines(lines: &[PlanLine], indent: &str) {
+    for (i, line) in lines.iter().enumerate() {
+        let last = i + 1 == lines.len();
+        println!("{}{}{}", indent, if last { "`--" } else { "|--" }, line.text);
+        print_plan_lines(&line.children, &format!("{}{}", indent, if last { "   " } else { "|  " }));
+    }
+}
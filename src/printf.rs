@@ -0,0 +1,287 @@
+use std::iter::Peekable;
+use std::str::Chars;
+use crate::constant::PRINTF_WIDTH_LIMIT;
+use crate::value::Value;
+
+/// The flags, width and precision of one `%` conversion.
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    zero: bool,
+    alternate: bool,
+    comma: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+/// The digits of a real that is not negative, rounded to the 17 significant SQLite works
+/// with, and the power of ten of the first: 2.675 is 26749999999999998 at 0.
+fn decode(real: f64) -> (Vec<u8>, i32) {
+    let text = format!("{:.16e}", real);
+    let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+    let digits = mantissa.bytes().filter(u8::is_ascii_digit).map(|b| b - b'0').collect();
+    (digits, exponent.parse().unwrap_or(0))
+}
+
+/// Keep the first `keep` digits, rounding half away from zero on the next one.
+fn round_digits(mut digits: Vec<u8>, mut exponent: i32, keep: i32) -> (Vec<u8>, i32) {
+    if keep < 0 {
+        return (vec![0], exponent);
+    }
+    let keep = keep as usize;
+    if keep >= digits.len() {
+        return (digits, exponent);
+    }
+    let round_up = digits[keep] >= 5;
+    digits.truncate(keep);
+    if round_up {
+        let mut i = keep;
+        loop {
+            if i == 0 {
+                digits.insert(0, 1);
+                exponent += 1;
+                break;
+            }
+            i -= 1;
+            if digits[i] == 9 {
+                digits[i] = 0;
+            } else {
+                digits[i] += 1;
+                break;
+            }
+        }
+    }
+    if digits.is_empty() {
+        digits.push(0);
+    }
+    (digits, exponent)
+}
+
+/// The digit for the given power of ten, zero past either end.
+fn digit_at(digits: &[u8], exponent: i32, power: i32) -> char {
+    let digit = usize::try_from(exponent - power).ok().and_then(|i| digits.get(i)).copied().unwrap_or(0);
+    char::from(b'0' + digit)
+}
+
+/// A real that is not negative, with `precision` digits after the point.
+fn fixed_digits(digits: &[u8], exponent: i32, precision: usize, alternate: bool) -> String {
+    let mut text: String = (0..=exponent.max(0)).rev().map(|power| digit_at(digits, exponent, power)).collect();
+    if precision > 0 || alternate {
+        text.push('.');
+    }
+    text.extend((1..=precision as i32).map(|power| digit_at(digits, exponent, -power)));
+    text
+}
+
+/// A real that is not negative, as `%f` writes it.
+pub fn fixed(real: f64, precision: usize, alternate: bool) -> String {
+    let (digits, exponent) = decode(real);
+    let (digits, exponent) = round_digits(digits, exponent, exponent + 1 + precision as i32);
+    fixed_digits(&digits, exponent, precision, alternate)
+}
+
+/// Rounded digits as `%e` writes them, with `precision` digits after the point.
+fn exponential_digits(digits: &[u8], exponent: i32, precision: usize, alternate: bool, upper: bool) -> String {
+    let mut text = fixed_digits(digits, 0, precision, alternate);
+    text.push(if upper { 'E' } else { 'e' });
+    text.push(if exponent < 0 { '-' } else { '+' });
+    text.push_str(&format!("{:02}", exponent.unsigned_abs()));
+    text
+}
+
+fn exponential(real: f64, precision: usize, alternate: bool, upper: bool) -> String {
+    let (digits, exponent) = decode(real);
+    let (digits, exponent) = if real == 0.0 { (vec![0], 0) } else { round_digits(digits, exponent, precision as i32 + 1) };
+    exponential_digits(&digits, exponent, precision, alternate, upper)
+}
+
+/// `%g`: `%e` for exponents below -4 or from the precision up, `%f` otherwise, without trailing
+/// zeros unless the alternate form asks for them.
+fn general(real: f64, precision: usize, alternate: bool, upper: bool) -> String {
+    let precision = precision.max(1);
+    let (digits, exponent) = decode(real);
+    let (digits, exponent) = if real == 0.0 { (vec![0], 0) } else { round_digits(digits, exponent, precision as i32) };
+    let text = if exponent < -4 || exponent >= precision as i32 {
+        exponential_digits(&digits, exponent, precision - 1, alternate, upper)
+    } else {
+        fixed_digits(&digits, exponent, (precision as i32 - 1 - exponent) as usize, alternate)
+    };
+    if alternate {
+        return text;
+    }
+    let (mantissa, suffix) = match text.find(['e', 'E']) {
+        Some(i) => text.split_at(i),
+        None => (text.as_str(), ""),
+    };
+    let mantissa = if mantissa.contains('.') { mantissa.trim_end_matches('0').trim_end_matches('.') } else { mantissa };
+    format!("{}{}", mantissa, suffix)
+}
+
+/// Digits with a comma between each group of three.
+fn group_thousands(digits: &str) -> String {
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+/// Pad to the width: on the right when left-aligned, else on the left with spaces, or for a
+/// number with zeros between its prefix (sign or `0x`) and its digits.
+fn pad(prefix: &str, body: &str, spec: &Spec, numeric: bool) -> String {
+    let len = prefix.chars().count() + body.chars().count();
+    let fill = spec.width.saturating_sub(len);
+    if spec.left {
+        format!("{}{}{}", prefix, body, " ".repeat(fill))
+    } else if spec.zero && numeric {
+        format!("{}{}{}", prefix, "0".repeat(fill), body)
+    } else {
+        format!("{}{}{}", " ".repeat(fill), prefix, body)
+    }
+}
+
+fn sign(negative: bool, spec: &Spec) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+/// Text as a string argument takes it, NULL as empty.
+fn text(value: &Value) -> String {
+    value.to_string()
+}
+
+/// A width or precision written in the format, clamped to `PRINTF_WIDTH_LIMIT`.
+fn digits(chars: &mut Peekable<Chars>) -> usize {
+    let mut n: usize = 0;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        n = n.saturating_mul(10).saturating_add(digit as usize);
+        chars.next();
+    }
+    n.min(PRINTF_WIDTH_LIMIT)
+}
+
+/// SQL `printf(format, ...)`, as SQLite formats it: `%d`, `%i` and `%u` integers, `%f`, `%e`
+/// and `%g` reals, `%x`, `%X` and `%o` in hex and octal, `%c` and `%s` text, `%q`, `%Q` and `%w`
+/// text quoted for SQL, and `%%`, each with the C flags, width and precision, where `*` takes
+/// them from the arguments. A missing argument reads as NULL, and an unknown conversion ends
+/// the output.
+pub fn format(format: &str, args: &[Value]) -> String {
+    let mut args = args.iter();
+    let mut next_arg = || args.next().cloned().unwrap_or(Value::Null);
+    let mut out = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let mut spec = Spec::default();
+        loop {
+            match chars.peek() {
+                Some('-') => spec.left = true,
+                Some('+') => spec.plus = true,
+                Some(' ') => spec.space = true,
+                Some('0') => spec.zero = true,
+                Some('#') => spec.alternate = true,
+                Some(',') => spec.comma = true,
+                Some('!') => {}
+                _ => break,
+            }
+            chars.next();
+        }
+        if chars.next_if_eq(&'*').is_some() {
+            let width = next_arg().to_integer().unwrap_or(0);
+            spec.left |= width < 0;
+            spec.width = usize::try_from(width.unsigned_abs()).unwrap_or(usize::MAX).min(PRINTF_WIDTH_LIMIT);
+        } else {
+            spec.width = digits(&mut chars);
+        }
+        if chars.next_if_eq(&'.').is_some() {
+            if chars.next_if_eq(&'*').is_some() {
+                let precision = next_arg().to_integer().unwrap_or(0).max(0);
+                spec.precision = Some(usize::try_from(precision).unwrap_or(usize::MAX).min(PRINTF_WIDTH_LIMIT));
+            } else {
+                spec.precision = Some(digits(&mut chars));
+            }
+        }
+        // The C length modifiers mean nothing here, as every integer is 64 bits.
+        while chars.next_if_eq(&'l').is_some() {}
+        let conversion = match chars.next() {
+            Some(conversion) => conversion,
+            None => break,
+        };
+        let formatted = match conversion {
+            '%' => String::from("%"),
+            'd' | 'i' => {
+                let i = next_arg().to_integer().unwrap_or(0);
+                let mut digits = i.unsigned_abs().to_string();
+                if let Some(precision) = spec.precision {
+                    digits = format!("{:0>1$}", digits, precision);
+                }
+                if spec.comma {
+                    digits = group_thousands(&digits);
+                }
+                pad(sign(i < 0, &spec), &digits, &spec, true)
+            }
+            'u' | 'x' | 'X' | 'o' => {
+                let u = next_arg().to_integer().unwrap_or(0) as u64;
+                let (mut digits, prefix) = match conversion {
+                    'x' => (format!("{:x}", u), "0x"),
+                    'X' => (format!("{:X}", u), "0X"),
+                    'o' => (format!("{:o}", u), "0"),
+                    _ => (u.to_string(), ""),
+                };
+                if let Some(precision) = spec.precision {
+                    digits = format!("{:0>1$}", digits, precision);
+                }
+                let prefix = if spec.alternate && u != 0 { prefix } else { "" };
+                pad(prefix, &digits, &spec, true)
+            }
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+                let real = next_arg().to_real().unwrap_or(0.0);
+                let precision = spec.precision.unwrap_or(6);
+                let body = match conversion {
+                    _ if real.is_infinite() => String::from("Inf"),
+                    'f' | 'F' => fixed(real.abs(), precision, spec.alternate),
+                    'e' | 'E' => exponential(real.abs(), precision, spec.alternate, conversion == 'E'),
+                    _ => general(real.abs(), precision, spec.alternate, conversion == 'G'),
+                };
+                pad(sign(real.is_sign_negative() && real != 0.0, &spec), &body, &spec, true)
+            }
+            'c' => {
+                let c = text(&next_arg()).chars().next().map(String::from).unwrap_or_default();
+                pad("", &c, &spec, false)
+            }
+            's' | 'z' | 'q' | 'Q' | 'w' => {
+                let value = next_arg();
+                let mut body = match (conversion, &value) {
+                    ('q', Value::Null) => String::from("(NULL)"),
+                    ('Q', Value::Null) => String::from("NULL"),
+                    ('q', _) => text(&value).replace('\'', "''"),
+                    ('Q', _) => format!("'{}'", text(&value).replace('\'', "''")),
+                    ('w', _) => text(&value).replace('"', "\"\""),
+                    _ => text(&value),
+                };
+                if let Some(precision) = spec.precision {
+                    body = body.chars().take(precision).collect();
+                }
+                pad("", &body, &spec, false)
+            }
+            _ => break,
+        };
+        out.push_str(&formatted);
+    }
+    out
+}
@@ -0,0 +1,154 @@
+use crate::value::Value;
+
+/// Append a varint: big-endian groups of seven bits, every byte but the last with its high bit
+/// set, except that a ninth byte, when one is needed, carries a full eight bits.
+pub fn put_varint(buf: &mut Vec<u8>, value: u64) {
+    if value >> 56 != 0 {
+        let mut bytes = [0_u8; 9];
+        bytes[8] = value as u8;
+        let mut rest = value >> 8;
+        for byte in bytes[..8].iter_mut().rev() {
+            *byte = (rest & 0x7f) as u8 | 0x80;
+            rest >>= 7;
+        }
+        buf.extend_from_slice(&bytes);
+        return;
+    }
+    let mut groups = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest != 0 {
+        groups.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    buf.extend(groups.into_iter().rev());
+}
+
+/// Read the varint at the start of `bytes`, returning it with the number of bytes it took.
+pub fn get_varint(bytes: &[u8]) -> (u64, usize) {
+    let mut value = 0_u64;
+    for (i, byte) in bytes.iter().take(8).enumerate() {
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return (value, i + 1);
+        }
+    }
+    ((value << 8) | bytes[8] as u64, 9)
+}
+
+/// As `get_varint`, or None if `bytes` end before the varint does.
+pub fn read_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let len = bytes.iter().take(8).position(|byte| byte & 0x80 == 0).map_or(9, |i| i + 1);
+    (len <= bytes.len()).then(|| get_varint(bytes))
+}
+
+pub fn varint_len(value: u64) -> usize {
+    let mut buf = Vec::new();
+    put_varint(&mut buf, value);
+    buf.len()
+}
+
+/// The serial type a record gives a value: 0 for NULL; 1 to 6 for integers of 1, 2, 3, 4, 6
+/// and 8 bytes, with 8 and 9 for the integers 0 and 1, which take none; 7 for a real; an even
+/// type from 12 for a blob and an odd one from 13 for text, which also tell their length.
+fn serial_type(value: &Value) -> u64 {
+    match value {
+        Value::Null => 0,
+        Value::Integer(0) => 8,
+        Value::Integer(1) => 9,
+        Value::Integer(i) => match *i {
+            -0x80..=0x7f => 1,
+            -0x8000..=0x7fff => 2,
+            -0x80_0000..=0x7f_ffff => 3,
+            -0x8000_0000..=0x7fff_ffff => 4,
+            -0x8000_0000_0000..=0x7fff_ffff_ffff => 5,
+            _ => 6,
+        },
+        Value::Real(_) => 7,
+        Value::Blob(b) => 12 + 2 * b.len() as u64,
+        Value::Text(s) => 13 + 2 * s.len() as u64,
+    }
+}
+
+/// How many bytes the body of a value of the serial type takes.
+fn serial_size(serial_type: u64) -> usize {
+    match serial_type {
+        1..=4 => serial_type as usize,
+        5 => 6,
+        6 | 7 => 8,
+        12.. => (serial_type as usize - 12) / 2,
+        _ => 0,
+    }
+}
+
+/// Decode the body of a value of the serial type. Integers are big-endian two's complement.
+fn decode_value(serial_type: u64, body: &[u8]) -> Value {
+    match serial_type {
+        0 => Value::Null,
+        8 => Value::Integer(0),
+        9 => Value::Integer(1),
+        1..=6 => {
+            let sign = if body[0] & 0x80 != 0 { 0xff } else { 0 };
+            let mut bytes = [sign; 8];
+            bytes[8 - body.len()..].copy_from_slice(body);
+            Value::Integer(i64::from_be_bytes(bytes))
+        }
+        7 => Value::Real(f64::from_be_bytes(body.try_into().unwrap())),
+        _ if serial_type.is_multiple_of(2) => Value::Blob(body.to_vec()),
+        _ => Value::Text(String::from_utf8_lossy(body).into_owned()),
+    }
+}
+
+/// Encode values in SQLite's record format: a header of its own size followed by the serial
+/// type of every value, all varints, then the body of every value in turn.
+pub fn encode_record(values: &[Value]) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut body = Vec::new();
+    for value in values {
+        let serial_type = serial_type(value);
+        put_varint(&mut types, serial_type);
+        match value {
+            Value::Integer(i) => body.extend_from_slice(&i.to_be_bytes()[8 - serial_size(serial_type)..]),
+            Value::Real(r) => body.extend_from_slice(&r.to_be_bytes()),
+            Value::Text(s) => body.extend_from_slice(s.as_bytes()),
+            Value::Blob(b) => body.extend_from_slice(b),
+            Value::Null => {}
+        }
+    }
+    // The header's size counts the varint that holds it.
+    let mut header_size = types.len() + 1;
+    while varint_len(header_size as u64) + types.len() > header_size {
+        header_size += 1;
+    }
+    let mut record = Vec::with_capacity(header_size + body.len());
+    put_varint(&mut record, header_size as u64);
+    record.extend(types);
+    record.extend(body);
+    record
+}
+
+/// Decode a record the engine wrote itself.
+pub fn decode_record(bytes: &[u8]) -> Vec<Value> {
+    read_record(bytes).expect("malformed record")
+}
+
+/// Decode a record, or None if it is cut short or its header names no serial type there is.
+pub fn read_record(bytes: &[u8]) -> Option<Vec<Value>> {
+    let (header_size, mut offset) = read_varint(bytes)?;
+    let header_size = usize::try_from(header_size).ok().filter(|size| *size <= bytes.len())?;
+    let mut body_offset = header_size;
+    let mut values = Vec::new();
+    while offset < header_size {
+        let (serial_type, len) = read_varint(&bytes[offset..header_size])?;
+        offset += len;
+        // Types 10 and 11 are reserved.
+        if serial_type == 10 || serial_type == 11 {
+            return None;
+        }
+        let size = serial_size(serial_type);
+        let body = bytes.get(body_offset..body_offset.checked_add(size)?)?;
+        values.push(decode_value(serial_type, body));
+        body_offset += size;
+    }
+    Some(values)
+}
+
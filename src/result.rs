@@ -1,5 +1,6 @@
 use std::process;
 use crate::constant::print_constants;
+use crate::file_format::save_database;
 use crate::table::{db_close, Table};
 
 
@@ -9,21 +10,74 @@ pub enum MetaCommandResult {
     MetaCommandUnrecognized,
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(PartialEq, Debug)]
 pub enum PrepareResult {
-    PrepareSuccess,
     PrepareUnrecognized,
     PrepareSyntaxErr,
     PrepareStringTooLong,
     PrepareInvalidId,
 }
 
-#[derive(PartialEq, Debug)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum ExecuteResult {
     ExecuteSuccess,
-    ExecuteFail,
-    ExecuteTableFull,
     ExecuteDuplicateKey,
+    ExecuteStringTooLong,
+    ExecuteNoSuchTable(String),
+    ExecuteNoSuchColumn(String),
+    ExecuteAmbiguousColumn(String),
+    ExecuteSubqueryColumns(usize),
+    /// The SELECTs either side of the compound operator return different numbers of columns.
+    ExecuteCompoundColumns(&'static str),
+    ExecuteDuplicateWithTable(String),
+    /// A WITH table's column list names a different number of columns than its SELECT returns.
+    ExecuteWithColumns(String, usize, usize),
+    ExecuteIndexExists(String),
+    ExecuteTableExists(String),
+    ExecuteDuplicateColumn(String),
+    /// A PRIMARY KEY of several columns, which only a WITHOUT ROWID table can have.
+    ExecuteCompositeRowidKey(String),
+    ExecutePrimaryKeyMissing(String),
+    ExecuteWithoutRowidIndex(String),
+    ExecuteValueCount(String, usize, usize),
+    ExecuteUniqueViolation(String),
+    ExecuteNotNullViolation(String),
+    ExecuteCheckViolation(String),
+    ExecuteForeignKeyViolation(String),
+    /// An ORDER BY or GROUP BY position past the last result column.
+    ExecuteTermOutOfRange(&'static str, usize),
+    ExecuteDatatypeMismatch,
+    ExecuteNoSuchFunction(String),
+    ExecuteNoSuchCollation(String),
+    /// The message of an error a registered function returned.
+    ExecuteFunctionError(String),
+    ExecuteMisuseOfAggregate(String),
+    ExecuteMisuseOfWindowFunction(String),
+    ExecuteWrongArgumentCount(String),
+    ExecuteIntegerOverflow,
+    ExecuteMalformedJson,
+    ExecuteBadJsonPath(String),
+    /// A full-text query that cannot be parsed or answered, or a tokenizer that cannot be made.
+    ExecuteFullTextError(String),
+    ExecuteVirtualTableIndex,
+    /// A statement that writes, run against a SQLite file, which is opened read-only.
+    ExecuteReadOnly,
+    /// A temporary file of a sort or aggregate that could not be written or read back.
+    ExecuteTempFileError(String),
+    /// A SQLite file whose pages cannot be read as the format lays them out.
+    ExecuteCorruptFile(String),
+    /// A write to a table the engine keeps itself.
+    ExecuteTableReadOnly(String),
+    /// A table named with the `sqlite_` prefix, which is kept for the engine's own tables.
+    ExecuteReservedName(String),
+    /// No rowid is left to give a new row, or the file has reached `PRAGMA max_page_count`.
+    /// Rows a statement wrote before the file filled up are kept.
+    ExecuteDatabaseFull,
+    /// A row the statement read has no entry to take out of the named table or index, so its
+    /// B-tree is damaged.
+    ExecuteMissingEntry(String),
 }
 
 pub fn get_meta_result(command: &str, table: &mut Table) -> MetaCommandResult {
@@ -34,7 +88,33 @@ pub fn get_meta_result(command: &str, table: &mut Table) -> MetaCommandResult {
         println!("Constants:");
         print_constants();
         return MetaCommandResult::MetaCommandSuccess;
+    } else if let Some(mode) = command.strip_prefix(".headers ") {
+        match mode.trim() {
+            "on" => table.headers = true,
+            "off" => table.headers = false,
+            _ => return MetaCommandResult::MetaCommandUnrecognized,
+        }
+        return MetaCommandResult::MetaCommandSuccess;
+    } else if command.eq(".dbinfo") {
+        match &table.pager.sqlite {
+            Some(sqlite) => if let Err(err) = sqlite.print_info(&table.pager) {
+                println!("Error: {}", err);
+            },
+            None => println!("Error: .dbinfo needs a SQLite database file"),
+        }
+        return MetaCommandResult::MetaCommandSuccess;
+    } else if let Some(path) = command.strip_prefix(".save ") {
+        if table.pager.sqlite.is_some() {
+            println!("Error: the database is already a SQLite file");
+        } else if let Err(err) = save_database(table, path.trim()) {
+            println!("Error: {}", err);
+        }
+        return MetaCommandResult::MetaCommandSuccess;
     } else if command.eq(".btree") {
+        if table.pager.sqlite.is_some() {
+            println!("Error: .btree needs a database in the native format");
+            return MetaCommandResult::MetaCommandSuccess;
+        }
         println!("Btree:");
         table.print_tree();
         return MetaCommandResult::MetaCommandSuccess;
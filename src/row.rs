@@ -1,6 +0,0 @@
-#[derive(Clone)]
-pub struct Row {
-    pub id: u32,
-    pub username: String,
-    pub email: String,
-}
\ No newline at end of file
@@ -0,0 +1,647 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+use crate::constant::{COLUMN_NAMES, EMAIL_SIZE, SEQUENCE_TABLE_NAME, TABLE_NAME, USERNAME_SIZE, USERS_ROOT_PAGE_NUM};
+use crate::cte::RecursiveRows;
+use crate::expr::Expr;
+use crate::collation::{Collation, Collations, KeyOrder};
+use crate::fts::FtsOptions;
+use crate::function::Functions;
+use crate::index::IndexTree;
+use crate::page::Page;
+use crate::pager::Pager;
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::*;
+use crate::record::{decode_record, encode_record};
+use crate::statement::prepare_statement;
+use crate::value::{parse_numeric, Value};
+
+/// A column's type affinity: the storage class its values are converted to where that loses
+/// nothing. Any column may still hold a value of any class.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColumnType {
+    Integer,
+    Real,
+    Numeric,
+    Text,
+    /// No affinity: values are stored as they come, as for a column declared without a type.
+    Blob,
+}
+
+impl ColumnType {
+    /// The affinity of a declared type, by SQLite's rules, tried in order: "INT" makes it
+    /// INTEGER; "CHAR", "CLOB" or "TEXT" makes it TEXT; "BLOB" or no type at all makes it
+    /// BLOB; "REAL", "FLOA" or "DOUB" makes it REAL; any other type is NUMERIC.
+    pub fn from_declared(type_name: &str) -> ColumnType {
+        let type_name = type_name.to_uppercase();
+        let has = |names: &[&str]| names.iter().any(|name| type_name.contains(name));
+        if has(&["INT"]) {
+            ColumnType::Integer
+        } else if has(&["CHAR", "CLOB", "TEXT"]) {
+            ColumnType::Text
+        } else if type_name.is_empty() || has(&["BLOB"]) {
+            ColumnType::Blob
+        } else if has(&["REAL", "FLOA", "DOUB"]) {
+            ColumnType::Real
+        } else {
+            ColumnType::Numeric
+        }
+    }
+
+    /// Convert a value to the column's affinity where that loses nothing, as SQLite does:
+    /// a TEXT column stores numbers as text; a numeric column stores text that spells out a
+    /// number as that number, INTEGER and NUMERIC ones keeping a real with an integer value
+    /// as an integer. Blobs are never converted.
+    pub fn apply_affinity(&self, value: Value) -> Value {
+        let integral = |r: f64| -> Value {
+            let i = r as i64;
+            if i as f64 == r && (-9.2e18..9.2e18).contains(&r) { Value::Integer(i) } else { Value::Real(r) }
+        };
+        match (self, value) {
+            (ColumnType::Blob, value) | (_, value @ (Value::Null | Value::Blob(_))) => value,
+            (ColumnType::Text, value @ (Value::Integer(_) | Value::Real(_))) => Value::Text(value.to_string()),
+            (ColumnType::Text, value) => value,
+            (_, Value::Text(s)) => match parse_numeric(&s) {
+                Some(number) => self.apply_affinity(number),
+                None => Value::Text(s),
+            },
+            (ColumnType::Real, Value::Integer(i)) => Value::Real(i as f64),
+            (ColumnType::Integer | ColumnType::Numeric, Value::Real(r)) => integral(r),
+            (_, value) => value,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ColumnDef {
+    pub name: String,
+    pub column_type: ColumnType,
+    pub primary_key: bool,
+    pub unique: bool,
+    pub not_null: bool,
+    pub default: Option<Expr>,
+    /// How the column's text compares, in comparisons, ORDER BY and the indexes on it.
+    pub collation: Collation,
+    /// Left out of `*` and of an INSERT without a column list, as the columns a full-text
+    /// table adds to those declared are.
+    pub hidden: bool,
+}
+
+/// What deleting a parent row does to the child rows that reference it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ForeignKeyAction {
+    /// Fail if referencing rows remain once the statement is done (the default).
+    NoAction,
+    /// Fail if any row references the deleted one.
+    Restrict,
+    /// Delete the referencing rows as well.
+    Cascade,
+    /// Set the referencing column to NULL.
+    SetNull,
+}
+
+/// `column REFERENCES parent_table(parent_column)`; without a parent column the reference
+/// is to the parent's primary key.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ForeignKey {
+    pub column: String,
+    pub parent_table: String,
+    pub parent_column: Option<String>,
+    pub on_delete: ForeignKeyAction,
+}
+
+impl ForeignKey {
+    pub fn parent_column_index(&self, parent_def: &TableDef) -> Option<usize> {
+        match &self.parent_column {
+            Some(column) => parent_def.column_index(column),
+            None => match parent_def.key_columns()[..] {
+                [column] => Some(column),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// A column of the primary key of a WITHOUT ROWID table, by position in the table.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct KeyColumn {
+    pub column: usize,
+    pub descending: bool,
+}
+
+/// Where a row lives in its table's B-tree: at its rowid, or in a WITHOUT ROWID table at the
+/// values of its primary key.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum RowKey {
+    Rowid(i64),
+    Key(Vec<Value>),
+}
+
+impl fmt::Display for RowKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RowKey::Rowid(rowid) => write!(f, "{}", rowid),
+            RowKey::Key(key) => {
+                let values: Vec<String> = key.iter().map(|value| value.to_string()).collect();
+                write!(f, "({})", values.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct CheckConstraint {
+    pub name: Option<String>,
+    pub expr: Expr,
+}
+
+/// A table's columns and the root of its B-tree. A table is keyed by its INTEGER PRIMARY KEY
+/// column, the way `users` is keyed by `id`, unless it is WITHOUT ROWID.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TableDef {
+    pub name: String,
+    pub columns: Vec<ColumnDef>,
+    pub checks: Vec<CheckConstraint>,
+    pub foreign_keys: Vec<ForeignKey>,
+    pub root_page_num: usize,
+    pub sql: String,
+    /// Set by `INTEGER PRIMARY KEY AUTOINCREMENT`: rowids are never reused, even once the rows
+    /// holding them are deleted, and the largest given so far is kept in `sqlite_sequence`.
+    pub autoincrement: bool,
+    /// The primary key of a WITHOUT ROWID table, which keeps its rows in an `IndexTree` as
+    /// records of the key columns followed by the others, ordered by the key.
+    pub without_rowid: Option<Vec<KeyColumn>>,
+    /// The rows of a table named by a WITH clause, which are held in memory instead of a
+    /// B-tree and have no key.
+    pub rows: Option<Rc<Vec<Vec<Value>>>>,
+    /// Set for a recursive WITH table, whose rows are computed as scans reach them instead.
+    pub recursive: Option<Rc<RefCell<RecursiveRows>>>,
+    /// Set for a full-text table, whose terms are kept in the shadow tables `fts` names after it.
+    pub fts: Option<FtsOptions>,
+}
+
+impl TableDef {
+    /// The built-in users table, rooted after the header page and never stored in the catalog.
+    pub fn users() -> TableDef {
+        let columns = COLUMN_NAMES.iter()
+            .map(|name| ColumnDef {
+                name: String::from(*name),
+                column_type: if *name == "id" { ColumnType::Integer } else { ColumnType::Text },
+                primary_key: *name == "id",
+                unique: false,
+                not_null: false,
+                default: None,
+                // Usernames are matched however they are capitalized.
+                collation: if *name == "username" { Collation::NoCase } else { Collation::Binary },
+                hidden: false,
+            })
+            .collect();
+        TableDef {
+            name: String::from(TABLE_NAME),
+            columns,
+            checks: Vec::new(),
+            foreign_keys: Vec::new(),
+            root_page_num: USERS_ROOT_PAGE_NUM,
+            sql: String::from("create table users (id integer primary key, username varchar(32) collate nocase, email varchar(255))"),
+            autoincrement: false,
+            without_rowid: None,
+            rows: None,
+            recursive: None,
+            fts: None,
+        }
+    }
+
+    /// A table whose rows are held in memory, as a WITH clause makes, with columns of the given
+    /// names, no affinity and no constraints.
+    pub fn in_memory(name: &str, columns: Vec<String>, rows: Vec<Vec<Value>>) -> TableDef {
+        let columns = columns.into_iter()
+            .map(|name| ColumnDef {
+                name,
+                column_type: ColumnType::Blob,
+                primary_key: false,
+                unique: false,
+                not_null: false,
+                default: None,
+                collation: Collation::Binary,
+                hidden: false,
+            })
+            .collect();
+        TableDef {
+            name: String::from(name),
+            columns,
+            checks: Vec::new(),
+            foreign_keys: Vec::new(),
+            root_page_num: 0,
+            sql: String::new(),
+            autoincrement: false,
+            without_rowid: None,
+            rows: Some(Rc::new(rows)),
+            recursive: None,
+            fts: None,
+        }
+    }
+
+    /// The table of AUTOINCREMENT sequences, with a row of name and largest rowid per table.
+    /// It lives in memory, saved with the catalog.
+    pub fn sequences(rows: Vec<Vec<Value>>) -> TableDef {
+        TableDef {
+            sql: format!("create table {}(name,seq)", SEQUENCE_TABLE_NAME),
+            ..TableDef::in_memory(SEQUENCE_TABLE_NAME, vec![String::from("name"), String::from("seq")], rows)
+        }
+    }
+
+    pub fn is_users(&self) -> bool {
+        self.name == TABLE_NAME
+    }
+
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The INTEGER PRIMARY KEY column, which holds the rowid. A WITHOUT ROWID table has none.
+    pub fn primary_key(&self) -> Option<usize> {
+        if self.without_rowid.is_some() {
+            return None;
+        }
+        self.columns.iter().position(|column| column.primary_key)
+    }
+
+    /// The columns of the primary key, in key order.
+    pub fn key_columns(&self) -> Vec<usize> {
+        match &self.without_rowid {
+            Some(key) => key.iter().map(|key_column| key_column.column).collect(),
+            None => self.primary_key().into_iter().collect(),
+        }
+    }
+
+    /// The tree a WITHOUT ROWID table keeps its rows in, ordered by its primary key.
+    pub fn key_tree(&self) -> Option<IndexTree> {
+        let key = self.without_rowid.as_ref()?;
+        let descending = key.iter().map(|key_column| key_column.descending).collect();
+        let collations = key.iter().map(|key_column| self.columns[key_column.column].collation.clone()).collect();
+        Some(IndexTree { root_page_num: self.root_page_num, order: KeyOrder { descending, collations }, key_len: Some(key.len()) })
+    }
+
+    /// Compare two keys of the table's rows, a WITHOUT ROWID table's in the order of its tree.
+    pub fn compare_keys(&self, a: &RowKey, b: &RowKey) -> Ordering {
+        match (a, b, self.key_tree()) {
+            (RowKey::Key(a), RowKey::Key(b), Some(tree)) => tree.compare(a, b),
+            _ => a.cmp(b),
+        }
+    }
+
+    /// The order of an index on the column: by its collation, then by rowid.
+    pub fn index_order(&self, column: usize) -> KeyOrder {
+        KeyOrder { descending: Vec::new(), collations: vec![self.columns[column].collation.clone()] }
+    }
+
+    /// The record a WITHOUT ROWID table stores a row as: the key columns, then the rest in
+    /// table order, as SQLite lays them out.
+    pub fn key_record(&self, row: &[Value]) -> Vec<Value> {
+        let key = self.key_columns();
+        let rest = (0..self.columns.len()).filter(|column| !key.contains(column));
+        key.iter().copied().chain(rest).map(|column| row[column].clone()).collect()
+    }
+
+    /// The row a WITHOUT ROWID table's record holds, back in table order.
+    pub fn record_row(&self, record: Vec<Value>) -> Vec<Value> {
+        let key = self.key_columns();
+        let rest = (0..self.columns.len()).filter(|column| !key.contains(column));
+        let mut row = vec![Value::Null; self.columns.len()];
+        for (column, value) in key.iter().copied().chain(rest).zip(record) {
+            row[column] = value;
+        }
+        row
+    }
+
+    /// Bind the custom collations of the columns, and the collations and function calls of the
+    /// CHECK and DEFAULT expressions, to what the connection registered. An expression with a
+    /// collation none is registered under is left as it is, to fail when it is evaluated.
+    pub fn bind(&mut self, collations: &Collations, functions: &Functions) {
+        for column in &mut self.columns {
+            collations.bind(&mut column.collation);
+            if let Some(Ok(default)) = column.default.as_ref().map(|default| default.bind(collations, functions)) {
+                column.default = Some(default);
+            }
+        }
+        for check in &mut self.checks {
+            if let Ok(expr) = check.expr.bind(collations, functions) {
+                check.expr = expr;
+            }
+        }
+    }
+
+    /// A collation of a column that no collation registered on the connection is bound to.
+    pub fn unknown_collation(&self) -> Option<&Collation> {
+        self.columns.iter().map(|column| &column.collation).find(|collation| !collation.is_known())
+    }
+
+    pub fn column_collation(&self, name: &str) -> Option<Collation> {
+        self.column_index(name).map(|column| self.columns[column].collation.clone())
+    }
+
+    /// Look up a column of the row by name, for evaluating expressions against it.
+    pub fn column_value(&self, row: &[Value], name: &str) -> Option<Value> {
+        self.column_index(name).map(|column| row[column].clone())
+    }
+
+    /// Check the NOT NULL and CHECK constraints of a row about to be written.
+    /// A CHECK passes unless its expression is false; NULL counts as passing, as in SQLite.
+    pub fn check_row(&self, row: &[Value]) -> ExecuteResult {
+        for (column, value) in self.columns.iter().zip(row) {
+            if column.not_null && value.is_null() {
+                return ExecuteNotNullViolation(format!("{}.{}", self.name, column.name));
+            }
+        }
+        for check in &self.checks {
+            match check.expr.evaluate(&|name| self.column_value(row, name)) {
+                Ok(value) if value.to_bool() == Some(false) => {
+                    let name = check.name.clone().unwrap_or_else(|| check.expr.to_string());
+                    return ExecuteCheckViolation(name);
+                }
+                Ok(_) => {}
+                Err(err) => return err,
+            }
+        }
+        ExecuteSuccess
+    }
+
+    /// The B-tree key of a row: its rowid, the value of its INTEGER PRIMARY KEY, or the values
+    /// of the primary key of a WITHOUT ROWID table, none of which may be NULL.
+    pub fn row_key(&self, values: &[Value]) -> Result<RowKey, ExecuteResult> {
+        if self.without_rowid.is_some() {
+            let key = self.key_columns();
+            if let Some(column) = key.iter().find(|column| values[**column].is_null()) {
+                return Err(ExecuteNotNullViolation(format!("{}.{}", self.name, self.columns[*column].name)));
+            }
+            return Ok(RowKey::Key(key.iter().map(|column| values[*column].clone()).collect()));
+        }
+        match &values[self.primary_key().unwrap()] {
+            Value::Integer(key) => Ok(RowKey::Rowid(*key)),
+            _ => Err(ExecuteDatatypeMismatch),
+        }
+    }
+
+    pub fn decode_row(&self, pager: &Pager, page: &Page, cell_num: usize) -> Vec<Value> {
+        decode_record(&pager.read_payload(page.leaf_node_slot(cell_num)))
+    }
+
+    /// Every row is stored as a record, overflowing its cell when long; the users table's
+    /// username and email keep the lengths of its original fixed layout.
+    pub fn encode_row(&self, values: &[Value]) -> Result<Vec<u8>, ExecuteResult> {
+        if self.is_users() && values[1..].iter().zip([USERNAME_SIZE, EMAIL_SIZE]).any(|(value, size)| value.to_string().len() >= size) {
+            return Err(ExecuteStringTooLong);
+        }
+        if self.without_rowid.is_some() {
+            return Ok(encode_record(&self.key_record(values)));
+        }
+        // What a full-text table's hidden columns hold is worked out by each search, not stored.
+        if let Some(fts) = &self.fts {
+            let mut values = values.to_vec();
+            values[fts.unindexed.len() + 1..].fill(Value::Null);
+            return Ok(encode_record(&values));
+        }
+        Ok(encode_record(values))
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct IndexDef {
+    pub name: String,
+    pub table_name: String,
+    /// The indexed column, or for an index on an expression the expression's SQL.
+    pub column: String,
+    /// The expression an index on an expression keeps the value of for every row.
+    pub expr: Option<Expr>,
+    pub unique: bool,
+}
+
+impl IndexDef {
+    pub fn sql(&self) -> String {
+        let unique = if self.unique { "unique " } else { "" };
+        format!("create {}index {} on {} ({})", unique, self.name, self.table_name, self.column)
+    }
+
+    /// How the index orders its keys: by the column's collation, or BINARY for an expression.
+    pub fn order(&self, table_def: &TableDef) -> KeyOrder {
+        match (&self.expr, table_def.column_index(&self.column)) {
+            (None, Some(column)) => table_def.index_order(column),
+            _ => KeyOrder::default(),
+        }
+    }
+
+    /// The value the index keeps for a row of its table.
+    pub fn key_value(&self, table_def: &TableDef, row: &[Value]) -> Result<Value, ExecuteResult> {
+        match &self.expr {
+            Some(expr) => expr.evaluate(&|name| table_def.column_value(row, name)),
+            None => Ok(row[table_def.column_index(&self.column).unwrap()].clone()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Index {
+    pub def: IndexDef,
+    pub tree: IndexTree,
+}
+
+/// Reads the fields of the catalog in turn, failing rather than reading past its end.
+struct CatalogReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> CatalogReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        match self.offset.checked_add(len).filter(|end| *end <= self.bytes.len()) {
+            Some(end) => {
+                let bytes = &self.bytes[self.offset..end];
+                self.offset = end;
+                Ok(bytes)
+            }
+            None => Err(String::from("database disk image is malformed: catalog ends early")),
+        }
+    }
+
+    fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn read_i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn read_text(&mut self) -> Result<String, String> {
+        let len = self.read_u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
+
+fn put_text(buf: &mut Vec<u8>, text: &str) {
+    buf.extend_from_slice(&(text.len() as u32).to_be_bytes());
+    buf.extend_from_slice(text.as_bytes());
+}
+
+/// Everything created with DDL, persisted after the file header as
+/// [entry count] followed by [root page|sql length|sql] for every entry, then
+/// [sequence count] followed by [name length|name|i64 sequence] for every AUTOINCREMENT table
+/// that has been given a rowid. Counts, pages and lengths are big-endian u32s.
+/// Tables come before indexes so an index never names a table not yet loaded.
+pub struct Schema {
+    pub tables: Vec<TableDef>,
+    pub indexes: Vec<Index>,
+    /// The tables of the WITH clause of the statement running, which hide any table of the
+    /// same name until it is done. They are never saved.
+    pub ctes: Vec<TableDef>,
+}
+
+impl Schema {
+    pub fn load(pager: &Pager) -> Result<Schema, String> {
+        // A SQLite file brings its own tables and indexes, and no users table.
+        if let Some(sqlite) = &pager.sqlite {
+            let (tables, indexes) = sqlite.schema_objects(pager)?;
+            return Ok(Schema { tables, indexes, ctes: Vec::new() });
+        }
+        let catalog = pager.read_catalog()?;
+        let mut schema = Schema {
+            tables: vec![TableDef::users()],
+            indexes: Vec::new(),
+            ctes: Vec::new(),
+        };
+        // A new file has nothing created yet.
+        if catalog.is_empty() {
+            return Ok(schema);
+        }
+        let mut reader = CatalogReader { bytes: &catalog, offset: 0 };
+        let num_entries = reader.read_u32()?;
+        for _ in 0..num_entries {
+            let root_page_num = reader.read_u32()? as usize;
+            let sql = reader.read_text()?;
+            if root_page_num >= pager.num_pages {
+                return Err(format!("database disk image is malformed: root page {} of {} out of range", root_page_num, sql));
+            }
+            match prepare_statement(&sql) {
+                Ok(stmt) if stmt.table_to_create.is_some() => {
+                    let mut def = stmt.table_to_create.unwrap();
+                    def.root_page_num = root_page_num;
+                    schema.tables.push(def);
+                }
+                Ok(stmt) if stmt.index_to_create.is_some() => {
+                    let def = stmt.index_to_create.unwrap();
+                    // A table is always in the catalog ahead of its indexes.
+                    let order = schema.find_table(&def.table_name)
+                        .map(|table_def| def.order(table_def))
+                        .unwrap_or_default();
+                    schema.indexes.push(Index { def, tree: IndexTree { root_page_num, order, key_len: None } });
+                }
+                _ => return Err(format!("database disk image is malformed: corrupt catalog entry: {}", sql)),
+            }
+        }
+        let num_sequences = reader.read_u32()?;
+        let mut sequences = Vec::new();
+        for _ in 0..num_sequences {
+            let name = reader.read_text()?;
+            let seq = reader.read_i64()?;
+            sequences.push(vec![Value::Text(name), Value::Integer(seq)]);
+        }
+        if schema.tables.iter().any(|table| table.autoincrement) {
+            schema.tables.push(TableDef::sequences(sequences));
+        }
+        Ok(schema)
+    }
+
+    /// Write the catalog back after the file header.
+    pub fn save(&self, pager: &mut Pager) {
+        let mut entries: Vec<(usize, String)> = self.tables.iter()
+            .filter(|table| !table.is_users() && table.rows.is_none())
+            .map(|table| (table.root_page_num, table.sql.clone()))
+            .collect();
+        entries.extend(self.indexes.iter().map(|index| (index.tree.root_page_num, index.def.sql())));
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        for (root_page_num, sql) in entries {
+            buf.extend_from_slice(&(root_page_num as u32).to_be_bytes());
+            put_text(&mut buf, &sql);
+        }
+        let sequences = self.find_table(SEQUENCE_TABLE_NAME).and_then(|table| table.rows.clone()).unwrap_or_default();
+        buf.extend_from_slice(&(sequences.len() as u32).to_be_bytes());
+        for row in sequences.iter() {
+            put_text(&mut buf, &row[0].to_string());
+            buf.extend_from_slice(&row[1].to_integer().unwrap_or(0).to_be_bytes());
+        }
+        pager.write_catalog(&buf);
+    }
+
+    /// Bind the custom collations and function calls of every table and index to what the
+    /// connection registered, as it does on opening and each time it registers one.
+    pub fn bind(&mut self, collations: &Collations, functions: &Functions) {
+        for table_def in &mut self.tables {
+            table_def.bind(collations, functions);
+        }
+        for index in &mut self.indexes {
+            index.tree.order.collations.iter_mut().for_each(|collation| collations.bind(collation));
+            if let Some(Ok(expr)) = index.def.expr.as_ref().map(|expr| expr.bind(collations, functions)) {
+                index.def.expr = Some(expr);
+            }
+        }
+    }
+
+    /// Fail with the first collation of the table, or of an index on it, that no collation
+    /// registered on the connection is bound to; nothing may read or write the table until
+    /// one is.
+    pub fn check_collations(&self, table_def: &TableDef) -> Result<(), ExecuteResult> {
+        let mut indexes = self.indexes.iter()
+            .filter(|index| index.def.table_name.eq_ignore_ascii_case(&table_def.name))
+            .flat_map(|index| &index.tree.order.collations);
+        match table_def.unknown_collation().or_else(|| indexes.find(|collation| !collation.is_known())) {
+            Some(collation) => Err(ExecuteNoSuchCollation(collation.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    pub fn find_table(&self, name: &str) -> Option<&TableDef> {
+        self.ctes.iter().chain(&self.tables).find(|table| table.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The largest rowid an AUTOINCREMENT table has been given, or 0 before its first.
+    pub fn sequence(&self, table_name: &str) -> i64 {
+        let rows = match self.find_table(SEQUENCE_TABLE_NAME).and_then(|table| table.rows.as_ref()) {
+            Some(rows) => rows,
+            None => return 0,
+        };
+        rows.iter()
+            .find(|row| row[0].to_string().eq_ignore_ascii_case(table_name))
+            .and_then(|row| row[1].to_integer())
+            .unwrap_or(0)
+    }
+
+    /// Record the largest rowid an AUTOINCREMENT table has been given, in `sqlite_sequence`.
+    pub fn set_sequence(&mut self, table_name: &str, seq: i64) {
+        let table = match self.tables.iter_mut().find(|table| table.name == SEQUENCE_TABLE_NAME) {
+            Some(table) => table,
+            None => return,
+        };
+        let mut rows = table.rows.as_deref().cloned().unwrap_or_default();
+        match rows.iter_mut().find(|row| row[0].to_string().eq_ignore_ascii_case(table_name)) {
+            Some(row) => row[1] = Value::Integer(seq),
+            None => rows.push(vec![Value::Text(String::from(table_name)), Value::Integer(seq)]),
+        }
+        table.rows = Some(Rc::new(rows));
+    }
+
+    pub fn find_index(&self, name: &str) -> Option<&Index> {
+        self.indexes.iter().find(|index| index.def.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn table_indexes(&self, table_name: &str) -> Vec<Index> {
+        if self.ctes.iter().any(|cte| cte.name.eq_ignore_ascii_case(table_name)) {
+            return Vec::new();
+        }
+        self.indexes.iter()
+            .filter(|index| index.def.table_name.eq_ignore_ascii_case(table_name))
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,235 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::rc::Rc;
+use crate::collation::KeyOrder;
+use crate::constant::{SORTER_MEMORY_BUDGET, SORTER_MERGE_FAN_IN};
+use crate::result::ExecuteResult;
+use crate::temp_file::{TempFile, TempFileReader};
+use crate::value::Value;
+
+/// A row waiting to be sorted, with the ORDER BY values it sorts by.
+struct SortRecord {
+    key: Vec<Value>,
+    row: Vec<Value>,
+}
+
+impl SortRecord {
+    /// Bytes the record takes in a run file, which also stands in for its size in memory.
+    fn size(&self) -> usize {
+        let mut buf = Vec::new();
+        self.key.iter().chain(&self.row).for_each(|value| value.serialize(&mut buf));
+        buf.len()
+    }
+}
+
+/// A sorted run spilled to a temporary file, one record of key then row values per row.
+struct Run {
+    reader: TempFileReader,
+    key_len: usize,
+}
+
+impl Run {
+    fn next_record(&mut self) -> Result<Option<SortRecord>, ExecuteResult> {
+        match self.reader.next() {
+            Some(values) => {
+                let mut key = values?;
+                let row = key.split_off(self.key_len);
+                Ok(Some(SortRecord { key, row }))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// The next record of a run being merged. The heap's greatest head is the one with the
+/// smallest key and, of equal keys, the one of the earliest run, which keeps equal keys in
+/// insertion order.
+struct Head {
+    order: Rc<KeyOrder>,
+    record: SortRecord,
+    run: usize,
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Head) -> Ordering {
+        self.order.compare(&other.record.key, &self.record.key).then(other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Head) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Head) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+/// Runs being merged into one sorted stream, with the next record of each in a heap.
+struct Merge {
+    runs: Vec<Run>,
+    heads: BinaryHeap<Head>,
+}
+
+impl Merge {
+    fn new(order: &Rc<KeyOrder>, files: Vec<TempFile>) -> Result<Merge, ExecuteResult> {
+        let key_len = order.descending.len();
+        let mut runs = Vec::new();
+        for file in files {
+            runs.push(Run { reader: file.into_reader()?, key_len });
+        }
+        let mut heads = BinaryHeap::new();
+        for (run, reader) in runs.iter_mut().enumerate() {
+            if let Some(record) = reader.next_record()? {
+                heads.push(Head { order: order.clone(), record, run });
+            }
+        }
+        Ok(Merge { runs, heads })
+    }
+
+    fn next_record(&mut self) -> Result<Option<SortRecord>, ExecuteResult> {
+        let head = match self.heads.pop() {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        if let Some(record) = self.runs[head.run].next_record()? {
+            self.heads.push(Head { order: head.order.clone(), record, run: head.run });
+        }
+        Ok(Some(head.record))
+    }
+}
+
+/// Sorts the rows of an ORDER BY. Rows are held in memory until they pass
+/// `SORTER_MEMORY_BUDGET` bytes; then they are sorted and spilled to a temporary file as a run,
+/// and the runs are merged when the rows are read back. Only the run being written is open
+/// until then, and a merge reads at most `SORTER_MERGE_FAN_IN` runs at once, merging more in
+/// passes first. Rows with equal keys keep the order they were inserted in.
+pub struct Sorter {
+    order: KeyOrder,
+    records: Vec<SortRecord>,
+    records_size: usize,
+    runs: Vec<TempFile>,
+}
+
+impl Sorter {
+    /// A sorter for keys of `order.descending.len()` values, in that order. NULLs sort first,
+    /// then numbers, then text, then blobs; a descending term reverses that.
+    pub fn new(order: KeyOrder) -> Sorter {
+        Sorter {
+            order,
+            records: Vec::new(),
+            records_size: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, key: Vec<Value>, row: Vec<Value>) -> Result<(), ExecuteResult> {
+        let record = SortRecord { key, row };
+        self.records_size += record.size();
+        self.records.push(record);
+        if self.records_size > SORTER_MEMORY_BUDGET {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn sort_records(&mut self) {
+        let order = &self.order;
+        self.records.sort_by(|a, b| order.compare(&a.key, &b.key));
+    }
+
+    fn spill(&mut self) -> Result<(), ExecuteResult> {
+        self.sort_records();
+        let mut run = TempFile::create()?;
+        for record in &self.records {
+            run.write_record(record.key.iter().chain(&record.row))?;
+        }
+        run.finish()?;
+        self.runs.push(run);
+        self.records.clear();
+        self.records_size = 0;
+        Ok(())
+    }
+
+    /// The rows in sorted order.
+    pub fn finish(mut self) -> Result<SortedRows, ExecuteResult> {
+        if self.runs.is_empty() {
+            self.sort_records();
+            return Ok(SortedRows { source: SortSource::Memory(self.records.into_iter()) });
+        }
+        if !self.records.is_empty() {
+            self.spill()?;
+        }
+        let order = Rc::new(self.order);
+        let mut runs = self.runs;
+        // Each pass merges neighbouring runs, so earlier rows stay in earlier runs.
+        while runs.len() > SORTER_MERGE_FAN_IN {
+            let mut merged = Vec::new();
+            let mut rest = runs.into_iter();
+            loop {
+                let group: Vec<TempFile> = rest.by_ref().take(SORTER_MERGE_FAN_IN).collect();
+                if group.is_empty() {
+                    break;
+                }
+                let mut merge = Merge::new(&order, group)?;
+                let mut run = TempFile::create()?;
+                while let Some(record) = merge.next_record()? {
+                    run.write_record(record.key.iter().chain(&record.row))?;
+                }
+                run.finish()?;
+                merged.push(run);
+            }
+            runs = merged;
+        }
+        Ok(SortedRows { source: SortSource::Merge(Merge::new(&order, runs)?) })
+    }
+}
+
+/// A sorted row with the key it was sorted by, as (key, row).
+pub type KeyedRow = (Vec<Value>, Vec<Value>);
+
+/// Rows coming out of a `Sorter`, in sorted order, or the error reading a run back.
+pub struct SortedRows {
+    source: SortSource,
+}
+
+/// Sorted records either straight from memory or merged from the runs.
+enum SortSource {
+    Memory(std::vec::IntoIter<SortRecord>),
+    Merge(Merge),
+}
+
+impl SortedRows {
+    /// Hand the rows to `output`, leaving out the first `offset` and stopping after `limit`.
+    pub fn output(self, offset: usize, limit: usize, mut output: impl FnMut(&[Value])) -> Result<(), ExecuteResult> {
+        for (i, row) in self.enumerate().take(offset.saturating_add(limit)) {
+            let row = row?;
+            if i >= offset {
+                output(&row);
+            }
+        }
+        Ok(())
+    }
+
+    /// The next row with the key it was sorted by.
+    pub fn next_keyed(&mut self) -> Option<Result<KeyedRow, ExecuteResult>> {
+        let record = match &mut self.source {
+            SortSource::Memory(records) => records.next().map(Ok),
+            SortSource::Merge(merge) => merge.next_record().transpose(),
+        };
+        record.map(|record| record.map(|record| (record.key, record.row)))
+    }
+}
+
+impl Iterator for SortedRows {
+    type Item = Result<Vec<Value>, ExecuteResult>;
+
+    fn next(&mut self) -> Option<Result<Vec<Value>, ExecuteResult>> {
+        self.next_keyed().map(|record| record.map(|(_, row)| row))
+    }
+}
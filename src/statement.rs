@@ -1,30 +1,258 @@
-use crate::constant::{EMAIL_SIZE, USERNAME_SIZE};
+use std::cmp::Ordering;
+use std::iter;
+use std::ops::Bound;
+use std::rc::Rc;
+use std::slice;
+use crate::aggregate::{Aggregate, Aggregator, FinishedGroup};
+use crate::collation::{Collation, Collations, KeyOrder};
+use crate::function::Functions;
+use crate::compound::{select_compound, CompoundOp};
+use crate::constant::{EMAIL_SIZE, SEQUENCE_TABLE_NAME, TABLE_NAME, USERNAME_SIZE};
+use crate::cte::{self, materialize_ctes, Cte};
 use crate::cursor::Cursor;
+use crate::expr::{BinaryOp, CompareOp, Expr};
+use crate::file_format::{self, BtreeCursor};
+use crate::fts;
+use crate::index::{IndexKey, IndexTree};
+use crate::parser::Parser;
+use crate::join::{for_each_joined_row, from_table, function_rows, resolve_columns, JoinScope};
+use crate::plan::{compile_statement, describe_function_scan, describe_joins, describe_scan, describe_temp_trees, needs_sort, plan_joins, select_temp_trees, plan_statement, plan_where, print_operations, print_query_plan, JoinStrategy, PlanLine, QueryPlan, CONSTANT_ROW};
 use crate::result::{ExecuteResult, PrepareResult};
 use crate::result::ExecuteResult::*;
 use crate::result::PrepareResult::*;
-use crate::row::Row;
+use crate::schema::{ColumnType, ForeignKey, ForeignKeyAction, Index, IndexDef, RowKey, Schema, TableDef};
+use crate::sorter::Sorter;
+use crate::subquery::{correlated_subqueries, describe_subquery, materialize_subqueries, subquery_of, subquery_value, with_subquery_columns};
 use crate::table::Table;
+use crate::tokenizer::tokenize;
+use crate::value::Value;
+use crate::window::{substitute_windows, window_calls, WindowStage};
 
-#[derive(PartialEq)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum StatementType {
     StatementInsert,
     StatementSelect,
-    StatementUnsupported,
+    StatementUpdate,
+    StatementDelete,
+    StatementCreateIndex,
+    StatementCreateTable,
+    StatementPragma,
 }
 
+#[derive(Clone, PartialEq, Debug)]
+pub enum ExplainMode {
+    ExplainOperations,
+    ExplainQueryPlan,
+}
+
+/// One entry of a SELECT list.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ResultColumn {
+    /// `*`: every column of the table, in table order.
+    All,
+    /// `table.*`: every column of one table of a join.
+    TableAll(String),
+    /// `expr [AS alias]`
+    Expr {
+        expr: Expr,
+        alias: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum JoinKind {
+    /// `[INNER] JOIN`: only pairs of rows that satisfy ON.
+    Inner,
+    /// `LEFT [OUTER] JOIN`: as INNER, plus a row of NULLs for a left row nothing matched.
+    Left,
+    /// `CROSS JOIN` or a comma: every pair of rows, filtered by any ON.
+    Cross,
+}
+
+/// One table joined onto the FROM clause: `kind table [[AS] alias] [ON expr]`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct JoinClause {
+    pub kind: JoinKind,
+    pub table_name: String,
+    pub alias: Option<String>,
+    /// The arguments of a table-valued function, such as `json_each(data)`, named in place of a
+    /// table.
+    pub args: Option<Vec<Expr>>,
+    pub on: Option<Expr>,
+}
+
+/// One `expr [ASC | DESC]` term of an ORDER BY.
+#[derive(Clone, PartialEq, Debug)]
+pub struct OrderingTerm {
+    pub expr: Expr,
+    pub descending: bool,
+}
+
+#[derive(Clone, PartialEq, Debug)]
 pub struct Statement {
-    stmt_type: StatementType,
-    row_to_insert: Option<Row>,
+    pub(crate) stmt_type: StatementType,
+    pub(crate) columns: Vec<String>,
+    pub(crate) values: Vec<Vec<Expr>>,
+    pub(crate) result_columns: Vec<ResultColumn>,
+    pub(crate) explain: Option<ExplainMode>,
+    pub(crate) table_name: String,
+    pub(crate) table_alias: Option<String>,
+    /// The arguments of the table-valued function the FROM clause names first, if it does.
+    pub(crate) table_args: Option<Vec<Expr>>,
+    pub(crate) joins: Vec<JoinClause>,
+    pub(crate) where_clause: Option<Expr>,
+    pub(crate) group_by: Vec<Expr>,
+    pub(crate) having: Option<Expr>,
+    pub(crate) order_by: Vec<OrderingTerm>,
+    pub(crate) limit: Option<Expr>,
+    pub(crate) offset: Option<Expr>,
+    pub(crate) assignments: Vec<(String, Expr)>,
+    pub(crate) index_to_create: Option<IndexDef>,
+    pub(crate) table_to_create: Option<TableDef>,
+    pub(crate) pragma: Option<(String, Option<Value>)>,
+    pub(crate) ctes: Vec<Cte>,
+    /// The SELECTs after the first of a compound SELECT, each with the operator before it. The
+    /// ORDER BY, LIMIT and OFFSET of the statement then apply to the whole compound.
+    pub(crate) compound: Vec<(CompoundOp, Statement)>,
+}
+
+impl Statement {
+    pub fn new(stmt_type: StatementType) -> Self {
+        Statement {
+            stmt_type,
+            columns: Vec::new(),
+            values: Vec::new(),
+            result_columns: Vec::new(),
+            explain: None,
+            table_name: String::from(TABLE_NAME),
+            table_alias: None,
+            table_args: None,
+            joins: Vec::new(),
+            where_clause: None,
+            group_by: Vec::new(),
+            having: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            assignments: Vec::new(),
+            index_to_create: None,
+            table_to_create: None,
+            pragma: None,
+            ctes: Vec::new(),
+            compound: Vec::new(),
+        }
+    }
+
+    /// Whether the FROM clause reads the table of that name.
+    pub(crate) fn reads_table(&self, name: &str) -> bool {
+        self.table_name.eq_ignore_ascii_case(name) || self.joins.iter().any(|join| join.table_name.eq_ignore_ascii_case(name))
+    }
+
+    /// The expressions evaluated against each row the statement reads: its result columns,
+    /// WHERE, GROUP BY, HAVING, ORDER BY and SET.
+    pub(crate) fn row_exprs(&self) -> Vec<&Expr> {
+        let mut exprs = Vec::new();
+        for column in &self.result_columns {
+            if let ResultColumn::Expr { expr, .. } = column {
+                exprs.push(expr);
+            }
+        }
+        exprs.extend(&self.where_clause);
+        exprs.extend(&self.group_by);
+        exprs.extend(&self.having);
+        exprs.extend(self.order_by.iter().map(|term| &term.expr));
+        exprs.extend(self.assignments.iter().map(|(_, expr)| expr));
+        exprs
+    }
+
+    /// Every expression of the statement: those of `row_exprs`, then the arguments of
+    /// table-valued functions and ON, LIMIT, OFFSET and VALUES, then those of the other SELECTs
+    /// of a compound.
+    pub(crate) fn exprs(&self) -> Vec<&Expr> {
+        let mut exprs = self.row_exprs();
+        exprs.extend(self.table_args.iter().flatten());
+        for join in &self.joins {
+            exprs.extend(join.args.iter().flatten());
+            exprs.extend(&join.on);
+        }
+        exprs.extend(&self.limit);
+        exprs.extend(&self.offset);
+        exprs.extend(self.values.iter().flatten());
+        exprs.extend(self.compound.iter().flat_map(|(_, select)| select.exprs()));
+        exprs
+    }
+
+    /// Every expression of the statement, in the order of `exprs`.
+    pub(crate) fn exprs_mut(&mut self) -> Vec<&mut Expr> {
+        let mut exprs = Vec::new();
+        for column in &mut self.result_columns {
+            if let ResultColumn::Expr { expr, .. } = column {
+                exprs.push(expr);
+            }
+        }
+        exprs.extend(&mut self.where_clause);
+        exprs.extend(&mut self.group_by);
+        exprs.extend(&mut self.having);
+        exprs.extend(self.order_by.iter_mut().map(|term| &mut term.expr));
+        exprs.extend(self.assignments.iter_mut().map(|(_, expr)| expr));
+        exprs.extend(self.table_args.iter_mut().flatten());
+        for join in &mut self.joins {
+            exprs.extend(join.args.iter_mut().flatten());
+            exprs.extend(&mut join.on);
+        }
+        exprs.extend(&mut self.limit);
+        exprs.extend(&mut self.offset);
+        exprs.extend(self.values.iter_mut().flatten());
+        exprs.extend(self.compound.iter_mut().flat_map(|(_, select)| select.exprs_mut()));
+        exprs
+    }
+
+    /// Bind the custom collations and function calls of the statement, those of its WITH
+    /// clause and of a table or index it creates included, to what the connection registered,
+    /// failing on a collation none is registered under.
+    pub(crate) fn bind(&mut self, collations: &Collations, functions: &Functions) -> Result<(), ExecuteResult> {
+        for expr in self.exprs_mut() {
+            *expr = expr.bind(collations, functions)?;
+        }
+        for cte in &mut self.ctes {
+            cte.initial.bind(collations, functions)?;
+            if let Some(recursive) = &mut cte.recursive {
+                recursive.bind(collations, functions)?;
+            }
+            for term in &mut cte.order_by {
+                term.expr = term.expr.bind(collations, functions)?;
+            }
+        }
+        if let Some(table_def) = &mut self.table_to_create {
+            table_def.bind(collations, functions);
+        }
+        if let Some(expr) = self.index_to_create.as_mut().and_then(|index_def| index_def.expr.as_mut()) {
+            *expr = expr.bind(collations, functions)?;
+        }
+        Ok(())
+    }
 }
 
-pub fn prepare_statement(command: &str) -> Result<Box<Option<Statement>>, PrepareResult> {
-    if command.starts_with("insert") {
+pub fn prepare_statement(command: &str) -> Result<Statement, PrepareResult> {
+    let keyword = command.split_whitespace().next().unwrap_or("").to_lowercase();
+    if keyword == "explain" {
+        // The keywords are tokens like any others: in any case, with any whitespace between.
+        let tokens = tokenize(command)?;
+        let is_query_plan = tokens.len() > 2 && tokens[1].0.is_keyword("query") && tokens[2].0.is_keyword("plan");
+        let (mode, start) = if is_query_plan { (ExplainMode::ExplainQueryPlan, 3) } else { (ExplainMode::ExplainOperations, 1) };
+        match tokens.get(start) {
+            Some((_, offset)) => prepare_explain(&command[*offset..], mode),
+            None => Err(PrepareSyntaxErr),
+        }
+    } else if keyword == "insert" && command.split_whitespace().nth(1).is_some_and(|word| word.eq_ignore_ascii_case("into")) {
+        Parser::new(command)?.parse_insert()
+    } else if command.starts_with("insert") {
         let args: Vec<&str> = command.split(" ").collect();
         if args.len() < 4 {
             return Err(PrepareSyntaxErr);
         }
-        let id = match args[1].trim().parse::<u32>() {
+        let id = match args[1].trim().parse::<i64>() {
             Ok(id) => id,
             Err(_) => return Err(PrepareInvalidId),
         };
@@ -38,63 +266,1724 @@ pub fn prepare_statement(command: &str) -> Result<Box<Option<Statement>>, Prepar
         } else {
             return Err(PrepareStringTooLong);
         };
-        Ok(Box::new(Some(Statement {
-            stmt_type: StatementType::StatementInsert,
-            row_to_insert: Some(Row {
-                id,
-                username,
-                email,
-            }),
-        })))
-    } else if command.starts_with("select") {
-        Ok(Box::new(Some(Statement {
-            stmt_type: StatementType::StatementSelect,
-            row_to_insert: None,
-        })))
+        let mut stmt = Statement::new(StatementType::StatementInsert);
+        stmt.values.push(vec![
+            Expr::Literal(Value::Integer(id)),
+            Expr::Literal(Value::Text(username)),
+            Expr::Literal(Value::Text(email)),
+        ]);
+        Ok(stmt)
+    } else if keyword == "select" {
+        Parser::new(command)?.parse_select()
+    } else if keyword == "with" {
+        Parser::new(command)?.parse_with()
+    } else if keyword == "update" {
+        Parser::new(command)?.parse_update()
+    } else if keyword == "delete" {
+        Parser::new(command)?.parse_delete()
+    } else if keyword == "create" {
+        Parser::new(command)?.parse_create()
+    } else if keyword == "pragma" {
+        Parser::new(command)?.parse_pragma()
     } else {
         Err(PrepareUnrecognized)
     }
 }
 
-pub fn execute_statement(statement: Box<Option<Statement>>, table: &mut Table) -> ExecuteResult {
-    let stmt = statement.unwrap();
+fn prepare_explain(command: &str, mode: ExplainMode) -> Result<Statement, PrepareResult> {
+    let mut stmt = prepare_statement(command.trim())?;
+    if stmt.explain.is_some() {
+        return Err(PrepareSyntaxErr);
+    }
+    stmt.explain = Some(mode);
+    Ok(stmt)
+}
+
+pub fn execute_statement(mut stmt: Statement, table: &mut Table) -> ExecuteResult {
+    if let Err(err) = stmt.bind(&table.collations, &table.functions) {
+        return err;
+    }
+    // The tables of a WITH clause last as long as the statement. EXPLAIN only needs their columns.
+    let result = match materialize_ctes(&mut stmt.ctes, stmt.explain.is_none(), table) {
+        Ok(()) => execute_body(stmt, table),
+        Err(err) => err,
+    };
+    table.schema.ctes.clear();
+    result
+}
+
+/// Execute a statement once the tables of its WITH clause are in the schema.
+fn execute_body(mut stmt: Statement, table: &mut Table) -> ExecuteResult {
+    // A table-valued function is looked up with its arguments once the columns resolve.
+    let table_exists = stmt.table_args.is_some() || table.schema.find_table(&stmt.table_name).is_some();
+    match stmt.stmt_type {
+        StatementType::StatementCreateTable if table_exists => return ExecuteTableExists(stmt.table_name),
+        StatementType::StatementCreateTable | StatementType::StatementPragma => {}
+        // A SELECT without FROM reads no table.
+        StatementType::StatementSelect if stmt.table_name.is_empty() => {}
+        _ if !table_exists => return ExecuteNoSuchTable(stmt.table_name),
+        _ => {}
+    }
+    let has_exprs = !matches!(stmt.stmt_type, StatementType::StatementCreateIndex | StatementType::StatementCreateTable | StatementType::StatementPragma);
+    if has_exprs {
+        let result = JoinScope::new(&stmt, &table.schema).and_then(|scope| resolve_columns(&mut stmt, &scope, &table.schema));
+        if let Err(err) = result {
+            return err;
+        }
+    }
+    match &stmt.explain {
+        Some(ExplainMode::ExplainOperations) => return execute_explain(&stmt, table),
+        Some(ExplainMode::ExplainQueryPlan) => return execute_explain_query_plan(&stmt, table),
+        None => {}
+    }
+    let writes = !matches!(stmt.stmt_type, StatementType::StatementSelect | StatementType::StatementPragma);
+    if writes && table.pager.sqlite.is_some() {
+        return ExecuteReadOnly;
+    }
+    // Tables held in memory, such as sqlite_sequence, are kept up to date by the engine alone.
+    let in_memory = table.schema.find_table(&stmt.table_name).is_some_and(|table_def| table_def.rows.is_some());
+    if writes && in_memory && stmt.stmt_type != StatementType::StatementCreateTable {
+        return ExecuteTableReadOnly(stmt.table_name);
+    }
+    if let Err(err) = materialize_subqueries(&mut stmt, table) {
+        return err;
+    }
     match &stmt.stmt_type {
         StatementType::StatementInsert => execute_insert(&stmt, table),
-        StatementType::StatementSelect => execute_select(table),
-        _ => ExecuteFail
+        StatementType::StatementSelect => execute_select(&stmt, table),
+        StatementType::StatementUpdate => execute_update(&stmt, table),
+        StatementType::StatementDelete => execute_delete(&stmt, table),
+        StatementType::StatementCreateIndex => execute_create_index(&stmt, table),
+        StatementType::StatementCreateTable => execute_create_table(&stmt, table),
+        StatementType::StatementPragma => execute_pragma(&stmt, table),
+    }
+}
+
+fn execute_explain(statement: &Statement, table: &Table) -> ExecuteResult {
+    print_operations(&compile_statement(statement, table));
+    ExecuteSuccess
+}
+
+fn execute_explain_query_plan(statement: &Statement, table: &Table) -> ExecuteResult {
+    print_query_plan(&query_plan(statement, table, &mut 0));
+    ExecuteSuccess
+}
+
+/// The EXPLAIN QUERY PLAN lines of a statement: how it reads each table, then every subquery
+/// with its own plan nested under it, then the temp B-trees it sorts with. `subqueries`
+/// counts the subqueries seen so far, which numbers them.
+fn query_plan(statement: &Statement, table: &Table, subqueries: &mut usize) -> Vec<PlanLine> {
+    if !statement.compound.is_empty() {
+        return compound_plan(statement, table, subqueries);
+    }
+    let plan = plan_statement(statement, table);
+    let (scans, table_def) = match from_table(&table.schema, &statement.table_name, statement.table_args.as_deref()).ok() {
+        Some(_) if !statement.joins.is_empty() => {
+            let scope = JoinScope::new(statement, &table.schema).unwrap();
+            let strategies = plan_joins(&scope, statement.where_clause.as_ref(), &table.schema);
+            (describe_joins(&scope, &strategies), Some(scope.def))
+        }
+        Some(table_def) if statement.table_args.is_some() => {
+            let num_args = statement.table_args.as_ref().map_or(0, Vec::len);
+            (vec![describe_function_scan(&table_def.name, num_args)], Some(table_def))
+        }
+        Some(table_def) => (vec![describe_scan(&plan, &table_def)], Some(table_def)),
+        None if statement.stmt_type == StatementType::StatementSelect => (vec![String::from(CONSTANT_ROW)], None),
+        // Creating a table reads no rows, so there is no plan to show.
+        None => return Vec::new(),
+    };
+    let mut lines = ctes_plan(statement, table, subqueries);
+    lines.extend(scans.into_iter().map(PlanLine::new));
+    for subquery in statement.exprs().into_iter().flat_map(|expr| expr.subqueries()) {
+        *subqueries += 1;
+        let number = *subqueries;
+        lines.push(PlanLine {
+            text: describe_subquery(subquery, number),
+            children: query_plan(&subquery_of(subquery).select, table, subqueries),
+        });
+    }
+    if let Some(table_def) = &table_def {
+        lines.extend(describe_temp_trees(&select_temp_trees(statement, table_def, &plan)).into_iter().map(PlanLine::new));
+    }
+    lines
+}
+
+/// The EXPLAIN QUERY PLAN lines of the tables of the statement's WITH clause.
+fn ctes_plan(statement: &Statement, table: &Table, subqueries: &mut usize) -> Vec<PlanLine> {
+    statement.ctes.iter()
+        .map(|cte| PlanLine {
+            text: format!("MATERIALIZE {}", cte.name),
+            children: cte_plan(cte, table, subqueries),
+        })
+        .collect()
+}
+
+/// The EXPLAIN QUERY PLAN lines of the SELECTs that compute a table of a WITH clause.
+fn cte_plan(cte: &Cte, table: &Table, subqueries: &mut usize) -> Vec<PlanLine> {
+    let initial = query_plan(&cte.initial, table, subqueries);
+    match &cte.recursive {
+        Some(recursive) => vec![
+            PlanLine { text: String::from("SETUP"), children: initial },
+            PlanLine { text: String::from("RECURSIVE STEP"), children: query_plan(recursive, table, subqueries) },
+        ],
+        None => initial,
+    }
+}
+
+/// The EXPLAIN QUERY PLAN lines of a compound SELECT: the plan of every SELECT under the
+/// operator that joins it on, then the temp B-tree of the compound's ORDER BY.
+fn compound_plan(statement: &Statement, table: &Table, subqueries: &mut usize) -> Vec<PlanLine> {
+    let mut lines = ctes_plan(statement, table, subqueries);
+    let mut first = statement.clone();
+    let selects = std::mem::take(&mut first.compound);
+    first.ctes.clear();
+    first.order_by.clear();
+    let mut children = vec![PlanLine { text: String::from("LEFT-MOST SUBQUERY"), children: query_plan(&first, table, subqueries) }];
+    for (op, select) in &selects {
+        let text = match op {
+            CompoundOp::UnionAll => String::from(op.keyword()),
+            _ => format!("{} USING TEMP B-TREE", op.keyword()),
+        };
+        children.push(PlanLine { text, children: query_plan(select, table, subqueries) });
+    }
+    lines.push(PlanLine { text: String::from("COMPOUND QUERY"), children });
+    if !statement.order_by.is_empty() {
+        lines.extend(describe_temp_trees(&["ORDER BY"]).into_iter().map(PlanLine::new));
+    }
+    lines
+}
+
+/// Fail before reading any rows if the WHERE clause names a column the table lacks.
+fn check_where(table_def: &TableDef, where_clause: Option<&Expr>) -> ExecuteResult {
+    let columns = where_clause.map(|expr| expr.columns()).unwrap_or_default();
+    match columns.into_iter().find(|column| table_def.column_index(column).is_none()) {
+        Some(column) => ExecuteNoSuchColumn(String::from(column)),
+        None => ExecuteSuccess,
+    }
+}
+
+fn unqualified(column: &str) -> String {
+    String::from(column.rsplit('.').next().unwrap())
+}
+
+/// Expand the SELECT list into the name and expression of every result column. A column
+/// is named by its alias, else by the column it reads (without the table a join qualifies it
+/// by), else by the expression's SQL.
+pub(crate) fn result_columns(statement: &Statement, table_def: Option<&TableDef>) -> Result<Vec<(String, Expr)>, ExecuteResult> {
+    let mut columns = Vec::new();
+    for result_column in &statement.result_columns {
+        match (result_column, table_def) {
+            (ResultColumn::All, Some(table_def)) => {
+                columns.extend(table_def.columns.iter()
+                    .filter(|column| !column.hidden)
+                    .map(|column| (unqualified(&column.name), Expr::Column(column.name.clone()))));
+            }
+            (ResultColumn::TableAll(name), Some(table_def)) => {
+                let prefix = format!("{}.", name);
+                columns.extend(table_def.columns.iter()
+                    .filter(|column| !column.hidden && column.name.to_lowercase().starts_with(&prefix))
+                    .map(|column| (String::from(&column.name[prefix.len()..]), Expr::Column(column.name.clone()))));
+            }
+            (ResultColumn::All, None) | (ResultColumn::TableAll(_), None) => return Err(ExecuteNoSuchTable(statement.table_name.clone())),
+            (ResultColumn::Expr { expr, alias }, _) => {
+                let missing = expr.columns().into_iter()
+                    .find(|name| table_def.is_none_or(|table_def| table_def.column_index(name).is_none()));
+                if let Some(name) = missing {
+                    return Err(ExecuteNoSuchColumn(String::from(name)));
+                }
+                let name = match (alias, expr, table_def) {
+                    (Some(alias), _, _) => alias.clone(),
+                    (None, Expr::Column(name), Some(table_def)) => unqualified(&table_def.columns[table_def.column_index(name).unwrap()].name),
+                    (None, expr, _) => expr.to_string(),
+                };
+                columns.push((name, expr.clone()));
+            }
+        }
+    }
+    Ok(columns)
+}
+
+/// Resolve an ORDER BY or GROUP BY term into the expression it stands for. As in SQLite, a
+/// number picks a result column by position and a name picks the result column it names
+/// before any column of the table, under a COLLATE too.
+fn resolve_term(expr: &Expr, clause: &'static str, columns: &[(String, Expr)],
+                table_def: Option<&TableDef>) -> Result<Expr, ExecuteResult> {
+    match expr {
+        Expr::Collate { operand, collation, declared: false } => Ok(Expr::Collate {
+            operand: Box::new(resolve_term(operand, clause, columns, table_def)?),
+            collation: collation.clone(),
+            declared: false,
+        }),
+        Expr::Literal(Value::Integer(position)) => match usize::try_from(*position) {
+            Ok(position) if (1..=columns.len()).contains(&position) => Ok(columns[position - 1].1.clone()),
+            _ => Err(ExecuteTermOutOfRange(clause, columns.len())),
+        },
+        Expr::Column(name) if columns.iter().any(|(column, _)| column.eq_ignore_ascii_case(name)) => {
+            Ok(columns.iter().find(|(column, _)| column.eq_ignore_ascii_case(name)).unwrap().1.clone())
+        }
+        expr => {
+            let missing = expr.columns().into_iter()
+                .find(|name| table_def.is_none_or(|table_def| table_def.column_index(name).is_none()));
+            if let Some(name) = missing {
+                return Err(ExecuteNoSuchColumn(String::from(name)));
+            }
+            Ok(expr.clone())
+        }
+    }
+}
+
+/// Resolve the ORDER BY terms into the expression each row sorts by and whether it sorts
+/// descending.
+pub(crate) fn order_by_terms(order_by: &[OrderingTerm], columns: &[(String, Expr)],
+                             table_def: Option<&TableDef>) -> Result<Vec<(Expr, bool)>, ExecuteResult> {
+    order_by.iter()
+        .map(|term| Ok((resolve_term(&term.expr, "ORDER BY", columns, table_def)?, term.descending)))
+        .collect()
+}
+
+/// The order the sorter puts resolved ORDER BY terms in, each under its collation.
+pub(crate) fn sort_order(order_by: &[(Expr, bool)], table_def: Option<&TableDef>) -> KeyOrder {
+    let column_collation = |name: &str| table_def?.column_collation(name);
+    KeyOrder {
+        descending: order_by.iter().map(|(_, descending)| *descending).collect(),
+        collations: order_by.iter().map(|(expr, _)| expr.collation(&column_collation)).collect(),
+    }
+}
+
+/// Whether the SELECT computes aggregates over groups of rows rather than one output row per row.
+pub(crate) fn is_aggregate_select(statement: &Statement) -> bool {
+    let aggregate_column = statement.result_columns.iter()
+        .any(|column| matches!(column, ResultColumn::Expr { expr, .. } if expr.contains_aggregate()));
+    !statement.group_by.is_empty() || statement.having.is_some() || aggregate_column
+        || statement.order_by.iter().any(|term| term.expr.contains_aggregate())
+}
+
+/// The grouping of an aggregate SELECT, resolved against its result columns.
+pub(crate) struct AggregateQuery {
+    pub(crate) group_by: Vec<Expr>,
+    /// The order of the GROUP BY values, under which equal ones make one group.
+    pub(crate) group_order: KeyOrder,
+    pub(crate) having: Option<Expr>,
+    /// Every distinct aggregate call of the result columns, HAVING and ORDER BY, in the order
+    /// found, with what each computes.
+    pub(crate) calls: Vec<Expr>,
+    pub(crate) aggregates: Vec<Aggregate>,
+    /// Set when nothing but `count(*)` of the whole table is asked for, which the leaf headers
+    /// answer without decoding a row.
+    pub(crate) counts_rows: bool,
+}
+
+impl AggregateQuery {
+    pub(crate) fn resolve(statement: &Statement, columns: &[(String, Expr)], order_by: &[(Expr, bool)],
+                          table_def: Option<&TableDef>) -> Result<AggregateQuery, ExecuteResult> {
+        let misuse = |expr: &Expr| match expr.aggregates().first() {
+            Some(Expr::Function { name, .. }) => Err(ExecuteMisuseOfAggregate(name.clone())),
+            _ => Ok(()),
+        };
+        if let Some(where_clause) = &statement.where_clause {
+            misuse(where_clause)?;
+        }
+        let mut group_by = Vec::new();
+        for term in &statement.group_by {
+            let expr = resolve_term(term, "GROUP BY", columns, table_def)?;
+            misuse(&expr)?;
+            group_by.push(expr);
+        }
+        // HAVING may name a result column by its alias where no column of the table has that name.
+        let having = statement.having.as_ref().map(|having| having.replace(&|expr| match expr {
+            Expr::Column(name) if table_def.is_none_or(|table_def| table_def.column_index(name).is_none()) => {
+                columns.iter().find(|(column, _)| column.eq_ignore_ascii_case(name)).map(|(_, expr)| expr.clone())
+            }
+            _ => None,
+        }));
+        let having_columns = having.as_ref().map(|having| having.columns()).unwrap_or_default();
+        if let Some(name) = having_columns.into_iter().find(|name| table_def.is_none_or(|table_def| table_def.column_index(name).is_none())) {
+            return Err(ExecuteNoSuchColumn(String::from(name)));
+        }
+        let outputs: Vec<&Expr> = columns.iter().map(|(_, expr)| expr)
+            .chain(&having)
+            .chain(order_by.iter().map(|(expr, _)| expr))
+            .collect();
+        let column_collation = |name: &str| table_def.and_then(|table_def| table_def.column_collation(name));
+        let group_order = KeyOrder {
+            descending: vec![false; group_by.len()],
+            collations: group_by.iter().map(|expr| expr.collation(&column_collation)).collect(),
+        };
+        let mut calls = Vec::new();
+        let mut aggregates = Vec::new();
+        for call in outputs.iter().flat_map(|expr| expr.aggregates()) {
+            if !calls.contains(call) {
+                aggregates.push(Aggregate::from_expr(call, &column_collation).unwrap()?);
+                calls.push(call.clone());
+            }
+        }
+        // Bare columns read a row of the group, so only a query without them can skip the rows,
+        // and only a table with a B-tree has leaf headers to count.
+        let counts_rows = table_def.is_some_and(|table_def| table_def.rows.is_none()) && statement.joins.is_empty() && statement.where_clause.is_none() && group_by.is_empty()
+            && correlated_subqueries(&statement.row_exprs()).is_empty()
+            && aggregates.iter().all(|aggregate| aggregate.is_count_star())
+            && outputs.iter().all(|expr| substitute_aggregates(expr, &calls, &[]).columns().is_empty());
+        Ok(AggregateQuery { group_by, group_order, having, calls, aggregates, counts_rows })
+    }
+}
+
+/// Replace every aggregate call in the expression with its value for a group. Calls without a
+/// value are replaced with NULL.
+fn substitute_aggregates(expr: &Expr, calls: &[Expr], values: &[Value]) -> Expr {
+    expr.replace(&|expr| {
+        let i = calls.iter().position(|call| call == expr)?;
+        Some(Expr::Literal(values.get(i).cloned().unwrap_or(Value::Null)))
+    })
+}
+
+fn print_result_row(values: &[Value]) {
+    let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    println!("{}", values.join(", "));
+}
+
+pub(crate) fn row_matches(table_def: &TableDef, row: &[Value], where_clause: Option<&Expr>) -> Result<bool, ExecuteResult> {
+    where_clause.map_or(Ok(true), |expr| expr.is_true(&|name| table_def.column_value(row, name)))
+}
+
+pub(crate) fn fetch_row(table: &Table, table_def: &TableDef, key: &RowKey) -> Option<Vec<Value>> {
+    let key = match key {
+        RowKey::Rowid(rowid) => *rowid,
+        RowKey::Key(key) => {
+            let record = table_def.key_tree().unwrap().find_record(&table.pager, key)?;
+            return Some(table_def.record_row(record));
+        }
+    };
+    let (page_num, cell_num) = table.find(table_def.root_page_num, key);
+    let page = table.pager.get_page_view(page_num).unwrap();
+    if cell_num < page.leaf_node_num_cells() && page.leaf_node_key(cell_num) == key {
+        return Some(table_def.decode_row(&table.pager, page, cell_num));
+    }
+    None
+}
+
+/// The row of a rowid table with the rowid, if it has one, read from the SQLite file when
+/// the database is one.
+pub(crate) fn read_row(table: &Table, table_def: &TableDef, rowid: i64) -> Result<Option<Vec<Value>>, ExecuteResult> {
+    if table.pager.sqlite.is_some() {
+        return file_format::find_row(&table.pager, table_def, rowid).map_err(ExecuteCorruptFile);
+    }
+    Ok(fetch_row(table, table_def, &RowKey::Rowid(rowid)))
+}
+
+/// The rowids of the index's entries whose value lies within the bounds, in index order.
+/// The entries of a SQLite file's index end with the rowid of their row.
+pub(crate) fn index_rowids(table: &Table, index: &Index, lower: Bound<&Value>, upper: Bound<&Value>) -> Result<Vec<i64>, ExecuteResult> {
+    if table.pager.sqlite.is_none() {
+        return Ok(index.tree.scan(&table.pager, lower, upper));
+    }
+    let records = file_format::index_records(&table.pager, &index.tree, lower.map(slice::from_ref), upper.map(slice::from_ref))
+        .map_err(ExecuteCorruptFile)?;
+    Ok(records.iter().filter_map(|record| record.last().and_then(Value::to_integer)).collect())
+}
+
+/// Visit every row the plan reaches that satisfies the WHERE clause, stopping early once
+/// `visit` returns false. `visit` is handed the table back, free to read it while the scan
+/// is paused. A WHERE clause that fails to evaluate ends the scan with its error.
+pub(crate) fn for_each_row<F: FnMut(&mut Table, &[Value]) -> bool>(table: &mut Table, table_def: &TableDef, plan: &QueryPlan,
+                                                                   where_clause: Option<&Expr>, mut visit: F) -> ExecuteResult {
+    let mut error = None;
+    let result = scan_rows(table, table_def, plan, |table, row| match row_matches(table_def, row, where_clause) {
+        Ok(matches) => !matches || visit(table, row),
+        Err(err) => {
+            error = Some(err);
+            false
+        }
+    });
+    error.unwrap_or(result)
+}
+
+/// Visit every row the plan reaches, stopping early once `visit` returns false.
+fn scan_rows<F: FnMut(&mut Table, &[Value]) -> bool>(table: &mut Table, table_def: &TableDef, plan: &QueryPlan,
+                                                     mut visit: F) -> ExecuteResult {
+    // The rows of a WITH table are in memory, and every plan scans them.
+    if let Some(state) = &table_def.recursive {
+        let result = cte::for_each_recursive_row(table, state, &mut visit);
+        return result.err().unwrap_or(ExecuteSuccess);
     }
+    if let Some(rows) = &table_def.rows {
+        for row in rows.iter() {
+            if !visit(table, row) {
+                return ExecuteSuccess;
+            }
+        }
+        return ExecuteSuccess;
+    }
+    if table.pager.sqlite.is_some() {
+        return for_each_file_row(table, table_def, plan, visit);
+    }
+    // A WITHOUT ROWID table is walked in key order, over the rows whose key starts with the
+    // values a seek gives, or over every row.
+    if let Some(tree) = table_def.key_tree() {
+        let key = match plan {
+            QueryPlan::KeySeek(key) => key.as_slice(),
+            _ => &[],
+        };
+        for record in tree.records(&table.pager, Bound::Included(key), Bound::Included(key)) {
+            let row = table_def.record_row(record);
+            if !visit(table, &row) {
+                return ExecuteSuccess;
+            }
+        }
+        return ExecuteSuccess;
+    }
+    match plan {
+        QueryPlan::FullScan | QueryPlan::KeySeek(_) => {
+            let mut cursor = Cursor::table_start(table, table_def.root_page_num);
+            while !cursor.end_of_table {
+                let row = cursor.cursor_value(table_def);
+                if !visit(cursor.table, &row) {
+                    return ExecuteSuccess;
+                }
+                cursor.advance();
+            }
+        }
+        QueryPlan::PrimaryKeySeek(key) => {
+            if let Some(row) = fetch_row(table, table_def, &RowKey::Rowid(*key)) {
+                visit(table, &row);
+            }
+        }
+        QueryPlan::IndexScan { index_name, lower, upper, .. } => {
+            let index = table.schema.find_index(index_name).unwrap();
+            for rowid in index.tree.scan(&table.pager, lower.as_ref(), upper.as_ref()) {
+                if let Some(row) = fetch_row(table, table_def, &RowKey::Rowid(rowid)) {
+                    if !visit(table, &row) {
+                        return ExecuteSuccess;
+                    }
+                }
+            }
+        }
+        // A query that fails was caught by `fts::answered_where` before the scan.
+        QueryPlan::FullTextMatch(matches) => {
+            let found = fts::search(&table.pager, &table.schema, table_def, matches).unwrap_or_default();
+            for (rowid, hidden) in found {
+                if let Some(mut row) = fetch_row(table, table_def, &RowKey::Rowid(rowid)) {
+                    let declared = table_def.fts.as_ref().unwrap().unindexed.len() + 1;
+                    row.splice(declared..declared + hidden.len(), hidden);
+                    if !visit(table, &row) {
+                        return ExecuteSuccess;
+                    }
+                }
+            }
+        }
+    }
+    ExecuteSuccess
+}
+
+/// `scan_rows` over a table of a SQLite file, whose pages are read as the scan reaches
+/// them. A seek reads only the pages on its way down the table or index.
+fn for_each_file_row<F: FnMut(&mut Table, &[Value]) -> bool>(table: &mut Table, table_def: &TableDef, plan: &QueryPlan,
+                                                             mut visit: F) -> ExecuteResult {
+    // A WITHOUT ROWID table is an index of its records, walked as the engine's own are.
+    if let Some(tree) = table_def.key_tree() {
+        let key = match plan {
+            QueryPlan::KeySeek(key) => key.as_slice(),
+            _ => &[],
+        };
+        let cursor = match key {
+            [] => BtreeCursor::index(&table.pager, tree.root_page_num as u32),
+            key => BtreeCursor::seek_index(&table.pager, tree.root_page_num as u32, &tree.order, key),
+        };
+        let mut cursor = match cursor {
+            Ok(cursor) => cursor,
+            Err(err) => return ExecuteCorruptFile(err),
+        };
+        loop {
+            match cursor.next_record(&table.pager) {
+                Ok(Some(record)) if tree.compare(&record, key) != Ordering::Greater => {
+                    let row = file_format::keyed_row(table_def, record);
+                    if !visit(table, &row) {
+                        return ExecuteSuccess;
+                    }
+                }
+                Ok(_) => return ExecuteSuccess,
+                Err(err) => return ExecuteCorruptFile(err),
+            }
+        }
+    }
+    let rowids = match plan {
+        QueryPlan::PrimaryKeySeek(key) => vec![*key],
+        QueryPlan::IndexScan { index_name, lower, upper, .. } => {
+            let index = table.schema.find_index(index_name).unwrap();
+            match index_rowids(table, index, lower.as_ref(), upper.as_ref()) {
+                Ok(rowids) => rowids,
+                Err(err) => return err,
+            }
+        }
+        _ => {
+            let mut cursor = match BtreeCursor::table(&table.pager, table_def.root_page_num as u32) {
+                Ok(cursor) => cursor,
+                Err(err) => return ExecuteCorruptFile(err),
+            };
+            loop {
+                match cursor.next_row(&table.pager) {
+                    Ok(Some((rowid, record))) => {
+                        let row = file_format::table_row(table_def, rowid, record);
+                        if !visit(table, &row) {
+                            return ExecuteSuccess;
+                        }
+                    }
+                    Ok(None) => return ExecuteSuccess,
+                    Err(err) => return ExecuteCorruptFile(err),
+                }
+            }
+        }
+    };
+    for rowid in rowids {
+        match read_row(table, table_def, rowid) {
+            Ok(Some(row)) => if !visit(table, &row) {
+                return ExecuteSuccess;
+            },
+            Ok(None) => {}
+            Err(err) => return err,
+        }
+    }
+    ExecuteSuccess
+}
+
+/// The definition of a row of the statement's FROM clause, described by `table_def`, once it
+/// is extended by the value of every correlated subquery the statement evaluates per row.
+fn source_row_def(statement: &Statement, table_def: &TableDef) -> TableDef {
+    with_subquery_columns(table_def, &correlated_subqueries(&statement.row_exprs()))
+}
+
+/// Visit every row of the statement's FROM clause that satisfies the WHERE clause, stopping
+/// early once `visit` returns false: the rows of its table, or the joined rows of its tables.
+/// Every correlated subquery runs for each row, and its value extends the row as
+/// `source_row_def` describes.
+fn for_each_source_row<F: FnMut(&[Value]) -> bool>(table: &mut Table, statement: &Statement, table_def: &TableDef,
+                                                   plan: &QueryPlan, mut visit: F) -> ExecuteResult {
+    let subqueries = correlated_subqueries(&statement.row_exprs());
+    let joined = if statement.joins.is_empty() {
+        None
+    } else {
+        let scope = JoinScope::new(statement, &table.schema).unwrap();
+        let strategies = plan_joins(&scope, statement.where_clause.as_ref(), &table.schema);
+        Some((scope, strategies))
+    };
+    // Only the first table's MATCH terms are answered, ahead of the scan or by its plan.
+    let (first_def, rowid_column, first_plan) = match &joined {
+        Some((scope, strategies)) => match &strategies[0] {
+            JoinStrategy::Outer(plan) => (&scope.tables[0].def, format!("{}.rowid", scope.tables[0].name), plan),
+            _ => (table_def, String::from("rowid"), plan),
+        },
+        None => (table_def, String::from("rowid"), plan),
+    };
+    let where_clause = match fts::answered_where(table, statement.where_clause.as_ref(), first_def, &rowid_column, first_plan) {
+        Ok(where_clause) => where_clause,
+        Err(err) => return err,
+    };
+    let mut where_clause = where_clause.as_ref();
+    // A WHERE that reads a subquery waits for its value; any other filters during the scan.
+    let late_where = where_clause.filter(|expr| !correlated_subqueries(&[expr]).is_empty());
+    if late_where.is_some() {
+        where_clause = None;
+    }
+    let mut result = ExecuteSuccess;
+    let mut visit_row = |table: &mut Table, row: &[Value]| {
+        if subqueries.is_empty() {
+            return visit(row);
+        }
+        let mut row = row.to_vec();
+        for subquery in &subqueries {
+            let column = |name: &str| table_def.column_index(name).and_then(|column| row.get(column).cloned());
+            match subquery_value(subquery, &column, table) {
+                Ok(value) => row.push(value),
+                Err(err) => {
+                    result = err;
+                    return false;
+                }
+            }
+        }
+        match row_matches(table_def, &row, late_where) {
+            Ok(matches) => !matches || visit(&row),
+            Err(err) => {
+                result = err;
+                false
+            }
+        }
+    };
+    let scan_result = if let Some((scope, strategies)) = &joined {
+        for_each_joined_row(table, scope, strategies, where_clause, &mut visit_row)
+    } else {
+        // The arguments of a table-valued function read no row, but its rows come from them.
+        let mut source_def = match from_table(&table.schema, &statement.table_name, statement.table_args.as_deref()) {
+            Ok(source_def) => source_def,
+            Err(err) => return err,
+        };
+        if let Some(args) = &statement.table_args {
+            match function_rows(&source_def.name, args, &|_| None) {
+                Ok(rows) => source_def.rows = Some(Rc::new(rows)),
+                Err(err) => return err,
+            }
+        }
+        for_each_row(table, &source_def, plan, where_clause, &mut visit_row)
+    };
+    if scan_result != ExecuteSuccess {
+        return scan_result;
+    }
+    result
+}
+
+/// The rows an UPDATE or DELETE acts on, as `source_row_def` describes them.
+fn collect_rows(statement: &Statement, table: &mut Table, table_def: &TableDef) -> Result<Vec<Vec<Value>>, ExecuteResult> {
+    let row_def = source_row_def(statement, table_def);
+    let where_clause = statement.where_clause.as_ref();
+    let result = check_where(&row_def, where_clause);
+    if result != ExecuteSuccess {
+        return Err(result);
+    }
+    let plan = plan_statement(statement, table);
+    let mut rows = Vec::new();
+    let result = for_each_source_row(table, statement, &row_def, &plan, |row| {
+        rows.push(row.to_vec());
+        true
+    });
+    if result != ExecuteSuccess {
+        return Err(result);
+    }
+    Ok(rows)
+}
+
+/// The entry the index keeps for a row. An index expression that fails is caught by
+/// `check_unique` before any row is written.
+fn index_key(table_def: &TableDef, index: &Index, key: i64, row: &[Value]) -> IndexKey {
+    IndexKey { value: index.def.key_value(table_def, row).unwrap_or(Value::Null), rowid: key }
+}
+
+/// Evaluate the VALUES of an INSERT into rows in table column order, converted to the
+/// column types. Columns left out of the column list take their DEFAULT, or NULL.
+pub(crate) fn insert_rows(statement: &Statement, table_def: &TableDef) -> Result<Vec<Vec<Value>>, ExecuteResult> {
+    let mut rows = Vec::new();
+    for values in &statement.values {
+        let mut row = Vec::with_capacity(table_def.columns.len());
+        if statement.columns.is_empty() {
+            // Hidden columns take no value, and hold NULL.
+            let num_visible = table_def.columns.iter().filter(|column| !column.hidden).count();
+            if values.len() != num_visible {
+                return Err(ExecuteValueCount(table_def.name.clone(), num_visible, values.len()));
+            }
+            let mut values = values.iter();
+            for column in &table_def.columns {
+                row.push(if column.hidden { Value::Null } else { values.next().unwrap().evaluate_constant()? });
+            }
+        } else {
+            for name in &statement.columns {
+                if table_def.column_index(name).is_none() {
+                    return Err(ExecuteNoSuchColumn(name.clone()));
+                }
+            }
+            for column in &table_def.columns {
+                let value = match statement.columns.iter().position(|name| column.name.eq_ignore_ascii_case(name)) {
+                    Some(position) => &values[position],
+                    None => match &column.default {
+                        Some(default) => default,
+                        None => {
+                            row.push(Value::Null);
+                            continue;
+                        }
+                    },
+                };
+                row.push(value.evaluate_constant()?);
+            }
+        }
+        let row = row.into_iter()
+            .zip(&table_def.columns)
+            .map(|(value, column)| column.column_type.apply_affinity(value))
+            .collect();
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Check that writing the rows leaves the primary key and every unique index without
+/// duplicates, and that every index expression can be evaluated for them. `replaced` holds the keys of rows that are removed by the same write.
+fn check_unique(table: &Table, table_def: &TableDef, indexes: &[Index],
+                rows: &[(RowKey, &[Value])], replaced: &[RowKey]) -> ExecuteResult {
+    let mut keys: Vec<&RowKey> = rows.iter().map(|(key, _)| key).collect();
+    keys.sort_by(|a, b| table_def.compare_keys(a, b));
+    if keys.windows(2).any(|pair| table_def.compare_keys(pair[0], pair[1]) == Ordering::Equal) {
+        return ExecuteDuplicateKey;
+    }
+    for (key, _) in rows {
+        let is_replaced = replaced.iter().any(|replaced| table_def.compare_keys(replaced, key) == Ordering::Equal);
+        if !is_replaced && fetch_row(table, table_def, key).is_some() {
+            return ExecuteDuplicateKey;
+        }
+    }
+
+    for index in indexes {
+        let values = rows.iter()
+            .map(|(_, row)| index.def.key_value(table_def, row))
+            .collect::<Result<Vec<Value>, ExecuteResult>>();
+        let values = match values {
+            Ok(values) => values,
+            Err(err) => return err,
+        };
+        if !index.def.unique {
+            continue;
+        }
+        let (violation, collation) = match (&index.def.expr, table_def.column_index(&index.def.column)) {
+            (None, Some(column)) => (
+                ExecuteUniqueViolation(format!("{}.{} (index {})", table_def.name, index.def.column, index.def.name)),
+                table_def.columns[column].collation.clone(),
+            ),
+            _ => (ExecuteUniqueViolation(format!("index '{}'", index.def.name)), Collation::Binary),
+        };
+        // NULLs never clash with each other.
+        let mut values: Vec<&Value> = values.iter().filter(|value| !value.is_null()).collect();
+        values.sort_by(|a, b| collation.compare(a, b));
+        if values.windows(2).any(|pair| collation.compare(pair[0], pair[1]) == Ordering::Equal) {
+            return violation;
+        }
+        for value in values {
+            let rowids = index.tree.scan(&table.pager, Bound::Included(value), Bound::Included(value));
+            if rowids.into_iter().any(|rowid| !replaced.contains(&RowKey::Rowid(rowid))) {
+                return violation;
+            }
+        }
+    }
+    ExecuteSuccess
+}
+
+fn insert_row(table: &mut Table, table_def: &TableDef, indexes: &[Index], key: &RowKey, row: &[Value], encoded: &[u8]) -> Result<(), ExecuteResult> {
+    table.pager.check_room()?;
+    let key = match key {
+        RowKey::Rowid(rowid) => *rowid,
+        // A WITHOUT ROWID table has no secondary indexes, so its record is all there is to write.
+        RowKey::Key(_) => {
+            table_def.key_tree().unwrap().insert_record(&mut table.pager, table_def.key_record(row));
+            return Ok(());
+        }
+    };
+    let (page_num, cell_num) = table.find(table_def.root_page_num, key);
+    let mut cursor = Cursor {
+        table,
+        page_num,
+        cell_num,
+        end_of_table: false,
+    };
+    cursor.leaf_node_insert(key, encoded);
+    for index in indexes {
+        index.tree.insert(&mut table.pager, index_key(table_def, index, key, row));
+    }
+    if table_def.fts.is_some() {
+        fts::index_row(table, table_def, key, row);
+    }
+    Ok(())
+}
+
+fn delete_row(table: &mut Table, table_def: &TableDef, indexes: &[Index], key: &RowKey, row: &[Value]) -> Result<(), ExecuteResult> {
+    let key = match key {
+        RowKey::Rowid(rowid) => *rowid,
+        RowKey::Key(key) => return delete_keyed_record(table, table_def, key),
+    };
+    let (page_num, cell_num) = table.find(table_def.root_page_num, key);
+    let mut cursor = Cursor {
+        table,
+        page_num,
+        cell_num,
+        end_of_table: false,
+    };
+    cursor.leaf_node_delete();
+    for index in indexes {
+        if !index.tree.delete(&mut table.pager, &index_key(table_def, index, key, row)) {
+            return Err(ExecuteMissingEntry(index.def.name.clone()));
+        }
+    }
+    if table_def.fts.is_some() {
+        fts::unindex_row(table, table_def, key, row)?;
+    }
+    Ok(())
+}
+
+/// Take the record of a WITHOUT ROWID table's row out of its tree.
+fn delete_keyed_record(table: &mut Table, table_def: &TableDef, key: &[Value]) -> Result<(), ExecuteResult> {
+    if !table_def.key_tree().unwrap().delete_record(&mut table.pager, key) {
+        return Err(ExecuteMissingEntry(table_def.name.clone()));
+    }
+    Ok(())
+}
+
+/// A row ready to be written: its key, its values and the cell value they encode to.
+struct EncodedRow {
+    key: RowKey,
+    row: Vec<Value>,
+    encoded: Vec<u8>,
+}
+
+/// Check each row's constraints and compute its key and cell value, failing before
+/// anything is written.
+fn encode_rows(table_def: &TableDef, rows: Vec<Vec<Value>>) -> Result<Vec<EncodedRow>, ExecuteResult> {
+    rows.into_iter()
+        .map(|row| {
+            let result = table_def.check_row(&row);
+            if result != ExecuteSuccess {
+                return Err(result);
+            }
+            let key = table_def.row_key(&row)?;
+            let encoded = table_def.encode_row(&row)?;
+            Ok(EncodedRow { key, row, encoded })
+        })
+        .collect()
+}
+
+fn keyed_rows(rows: &[EncodedRow]) -> Vec<(RowKey, &[Value])> {
+    rows.iter().map(|row| (row.key.clone(), row.row.as_slice())).collect()
+}
+
+/// Rewrite a row in place with new values under the same key, moving its index entries.
+fn update_row(table: &mut Table, table_def: &TableDef, indexes: &[Index], old_row: &[Value], new_row: &EncodedRow) -> Result<(), ExecuteResult> {
+    table.pager.check_room()?;
+    let rowid = match &new_row.key {
+        RowKey::Rowid(rowid) => *rowid,
+        RowKey::Key(key) => {
+            delete_keyed_record(table, table_def, key)?;
+            table_def.key_tree().unwrap().insert_record(&mut table.pager, table_def.key_record(&new_row.row));
+            return Ok(());
+        }
+    };
+    let (page_num, cell_num) = table.find(table_def.root_page_num, rowid);
+    let mut cursor = Cursor {
+        table,
+        page_num,
+        cell_num,
+        end_of_table: false,
+    };
+    cursor.update_value(&new_row.encoded);
+    for index in indexes {
+        let old_index_key = index_key(table_def, index, rowid, old_row);
+        let new_index_key = index_key(table_def, index, rowid, &new_row.row);
+        if old_index_key != new_index_key {
+            if !index.tree.delete(&mut table.pager, &old_index_key) {
+                return Err(ExecuteMissingEntry(index.def.name.clone()));
+            }
+            index.tree.insert(&mut table.pager, new_index_key);
+        }
+    }
+    if table_def.fts.is_some() {
+        fts::unindex_row(table, table_def, rowid, old_row)?;
+        fts::index_row(table, table_def, rowid, &new_row.row);
+    }
+    Ok(())
+}
+
+/// Rows of the table whose column holds the value, found the way a WHERE clause would.
+fn rows_with_value(table: &mut Table, table_def: &TableDef, column: usize, value: &Value) -> Vec<Vec<Value>> {
+    let where_clause = Expr::Binary {
+        op: BinaryOp::Compare(CompareOp::Eq),
+        left: Box::new(Expr::Column(table_def.columns[column].name.clone())),
+        right: Box::new(Expr::Literal(value.clone())),
+    };
+    let plan = plan_where(Some(&where_clause), table_def, &table.schema);
+    let mut rows = Vec::new();
+    for_each_row(table, table_def, &plan, Some(&where_clause), |_, row| {
+        rows.push(row.to_vec());
+        true
+    });
+    rows
+}
+
+fn foreign_key_violation(child_def: &TableDef, foreign_key: &ForeignKey, parent_def: &TableDef) -> ExecuteResult {
+    let parent_column = foreign_key.parent_column_index(parent_def).unwrap();
+    ExecuteForeignKeyViolation(format!("{}.{} -> {}.{}", child_def.name, foreign_key.column,
+                                       parent_def.name, parent_def.columns[parent_column].name))
+}
+
+/// Check that every non-NULL reference in the rows points at an existing parent row.
+/// Rows referencing another row written by the same statement are satisfied too.
+fn check_references(table: &mut Table, table_def: &TableDef, rows: &[(RowKey, &[Value])]) -> ExecuteResult {
+    if !table.foreign_keys {
+        return ExecuteSuccess;
+    }
+    for foreign_key in &table_def.foreign_keys {
+        let column = table_def.column_index(&foreign_key.column).unwrap();
+        let parent_def = table.schema.find_table(&foreign_key.parent_table).unwrap().clone();
+        if let Err(err) = table.schema.check_collations(&parent_def) {
+            return err;
+        }
+        let parent_column = foreign_key.parent_column_index(&parent_def).unwrap();
+        for (_, row) in rows {
+            let value = &row[column];
+            if value.is_null() {
+                continue;
+            }
+            let written = parent_def.name == table_def.name && rows.iter().any(|(_, other)| other[parent_column] == *value);
+            if !written && rows_with_value(table, &parent_def, parent_column, value).is_empty() {
+                return foreign_key_violation(table_def, foreign_key, &parent_def);
+            }
+        }
+    }
+    ExecuteSuccess
+}
+
+/// Every foreign key in the schema that references the table, with the table declaring it,
+/// each of which must have its collations registered.
+fn referencing_keys(table: &Table, table_def: &TableDef) -> Result<Vec<(TableDef, ForeignKey)>, ExecuteResult> {
+    let keys: Vec<(TableDef, ForeignKey)> = table.schema.tables.iter()
+        .flat_map(|child_def| child_def.foreign_keys.iter()
+            .filter(|foreign_key| foreign_key.parent_table.eq_ignore_ascii_case(&table_def.name))
+            .map(move |foreign_key| (child_def.clone(), foreign_key.clone())))
+        .collect();
+    for (child_def, _) in &keys {
+        table.schema.check_collations(child_def)?;
+    }
+    Ok(keys)
+}
+
+/// Fail if an update changes a referenced value that child rows still point at.
+fn check_referenced(table: &mut Table, table_def: &TableDef, old_rows: &[Vec<Value>], new_rows: &[EncodedRow]) -> ExecuteResult {
+    if !table.foreign_keys {
+        return ExecuteSuccess;
+    }
+    let keys = match referencing_keys(table, table_def) {
+        Ok(keys) => keys,
+        Err(err) => return err,
+    };
+    for (child_def, foreign_key) in keys {
+        let parent_column = foreign_key.parent_column_index(table_def).unwrap();
+        let column = child_def.column_index(&foreign_key.column).unwrap();
+        for (old_row, new_row) in old_rows.iter().zip(new_rows) {
+            let value = &old_row[parent_column];
+            if value.is_null() || *value == new_row.row[parent_column] {
+                continue;
+            }
+            if !rows_with_value(table, &child_def, column, value).is_empty() {
+                return foreign_key_violation(&child_def, &foreign_key, table_def);
+            }
+        }
+    }
+    ExecuteSuccess
+}
+
+/// Everything a DELETE writes once the ON DELETE actions of referencing tables are followed.
+#[derive(Default)]
+struct DeleteCascade {
+    deletes: Vec<(TableDef, Vec<Value>)>,
+    /// Referencing rows to rewrite with the column set to NULL.
+    set_nulls: Vec<(TableDef, usize, Vec<Value>)>,
+    /// Rows under NO ACTION, which must be gone by the end of the statement.
+    no_actions: Vec<(TableDef, Vec<Value>, ExecuteResult)>,
+}
+
+impl DeleteCascade {
+    fn is_deleted(&self, table_def: &TableDef, row: &[Value]) -> bool {
+        self.deletes.iter().any(|(deleted_def, deleted)| deleted_def.name == table_def.name && deleted == row)
+    }
+
+    /// Schedule the rows for deletion, then follow every foreign key referencing them.
+    fn delete(&mut self, table: &mut Table, table_def: &TableDef, rows: Vec<Vec<Value>>) -> Result<(), ExecuteResult> {
+        let rows: Vec<Vec<Value>> = rows.into_iter().filter(|row| !self.is_deleted(table_def, row)).collect();
+        self.deletes.extend(rows.iter().map(|row| (table_def.clone(), row.clone())));
+        if !table.foreign_keys {
+            return Ok(());
+        }
+        for (child_def, foreign_key) in referencing_keys(table, table_def)? {
+            let parent_column = foreign_key.parent_column_index(table_def).unwrap();
+            let column = child_def.column_index(&foreign_key.column).unwrap();
+            for row in &rows {
+                let value = &row[parent_column];
+                if value.is_null() {
+                    continue;
+                }
+                let children: Vec<Vec<Value>> = rows_with_value(table, &child_def, column, value).into_iter()
+                    .filter(|child| !self.is_deleted(&child_def, child))
+                    .collect();
+                if children.is_empty() {
+                    continue;
+                }
+                match foreign_key.on_delete {
+                    ForeignKeyAction::Cascade => self.delete(table, &child_def, children)?,
+                    ForeignKeyAction::SetNull => {
+                        for mut child in children {
+                            child[column] = Value::Null;
+                            let result = child_def.check_row(&child);
+                            if result != ExecuteSuccess {
+                                return Err(result);
+                            }
+                            self.set_nulls.push((child_def.clone(), column, child));
+                        }
+                    }
+                    ForeignKeyAction::Restrict => return Err(foreign_key_violation(&child_def, &foreign_key, table_def)),
+                    ForeignKeyAction::NoAction => {
+                        let violation = foreign_key_violation(&child_def, &foreign_key, table_def);
+                        self.no_actions.extend(children.into_iter().map(|child| (child_def.clone(), child, violation.clone())));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(self, table: &mut Table) -> ExecuteResult {
+        for (child_def, child, violation) in &self.no_actions {
+            if !self.is_deleted(child_def, child) {
+                return violation.clone();
+            }
+        }
+        for (table_def, row) in &self.deletes {
+            let key = table_def.row_key(row).unwrap();
+            let indexes = table.schema.table_indexes(&table_def.name);
+            if let Err(err) = delete_row(table, table_def, &indexes, &key, row) {
+                return err;
+            }
+        }
+        for (table_def, column, row) in self.set_nulls {
+            let key = table_def.row_key(&row).unwrap();
+            // A row reached through several references is rewritten from its current values.
+            let old_row = match fetch_row(table, &table_def, &key) {
+                Some(old_row) => old_row,
+                None => continue,
+            };
+            let mut new_row = old_row.clone();
+            new_row[column] = Value::Null;
+            let encoded = match table_def.encode_row(&new_row) {
+                Ok(encoded) => encoded,
+                Err(err) => return err,
+            };
+            let indexes = table.schema.table_indexes(&table_def.name);
+            if let Err(err) = update_row(table, &table_def, &indexes, &old_row, &EncodedRow { key, row: new_row, encoded }) {
+                return err;
+            }
+        }
+        ExecuteSuccess
+    }
+}
+
+/// Give each row inserted without a rowid the next one: one past the largest rowid in the
+/// table so far, or in an AUTOINCREMENT table one past the largest it has ever held. Rows
+/// take their rowids in turn, so each counts those given before it.
+pub(crate) fn assign_rowids(table: &Table, table_def: &TableDef, rows: &mut [Vec<Value>]) -> Result<(), ExecuteResult> {
+    // A WITHOUT ROWID table has no rowids to give.
+    let key = match table_def.primary_key() {
+        Some(key) => key,
+        None => return Ok(()),
+    };
+    // The table's largest rowid is only looked up once a row needs one.
+    let mut stored_max = None;
+    let mut batch_max = None;
+    for row in rows {
+        match row[key] {
+            Value::Null => {
+                let stored = *stored_max.get_or_insert_with(|| {
+                    let max_key = table.max_key(table_def.root_page_num);
+                    if table_def.autoincrement {
+                        max_key.max(Some(table.schema.sequence(&table_def.name)))
+                    } else {
+                        max_key
+                    }
+                });
+                let rowid = stored.max(batch_max).unwrap_or(0).checked_add(1).ok_or(ExecuteDatabaseFull)?;
+                row[key] = Value::Integer(rowid);
+                batch_max = Some(rowid);
+            }
+            Value::Integer(rowid) => batch_max = batch_max.max(Some(rowid)),
+            _ => {}
+        }
+    }
+    Ok(())
 }
 
 fn execute_insert(statement: &Statement, table: &mut Table) -> ExecuteResult {
-    match statement.row_to_insert.as_ref() {
-        Some(row_to_insert) => {
-            let (page_num, cell_num) = table.find(row_to_insert.id);
-            let page = table.pager.get_page(page_num);
-            if cell_num < page.leaf_node_num_cells() {
-                let key_at_index = page.leaf_node_key(cell_num);
-                if key_at_index == row_to_insert.id {
-                    return ExecuteDuplicateKey;
+    let table_def = table.schema.find_table(&statement.table_name).unwrap().clone();
+    let indexes = table.schema.table_indexes(&table_def.name);
+    let rows = insert_rows(statement, &table_def)
+        .and_then(|mut rows| assign_rowids(table, &table_def, &mut rows).map(|_| rows))
+        .and_then(|rows| encode_rows(&table_def, rows));
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(err) => return err,
+    };
+    let result = check_unique(table, &table_def, &indexes, &keyed_rows(&rows), &[]);
+    if result != ExecuteSuccess {
+        return result;
+    }
+    let result = check_references(table, &table_def, &keyed_rows(&rows));
+    if result != ExecuteSuccess {
+        return result;
+    }
+    let max_key = rows.iter()
+        .filter_map(|row| match row.key {
+            RowKey::Rowid(rowid) => Some(rowid),
+            RowKey::Key(_) => None,
+        })
+        .max();
+    for row in rows {
+        if let Err(err) = insert_row(table, &table_def, &indexes, &row.key, &row.row, &row.encoded) {
+            return err;
+        }
+    }
+    if let Some(max_key) = max_key.filter(|max_key| table_def.autoincrement && *max_key > table.schema.sequence(&table_def.name)) {
+        table.schema.set_sequence(&table_def.name, max_key);
+        table.schema.save(&mut table.pager);
+    }
+    ExecuteSuccess
+}
+
+/// Evaluate a LIMIT or OFFSET, which must be an integer, or convert to one without loss;
+/// a negative one counts as absent.
+pub(crate) fn limit_value(expr: Option<&Expr>) -> Result<Option<usize>, ExecuteResult> {
+    let value = match expr {
+        Some(expr) => expr.evaluate_constant()?,
+        None => return Ok(None),
+    };
+    let count = match ColumnType::Integer.apply_affinity(value) {
+        Value::Integer(count) => count,
+        _ => return Err(ExecuteDatatypeMismatch),
+    };
+    Ok(usize::try_from(count).ok())
+}
+
+fn execute_select(statement: &Statement, table: &mut Table) -> ExecuteResult {
+    let headers = table.headers;
+    select_rows(statement, table, headers, &mut |values| print_result_row(values))
+}
+
+/// Run a SELECT and collect its result rows.
+pub(crate) fn query_rows(statement: &Statement, table: &mut Table) -> Result<Vec<Vec<Value>>, ExecuteResult> {
+    let mut rows = Vec::new();
+    match select_rows(statement, table, false, &mut |row| rows.push(row.to_vec())) {
+        ExecuteSuccess => Ok(rows),
+        err => Err(err),
+    }
+}
+
+/// The names of a resolved SELECT's result columns, of the first SELECT for a compound.
+pub(crate) fn result_names(statement: &Statement, schema: &Schema) -> Result<Vec<String>, ExecuteResult> {
+    let scope = JoinScope::new(statement, schema)?;
+    Ok(result_columns(statement, scope.row_def())?.into_iter().map(|(name, _)| name).collect())
+}
+
+/// The collation each result column of a SELECT compares by: its expression's, as ORDER BY
+/// would sort it.
+pub(crate) fn result_collations(statement: &Statement, schema: &Schema) -> Result<Vec<Collation>, ExecuteResult> {
+    let scope = JoinScope::new(statement, schema)?;
+    let row_def = scope.row_def();
+    let column_collation = |name: &str| row_def?.column_collation(name);
+    Ok(result_columns(statement, row_def)?.iter().map(|(_, expr)| expr.collation(&column_collation)).collect())
+}
+
+/// Run a SELECT, handing each result row to `output`, after a row of the column names if
+/// `headers` is set.
+pub(crate) fn select_rows(statement: &Statement, table: &mut Table, headers: bool,
+                          output: &mut dyn FnMut(&[Value])) -> ExecuteResult {
+    if !statement.compound.is_empty() {
+        return select_compound(statement, table, headers, output);
+    }
+    let table_def = if statement.joins.is_empty() {
+        from_table(&table.schema, &statement.table_name, statement.table_args.as_deref()).ok()
+    } else {
+        JoinScope::new(statement, &table.schema).ok().map(|scope| scope.def)
+    };
+    let columns = match result_columns(statement, table_def.as_ref()) {
+        Ok(columns) => columns,
+        Err(err) => return err,
+    };
+    // Past the expansion of `*`, a row also holds the value of every correlated subquery.
+    let table_def = table_def.map(|table_def| source_row_def(statement, &table_def));
+    let where_clause = statement.where_clause.as_ref();
+    // Window calls see the rows WHERE, GROUP BY and HAVING leave, so those cannot use them.
+    let clauses = where_clause.into_iter().chain(&statement.group_by).chain(&statement.having);
+    if let Some(Expr::Window { name, .. }) = clauses.flat_map(Expr::windows).next() {
+        return ExecuteMisuseOfWindowFunction(name.clone());
+    }
+    if let Some(table_def) = &table_def {
+        let result = check_where(table_def, where_clause);
+        if result != ExecuteSuccess {
+            return result;
+        }
+    }
+    let order_by = match order_by_terms(&statement.order_by, &columns, table_def.as_ref()) {
+        Ok(order_by) => order_by,
+        Err(err) => return err,
+    };
+    let outputs: Vec<&Expr> = columns.iter().map(|(_, expr)| expr).chain(order_by.iter().map(|(expr, _)| expr)).collect();
+    let windows = window_calls(&outputs);
+    let aggregate_query = if is_aggregate_select(statement) {
+        match AggregateQuery::resolve(statement, &columns, &order_by, table_def.as_ref()) {
+            Ok(query) => Some(query),
+            Err(err) => return err,
+        }
+    } else {
+        None
+    };
+    let (limit, offset) = match (limit_value(statement.limit.as_ref()), limit_value(statement.offset.as_ref())) {
+        (Ok(limit), Ok(offset)) => (limit.unwrap_or(usize::MAX), offset.unwrap_or(0)),
+        (Err(err), _) | (_, Err(err)) => return err,
+    };
+    if headers {
+        let names: Vec<Value> = columns.iter().map(|(name, _)| Value::Text(name.clone())).collect();
+        output(&names);
+    }
+    if limit == 0 {
+        return ExecuteSuccess;
+    }
+    if let Some(query) = aggregate_query {
+        let result = aggregate_rows(statement, table, table_def.as_ref(), &columns, &order_by, &query, &windows)
+            .and_then(|sorter| sorter.finish()?.output(offset, limit, |values| output(values)));
+        return result.err().unwrap_or(ExecuteSuccess);
+    }
+    let table_def = match table_def {
+        Some(table_def) => table_def,
+        None => {
+            // Without FROM the list is evaluated once, against no row.
+            let mut window_values = Vec::new();
+            if !windows.is_empty() {
+                let constant = |expr: &Expr, _: &[Value]| expr.evaluate_constant();
+                let result = WindowStage::new(&windows, &constant, &|_| None).and_then(|mut stage| {
+                    stage.insert(Vec::new())?;
+                    stage.finish(&mut |row| {
+                        window_values = row;
+                        Ok(true)
+                    })
+                });
+                if let Err(err) = result {
+                    return err;
                 }
             }
-            let mut cursor = Cursor {
-                table,
-                page_num,
-                cell_num,
-                end_of_table: false,
+            let values: Result<Vec<Value>, ExecuteResult> = columns.iter()
+                .map(|(_, expr)| substitute_windows(expr, &windows, &window_values).evaluate_constant())
+                .collect();
+            return match values {
+                Ok(values) if offset == 0 => {
+                    output(&values);
+                    ExecuteSuccess
+                }
+                Ok(_) => ExecuteSuccess,
+                Err(err) => err,
             };
-            unsafe { cursor.leaf_node_insert((*row_to_insert).id, row_to_insert) };
-            ExecuteSuccess
         }
-        _ => ExecuteFail
+    };
+    let plan = plan_statement(statement, table);
+    // Rows leave the window stage in the order of its last window rather than the scan's.
+    let mut sorter = if needs_sort(&order_by, &table_def, &plan) || (!windows.is_empty() && !order_by.is_empty()) {
+        Some(Sorter::new(sort_order(&order_by, Some(&table_def))))
+    } else {
+        None
+    };
+    // A row out of the window stage ends with the value of every window call.
+    let evaluate = |expr: &Expr, row: &[Value]| {
+        let (row, window_values) = row.split_at(row.len() - windows.len());
+        let column = |name: &str| table_def.column_value(row, name);
+        if windows.is_empty() {
+            expr.evaluate(&column)
+        } else {
+            substitute_windows(expr, &windows, window_values).evaluate(&column)
+        }
+    };
+    let mut result = ExecuteSuccess;
+    let (mut skipped, mut output_rows) = (0, 0);
+    let mut visit = |row: &[Value]| {
+        if let Some(sorter) = &mut sorter {
+            // Every row has to be sorted before the first one can be output.
+            let key: Result<Vec<Value>, ExecuteResult> = order_by.iter().map(|(expr, _)| evaluate(expr, row)).collect();
+            let values: Result<Vec<Value>, ExecuteResult> = columns.iter().map(|(_, expr)| evaluate(expr, row)).collect();
+            if let Err(err) = key.and_then(|key| sorter.insert(key, values?)) {
+                result = err;
+            }
+            return result == ExecuteSuccess;
+        }
+        if skipped < offset {
+            skipped += 1;
+            return true;
+        }
+        let values: Result<Vec<Value>, ExecuteResult> = columns.iter().map(|(_, expr)| evaluate(expr, row)).collect();
+        match values {
+            Ok(values) => output(&values),
+            Err(err) => {
+                result = err;
+                return false;
+            }
+        }
+        // Stop the cursor as soon as the LIMIT is met rather than walking the rest of the table.
+        output_rows += 1;
+        output_rows < limit
+    };
+    let scan_result = if windows.is_empty() {
+        for_each_source_row(table, statement, &table_def, &plan, &mut visit)
+    } else {
+        let source = |expr: &Expr, row: &[Value]| expr.evaluate(&|name| table_def.column_value(row, name));
+        let mut stage = match WindowStage::new(&windows, &source, &|name| table_def.column_collation(name)) {
+            Ok(stage) => stage,
+            Err(err) => return err,
+        };
+        let mut stage_result = Ok(());
+        let scan_result = for_each_source_row(table, statement, &table_def, &plan, |row| {
+            stage_result = stage.insert(row.to_vec());
+            stage_result.is_ok()
+        });
+        match stage_result.and_then(|()| stage.finish(&mut |row| Ok(visit(&row)))) {
+            Err(err) if scan_result == ExecuteSuccess => err,
+            _ => scan_result,
+        }
+    };
+    if scan_result != ExecuteSuccess {
+        return scan_result;
+    }
+    if result != ExecuteSuccess {
+        return result;
+    }
+    if let Some(sorter) = sorter {
+        let sorted = sorter.finish().and_then(|rows| rows.output(offset, limit, |values| output(values)));
+        if let Err(err) = sorted {
+            return err;
+        }
+    }
+    ExecuteSuccess
+}
+
+/// Fold the rows of an aggregate SELECT into groups and sort the result row of every group that
+/// passes HAVING, by the ORDER BY terms or else by the GROUP BY values. With window calls, the
+/// groups go through the window stage first, and without ORDER BY keep the order it leaves them in.
+fn aggregate_rows(statement: &Statement, table: &mut Table, table_def: Option<&TableDef>, columns: &[(String, Expr)],
+                  order_by: &[(Expr, bool)], query: &AggregateQuery, windows: &[Expr]) -> Result<Sorter, ExecuteResult> {
+    let mut sorter = if !order_by.is_empty() {
+        Sorter::new(sort_order(order_by, table_def))
+    } else if windows.is_empty() {
+        Sorter::new(query.group_order.clone())
+    } else {
+        Sorter::new(KeyOrder::default())
+    };
+    // A group stands as a row of its aggregate values followed by its row, which for the one
+    // group of an empty table is all NULLs.
+    let evaluate = |expr: &Expr, row: &[Value]| -> Result<Value, ExecuteResult> {
+        let (values, row) = row.split_at(query.calls.len());
+        let column = |name: &str| match table_def {
+            Some(table_def) => table_def.column_value(row, name),
+            None => Some(Value::Null),
+        };
+        substitute_aggregates(expr, &query.calls, values).evaluate(&column)
+    };
+    let mut stage = if windows.is_empty() { None } else { Some(WindowStage::new(windows, &evaluate, &|name| table_def?.column_collation(name))?) };
+    let mut sort = |row: &[Value], key: Vec<Value>| -> Result<(), ExecuteResult> {
+        let (row, window_values) = row.split_at(row.len() - windows.len());
+        let evaluate = |expr: &Expr| evaluate(&substitute_windows(expr, windows, window_values), row);
+        let key = if order_by.is_empty() {
+            key
+        } else {
+            order_by.iter().map(|(expr, _)| evaluate(expr)).collect::<Result<Vec<Value>, ExecuteResult>>()?
+        };
+        let values = columns.iter().map(|(_, expr)| evaluate(expr)).collect::<Result<Vec<Value>, ExecuteResult>>()?;
+        sorter.insert(key, values)
+    };
+    let mut output = |group: FinishedGroup| -> Result<(), ExecuteResult> {
+        let row_len = table_def.map_or(0, |table_def| table_def.columns.len());
+        let row: Vec<Value> = group.values.into_iter()
+            .chain(group.row.unwrap_or_else(|| vec![Value::Null; row_len]))
+            .collect();
+        if let Some(having) = &query.having {
+            if !evaluate(having, &row)?.to_bool().unwrap_or(false) {
+                return Ok(());
+            }
+        }
+        match &mut stage {
+            Some(stage) => stage.insert(row),
+            None => sort(&row, group.key),
+        }
+    };
+    if query.counts_rows {
+        let root_page_num = table_def.unwrap().root_page_num;
+        let count = match &table.pager.sqlite {
+            Some(_) => file_format::count_rows(&table.pager, root_page_num as u32).map_err(ExecuteCorruptFile)?,
+            None => table.count_rows(root_page_num),
+        } as i64;
+        let values = vec![Value::Integer(count); query.aggregates.len()];
+        output(FinishedGroup { key: Vec::new(), row: None, values })?;
+    } else {
+        let column = |row: &[Value], name: &str| table_def.and_then(|table_def| table_def.column_value(row, name));
+        let mut aggregator = Aggregator::new(&query.group_by, query.group_order.clone(), &query.aggregates, &column);
+        match table_def {
+            Some(table_def) => {
+                let plan = plan_statement(statement, table);
+                let mut result = Ok(());
+                let scan_result = for_each_source_row(table, statement, table_def, &plan, |row| {
+                    result = aggregator.add_row(row.to_vec());
+                    result.is_ok()
+                });
+                if scan_result != ExecuteSuccess {
+                    return Err(scan_result);
+                }
+                result?;
+            }
+            // Without FROM the aggregates see a single row of no columns.
+            None => aggregator.add_row(Vec::new())?,
+        }
+        aggregator.finish(&mut output)?;
+    }
+    if let Some(stage) = stage {
+        stage.finish(&mut |row| sort(&row, Vec::new()).map(|()| true))?;
+    }
+    Ok(sorter)
+}
+
+fn execute_update(statement: &Statement, table: &mut Table) -> ExecuteResult {
+    let table_def = table.schema.find_table(&statement.table_name).unwrap().clone();
+    let indexes = table.schema.table_indexes(&table_def.name);
+    let mut assignments = Vec::new();
+    for (name, expr) in &statement.assignments {
+        match table_def.column_index(name) {
+            Some(column) => assignments.push((column, expr)),
+            None => return ExecuteNoSuchColumn(name.clone()),
+        }
+    }
+    let mut old_rows = match collect_rows(statement, table, &table_def) {
+        Ok(rows) => rows,
+        Err(err) => return err,
+    };
+    let row_def = source_row_def(statement, &table_def);
+    // Apply every assignment and check every constraint before writing, so a bad value changes no rows.
+    let mut old_keys = Vec::new();
+    let mut new_rows = Vec::new();
+    for row in &mut old_rows {
+        old_keys.push(table_def.row_key(row).unwrap());
+        // Every SET expression sees the row as it was before the update.
+        let mut new_row = row[..table_def.columns.len()].to_vec();
+        for (column, expr) in &assignments {
+            let value = match expr.evaluate(&|name| row_def.column_value(row, name)) {
+                Ok(value) => value,
+                Err(err) => return err,
+            };
+            new_row[*column] = table_def.columns[*column].column_type.apply_affinity(value);
+        }
+        // The values of correlated subqueries are no part of the stored row.
+        row.truncate(table_def.columns.len());
+        new_rows.push(new_row);
+    }
+    let new_rows = match encode_rows(&table_def, new_rows) {
+        Ok(rows) => rows,
+        Err(err) => return err,
+    };
+    let result = check_unique(table, &table_def, &indexes, &keyed_rows(&new_rows), &old_keys);
+    if result != ExecuteSuccess {
+        return result;
+    }
+    let result = check_references(table, &table_def, &keyed_rows(&new_rows));
+    if result != ExecuteSuccess {
+        return result;
+    }
+    let result = check_referenced(table, &table_def, &old_rows, &new_rows);
+    if result != ExecuteSuccess {
+        return result;
+    }
+
+    // Rows whose key changes move, so take them all out before putting any back.
+    for ((old_key, old_row), new_row) in old_keys.iter().zip(&old_rows).zip(&new_rows) {
+        if *old_key != new_row.key {
+            if let Err(err) = delete_row(table, &table_def, &indexes, old_key, old_row) {
+                return err;
+            }
+        }
+    }
+    for ((old_key, old_row), new_row) in old_keys.iter().zip(&old_rows).zip(&new_rows) {
+        if *old_key != new_row.key {
+            if let Err(err) = insert_row(table, &table_def, &indexes, &new_row.key, &new_row.row, &new_row.encoded) {
+                return err;
+            }
+            continue;
+        }
+        if let Err(err) = update_row(table, &table_def, &indexes, old_row, new_row) {
+            return err;
+        }
+    }
+    ExecuteSuccess
+}
+
+fn execute_delete(statement: &Statement, table: &mut Table) -> ExecuteResult {
+    let table_def = table.schema.find_table(&statement.table_name).unwrap().clone();
+    let rows = match collect_rows(statement, table, &table_def) {
+        Ok(rows) => rows.into_iter().map(|row| row[..table_def.columns.len()].to_vec()).collect(),
+        Err(err) => return err,
+    };
+    // Work out every cascaded write first, so a RESTRICT anywhere changes no rows.
+    let mut cascade = DeleteCascade::default();
+    if let Err(err) = cascade.delete(table, &table_def, rows) {
+        return err;
     }
+    cascade.apply(table)
 }
 
-fn execute_select(table: &mut Table) -> ExecuteResult {
-    let mut cursor = Cursor::table_start(table);
-    while !cursor.end_of_table {
-        let row = cursor.cursor_value();
-        println!("{}, {}, {}", (*row).id, (*row).username, (*row).email);
-        cursor.advance();
+fn execute_create_index(statement: &Statement, table: &mut Table) -> ExecuteResult {
+    let table_def = table.schema.find_table(&statement.table_name).unwrap().clone();
+    let def = statement.index_to_create.clone().unwrap();
+    let columns = match &def.expr {
+        Some(expr) => expr.columns(),
+        None => vec![def.column.as_str()],
+    };
+    if let Some(name) = columns.into_iter().find(|name| table_def.column_index(name).is_none()) {
+        return ExecuteNoSuchColumn(name.to_string());
     }
+    if table.schema.find_index(&def.name).is_some() {
+        return ExecuteIndexExists(def.name);
+    }
+    if table_def.without_rowid.is_some() {
+        return ExecuteWithoutRowidIndex(table_def.name);
+    }
+    if table_def.fts.is_some() {
+        return ExecuteVirtualTableIndex;
+    }
+    if let Err(err) = table.schema.check_collations(&table_def) {
+        return err;
+    }
+    let rows = match collect_rows(statement, table, &table_def) {
+        Ok(rows) => rows,
+        Err(err) => return err,
+    };
+    if let Err(err) = table.pager.check_room() {
+        return err;
+    }
+    let tree = IndexTree::create(&mut table.pager, def.order(&table_def));
+    let index = Index { def, tree };
+    let keyed: Vec<(RowKey, &[Value])> = rows.iter()
+        .map(|row| (table_def.row_key(row).unwrap(), row.as_slice()))
+        .collect();
+    // Every row is already stored and the new tree is empty, so only clashes between
+    // existing rows, or an expression that fails on one, can show up.
+    let keys: Vec<RowKey> = keyed.iter().map(|(key, _)| key.clone()).collect();
+    let result = check_unique(table, &table_def, std::slice::from_ref(&index), &keyed, &keys);
+    if result != ExecuteSuccess {
+        return result;
+    }
+    for (key, row) in keyed {
+        if let Err(err) = table.pager.check_room() {
+            return err;
+        }
+        if let RowKey::Rowid(rowid) = key {
+            index.tree.insert(&mut table.pager, index_key(&table_def, &index, rowid, row));
+        }
+    }
+    table.schema.indexes.push(index);
+    table.schema.save(&mut table.pager);
     ExecuteSuccess
-}
\ No newline at end of file
+}
+
+fn execute_pragma(statement: &Statement, table: &mut Table) -> ExecuteResult {
+    let (name, value) = statement.pragma.clone().unwrap();
+    // As in SQLite, a limit below one only reads it, and one below the pages the file already
+    // has is raised to them.
+    if name == "max_page_count" {
+        if let Some(Value::Integer(i)) = value.filter(|value| *value > Value::Integer(0)) {
+            table.pager.max_pages = (i as usize).max(table.pager.num_pages);
+        }
+        println!("{}", table.pager.max_pages);
+        return ExecuteSuccess;
+    }
+    // Like SQLite, unknown pragmas and values are ignored.
+    if name != "foreign_keys" {
+        return ExecuteSuccess;
+    }
+    match value {
+        None => println!("{}", table.foreign_keys as i64),
+        Some(Value::Integer(i)) => table.foreign_keys = i != 0,
+        Some(Value::Text(text)) => match text.as_str() {
+            "on" | "true" | "yes" => table.foreign_keys = true,
+            "off" | "false" | "no" => table.foreign_keys = false,
+            _ => {}
+        },
+        Some(_) => {}
+    }
+    ExecuteSuccess
+}
+
+fn execute_create_table(statement: &Statement, table: &mut Table) -> ExecuteResult {
+    let mut table_def = statement.table_to_create.clone().unwrap();
+    if table_def.name.to_lowercase().starts_with("sqlite_") {
+        return ExecuteReservedName(table_def.name);
+    }
+    for (i, column) in table_def.columns.iter().enumerate() {
+        if table_def.columns[..i].iter().any(|other| other.name == column.name) {
+            return ExecuteDuplicateColumn(column.name.clone());
+        }
+    }
+    match &table_def.without_rowid {
+        Some(key) if key.is_empty() => return ExecutePrimaryKeyMissing(table_def.name),
+        // Its rows are stored by their key alone, with no rowid for an index entry to point at.
+        Some(_) if table_def.columns.iter().any(|column| column.unique && !column.primary_key) => {
+            return ExecuteWithoutRowidIndex(table_def.name);
+        }
+        Some(_) => {}
+        // A single-column key that is not an INTEGER PRIMARY KEY was made UNIQUE by the parser.
+        None if table_def.columns.iter().filter(|column| column.primary_key).count() > 1 => {
+            return ExecuteCompositeRowidKey(table_def.name);
+        }
+        None => {}
+    }
+    for default in table_def.columns.iter().filter_map(|column| column.default.as_ref()) {
+        if let Err(err) = default.evaluate_constant() {
+            return err;
+        }
+    }
+    if let Some(column) = table_def.columns.iter().find(|column| !column.collation.is_known()) {
+        return ExecuteNoSuchCollation(column.collation.to_string());
+    }
+    for foreign_key in &table_def.foreign_keys {
+        if table_def.column_index(&foreign_key.column).is_none() {
+            return ExecuteNoSuchColumn(foreign_key.column.clone());
+        }
+        // A table may reference itself.
+        let parent_def = if foreign_key.parent_table == table_def.name {
+            &table_def
+        } else {
+            match table.schema.find_table(&foreign_key.parent_table) {
+                Some(parent_def) => parent_def,
+                None => return ExecuteNoSuchTable(foreign_key.parent_table.clone()),
+            }
+        };
+        if foreign_key.parent_column_index(parent_def).is_none() {
+            return ExecuteNoSuchColumn(foreign_key.parent_column.clone().unwrap_or_default());
+        }
+    }
+    // A full-text table keeps its index in tables of its own, created along with it.
+    let mut shadow_defs = Vec::new();
+    if let Some(fts) = &table_def.fts {
+        if let Err(err) = fts.tokenizer() {
+            return err;
+        }
+        for sql in fts::shadow_tables(&table_def.name) {
+            let shadow_def = prepare_statement(&sql).unwrap().table_to_create.unwrap();
+            if table.schema.find_table(&shadow_def.name).is_some() {
+                return ExecuteTableExists(shadow_def.name);
+            }
+            shadow_defs.push(shadow_def);
+        }
+    }
+
+    if let Err(err) = table.pager.check_room() {
+        return err;
+    }
+    for table_def in iter::once(&mut table_def).chain(&mut shadow_defs) {
+        table_def.root_page_num = table.pager.allocate_page();
+        let root = table.pager.get_page(table_def.root_page_num);
+        root.initialize_leaf_node();
+        root.set_node_root(true);
+    }
+
+    // Each UNIQUE column gets an index of its own, named the way SQLite names them.
+    let unique_columns: Vec<usize> = table_def.columns.iter()
+        .enumerate()
+        .filter(|(_, column)| column.unique && !column.primary_key)
+        .map(|(column, _)| column)
+        .collect();
+    for (i, column) in unique_columns.into_iter().enumerate() {
+        let def = IndexDef {
+            name: format!("sqlite_autoindex_{}_{}", table_def.name, i + 1),
+            table_name: table_def.name.clone(),
+            column: table_def.columns[column].name.clone(),
+            expr: None,
+            unique: true,
+        };
+        let tree = IndexTree::create(&mut table.pager, table_def.index_order(column));
+        table.schema.indexes.push(Index { def, tree });
+    }
+    // The first AUTOINCREMENT table brings the table that keeps every sequence.
+    if table_def.autoincrement && table.schema.find_table(SEQUENCE_TABLE_NAME).is_none() {
+        table.schema.tables.push(TableDef::sequences(Vec::new()));
+    }
+    table.schema.tables.push(table_def);
+    table.schema.tables.extend(shadow_defs);
+    table.schema.save(&mut table.pager);
+    ExecuteSuccess
+}
@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+use crate::expr::Expr;
+use crate::function::Functions;
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::*;
+use crate::collation::{CollatedKey, Collation, Collations, KeyOrder};
+use crate::schema::{ColumnDef, ColumnType, TableDef};
+use crate::statement::{query_rows, Statement};
+use crate::table::Table;
+use crate::value::Value;
+
+/// A SELECT nested in an expression.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Subquery {
+    pub select: Statement,
+    /// The SELECT as written, which names the subquery in result columns and query plans.
+    pub sql: String,
+    /// The rows of an uncorrelated subquery, read once before the statement runs. A correlated
+    /// subquery never has any, since it runs again for every row of the query around it.
+    pub rows: Option<Rc<Vec<Vec<Value>>>>,
+    /// The rows of an uncorrelated IN subquery once more, to look its operand up in.
+    pub values: Option<Rc<InValues>>,
+}
+
+impl Subquery {
+    /// A copy of the subquery with its SELECT bound as `Statement::bind` binds one.
+    pub fn bind(&self, collations: &Collations, functions: &Functions) -> Result<Subquery, ExecuteResult> {
+        let mut subquery = self.clone();
+        subquery.select.bind(collations, functions)?;
+        Ok(subquery)
+    }
+
+    /// How many levels out the furthest query the subquery reads a column of is, counting
+    /// those its own subqueries read; 0 when it reads none.
+    pub fn correlation_depth(&self) -> usize {
+        self.select.exprs().iter().map(|expr| expr.outer_depth()).max().unwrap_or(0)
+    }
+
+    /// Whether the subquery reads a column of a query it is nested in.
+    pub fn is_correlated(&self) -> bool {
+        self.correlation_depth() > 0
+    }
+}
+
+/// The values of an IN subquery, each held once under the collation its operand compares by,
+/// so that looking an operand up does not read every row.
+#[derive(PartialEq, Debug)]
+pub struct InValues {
+    order: Rc<KeyOrder>,
+    values: HashSet<CollatedKey>,
+    /// Whether a row is NULL, which any operand might equal.
+    has_null: bool,
+}
+
+impl InValues {
+    fn new(rows: &[Vec<Value>], collation: Collation) -> InValues {
+        let order = Rc::new(KeyOrder { descending: Vec::new(), collations: vec![collation] });
+        let values = rows.iter()
+            .filter(|row| !row[0].is_null())
+            .map(|row| CollatedKey { order: order.clone(), key: vec![row[0].clone()] })
+            .collect();
+        InValues { order, values, has_null: rows.iter().any(|row| row[0].is_null()) }
+    }
+
+    /// Whether the operand is one of the values, as comparing it with each in turn would say:
+    /// unknown rather than false when it is NULL or a value is, unless there are no values.
+    pub fn contains(&self, operand: &Value) -> Option<bool> {
+        if operand.is_null() {
+            if self.values.is_empty() && !self.has_null { Some(false) } else { None }
+        } else if self.values.contains(&CollatedKey { order: self.order.clone(), key: vec![operand.clone()] }) {
+            Some(true)
+        } else if self.has_null {
+            None
+        } else {
+            Some(false)
+        }
+    }
+}
+
+/// The subquery of a scalar subquery, EXISTS or IN (select ...) expression.
+pub fn subquery_of(expr: &Expr) -> &Subquery {
+    match expr {
+        Expr::Subquery(subquery) | Expr::Exists(subquery) | Expr::InSelect { subquery, .. } => subquery,
+        _ => unreachable!("not a subquery: {}", expr),
+    }
+}
+
+/// A copy of the subquery expression that reads the given rows.
+fn with_rows(expr: &Expr, rows: Vec<Vec<Value>>) -> Expr {
+    let mut expr = expr.clone();
+    match &mut expr {
+        Expr::Subquery(subquery) | Expr::Exists(subquery) | Expr::InSelect { subquery, .. } => {
+            subquery.rows = Some(Rc::new(rows));
+        }
+        _ => unreachable!(),
+    }
+    expr
+}
+
+/// Run the SELECT of a subquery expression. A scalar subquery and EXISTS only look at the
+/// first row, so without a LIMIT of their own they stop there.
+fn run(expr: &Expr, select: &Statement, table: &mut Table) -> Result<Vec<Vec<Value>>, ExecuteResult> {
+    let mut select = select.clone();
+    if !matches!(expr, Expr::InSelect { .. }) && select.limit.is_none() {
+        select.limit = Some(Expr::Literal(Value::Integer(1)));
+    }
+    query_rows(&select, table)
+}
+
+/// Run every uncorrelated subquery of the statement once and keep its rows in the expression.
+/// Subqueries nested in a correlated one are run too, if they do not depend on it, so that
+/// running it again for every row does not run them again.
+pub fn materialize_subqueries(statement: &mut Statement, table: &mut Table) -> Result<(), ExecuteResult> {
+    for expr in statement.exprs_mut() {
+        *expr = expr.try_replace(&mut |expr| materialize(expr, table))?;
+    }
+    Ok(())
+}
+
+fn materialize(expr: &Expr, table: &mut Table) -> Result<Option<Expr>, ExecuteResult> {
+    if !matches!(expr, Expr::Subquery(_) | Expr::Exists(_) | Expr::InSelect { .. }) {
+        return Ok(None);
+    }
+    let mut subquery = subquery_of(expr).clone();
+    materialize_subqueries(&mut subquery.select, table)?;
+    let correlated = subquery.is_correlated();
+    let mut expr = match expr {
+        Expr::Subquery(_) => Expr::Subquery(Box::new(subquery)),
+        Expr::Exists(_) => Expr::Exists(Box::new(subquery)),
+        Expr::InSelect { operand, negated, .. } => Expr::InSelect {
+            operand: Box::new(operand.try_replace(&mut |expr| materialize(expr, table))?),
+            subquery: Box::new(subquery),
+            negated: *negated,
+        },
+        _ => unreachable!(),
+    };
+    if !correlated {
+        let rows = run(&expr, &subquery_of(&expr).select, table)?;
+        expr = with_rows(&expr, rows);
+        if let Expr::InSelect { operand, subquery, .. } = &mut expr {
+            subquery.values = Some(Rc::new(InValues::new(subquery.rows.as_ref().unwrap(), operand.collation(&|_| None))));
+        }
+    }
+    Ok(Some(expr))
+}
+
+/// The correlated subqueries of the expressions, each once, every one after those its
+/// operand reads.
+pub fn correlated_subqueries<'a>(exprs: &[&'a Expr]) -> Vec<&'a Expr> {
+    let mut seen = HashSet::new();
+    exprs.iter()
+        .flat_map(|expr| expr.subqueries())
+        .filter(|expr| subquery_of(expr).rows.is_none())
+        .filter(|expr| seen.insert(expr.to_string()))
+        .collect()
+}
+
+/// The definition of a row extended by the value of each correlated subquery, which the
+/// subquery's expression looks up as a column named by its SQL.
+pub fn with_subquery_columns(table_def: &TableDef, subqueries: &[&Expr]) -> TableDef {
+    let mut table_def = table_def.clone();
+    table_def.columns.extend(subqueries.iter().map(|subquery| ColumnDef {
+        name: subquery.to_string(),
+        column_type: ColumnType::Blob,
+        primary_key: false,
+        unique: false,
+        not_null: false,
+        default: None,
+        collation: Collation::Binary,
+        hidden: false,
+    }));
+    table_def
+}
+
+/// The value of a correlated subquery expression for one row of the query it is nested in,
+/// `column` looking up that row's columns.
+pub fn subquery_value(expr: &Expr, column: &dyn Fn(&str) -> Option<Value>, table: &mut Table) -> Result<Value, ExecuteResult> {
+    let mut select = subquery_of(expr).select.clone();
+    bind_outer_columns(&mut select, 1, column)?;
+    let rows = run(expr, &select, table)?;
+    with_rows(expr, rows).evaluate(column)
+}
+
+/// Replace every column the SELECT reads of the query `depth` levels out with its value,
+/// `column` looking it up, in the subqueries nested in the SELECT as well.
+fn bind_outer_columns(select: &mut Statement, depth: usize, column: &dyn Fn(&str) -> Option<Value>) -> Result<(), ExecuteResult> {
+    for select_expr in select.exprs_mut() {
+        *select_expr = select_expr.try_replace(&mut |expr| bind_outer_column(expr, depth, column))?;
+    }
+    Ok(())
+}
+
+fn bind_outer_column(expr: &Expr, depth: usize, column: &dyn Fn(&str) -> Option<Value>) -> Result<Option<Expr>, ExecuteResult> {
+    Ok(match expr {
+        Expr::OuterColumn { name, depth: column_depth } if *column_depth == depth => match column(name) {
+            Some(value) => Some(Expr::Literal(value)),
+            None => return Err(ExecuteNoSuchColumn(name.clone())),
+        },
+        Expr::Subquery(_) | Expr::Exists(_) | Expr::InSelect { .. } => {
+            let mut subquery = subquery_of(expr).clone();
+            bind_outer_columns(&mut subquery.select, depth + 1, column)?;
+            Some(match expr {
+                Expr::Subquery(_) => Expr::Subquery(Box::new(subquery)),
+                Expr::Exists(_) => Expr::Exists(Box::new(subquery)),
+                Expr::InSelect { operand, negated, .. } => Expr::InSelect {
+                    operand: Box::new(operand.try_replace(&mut |expr| bind_outer_column(expr, depth, column))?),
+                    subquery: Box::new(subquery),
+                    negated: *negated,
+                },
+                _ => unreachable!(),
+            })
+        }
+        _ => None,
+    })
+}
+
+/// How EXPLAIN QUERY PLAN names the subquery expression, the `number`th of its statement.
+pub fn describe_subquery(expr: &Expr, number: usize) -> String {
+    let correlated = if subquery_of(expr).is_correlated() { "CORRELATED " } else { "" };
+    let kind = if matches!(expr, Expr::InSelect { .. }) { "LIST" } else { "SCALAR" };
+    format!("{}{} SUBQUERY {}", correlated, kind, number)
+}
@@ -1,25 +1,84 @@
+use std::cmp::Ordering;
 use std::process;
-use crate::constant::{INTERNAL_NODE_CELL_SIZE, INTERNAL_NODE_MAX_CELLS};
+use std::rc::Rc;
+use crate::collation::Collations;
+use crate::constant::{INTERNAL_NODE_MAX_CELLS, USERS_ROOT_PAGE_NUM};
+use crate::function::{self, Functions};
 use crate::node::NodeType;
 use crate::node::NodeType::NodeLeaf;
 use crate::page::Page;
 use crate::pager::{Pager, pager_open};
+use crate::result::ExecuteResult::ExecuteSuccess;
+use crate::schema::Schema;
+use crate::statement::execute_statement;
+use crate::upgrade;
+use crate::value::Value;
 
 pub struct Table {
-    pub(crate) root_page_num: usize,
     pub(crate) pager: Pager,
+    pub(crate) schema: Schema,
+    /// Set by `PRAGMA foreign_keys`; off by default, as in SQLite.
+    pub(crate) foreign_keys: bool,
+    /// Set by `.headers on`: SELECT prints its column names before the rows.
+    pub(crate) headers: bool,
+    /// The collations registered by `create_collation`, which only this connection sees.
+    pub(crate) collations: Collations,
+    /// The functions registered by `create_scalar_function` and `create_aggregate_function`,
+    /// which only this connection sees.
+    pub(crate) functions: Functions,
 }
 
+/// An open database, as a program embedding the engine holds it.
+pub type Connection = Table;
+
 impl Table {
     pub(crate) fn new(pager: Pager) -> Self {
+        let mut schema = Schema::load(&pager).unwrap_or_else(|err| {
+            println!("Error: {}", err);
+            process::exit(0x0100);
+        });
+        let (collations, functions) = (Collations::default(), Functions::default());
+        schema.bind(&collations, &functions);
         Table {
+            schema,
             pager,
-            root_page_num: 0,
+            foreign_keys: false,
+            headers: false,
+            collations,
+            functions,
         }
     }
 
-    pub fn find(&self, key: u32) -> (usize, usize) {
-        let root_page_num = self.root_page_num;
+    /// Register `compare` as the collation `COLLATE name` stands for on this connection. A name
+    /// registered before is replaced; BINARY, NOCASE and RTRIM cannot be. The tables and indexes
+    /// that declare it can be used from then on.
+    pub fn create_collation<F: Fn(&str, &str) -> Ordering + 'static>(&mut self, name: &str, compare: F) {
+        self.collations.register(name, Rc::new(compare));
+        self.schema.bind(&self.collations, &self.functions);
+    }
+
+    /// Register `func` as the SQL function `name` on this connection, taking `num_args`
+    /// arguments, or any number when negative. An error it returns fails the statement with
+    /// that message. A name registered before is replaced; the built-in functions cannot be.
+    pub fn create_scalar_function<F: Fn(&[Value]) -> Result<Value, String> + 'static>(&mut self, name: &str, num_args: i32, func: F) {
+        self.functions.register_scalar(name, num_args, Rc::new(func));
+        self.schema.bind(&self.collations, &self.functions);
+    }
+
+    /// Register an aggregate function `name` as `create_scalar_function` registers a scalar one.
+    /// Each group starts from `T::default()`, `step` folds in the arguments of every row, NULLs
+    /// included, and `finalize` reads the result; under a window it is read after every row.
+    pub fn create_aggregate_function<T, S, F>(&mut self, name: &str, num_args: i32, step: S, finalize: F)
+    where
+        T: Default + 'static,
+        S: Fn(&mut T, &[Value]) -> Result<(), String> + 'static,
+        F: Fn(&T) -> Result<Value, String> + 'static,
+    {
+        self.functions.register_aggregate(name, num_args, function::aggregate_factory(step, finalize));
+        self.schema.bind(&self.collations, &self.functions);
+    }
+
+    pub fn find(&self, root_page_num: usize, key: i64) -> (usize, usize) {
         let page = self.pager.get_page_view(root_page_num);
         if page.is_none() {
             return (0, 0);
@@ -27,7 +86,7 @@ impl Table {
         self.find_by_page(page.unwrap(), key, root_page_num)
     }
 
-    fn find_by_page_num(&self, page_num: usize, key: u32) -> (usize, usize) {
+    fn find_by_page_num(&self, page_num: usize, key: i64) -> (usize, usize) {
         let page = self.pager.get_page_view(page_num);
         if page.is_none() {
             println!("page {} not exist", page_num);
@@ -36,7 +95,7 @@ impl Table {
         self.find_by_page(page.unwrap(), key, page_num)
     }
 
-    fn find_by_page(&self, page: &Page, key: u32, page_num: usize) -> (usize, usize) {
+    fn find_by_page(&self, page: &Page, key: i64, page_num: usize) -> (usize, usize) {
         if *page.get_node_type() == NodeLeaf {
             self.leaf_node_find(page, key, page_num)
         } else {
@@ -44,7 +103,7 @@ impl Table {
         }
     }
 
-    pub fn internal_node_find(&self, page: &Page, key: u32) -> (usize, usize) {
+    pub fn internal_node_find(&self, page: &Page, key: i64) -> (usize, usize) {
         let cell_index = page.internal_node_find_child(key);
         if page.get_internal_node_key(cell_index) >= key {
             let child_page_num = page.get_internal_node_child(cell_index);
@@ -54,43 +113,93 @@ impl Table {
         self.find_by_page_num(right_child_num, key)
     }
 
-    fn leaf_node_find(&self, page: &Page, key: u32, page_num: usize) -> (usize, usize) {
+    fn leaf_node_find(&self, page: &Page, key: i64, page_num: usize) -> (usize, usize) {
         (page_num, page.leaf_node_find(key))
     }
 
-    pub fn internal_node_insert(&mut self, parent_page_num: usize, child_page_num: usize) {
-        let child_max_key = self.pager.get_page_view(child_page_num).unwrap()
-            .get_node_max_key();
+    /// Count the rows of a table from the cell counts in its leaf headers, without decoding any row.
+    pub fn count_rows(&self, root_page_num: usize) -> usize {
+        let mut page_num = self.pager.get_leftmost_leaf_page_num(root_page_num);
+        let mut count = 0;
+        loop {
+            let page = self.pager.get_page_view(page_num).unwrap();
+            count += page.leaf_node_num_cells();
+            page_num = page.get_leaf_node_next_leaf();
+            if page_num == 0 {
+                return count;
+            }
+        }
+    }
 
-        let parent = self.pager.get_page(parent_page_num);
-        let right_child_page_num = parent.get_internal_node_right_child();
-        let child_max_key_index = parent.internal_node_find_child(child_max_key);
-        let origin_num_keys = parent.get_internal_node_num_keys();
-        if origin_num_keys >= INTERNAL_NODE_MAX_CELLS {
-            println!("Need to implement splitting internal node");
-            process::exit(0x0010);
+    /// The largest key of a table, from the last cell of its rightmost leaf that has any, or
+    /// None if the table is empty. Deleting rows can leave the rightmost leaves empty, so the
+    /// children left of them are tried in turn.
+    pub fn max_key(&self, page_num: usize) -> Option<i64> {
+        let page = self.pager.get_page_view(page_num).unwrap();
+        if page.is_leaf_node() {
+            return (page.leaf_node_num_cells() > 0).then(|| page.get_node_max_key());
+        }
+        (0..page.get_internal_node_num_keys())
+            .map(|i| page.get_internal_node_child(i))
+            .chain(std::iter::once(page.get_internal_node_right_child()))
+            .rev()
+            .find_map(|child| self.max_key(child))
+    }
+
+    /// Record in the parent that `left_page_num` was split, keys up to `separator` staying on the
+    /// left and the rest moving to `right_page_num`. A parent left with too many keys is split
+    /// in turn; the root moves both its halves to new pages, so it keeps its page number.
+    pub fn internal_node_split_child(&mut self, parent_page_num: usize, left_page_num: usize, separator: i64, right_page_num: usize) {
+        let parent = self.pager.get_page_view(parent_page_num).unwrap();
+        let num_keys = parent.get_internal_node_num_keys();
+        let mut keys: Vec<i64> = (0..num_keys).map(|i| parent.get_internal_node_key(i)).collect();
+        let mut children: Vec<usize> = (0..=num_keys).map(|i| parent.get_internal_node_child(i)).collect();
+        let (is_root, grandparent_page_num) = (parent.is_node_root(), parent.get_node_parent());
+        let child_index = children.iter().position(|child| *child == left_page_num).unwrap();
+        keys.insert(child_index, separator);
+        children.insert(child_index + 1, right_page_num);
+        if keys.len() <= INTERNAL_NODE_MAX_CELLS {
+            self.set_internal_node(parent_page_num, &keys, &children);
+            return;
         }
-        parent.increase_internal_node_num_keys(1);
-        let right_child_max_key = self.pager.get_page_view(right_child_page_num).unwrap()
-            .get_node_max_key();
-
-        if child_max_key > right_child_max_key {
-            let parent = self.pager.get_page(parent_page_num);
-            parent.set_internal_node_right_child(child_page_num);
-            parent.set_internal_node_child(origin_num_keys, right_child_page_num);
-            parent.set_internal_node_key(origin_num_keys, right_child_max_key);
+
+        let right_keys = keys.split_off(keys.len() / 2 + 1);
+        let promoted = keys.pop().unwrap();
+        let right_children = children.split_off(keys.len() + 1);
+        if is_root {
+            let left_page_num = self.new_internal_node(parent_page_num, &keys, &children);
+            let right_page_num = self.new_internal_node(parent_page_num, &right_keys, &right_children);
+            self.set_internal_node(parent_page_num, &[promoted], &[left_page_num, right_page_num]);
         } else {
-            let parent = self.pager.get_page(parent_page_num);
-            for i in (child_max_key_index + 1..=origin_num_keys).rev() {
-                unsafe {
-                    std::ptr::copy_nonoverlapping(parent.leaf_node_cell(i - 1),
-                                                  parent.leaf_node_cell(i) as *mut u8,
-                                                  INTERNAL_NODE_CELL_SIZE);
-                }
-            }
-            parent.set_internal_node_child(child_max_key_index, child_page_num);
-            parent.set_internal_node_key(child_max_key_index, child_max_key);
+            let right_page_num = self.new_internal_node(grandparent_page_num, &right_keys, &right_children);
+            self.set_internal_node(parent_page_num, &keys, &children);
+            self.internal_node_split_child(grandparent_page_num, parent_page_num, promoted, right_page_num);
+        }
+    }
+
+    /// Lay out an internal node's keys and its children, one more than the keys.
+    fn set_internal_node(&mut self, page_num: usize, keys: &[i64], children: &[usize]) {
+        let page = self.pager.get_page(page_num);
+        page.set_internal_node_num_keys(keys.len());
+        for (i, key) in keys.iter().enumerate() {
+            page.set_internal_node_child(i, children[i]);
+            page.set_internal_node_key(i, *key);
+        }
+        page.set_internal_node_right_child(children[keys.len()]);
+    }
+
+    /// Make an internal node of the keys and children under the parent, pointing each child
+    /// back at it.
+    fn new_internal_node(&mut self, parent_page_num: usize, keys: &[i64], children: &[usize]) -> usize {
+        let page_num = self.pager.allocate_page();
+        let page = self.pager.get_page(page_num);
+        page.initialize_internal_node();
+        page.set_node_parent(parent_page_num);
+        self.set_internal_node(page_num, keys, children);
+        for child in children {
+            self.pager.get_page(*child).set_node_parent(page_num);
         }
+        page_num
     }
 
     pub fn print_tree(&self) {
@@ -99,47 +208,64 @@ impl Table {
                 (0..level).for_each(|_| print!(" "));
             }
             let node = pager.get_page_view(page_num);
-            match node {
-                Some(page) => {
-                    match page.get_node_type() {
-                        NodeType::NodeLeaf => {
-                            let num_keys = page.leaf_node_num_cells();
-                            indent(indentation_level);
-                            println!("- leaf (size {})", num_keys);
-                            for i in 0..num_keys {
-                                indent(indentation_level + 1);
-                                println!("{}", page.leaf_node_key(i));
-                            }
+            if let Some(page) = node {
+                match page.get_node_type() {
+                    NodeType::NodeLeaf => {
+                        let num_keys = page.leaf_node_num_cells();
+                        indent(indentation_level);
+                        println!("- leaf (size {})", num_keys);
+                        for i in 0..num_keys {
+                            indent(indentation_level + 1);
+                            println!("{}", page.leaf_node_key(i));
                         }
-                        NodeType::NodeInternal => {
-                            let num_keys = page.get_internal_node_num_keys();
-                            indent(indentation_level);
-                            println!("- internal (size {})", num_keys);
-                            for i in 0..num_keys {
-                                let child = page.get_internal_node_child(i);
-                                print_tree_node(pager, child, indentation_level + 1);
-                                indent(indentation_level + 1);
-                                println!("- key {}", page.get_internal_node_key(i));
-                            }
-                            let child = page.get_internal_node_right_child();
+                    }
+                    NodeType::NodeInternal => {
+                        let num_keys = page.get_internal_node_num_keys();
+                        indent(indentation_level);
+                        println!("- internal (size {})", num_keys);
+                        for i in 0..num_keys {
+                            let child = page.get_internal_node_child(i);
                             print_tree_node(pager, child, indentation_level + 1);
+                            indent(indentation_level + 1);
+                            println!("- key {}", page.get_internal_node_key(i));
                         }
+                        let child = page.get_internal_node_right_child();
+                        print_tree_node(pager, child, indentation_level + 1);
                     }
                 }
-                _ => ()
             }
         }
-        print_tree_node(&self.pager, 0, 0);
+        print_tree_node(&self.pager, USERS_ROOT_PAGE_NUM, 0);
     }
 }
 
 pub fn db_open(file_name: &str) -> Table {
+    // A file from before the header is moved aside, and its rows go into a new one.
+    let headerless_rows = upgrade::take_headerless_file(file_name).unwrap_or_else(|err| {
+        println!("Error: {}", err);
+        process::exit(0x0100);
+    });
     let pager = pager_open(file_name);
-    Table::new(pager)
+    let mut table = Table::new(pager);
+    if let Some(rows) = headerless_rows {
+        if !rows.is_empty() {
+            let result = execute_statement(upgrade::insert_statement(rows), &mut table);
+            if result != ExecuteSuccess {
+                println!("Error: cannot upgrade {}: {:?}", file_name, result);
+                process::exit(0x0100);
+            }
+        }
+        for i in 0..table.pager.num_pages {
+            table.pager.pager_flush(i);
+        }
+        println!("Upgraded {} from before the file header; the original is kept as {}.bak", file_name, file_name);
+    }
+    table
 }
 
 pub fn db_close(table: &mut Table) {
     for i in 0..table.pager.num_pages {
         table.pager.pager_flush(i);
     }
+    table.pager.close();
 }
\ No newline at end of file
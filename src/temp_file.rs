@@ -0,0 +1,107 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::*;
+use crate::value::Value;
+
+static NEXT_TEMP_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary file of records, each a list of values stored as [u32 length|values] with the
+/// value codec records and index keys use. Written once, then read back from the start.
+/// The file is only open while it is written and while it is read, and is removed when
+/// dropped.
+pub struct TempFile {
+    path: PathBuf,
+    /// None once writing is finished.
+    writer: Option<BufWriter<File>>,
+}
+
+fn io_error(action: &str, err: std::io::Error) -> ExecuteResult {
+    ExecuteTempFileError(format!("cannot {} temporary file: {}", action, err))
+}
+
+impl TempFile {
+    /// Create a file of a name no other file has, going on to the next name while one does, so
+    /// that a file left by another process is never written over or removed.
+    pub fn create() -> Result<TempFile, ExecuteResult> {
+        loop {
+            let id = NEXT_TEMP_FILE_ID.fetch_add(1, Ordering::Relaxed);
+            let path = std::env::temp_dir().join(format!("sqlite-rs-{}-{}", process::id(), id));
+            match OpenOptions::new().write(true).read(true).create_new(true).open(&path) {
+                Ok(file) => return Ok(TempFile { path, writer: Some(BufWriter::new(file)) }),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(io_error("create", err)),
+            }
+        }
+    }
+
+    pub fn write_record<'a>(&mut self, values: impl IntoIterator<Item = &'a Value>) -> Result<(), ExecuteResult> {
+        let mut buf = Vec::new();
+        values.into_iter().for_each(|value| value.serialize(&mut buf));
+        let writer = self.writer.as_mut().expect("temporary file written after it was finished");
+        writer.write_all(&(buf.len() as u32).to_ne_bytes())
+            .and_then(|_| writer.write_all(&buf))
+            .map_err(|err| io_error("write", err))
+    }
+
+    /// Finish writing and close the file, which keeps its records until it is read.
+    pub fn finish(&mut self) -> Result<(), ExecuteResult> {
+        match self.writer.take() {
+            Some(mut writer) => writer.flush().map_err(|err| io_error("write", err)),
+            None => Ok(()),
+        }
+    }
+
+    /// Finish writing and read the records back in the order they were written.
+    pub fn into_reader(mut self) -> Result<TempFileReader, ExecuteResult> {
+        self.finish()?;
+        match File::open(&self.path) {
+            Ok(file) => Ok(TempFileReader { reader: BufReader::new(file), _file: self }),
+            Err(err) => Err(io_error("read", err)),
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub struct TempFileReader {
+    reader: BufReader<File>,
+    /// Kept so the file lasts until it has been read.
+    _file: TempFile,
+}
+
+impl Iterator for TempFileReader {
+    type Item = Result<Vec<Value>, ExecuteResult>;
+
+    fn next(&mut self) -> Option<Result<Vec<Value>, ExecuteResult>> {
+        // The records end where the file does; a length cut short, or a failed read, is an error.
+        match self.reader.fill_buf() {
+            Ok([]) => return None,
+            Ok(_) => {}
+            Err(err) => return Some(Err(io_error("read", err))),
+        }
+        let mut len = [0_u8; 4];
+        if let Err(err) = self.reader.read_exact(&mut len) {
+            return Some(Err(io_error("read", err)));
+        }
+        let mut bytes = vec![0_u8; u32::from_ne_bytes(len) as usize];
+        if let Err(err) = self.reader.read_exact(&mut bytes) {
+            return Some(Err(io_error("read", err)));
+        }
+        let mut values = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let (value, len) = Value::deserialize(&bytes[offset..]);
+            values.push(value);
+            offset += len;
+        }
+        Some(Ok(values))
+    }
+}
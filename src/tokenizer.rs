@@ -0,0 +1,164 @@
+use crate::result::PrepareResult;
+use crate::result::PrepareResult::*;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Token {
+    /// A keyword or identifier; keywords are matched case-insensitively.
+    Ident(String),
+    Integer(i64),
+    Real(f64),
+    Str(String),
+    /// `X'hex digits'`
+    Blob(Vec<u8>),
+    LParen,
+    RParen,
+    Comma,
+    Dot,
+    Star,
+    Plus,
+    Minus,
+    Slash,
+    Percent,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// `->`
+    Arrow,
+    /// `->>`
+    LongArrow,
+    Semicolon,
+}
+
+impl Token {
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Ident(ident) if ident.eq_ignore_ascii_case(keyword))
+    }
+}
+
+/// Split a command into tokens, each with the byte offset it starts at.
+pub fn tokenize(command: &str) -> Result<Vec<(Token, usize)>, PrepareResult> {
+    let chars: Vec<char> = command.chars().collect();
+    let offsets: Vec<usize> = command.char_indices().map(|(offset, _)| offset).collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let offset = offsets[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if (c == 'x' || c == 'X') && chars.get(i + 1) == Some(&'\'') {
+            let start = i + 2;
+            let end = match chars[start..].iter().position(|c| *c == '\'') {
+                Some(len) => start + len,
+                None => return Err(PrepareSyntaxErr),
+            };
+            let digits: Vec<u32> = chars[start..end].iter().map(|c| c.to_digit(16)).collect::<Option<_>>().ok_or(PrepareSyntaxErr)?;
+            if !digits.len().is_multiple_of(2) {
+                return Err(PrepareSyntaxErr);
+            }
+            let bytes = digits.chunks(2).map(|pair| (pair[0] * 16 + pair[1]) as u8).collect();
+            tokens.push((Token::Blob(bytes), offset));
+            i = end + 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), offset));
+        } else if matches!(c, '"' | '`' | '[') {
+            // A quoted identifier: "name", `name` or [name], as SQLite files' schemas use.
+            let close = if c == '[' { ']' } else { c };
+            let start = i + 1;
+            let end = match chars[start..].iter().position(|c| *c == close) {
+                Some(len) => start + len,
+                None => return Err(PrepareSyntaxErr),
+            };
+            tokens.push((Token::Ident(chars[start..end].iter().collect()), offset));
+            i = end + 1;
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) {
+            // digits [. digits] [e [+|-] digits], or . digits [e ...]: a real unless it is
+            // all digits and fits in an integer.
+            let start = i;
+            let skip_digits = |mut i: usize| {
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                i
+            };
+            i = skip_digits(i);
+            let mut real = false;
+            if chars.get(i) == Some(&'.') {
+                i = skip_digits(i + 1);
+                real = true;
+            }
+            if matches!(chars.get(i), Some('e' | 'E')) {
+                let mut exponent = i + 1;
+                if matches!(chars.get(exponent), Some('+' | '-')) {
+                    exponent += 1;
+                }
+                if !chars.get(exponent).is_some_and(char::is_ascii_digit) {
+                    return Err(PrepareSyntaxErr);
+                }
+                i = skip_digits(exponent);
+                real = true;
+            }
+            let number: String = chars[start..i].iter().collect();
+            match number.parse::<i64>() {
+                Ok(n) if !real => tokens.push((Token::Integer(n), offset)),
+                _ => tokens.push((Token::Real(number.parse().map_err(|_| PrepareSyntaxErr)?), offset)),
+            }
+        } else if c == '\'' {
+            // SQL strings escape a quote by doubling it.
+            let mut s = String::new();
+            i += 1;
+            loop {
+                if i >= chars.len() {
+                    return Err(PrepareSyntaxErr);
+                }
+                if chars[i] == '\'' {
+                    if i + 1 < chars.len() && chars[i + 1] == '\'' {
+                        s.push('\'');
+                        i += 2;
+                        continue;
+                    }
+                    i += 1;
+                    break;
+                }
+                s.push(chars[i]);
+                i += 1;
+            }
+            tokens.push((Token::Str(s), offset));
+        } else {
+            let next = chars.get(i + 1).copied();
+            let (token, len) = match (c, next) {
+                ('-', Some('>')) if chars.get(i + 2) == Some(&'>') => (Token::LongArrow, 3),
+                ('-', Some('>')) => (Token::Arrow, 2),
+                ('<', Some('=')) => (Token::Le, 2),
+                ('<', Some('>')) => (Token::Ne, 2),
+                ('>', Some('=')) => (Token::Ge, 2),
+                ('!', Some('=')) => (Token::Ne, 2),
+                ('=', Some('=')) => (Token::Eq, 2),
+                ('(', _) => (Token::LParen, 1),
+                (')', _) => (Token::RParen, 1),
+                (',', _) => (Token::Comma, 1),
+                ('.', _) => (Token::Dot, 1),
+                ('*', _) => (Token::Star, 1),
+                ('+', _) => (Token::Plus, 1),
+                ('-', _) => (Token::Minus, 1),
+                ('/', _) => (Token::Slash, 1),
+                ('%', _) => (Token::Percent, 1),
+                ('=', _) => (Token::Eq, 1),
+                ('<', _) => (Token::Lt, 1),
+                ('>', _) => (Token::Gt, 1),
+                (';', _) => (Token::Semicolon, 1),
+                _ => return Err(PrepareSyntaxErr),
+            };
+            tokens.push((token, offset));
+            i += len;
+        }
+    }
+    Ok(tokens)
+}
@@ -0,0 +1,113 @@
+use std::fs;
+use std::path::Path;
+use crate::constant::{FILE_MAGIC, PAGE_SIZE};
+use crate::expr::Expr;
+use crate::statement::{Statement, StatementType};
+use crate::value::Value;
+
+// The layout of a file from before the file header, which held only the users table, rooted
+// at page 0, in native-endian fields and fixed-size rows:
+// Node Header: NODE TYPE (0 internal, 1 leaf)|IS ROOT|PARENT POINTER
+// Leaf Node: Node Header|NUM CELLS|NEXT LEAF|[KEY u32|ID u32|USERNAME [u8; 32]|EMAIL [u8; 255]]
+// Internal Node: Node Header|NUM KEYS|RIGHT CHILD|[CHILD usize|KEY u32]
+const NODE_TYPE_OFFSET: usize = 0;
+const IS_ROOT_OFFSET: usize = 1;
+const NUM_CELLS_OFFSET: usize = 10;
+const RIGHT_CHILD_OFFSET: usize = 18;
+const NODE_HEADER_SIZE: usize = 26;
+const USERNAME_SIZE: usize = 32;
+const EMAIL_SIZE: usize = 255;
+const LEAF_CELL_SIZE: usize = 4 + 4 + USERNAME_SIZE + EMAIL_SIZE;
+const INTERNAL_CELL_SIZE: usize = 8 + 4;
+
+/// A row of the users table of a file from before the header.
+pub struct UserRow {
+    id: u32,
+    username: String,
+    email: String,
+}
+
+/// Whether the bytes look like a file from before the header: whole pages, the first of
+/// them the users table's root node.
+fn is_headerless(bytes: &[u8]) -> bool {
+    !bytes.is_empty()
+        && bytes.len().is_multiple_of(PAGE_SIZE)
+        && !bytes.starts_with(FILE_MAGIC)
+        && bytes[NODE_TYPE_OFFSET] <= 1
+        && bytes[IS_ROOT_OFFSET] == 1
+}
+
+fn read_usize(page: &[u8], offset: usize) -> usize {
+    usize::from_ne_bytes(page[offset..offset + 8].try_into().unwrap())
+}
+
+/// A fixed-size text field, padded with NULs.
+fn read_text(field: &[u8]) -> Result<String, String> {
+    let len = field.iter().position(|&byte| byte == 0).unwrap_or(field.len());
+    String::from_utf8(field[..len].to_vec()).map_err(|_| String::from("a username or email is not UTF-8"))
+}
+
+/// Append the rows of the tree rooted at `page_num` to `rows` in key order. Every page is read
+/// at most once, so a tree that loops is refused.
+fn read_rows(bytes: &[u8], page_num: usize, visited: &mut [bool], rows: &mut Vec<UserRow>) -> Result<(), String> {
+    if page_num >= visited.len() || visited[page_num] {
+        return Err(format!("page {} is out of range or read twice", page_num));
+    }
+    visited[page_num] = true;
+    let page = &bytes[page_num * PAGE_SIZE..(page_num + 1) * PAGE_SIZE];
+    let num_cells = read_usize(page, NUM_CELLS_OFFSET);
+    if page[NODE_TYPE_OFFSET] == 1 {
+        if num_cells > (PAGE_SIZE - NODE_HEADER_SIZE) / LEAF_CELL_SIZE {
+            return Err(format!("leaf page {} claims {} cells", page_num, num_cells));
+        }
+        for cell in page[NODE_HEADER_SIZE..].chunks_exact(LEAF_CELL_SIZE).take(num_cells) {
+            rows.push(UserRow {
+                id: u32::from_ne_bytes(cell[4..8].try_into().unwrap()),
+                username: read_text(&cell[8..8 + USERNAME_SIZE])?,
+                email: read_text(&cell[8 + USERNAME_SIZE..])?,
+            });
+        }
+    } else {
+        if num_cells > (PAGE_SIZE - NODE_HEADER_SIZE) / INTERNAL_CELL_SIZE {
+            return Err(format!("internal page {} claims {} keys", page_num, num_cells));
+        }
+        for cell in page[NODE_HEADER_SIZE..].chunks_exact(INTERNAL_CELL_SIZE).take(num_cells) {
+            read_rows(bytes, read_usize(cell, 0), visited, rows)?;
+        }
+        read_rows(bytes, read_usize(page, RIGHT_CHILD_OFFSET), visited, rows)?;
+    }
+    Ok(())
+}
+
+/// If the file at `path` was written before the file header existed, move it aside to
+/// `<path>.bak` and return its users rows, for the new file made in its place to take in.
+/// Fails on such a file whose tree cannot be read, leaving it where it is.
+pub fn take_headerless_file(path: &str) -> Result<Option<Vec<UserRow>>, String> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) if is_headerless(&bytes) => bytes,
+        _ => return Ok(None),
+    };
+    let mut rows = Vec::new();
+    let mut visited = vec![false; bytes.len() / PAGE_SIZE];
+    read_rows(&bytes, 0, &mut visited, &mut rows)
+        .map_err(|err| format!("file is not a database of this format, nor one from before its header: {}", err))?;
+    let backup = format!("{}.bak", path);
+    if Path::new(&backup).exists() {
+        return Err(format!("{} is from before the file header, but {} is in the way of upgrading it", path, backup));
+    }
+    fs::rename(path, &backup).map_err(|err| format!("cannot move {} aside: {}", path, err))?;
+    Ok(Some(rows))
+}
+
+/// The INSERT that puts rows read by `take_headerless_file` into the users table.
+pub fn insert_statement(rows: Vec<UserRow>) -> Statement {
+    let mut stmt = Statement::new(StatementType::StatementInsert);
+    stmt.values = rows.into_iter()
+        .map(|row| vec![
+            Expr::Literal(Value::Integer(row.id as i64)),
+            Expr::Literal(Value::Text(row.username)),
+            Expr::Literal(Value::Text(row.email)),
+        ])
+        .collect();
+    stmt
+}
@@ -0,0 +1,274 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// A single column value, as read from a row or written in a statement.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// Compare an integer with a real exactly, even where the integer has no exact real.
+fn compare_integer_real(i: i64, r: f64) -> Ordering {
+    if r >= 9223372036854775808.0 {
+        return Ordering::Less;
+    }
+    if r < -9223372036854775808.0 {
+        return Ordering::Greater;
+    }
+    match i.cmp(&(r as i64)) {
+        Ordering::Equal => 0.0.partial_cmp(&r.fract()).unwrap_or(Ordering::Equal),
+        ordering => ordering,
+    }
+}
+
+impl Ord for Value {
+    /// NULL sorts first, then numbers by value, whether integer or real, then text, then
+    /// blobs, as in SQLite. Text and blobs compare byte by byte.
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            (Value::Real(a), Value::Real(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (Value::Integer(a), Value::Real(b)) => compare_integer_real(*a, *b),
+            (Value::Real(a), Value::Integer(b)) => compare_integer_real(*b, *a).reverse(),
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+            (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Values are equal when they compare equal, so 1 and 1.0 are the same value.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    /// A real with an integer value hashes as that integer, since it equals it.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_rank().hash(state);
+        match self {
+            Value::Null => {}
+            Value::Integer(i) => i.hash(state),
+            Value::Real(r) if compare_integer_real(*r as i64, *r) == Ordering::Equal => (*r as i64).hash(state),
+            Value::Real(r) => r.to_bits().hash(state),
+            Value::Text(s) => s.hash(state),
+            Value::Blob(b) => b.hash(state),
+        }
+    }
+}
+
+/// A real the way SQLite prints it: up to 15 significant digits, always with a decimal point
+/// or exponent so that it reads back as a real.
+fn format_real(real: f64) -> String {
+    if real.is_infinite() {
+        return String::from(if real > 0.0 { "Inf" } else { "-Inf" });
+    }
+    if real == 0.0 {
+        return String::from("0.0");
+    }
+    let scientific = format!("{:.14e}", real);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let trim = |digits: &str| -> String {
+        let digits = digits.trim_end_matches('0');
+        if digits.ends_with('.') { format!("{}0", digits) } else { String::from(digits) }
+    };
+    if !(-4..15).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", trim(mantissa), sign, exponent.abs())
+    } else {
+        trim(&format!("{:.*}", (14 - exponent) as usize, real))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Real(r) => write!(f, "{}", format_real(*r)),
+            Value::Text(s) => write!(f, "{}", s),
+            Value::Blob(b) => write!(f, "{}", String::from_utf8_lossy(b)),
+        }
+    }
+}
+
+/// The longest prefix of `s` that reads as a number, with whether it needs a real to hold it.
+fn numeric_prefix(s: &str) -> (&str, bool) {
+    let bytes = s.as_bytes();
+    let digits = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
+    let sign_end = if matches!(bytes.first(), Some(b'+' | b'-')) { 1 } else { 0 };
+    let mut end = digits(sign_end);
+    let mut real = false;
+    if bytes.get(end) == Some(&b'.') {
+        let fraction_end = digits(end + 1);
+        if end > sign_end || fraction_end > end + 1 {
+            end = fraction_end;
+            real = true;
+        }
+    }
+    let has_digits = s[..end].bytes().any(|b| b.is_ascii_digit());
+    if has_digits && matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let mut exponent = end + 1;
+        if matches!(bytes.get(exponent), Some(b'+' | b'-')) {
+            exponent += 1;
+        }
+        let exponent_end = digits(exponent);
+        if exponent_end > exponent {
+            end = exponent_end;
+            real = true;
+        }
+    }
+    if !has_digits {
+        return ("", false);
+    }
+    (&s[..end], real)
+}
+
+/// The number a numeric prefix reads as: an integer if it is one and fits, else a real.
+fn parse_number(prefix: &str, real: bool) -> Value {
+    if !real {
+        if let Ok(i) = prefix.parse::<i64>() {
+            return Value::Integer(i);
+        }
+    }
+    Value::Real(prefix.parse().unwrap_or(0.0))
+}
+
+/// The number text spells out in full, ignoring surrounding spaces, if it spells one.
+pub fn parse_numeric(text: &str) -> Option<Value> {
+    let text = text.trim();
+    let (prefix, real) = numeric_prefix(text);
+    if prefix.is_empty() || prefix.len() != text.len() {
+        return None;
+    }
+    Some(parse_number(prefix, real))
+}
+
+const VALUE_TAG_INTEGER: u8 = 0;
+const VALUE_TAG_TEXT: u8 = 1;
+const VALUE_TAG_NULL: u8 = 2;
+const VALUE_TAG_REAL: u8 = 3;
+const VALUE_TAG_BLOB: u8 = 4;
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// The rank of the value's storage class in the sort order.
+    fn type_rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Integer(_) | Value::Real(_) => 1,
+            Value::Text(_) => 2,
+            Value::Blob(_) => 3,
+        }
+    }
+
+    /// A real result, or NULL for one that is not a number, as SQLite gives for `0.0 / 0.0`.
+    pub fn from_real(real: f64) -> Value {
+        if real.is_nan() { Value::Null } else { Value::Real(real) }
+    }
+
+    /// The value as a number for arithmetic: text and blobs convert through their leading
+    /// number, as in SQLite, so '12ab' is 12, '1.5e2x' is 150.0 and 'ab' is 0. NULL has none.
+    pub fn to_numeric(&self) -> Option<Value> {
+        match self {
+            Value::Null => None,
+            Value::Integer(_) | Value::Real(_) => Some(self.clone()),
+            Value::Text(_) | Value::Blob(_) => {
+                let text = self.to_string();
+                let (prefix, real) = numeric_prefix(text.trim_start());
+                Some(if prefix.is_empty() { Value::Integer(0) } else { parse_number(prefix, real) })
+            }
+        }
+    }
+
+    /// The value as an integer: a real drops its fraction, saturating at the ends of the range.
+    pub fn to_integer(&self) -> Option<i64> {
+        match self.to_numeric()? {
+            Value::Integer(i) => Some(i),
+            Value::Real(r) => Some(r as i64),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn to_real(&self) -> Option<f64> {
+        match self.to_numeric()? {
+            Value::Integer(i) => Some(i as f64),
+            Value::Real(r) => Some(r),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Truth value in a condition: zero is false, any other number true, NULL unknown.
+    pub fn to_bool(&self) -> Option<bool> {
+        self.to_real().map(|r| r != 0.0)
+    }
+
+    /// Append the tagged encoding shared by records and index keys: [tag] for NULL,
+    /// [tag|i64] for integers, [tag|f64] for reals, [tag|u16 length|bytes] for text and blobs.
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        match self {
+            Value::Null => buf.push(VALUE_TAG_NULL),
+            Value::Integer(i) => {
+                buf.push(VALUE_TAG_INTEGER);
+                buf.extend_from_slice(&i.to_ne_bytes());
+            }
+            Value::Real(r) => {
+                buf.push(VALUE_TAG_REAL);
+                buf.extend_from_slice(&r.to_ne_bytes());
+            }
+            Value::Text(s) => {
+                buf.push(VALUE_TAG_TEXT);
+                buf.extend_from_slice(&(s.len() as u16).to_ne_bytes());
+                buf.extend_from_slice(s.as_bytes());
+            }
+            Value::Blob(b) => {
+                buf.push(VALUE_TAG_BLOB);
+                buf.extend_from_slice(&(b.len() as u16).to_ne_bytes());
+                buf.extend_from_slice(b);
+            }
+        }
+    }
+
+    /// Decode the value at the start of `bytes`, returning it with the number of bytes it used.
+    pub fn deserialize(bytes: &[u8]) -> (Value, usize) {
+        match bytes[0] {
+            VALUE_TAG_NULL => (Value::Null, 1),
+            VALUE_TAG_INTEGER => (Value::Integer(i64::from_ne_bytes(bytes[1..9].try_into().unwrap())), 9),
+            VALUE_TAG_REAL => (Value::Real(f64::from_ne_bytes(bytes[1..9].try_into().unwrap())), 9),
+            tag => {
+                let len = u16::from_ne_bytes(bytes[1..3].try_into().unwrap()) as usize;
+                let payload = &bytes[3..3 + len];
+                let value = if tag == VALUE_TAG_BLOB {
+                    Value::Blob(payload.to_vec())
+                } else {
+                    Value::Text(String::from_utf8_lossy(payload).into_owned())
+                };
+                (value, 3 + len)
+            }
+        }
+    }
+}
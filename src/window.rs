@@ -0,0 +1,536 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Range;
+use crate::aggregate::{Accumulator, AggregateFunc};
+use crate::collation::{Collation, KeyOrder};
+use crate::expr::Expr;
+use crate::result::ExecuteResult;
+use crate::result::ExecuteResult::*;
+use crate::sorter::Sorter;
+use crate::statement::OrderingTerm;
+use crate::value::Value;
+
+/// Whether a frame's offsets count rows, or the distance of ORDER BY values from the current row's.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FrameUnit {
+    Rows,
+    Range,
+}
+
+/// One end of a window frame.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    Preceding(i64),
+    CurrentRow,
+    Following(i64),
+    UnboundedFollowing,
+}
+
+/// The rows around the current row that a frame-aware function reads: `unit BETWEEN start AND end`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Frame {
+    pub unit: FrameUnit,
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+
+/// The window of `OVER (...)`: which rows form the current row's partition, the order they are
+/// taken in, and the frame.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Window {
+    pub partition_by: Vec<Expr>,
+    pub order_by: Vec<OrderingTerm>,
+    pub frame: Option<Frame>,
+}
+
+impl Window {
+    /// The frame of the window; without one, every row up to the last peer of the current row,
+    /// which is the whole partition when there is no ORDER BY.
+    fn frame(&self) -> Frame {
+        self.frame.clone().unwrap_or(Frame {
+            unit: FrameUnit::Range,
+            start: FrameBound::UnboundedPreceding,
+            end: FrameBound::CurrentRow,
+        })
+    }
+}
+
+impl fmt::Display for FrameBound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameBound::UnboundedPreceding => write!(f, "UNBOUNDED PRECEDING"),
+            FrameBound::Preceding(offset) => write!(f, "{} PRECEDING", offset),
+            FrameBound::CurrentRow => write!(f, "CURRENT ROW"),
+            FrameBound::Following(offset) => write!(f, "{} FOLLOWING", offset),
+            FrameBound::UnboundedFollowing => write!(f, "UNBOUNDED FOLLOWING"),
+        }
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut clauses = Vec::new();
+        if !self.partition_by.is_empty() {
+            let exprs: Vec<String> = self.partition_by.iter().map(|expr| expr.to_string()).collect();
+            clauses.push(format!("PARTITION BY {}", exprs.join(", ")));
+        }
+        if !self.order_by.is_empty() {
+            let terms: Vec<String> = self.order_by.iter()
+                .map(|term| format!("{}{}", term.expr, if term.descending { " DESC" } else { "" }))
+                .collect();
+            clauses.push(format!("ORDER BY {}", terms.join(", ")));
+        }
+        if let Some(frame) = &self.frame {
+            let unit = match frame.unit {
+                FrameUnit::Rows => "ROWS",
+                FrameUnit::Range => "RANGE",
+            };
+            clauses.push(format!("{} BETWEEN {} AND {}", unit, frame.start, frame.end));
+        }
+        write!(f, "{}", clauses.join(" "))
+    }
+}
+
+/// The functions that only run over a window; the aggregates run over one too.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WindowFunc {
+    RowNumber,
+    Rank,
+    DenseRank,
+    PercentRank,
+    CumeDist,
+    Ntile,
+    Lag,
+    Lead,
+    FirstValue,
+    LastValue,
+    NthValue,
+}
+
+impl WindowFunc {
+    pub fn from_name(name: &str) -> Option<WindowFunc> {
+        match name.to_lowercase().as_str() {
+            "row_number" => Some(WindowFunc::RowNumber),
+            "rank" => Some(WindowFunc::Rank),
+            "dense_rank" => Some(WindowFunc::DenseRank),
+            "percent_rank" => Some(WindowFunc::PercentRank),
+            "cume_dist" => Some(WindowFunc::CumeDist),
+            "ntile" => Some(WindowFunc::Ntile),
+            "lag" => Some(WindowFunc::Lag),
+            "lead" => Some(WindowFunc::Lead),
+            "first_value" => Some(WindowFunc::FirstValue),
+            "last_value" => Some(WindowFunc::LastValue),
+            "nth_value" => Some(WindowFunc::NthValue),
+            _ => None,
+        }
+    }
+
+    /// The argument counts the function accepts.
+    fn arity(&self) -> (usize, usize) {
+        match self {
+            WindowFunc::RowNumber | WindowFunc::Rank | WindowFunc::DenseRank
+            | WindowFunc::PercentRank | WindowFunc::CumeDist => (0, 0),
+            WindowFunc::Ntile | WindowFunc::FirstValue | WindowFunc::LastValue => (1, 1),
+            WindowFunc::Lag | WindowFunc::Lead => (1, 3),
+            WindowFunc::NthValue => (2, 2),
+        }
+    }
+}
+
+/// What a window call computes.
+#[derive(Clone, PartialEq, Debug)]
+enum Function {
+    Window(WindowFunc),
+    Aggregate(AggregateFunc),
+}
+
+/// A window call of a query, resolved.
+struct WindowCall {
+    function: Function,
+    args: Vec<Expr>,
+    frame: Frame,
+}
+
+impl WindowCall {
+    fn from_expr(expr: &Expr) -> Result<WindowCall, ExecuteResult> {
+        let (name, args, window, registered) = match expr {
+            Expr::Window { name, args, window, registered } => (name, args, window, registered),
+            _ => unreachable!("not a window call: {}", expr),
+        };
+        let (function, (min_args, max_args)) = match (WindowFunc::from_name(name), AggregateFunc::from_call(name, registered.as_ref())) {
+            (Some(func), _) => (Function::Window(func), func.arity()),
+            (None, Some(func)) => {
+                let arity = func.arity();
+                (Function::Aggregate(func), arity)
+            }
+            (None, None) => return Err(ExecuteNoSuchFunction(name.clone())),
+        };
+        if args.len() < min_args || args.len() > max_args {
+            return Err(ExecuteWrongArgumentCount(name.clone()));
+        }
+        // A window call cannot read the value of another.
+        let exprs = args.iter().chain(&window.partition_by).chain(window.order_by.iter().map(|term| &term.expr));
+        if let Some(Expr::Window { name, .. }) = exprs.flat_map(Expr::windows).next() {
+            return Err(ExecuteMisuseOfWindowFunction(name.clone()));
+        }
+        Ok(WindowCall { function, args: args.clone(), frame: window.frame() })
+    }
+}
+
+/// The window calls of the expressions, each once, in the order found.
+pub fn window_calls(exprs: &[&Expr]) -> Vec<Expr> {
+    let mut calls: Vec<Expr> = Vec::new();
+    for call in exprs.iter().flat_map(|expr| expr.windows()) {
+        if !calls.contains(call) {
+            calls.push(call.clone());
+        }
+    }
+    calls
+}
+
+/// Replace every window call in the expression with its value for a row.
+pub fn substitute_windows(expr: &Expr, calls: &[Expr], values: &[Value]) -> Expr {
+    expr.replace(&|expr| {
+        let i = calls.iter().position(|call| call == expr)?;
+        Some(Expr::Literal(values[i].clone()))
+    })
+}
+
+/// How many times the window calls of the expressions sort their rows: once for every distinct
+/// PARTITION BY and ORDER BY but an empty one.
+pub fn window_sorts(exprs: &[&Expr]) -> usize {
+    let mut sorts: Vec<(&[Expr], &[OrderingTerm])> = Vec::new();
+    for call in exprs.iter().flat_map(|expr| expr.windows()) {
+        if let Expr::Window { window, .. } = call {
+            let sort = (window.partition_by.as_slice(), window.order_by.as_slice());
+            if (!sort.0.is_empty() || !sort.1.is_empty()) && !sorts.contains(&sort) {
+                sorts.push(sort);
+            }
+        }
+    }
+    sorts.len()
+}
+
+/// Evaluates an expression of a window call against a row of the query.
+pub type Evaluate<'a> = dyn Fn(&Expr, &[Value]) -> Result<Value, ExecuteResult> + 'a;
+
+/// The window calls sharing one PARTITION BY and ORDER BY, computed in one pass over the rows
+/// sorted by them.
+struct Pass {
+    partition_by: Vec<Expr>,
+    order_by: Vec<(Expr, bool)>,
+    /// The collation of each PARTITION BY then ORDER BY value, which rows are sorted, split
+    /// into partitions and told apart from their peers under.
+    collations: Vec<Collation>,
+    calls: Vec<usize>,
+}
+
+impl Pass {
+    fn sorter(&self) -> Sorter {
+        let descending = self.partition_by.iter().map(|_| false)
+            .chain(self.order_by.iter().map(|(_, descending)| *descending))
+            .collect();
+        Sorter::new(KeyOrder { descending, collations: self.collations.clone() })
+    }
+
+    /// Whether two keys hold equal values in the range.
+    fn equal(&self, a: &[Value], b: &[Value], range: Range<usize>) -> bool {
+        range.into_iter().all(|i| self.collations[i].compare(&a[i], &b[i]) == Ordering::Equal)
+    }
+
+    fn key_len(&self) -> usize {
+        self.partition_by.len() + self.order_by.len()
+    }
+
+    fn key(&self, row: &[Value], evaluate: &Evaluate) -> Result<Vec<Value>, ExecuteResult> {
+        self.partition_by.iter()
+            .chain(self.order_by.iter().map(|(expr, _)| expr))
+            .map(|expr| evaluate(expr, row))
+            .collect()
+    }
+}
+
+/// Computes the window calls of a query over its rows. The rows are sorted by the PARTITION BY
+/// then ORDER BY values of the first window, and read back one partition at a time; once a
+/// partition is buffered, the value of every call over that window is appended to its rows.
+/// The rows then go through a sort for the next window, and out after the last.
+pub struct WindowStage<'a> {
+    calls: Vec<WindowCall>,
+    passes: Vec<Pass>,
+    /// Evaluates an expression of a call or its window against a row of the query.
+    evaluate: &'a Evaluate<'a>,
+    sorter: Sorter,
+}
+
+impl<'a> WindowStage<'a> {
+    /// `column_collation` gives the collation of a column of the rows, which PARTITION BY and
+    /// ORDER BY terms naming it take.
+    pub fn new(calls: &[Expr], evaluate: &'a Evaluate<'a>, column_collation: &dyn Fn(&str) -> Option<Collation>)
+               -> Result<WindowStage<'a>, ExecuteResult> {
+        let mut passes: Vec<Pass> = Vec::new();
+        for (i, call) in calls.iter().enumerate() {
+            let window = match call {
+                Expr::Window { window, .. } => window,
+                _ => unreachable!(),
+            };
+            let order_by: Vec<(Expr, bool)> = window.order_by.iter()
+                .map(|term| (term.expr.clone(), term.descending))
+                .collect();
+            match passes.iter_mut().find(|pass| pass.partition_by == window.partition_by && pass.order_by == order_by) {
+                Some(pass) => pass.calls.push(i),
+                None => {
+                    let collations = window.partition_by.iter()
+                        .chain(order_by.iter().map(|(expr, _)| expr))
+                        .map(|expr| expr.collation(column_collation))
+                        .collect();
+                    passes.push(Pass { partition_by: window.partition_by.clone(), order_by, collations, calls: vec![i] });
+                }
+            }
+        }
+        let calls = calls.iter().map(WindowCall::from_expr).collect::<Result<Vec<WindowCall>, ExecuteResult>>()?;
+        let sorter = passes[0].sorter();
+        Ok(WindowStage { calls, passes, evaluate, sorter })
+    }
+
+    /// Take in a row of the query, which gets a slot for the value of every call.
+    pub fn insert(&mut self, mut row: Vec<Value>) -> Result<(), ExecuteResult> {
+        row.extend(self.calls.iter().map(|_| Value::Null));
+        let key = self.passes[0].key(&row, self.evaluate)?;
+        self.sorter.insert(key.clone(), [key, row].concat())
+    }
+
+    /// Compute every call and hand the rows to `output`, each ending with the value of every
+    /// call, until it returns false.
+    pub fn finish(self, output: &mut dyn FnMut(Vec<Value>) -> Result<bool, ExecuteResult>) -> Result<(), ExecuteResult> {
+        let WindowStage { calls, passes, evaluate, mut sorter } = self;
+        for (i, pass) in passes.iter().enumerate() {
+            let mut next = passes.get(i + 1).map(|next| (next, next.sorter()));
+            let key_len = pass.key_len();
+            let partition_len = pass.partition_by.len();
+            let mut partition: Vec<(Vec<Value>, Vec<Value>)> = Vec::new();
+            let mut records = sorter.finish()?;
+            loop {
+                let record = records.next().transpose()?;
+                let partition_ends = match (&record, partition.first()) {
+                    (Some(record), Some((key, _))) => !pass.equal(record, key, 0..partition_len),
+                    (None, Some(_)) => true,
+                    (_, None) => false,
+                };
+                if partition_ends {
+                    let mut rows = std::mem::take(&mut partition);
+                    compute_partition(&calls, pass, &mut rows, evaluate)?;
+                    for (_, row) in rows {
+                        match &mut next {
+                            Some((next, sorter)) => {
+                                let key = next.key(&row, evaluate)?;
+                                sorter.insert(key.clone(), [key, row].concat())?;
+                            }
+                            None => {
+                                if !output(row)? {
+                                    return Ok(());
+                                }
+                            }
+                        }
+                    }
+                }
+                match record {
+                    Some(mut key) => {
+                        let row = key.split_off(key_len);
+                        partition.push((key, row));
+                    }
+                    None => break,
+                }
+            }
+            match next {
+                Some((_, next_sorter)) => sorter = next_sorter,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rows of a partition in window order, each with its PARTITION BY then ORDER BY values.
+type Partition = [(Vec<Value>, Vec<Value>)];
+
+/// Fill in the slots of the pass's calls for every row of a partition.
+fn compute_partition(calls: &[WindowCall], pass: &Pass, partition: &mut Partition,
+                     evaluate: &Evaluate) -> Result<(), ExecuteResult> {
+    let order_start = pass.partition_by.len();
+    // Rows with equal ORDER BY values are peers; without ORDER BY, every row is.
+    let mut peers = Vec::with_capacity(partition.len());
+    let mut peer_groups = Vec::with_capacity(partition.len());
+    let mut start = 0;
+    while start < partition.len() {
+        let end = (start..partition.len())
+            .find(|&j| !pass.equal(&partition[j].0, &partition[start].0, order_start..pass.key_len()))
+            .unwrap_or(partition.len());
+        peers.extend((start..end).map(|_| (start, end)));
+        let group = peer_groups.last().map_or(1, |group| group + 1);
+        peer_groups.extend((start..end).map(|_| group));
+        start = end;
+    }
+    let descending = pass.order_by.first().is_some_and(|(_, descending)| *descending);
+    for &c in &pass.calls {
+        let call = &calls[c];
+        let args = partition.iter()
+            .map(|(_, row)| call.args.iter().map(|arg| evaluate(arg, row)).collect())
+            .collect::<Result<Vec<Vec<Value>>, ExecuteResult>>()?;
+        let frame = |i: usize| frame_bounds(&call.frame, partition, order_start, descending, peers[i], i);
+        let len = partition.len();
+        let values: Vec<Value> = match &call.function {
+            Function::Window(WindowFunc::RowNumber) => (0..len).map(|i| Value::Integer(i as i64 + 1)).collect(),
+            Function::Window(WindowFunc::Rank) => peers.iter().map(|(start, _)| Value::Integer(*start as i64 + 1)).collect(),
+            Function::Window(WindowFunc::DenseRank) => peer_groups.iter().map(|group| Value::Integer(*group)).collect(),
+            Function::Window(WindowFunc::PercentRank) => peers.iter()
+                .map(|(start, _)| {
+                    let rank = if len > 1 { *start as f64 / (len - 1) as f64 } else { 0.0 };
+                    Value::Real(rank)
+                })
+                .collect(),
+            Function::Window(WindowFunc::CumeDist) => peers.iter()
+                .map(|(_, end)| Value::Real(*end as f64 / len as f64))
+                .collect(),
+            Function::Window(WindowFunc::Ntile) => (0..len)
+                .map(|i| {
+                    let buckets = match args[i][0].to_integer() {
+                        Some(buckets) if buckets > 0 => buckets as usize,
+                        _ => return Err(ExecuteDatatypeMismatch),
+                    };
+                    // The first `len % buckets` buckets take one row more than the rest.
+                    let (size, larger) = (len / buckets, len % buckets);
+                    let bucket = if i < larger * (size + 1) {
+                        i / (size + 1)
+                    } else {
+                        larger + (i - larger * (size + 1)) / size
+                    };
+                    Ok(Value::Integer(bucket as i64 + 1))
+                })
+                .collect::<Result<Vec<Value>, ExecuteResult>>()?,
+            Function::Window(func @ (WindowFunc::Lag | WindowFunc::Lead)) => (0..len)
+                .map(|i| {
+                    let offset = match args[i].get(1) {
+                        Some(offset) => offset.to_integer().ok_or(ExecuteDatatypeMismatch)?,
+                        None => 1,
+                    };
+                    let target = if *func == WindowFunc::Lag { i as i64 - offset } else { i as i64 + offset };
+                    Ok(match usize::try_from(target).ok().filter(|&target| target < len) {
+                        Some(target) => args[target][0].clone(),
+                        None => args[i].get(2).cloned().unwrap_or(Value::Null),
+                    })
+                })
+                .collect::<Result<Vec<Value>, ExecuteResult>>()?,
+            Function::Window(WindowFunc::FirstValue) => (0..len)
+                .map(|i| {
+                    let (start, end) = frame(i);
+                    if start < end { args[start][0].clone() } else { Value::Null }
+                })
+                .collect(),
+            Function::Window(WindowFunc::LastValue) => (0..len)
+                .map(|i| {
+                    let (start, end) = frame(i);
+                    if start < end { args[end - 1][0].clone() } else { Value::Null }
+                })
+                .collect(),
+            Function::Window(WindowFunc::NthValue) => (0..len)
+                .map(|i| {
+                    let n = match args[i][1].to_integer() {
+                        Some(n) if n > 0 => n as usize,
+                        _ => return Err(ExecuteDatatypeMismatch),
+                    };
+                    let (start, end) = frame(i);
+                    Ok(if start + n - 1 < end { args[start + n - 1][0].clone() } else { Value::Null })
+                })
+                .collect::<Result<Vec<Value>, ExecuteResult>>()?,
+            Function::Aggregate(func) if call.frame.start == FrameBound::UnboundedPreceding => {
+                // Every frame starts at the partition's first row and ends no earlier than the
+                // last, so one accumulator takes in each row as the frames reach it.
+                let mut accumulator = Accumulator::new(func, &Collation::Binary);
+                let mut stepped = 0;
+                let mut values = Vec::with_capacity(len);
+                for i in 0..len {
+                    let (_, end) = frame(i);
+                    while stepped < end {
+                        accumulator.step(&args[stepped])?;
+                        stepped += 1;
+                    }
+                    values.push(accumulator.value()?);
+                }
+                values
+            }
+            Function::Aggregate(func) => (0..len)
+                .map(|i| {
+                    let (start, end) = frame(i);
+                    let mut accumulator = Accumulator::new(func, &Collation::Binary);
+                    for args in args.iter().take(end).skip(start) {
+                        accumulator.step(args)?;
+                    }
+                    accumulator.value()
+                })
+                .collect::<Result<Vec<Value>, ExecuteResult>>()?,
+        };
+        for ((_, row), value) in partition.iter_mut().zip(values) {
+            let slot = row.len() - calls.len() + c;
+            row[slot] = value;
+        }
+    }
+    Ok(())
+}
+
+/// The rows of the frame of the `i`th row of a partition, as a range of positions that is
+/// empty when the frame holds no rows. `peers` is the range of the row's peers.
+fn frame_bounds(frame: &Frame, partition: &Partition, order_start: usize, descending: bool,
+                peers: (usize, usize), i: usize) -> (usize, usize) {
+    let len = partition.len();
+    let (start, end) = match frame.unit {
+        FrameUnit::Rows => {
+            let start = match frame.start {
+                FrameBound::UnboundedPreceding => 0,
+                FrameBound::Preceding(offset) => i.saturating_sub(offset as usize),
+                FrameBound::CurrentRow => i,
+                FrameBound::Following(offset) => i.saturating_add(offset as usize),
+                FrameBound::UnboundedFollowing => len,
+            };
+            let end = match frame.end {
+                FrameBound::UnboundedPreceding => 0,
+                FrameBound::Preceding(offset) => (i + 1).saturating_sub(offset as usize),
+                FrameBound::CurrentRow => i + 1,
+                FrameBound::Following(offset) => i.saturating_add(offset as usize).saturating_add(1),
+                FrameBound::UnboundedFollowing => len,
+            };
+            (start, end)
+        }
+        FrameUnit::Range => {
+            // An offset bounds how far the single ORDER BY value of a row may be from the current
+            // row's, measured in the direction the rows are sorted. A NULL is only in range of a NULL.
+            let in_range = |j: usize, bound: &dyn Fn(f64) -> bool| {
+                let (current, value) = (&partition[i].0[order_start], &partition[j].0[order_start]);
+                match (current.to_real(), value.to_real()) {
+                    _ if current.is_null() || value.is_null() => current.is_null() && value.is_null(),
+                    (Some(current), Some(value)) => bound(if descending { current - value } else { value - current }),
+                    _ => false,
+                }
+            };
+            let first = |bound: &dyn Fn(f64) -> bool| (0..len).find(|&j| in_range(j, bound)).unwrap_or(len);
+            let last = |bound: &dyn Fn(f64) -> bool| (0..len).rev().find(|&j| in_range(j, bound)).map_or(0, |j| j + 1);
+            let start = match frame.start {
+                FrameBound::UnboundedPreceding => 0,
+                FrameBound::Preceding(offset) => first(&|distance| distance >= -(offset as f64)),
+                FrameBound::CurrentRow => peers.0,
+                FrameBound::Following(offset) => first(&|distance| distance >= offset as f64),
+                FrameBound::UnboundedFollowing => len,
+            };
+            let end = match frame.end {
+                FrameBound::UnboundedPreceding => 0,
+                FrameBound::Preceding(offset) => last(&|distance| distance <= -(offset as f64)),
+                FrameBound::CurrentRow => peers.1,
+                FrameBound::Following(offset) => last(&|distance| distance <= offset as f64),
+                FrameBound::UnboundedFollowing => len,
+            };
+            (start, end)
+        }
+    };
+    let end = end.min(len);
+    (start.min(end), end)
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use common::typed_db;
+
+#[test]
+fn columns_convert_values_by_affinity() {
+    let db = typed_db();
+    let lines = db.query(&[
+        "select typeof(i), typeof(r), typeof(n), typeof(x), typeof(b) from t",
+        "select i, r, n, x, hex(b) from t",
+    ]);
+    assert_eq!(lines, [
+        "integer, real, integer, text, text",
+        "text, real, integer, text, blob",
+        "integer, text, text, null, integer",
+        "12, 3.0, 4, 5, 37",
+        "a1, 2.0, 100, 1.5, 00FF",
+        "3, x, 0x10, , 31",
+    ]);
+}
+
+#[test]
+fn values_compare_and_sort_by_storage_class() {
+    let db = typed_db();
+    let lines = db.query(&[
+        "select 1 < 'a', 2 > x'00', 10 = '10', i = '12', x = 5 from t order by rowid limit 1",
+        "select i from t order by i",
+        "select 1.0, 1e20, 0.1 + 0.2, -0.0, 5 / 2, 5 / 2.0, 9007199254740993.0",
+    ]);
+    assert_eq!(lines, [
+        "1, 0, 0, 1, 1",
+        "3", "12", "a1",
+        "1.0, 1.0e+20, 0.3, 0.0, 2, 2.5, 9.00719925474099e+15",
+    ]);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{pay_db, TempDb};
+
+#[test]
+fn aggregates_per_group_with_having() {
+    let db = pay_db();
+    let lines = db.query(&[
+        "select dept, count(*), count(pay), sum(pay), avg(pay), min(pay), max(pay) from s group by dept",
+        "select dept, sum(pay) from s group by dept having sum(pay) > 6 order by 2 desc",
+        "select dept, group_concat(name), group_concat(pay, '-') from s group by dept",
+        "select dept, max(pay), name from s group by dept",
+        "select count(distinct dept), max(pay) + 1 from s",
+    ]);
+    assert_eq!(lines, [
+        "a, 3, 3, 70, 23.3333333333333, 10, 30",
+        "b, 3, 2, 13, 6.5, 5, 8",
+        "c, 1, 1, 7, 7.0, 7, 7",
+        "a, 70",
+        "b, 13",
+        "c, 7",
+        "a, x,y,q, 10-30-30",
+        "b, z,w,u, 5-8",
+        "c, v, 7",
+        "a, 30, y",
+        "b, 8, u",
+        "c, 7, v",
+        "3, 31",
+    ]);
+}
+
+#[test]
+fn aggregates_of_no_rows() {
+    let db = pay_db();
+    let lines = db.query(&[
+        "select count(*), max(pay) is null from s where pay > 100",
+        "select dept, count(*) from s where pay > 100 group by dept",
+    ]);
+    assert_eq!(lines, ["0, 1"]);
+}
+
+#[test]
+fn count_of_a_table_reads_leaf_headers() {
+    let db = pay_db();
+    let lines = db.query(&["select count(*) from s", "explain select count(*) from s"]);
+    assert_eq!(lines[0], "7");
+    assert!(lines.iter().any(|line| line.contains("CountCells")));
+}
+
+#[test]
+fn many_groups_spill_to_partitions() {
+    let db = TempDb::with_users(200);
+    let lines = db.query(&[
+        "select count(*) from users a cross join users b group by a.id, b.id % 50 having count(*) != 4",
+        "select a.id, b.id % 50, count(*), sum(b.id) from users a cross join users b group by a.id, b.id % 50 \
+         order by 4 desc, 1 limit 2",
+    ]);
+    assert_eq!(lines, ["1, 0, 4, 500", "2, 0, 4, 500"]);
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use common::TempDb;
+
+#[test]
+fn integer_overflow_gives_a_real() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "select 9223372036854775807 + 1, typeof(9223372036854775807 + 1)",
+        "select 9223372036854775807 * 2, -9223372036854775807 - 10",
+        "select -(-9223372036854775807 - 1), (-9223372036854775807 - 1) / -1",
+        "insert 1 alice alice@example.com",
+        "select id + 9223372036854775807 from users where id + 9223372036854775807 > 0",
+    ]);
+    assert_eq!(lines, [
+        "9.22337203685478e+18, real",
+        "1.84467440737096e+19, -9.22337203685478e+18",
+        "9.22337203685478e+18, 9.22337203685478e+18",
+        "9.22337203685478e+18",
+    ]);
+}
+
+#[test]
+fn sum_of_integers_that_overflows_fails() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (n integer)",
+        "insert into t values (9223372036854775807), (1)",
+        "select sum(n) from t",
+        "select abs(-9223372036854775807 - 1)",
+    ]);
+    assert_eq!(lines, ["Error: integer overflow", "Error: integer overflow"]);
+}
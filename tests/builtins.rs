@@ -0,0 +1,86 @@
+mod common;
+
+use common::TempDb;
+
+#[test]
+fn printf_clamps_width_and_precision() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "select length(printf('%*d', 9999999999, 1)), length(printf('%-*d', -9999999999, 1))",
+        "select length(printf('%.999999999f', 1.0)), length(printf('%99999999999999999999999d', 1))",
+        "select printf('%5.2f|%-4d|%.*s', 3.14159, 7, 2, 'abc')",
+    ]);
+    assert_eq!(lines, ["1000000, 1000000", "1000002, 1000000", " 3.14|7   |ab"]);
+}
+
+#[test]
+fn dates_out_of_range_are_null() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "select date('2024-01-01', '+9999999999999 years') is null, date('2024-01-01', '+1e300 days') is null",
+        "select datetime(1e300, 'unixepoch') is null, date('2024-01-01', '+9223372036854775807 months') is null",
+        "select date('2024-01-01', '+10000 years', '-10000 years') is null, date('2024-01-01', '-3000 years', '+3000 years')",
+    ]);
+    assert_eq!(lines, ["1, 1", "1, 1", "1, 2024-01-01"]);
+}
+
+#[test]
+fn strftime_counts_weeks_from_sunday_and_monday() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "select strftime('%U %W', '2024-01-01'), strftime('%U %W', '2023-01-01'), strftime('%U %W', '2024-01-07')",
+        "select strftime('%U %W', '2023-12-31')",
+    ]);
+    assert_eq!(lines, ["00 01, 01 00, 01 01", "53 52"]);
+}
+
+#[test]
+fn string_functions() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "select length('héllo'), length(x'0102'), lower('ÀB'), upper('abc'), substr('hello', 2, 3), \
+         substr('hello', -3), substr('hello', 0, 2)",
+        "select trim('  a  '), ltrim('xxa', 'x'), rtrim('a  '), replace('banana', 'an', 'AN'), \
+         instr('banana', 'nan'), instr('a', '')",
+        "select hex('A'), hex(255), typeof(length(null))",
+        "select substr('abc')",
+    ]);
+    assert_eq!(lines, [
+        "5, 2, Àb, ABC, ell, llo, h",
+        "a, a, a, bANANa, 3, 1",
+        "41, 323535, null",
+        "Error: wrong number of arguments to function substr()",
+    ]);
+}
+
+#[test]
+fn math_null_handling_and_printf() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "select abs(-3), abs(-2.5), round(2.5), round(-2.5), round(1.005, 2), round(3.14159, 3)",
+        "select coalesce(null, null, 3), ifnull(null, 'x'), typeof(nullif(1, 1)), nullif(1, 2)",
+        "select printf('%d|%5s|%-5s|%05.1f|%x|%q|%Q', 42, 'ab', 'cd', 3.14159, 255, 'it''s', null), format('%,d', 1234567)",
+        "select typeof(random()), random() = random()",
+    ]);
+    assert_eq!(lines, [
+        "3, 2.5, 3.0, -3.0, 1.0, 3.142",
+        "3, x, null, 1",
+        "42|   ab|cd   |003.1|ff|it''s|NULL, 1,234,567",
+        "integer, 0",
+    ]);
+}
+
+#[test]
+fn date_and_time_functions() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "select date('2024-02-30'), time('12:34:56.789'), datetime('2024-01-01 10:00', '+90 minutes')",
+        "select julianday('2000-01-01'), strftime('%Y-%j %H', '2024-12-31 23:00'), date('2024-01-31', '+1 month')",
+        "select date(1700000000, 'unixepoch'), date('2024-05-15', 'start of month', 'weekday 0')",
+    ]);
+    assert_eq!(lines, [
+        "2024-03-01, 12:34:56, 2024-01-01 11:30:00",
+        "2451544.5, 2024-366 23, 2024-03-02",
+        "2023-11-14, 2024-05-05",
+    ]);
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use std::fs;
+use common::TempDb;
+
+#[test]
+fn catalog_spanning_pages_survives_reopen() {
+    let db = TempDb::new();
+    let creates: Vec<String> = (0..60)
+        .map(|i| format!("create table table_with_a_long_name_{} (id integer primary key, label text not null default 'none')", i))
+        .collect();
+    let creates: Vec<&str> = creates.iter().map(String::as_str).collect();
+    db.query(&creates);
+    db.query(&["insert into table_with_a_long_name_59 values (1, 'last')"]);
+    let lines = db.query(&[
+        "select label from table_with_a_long_name_59",
+        "select count(*) from table_with_a_long_name_0",
+    ]);
+    assert_eq!(lines, ["last", "0"]);
+}
+
+/// A page of a file from before the header, a leaf of the users table holding the rows.
+fn headerless_leaf(is_root: bool, rows: &[(u32, &str, &str)]) -> Vec<u8> {
+    let mut page = vec![0u8; 4096];
+    page[0] = 1;
+    page[1] = is_root as u8;
+    page[10..18].copy_from_slice(&rows.len().to_ne_bytes());
+    for (i, (id, username, email)) in rows.iter().enumerate() {
+        let cell = &mut page[26 + i * 295..26 + (i + 1) * 295];
+        cell[..4].copy_from_slice(&id.to_ne_bytes());
+        cell[4..8].copy_from_slice(&id.to_ne_bytes());
+        cell[8..8 + username.len()].copy_from_slice(username.as_bytes());
+        cell[40..40 + email.len()].copy_from_slice(email.as_bytes());
+    }
+    page
+}
+
+/// The root of a file from before the header whose users table is split over the leaves.
+fn headerless_root(children: &[(usize, u32)], right_child: usize) -> Vec<u8> {
+    let mut page = vec![0u8; 4096];
+    page[1] = 1;
+    page[10..18].copy_from_slice(&children.len().to_ne_bytes());
+    page[18..26].copy_from_slice(&right_child.to_ne_bytes());
+    for (i, (child, key)) in children.iter().enumerate() {
+        page[26 + i * 12..34 + i * 12].copy_from_slice(&child.to_ne_bytes());
+        page[34 + i * 12..38 + i * 12].copy_from_slice(&key.to_ne_bytes());
+    }
+    page
+}
+
+#[test]
+fn file_without_header_is_upgraded() {
+    let db = TempDb::new();
+    let backup = format!("{}.bak", db.path.display());
+    let _ = fs::remove_file(&backup);
+    let bytes = [
+        headerless_root(&[(1, 2)], 2),
+        headerless_leaf(false, &[(1, "ann", "ann@example.com"), (2, "bo", "bo@example.com")]),
+        headerless_leaf(false, &[(3, "cy", "cy@example.com")]),
+    ].concat();
+    fs::write(&db.path, &bytes).unwrap();
+    let lines = db.query(&["select * from users", "insert into users values (4, 'di', 'di@example.com')"]);
+    assert_eq!(lines, [
+        format!("Upgraded {} from before the file header; the original is kept as {}", db.path.display(), backup),
+        String::from("1, ann, ann@example.com"),
+        String::from("2, bo, bo@example.com"),
+        String::from("3, cy, cy@example.com"),
+    ]);
+    assert_eq!(fs::read(&backup).unwrap(), bytes);
+    let lines = db.query(&["select id, username from users where id > 2"]);
+    assert_eq!(lines, ["3, cy", "4, di"]);
+    fs::remove_file(&backup).unwrap();
+}
+
+#[test]
+fn empty_file_without_header_is_upgraded() {
+    let db = TempDb::new();
+    let backup = format!("{}.bak", db.path.display());
+    let _ = fs::remove_file(&backup);
+    fs::write(&db.path, headerless_leaf(true, &[])).unwrap();
+    let lines = db.query(&["select count(*) from users"]);
+    assert_eq!(lines[1..], ["0"]);
+    fs::remove_file(&backup).unwrap();
+}
+
+#[test]
+fn file_without_header_that_cannot_be_read_is_left_alone() {
+    let db = TempDb::new();
+    // The root's only child is past the end of the file.
+    let bytes = [headerless_root(&[], 9), headerless_leaf(false, &[])].concat();
+    fs::write(&db.path, &bytes).unwrap();
+    let lines = db.query(&["select * from users"]);
+    assert_eq!(lines, ["Error: file is not a database of this format, nor one from before its header: page 9 is out of range or read twice"]);
+    assert_eq!(fs::read(&db.path).unwrap(), bytes);
+    assert!(!std::path::Path::new(&format!("{}.bak", db.path.display())).exists());
+}
+
+#[test]
+fn file_that_is_no_database_is_refused() {
+    let db = TempDb::new();
+    fs::write(&db.path, vec![b'x'; 4096]).unwrap();
+    let lines = db.query(&["select * from users"]);
+    assert_eq!(lines, ["Error: file is not a database of this format"]);
+}
+
+#[test]
+fn file_of_an_older_version_is_refused() {
+    let db = TempDb::with(&["create table t (id integer primary key)"]);
+    let mut bytes = fs::read(&db.path).unwrap();
+    bytes[16..20].copy_from_slice(&2u32.to_be_bytes());
+    fs::write(&db.path, bytes).unwrap();
+    let lines = db.query(&["select * from t"]);
+    assert_eq!(lines[0], "Error: file format version 2 was written by an older build (this build reads version 3); \
+                          open it with that build and .save it as a SQLite file, which this build reads");
+}
+
+#[test]
+fn file_of_another_version_is_refused() {
+    let db = TempDb::with(&["create table t (id integer primary key)"]);
+    let mut bytes = fs::read(&db.path).unwrap();
+    bytes[16..20].copy_from_slice(&99u32.to_be_bytes());
+    fs::write(&db.path, bytes).unwrap();
+    let lines = db.query(&["select * from t"]);
+    assert_eq!(lines[0], "Error: unsupported file format version 99 (this build reads version 3)");
+}
+
+#[test]
+fn truncated_catalog_is_refused() {
+    let db = TempDb::with(&["create table t (id integer primary key)"]);
+    let mut bytes = fs::read(&db.path).unwrap();
+    // Claim a catalog far longer than the file holds.
+    bytes[20..24].copy_from_slice(&100_000u32.to_be_bytes());
+    fs::write(&db.path, bytes).unwrap();
+    let lines = db.query(&["select * from t"]);
+    assert!(lines[0].starts_with("Error: database disk image is malformed"), "{:?}", lines);
+}
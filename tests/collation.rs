@@ -0,0 +1,188 @@
+mod common;
+
+use std::cell::RefCell;
+use sqlite_rust::result::ExecuteResult::*;
+use sqlite_rust::value::Value;
+use common::{close, execute, execute_all, record_calls, reverse_db, text, TempDb};
+
+/// The recorded calls in order. `record` runs as rows are read, before any sort, so a test
+/// that checks an order records each row's place in it first.
+fn sorted(calls: &RefCell<Vec<Vec<Value>>>) -> Vec<Vec<Value>> {
+    let mut calls = calls.borrow().clone();
+    calls.sort();
+    calls
+}
+
+/// The insert of ten names in two cases each into `g`, which crossed with 300 users makes
+/// groups enough to spill.
+fn names_insert() -> String {
+    let values: Vec<String> = (1..=10).map(|i| format!("('name{}'), ('NAME{}')", i, i)).collect();
+    format!("insert into g values {}", values.join(", "))
+}
+
+#[test]
+fn unregistered_collation_fails_every_statement_on_its_table() {
+    let db = reverse_db();
+    let mut connection = db.connect();
+    let no_such_collation = ExecuteNoSuchCollation(String::from("reverse"));
+    for sql in [
+        "select s from t",
+        "select count(*) from users, t",
+        "select id from users where id in (select id from t)",
+        "insert into t (s) values ('c')",
+        "update t set s = 'c'",
+        "delete from t",
+        "create index t_s2 on t (s)",
+        "select 'a' = 'b' collate reverse from users",
+    ] {
+        assert_eq!(execute(&mut connection, sql), no_such_collation, "{}", sql);
+    }
+    execute_all(&mut connection, &["select id from users"]);
+    connection.create_collation("reverse", |a, b| b.cmp(a));
+    let calls = record_calls(&mut connection);
+    // The statements that failed wrote nothing.
+    execute_all(&mut connection, &[
+        "select record(id, s) from t",
+        "insert into t (s) values ('c')",
+        "select record(s) from t where s > 'b'",
+        "select record(id) from t where s = 'c'",
+    ]);
+    assert_eq!(*calls.borrow(), [
+        vec![Value::Integer(1), text("a")],
+        vec![Value::Integer(2), text("b")],
+        vec![text("a")],
+        vec![Value::Integer(3)],
+    ]);
+    calls.borrow_mut().clear();
+    execute_all(&mut connection, &["select record(row_number() over (order by s), s) from t"]);
+    assert_eq!(sorted(&calls), [
+        vec![Value::Integer(1), text("c")],
+        vec![Value::Integer(2), text("b")],
+        vec![Value::Integer(3), text("a")],
+    ]);
+}
+
+#[test]
+fn collations_are_registered_per_connection() {
+    let (a, b) = (TempDb::new(), TempDb::new());
+    let (mut a, mut b) = (a.connect(), b.connect());
+    a.create_collation("reverse", |a, b| b.cmp(a));
+    let calls = record_calls(&mut a);
+    execute_all(&mut a, &[
+        "create table t (s text collate reverse)",
+        "insert into t values ('a'), ('c'), ('b')",
+        "select record(row_number() over (order by s), s, s = 'B', s = 'b', s < 'b') from t",
+    ]);
+    assert_eq!(sorted(&calls), [
+        vec![Value::Integer(1), text("c"), Value::Integer(0), Value::Integer(0), Value::Integer(1)],
+        vec![Value::Integer(2), text("b"), Value::Integer(0), Value::Integer(1), Value::Integer(0)],
+        vec![Value::Integer(3), text("a"), Value::Integer(0), Value::Integer(0), Value::Integer(0)],
+    ]);
+    assert_eq!(execute(&mut b, "create table t (s text collate reverse)"), ExecuteNoSuchCollation(String::from("reverse")));
+    assert_eq!(execute(&mut b, "select 1 order by 'x' collate reverse"), ExecuteNoSuchCollation(String::from("reverse")));
+}
+
+#[test]
+fn usernames_compare_nocase() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "insert into users values (1, 'Alice', 'a@example.com'), (2, 'bob', 'b@example.com'), (3, 'alice', 'c@example.com')",
+        "select id from users where username = 'ALICE'",
+        "select id from users where username = 'alice' collate binary",
+        "select username from users order by username",
+    ]);
+    assert_eq!(lines, ["1", "3", "3", "Alice", "alice", "bob"]);
+}
+
+#[test]
+fn grouping_and_aggregates_use_the_column_collation() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (id integer primary key, username text collate nocase)",
+        "insert into t (username) values ('Alice'), ('bob'), ('alice'), ('ALICE')",
+        "select username, count(*) from t group by username",
+        "select count(distinct username), min(username), max(username) from t",
+        "select username from t union select 'ALICE'",
+        "select username collate binary, count(*) from t group by 1",
+    ]);
+    assert_eq!(lines, ["Alice, 3", "bob, 1", "2, Alice, bob", "ALICE", "bob", "ALICE, 1", "Alice, 1", "alice, 1", "bob, 1"]);
+}
+
+#[test]
+fn windows_use_the_column_collation() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (s text collate nocase)",
+        "insert into t values ('B'), ('a'), ('c'), ('b')",
+        "select s, row_number() over (order by s), rank() over (order by s), count(*) over (partition by s) from t",
+    ]);
+    assert_eq!(lines, ["a, 1, 1, 1", "B, 2, 2, 2", "b, 3, 2, 2", "c, 4, 4, 1"]);
+}
+
+#[test]
+fn spilled_groups_use_the_column_collation() {
+    let db = TempDb::with_users(300);
+    db.query(&["create table g (s text collate nocase)", &names_insert()]);
+    let lines = db.query(&[
+        "select count(*) from users cross join g group by g.s, users.id having count(*) != 2",
+        "select count(distinct g.s) from users cross join g",
+        "select g.s, users.id, count(*) from users cross join g group by g.s, users.id order by 1, 2 limit 2",
+    ]);
+    assert_eq!(lines, ["10", "name1, 1, 2", "name1, 2, 2"]);
+}
+
+#[test]
+fn groups_under_a_custom_collation_are_found_past_the_spill_depth() {
+    let db = TempDb::with_users(300);
+    let mut connection = db.connect();
+    connection.create_collation("folded", |a, b| a.to_lowercase().cmp(&b.to_lowercase()));
+    execute_all(&mut connection, &["create table g (s text collate folded)", &names_insert()]);
+    let calls = record_calls(&mut connection);
+    execute_all(&mut connection, &["select record(count(*)) from users cross join g group by g.s, users.id having count(*) != 2"]);
+    assert!(calls.borrow().is_empty(), "{:?}", calls.borrow());
+    execute_all(&mut connection, &[
+        "select record(row_number() over (order by g.s), lower(g.s), count(*)) from users cross join g group by g.s",
+    ]);
+    assert_eq!(sorted(&calls)[..3], [
+        vec![Value::Integer(1), text("name1"), Value::Integer(600)],
+        vec![Value::Integer(2), text("name10"), Value::Integer(600)],
+        vec![Value::Integer(3), text("name2"), Value::Integer(600)],
+    ]);
+    assert_eq!(calls.borrow().len(), 10);
+    close(connection);
+}
+
+#[test]
+fn declared_binary_wins_when_it_comes_first() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (a text collate nocase, b text)",
+        "create table q (c text)",
+        "insert into t values ('X', 'x')",
+        "insert into q values ('x')",
+        "select b = a, a = b, b in (a), a in (b), b between a and a from t",
+        "select count(*) from q join t on q.c = t.a",
+        "select count(*) from q join t on t.a = q.c",
+        "select (select count(*) from q where t.a = q.c), (select count(*) from q where q.c = t.a) from t",
+    ]);
+    assert_eq!(lines, ["0, 1, 0, 1, 0", "0", "1", "1, 0"]);
+}
+
+#[test]
+fn terms_naming_result_columns_take_a_collate() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (s text)",
+        "insert into t values ('b'), ('A'), ('c')",
+        "select s from t order by 1 collate nocase",
+        "select s as x from t order by x collate nocase desc",
+        "select 'b' union all select 'A' union all select 'c' order by 1 collate nocase",
+        "select s from t order by 2 collate nocase",
+    ]);
+    assert_eq!(lines, [
+        "A", "b", "c",
+        "c", "b", "A",
+        "A", "b", "c",
+        "Error: ORDER BY term out of range - should be between 1 and 1",
+    ]);
+}
@@ -0,0 +1,223 @@
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use assert_cmd::Command;
+use sqlite_rust::result::ExecuteResult;
+use sqlite_rust::statement::{execute_statement, prepare_statement};
+use sqlite_rust::table::{db_close, db_open, Connection};
+use sqlite_rust::value::Value;
+
+static NEXT_DB_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A database file in the temporary directory, removed when dropped.
+pub struct TempDb {
+    pub path: PathBuf,
+}
+
+impl TempDb {
+    pub fn new() -> TempDb {
+        let id = NEXT_DB_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("sqlite-rs-test-{}-{}.db", std::process::id(), id));
+        let _ = fs::remove_file(&path);
+        TempDb { path }
+    }
+
+    /// A new database with the setup statements run on it, every one of which must succeed.
+    pub fn with(setup: &[&str]) -> TempDb {
+        let db = TempDb::new();
+        let lines = db.query(setup);
+        assert!(lines.is_empty(), "setup printed {:?}", lines);
+        db
+    }
+
+    /// A new database whose users table holds rows 1 to `count`.
+    pub fn with_users(count: usize) -> TempDb {
+        let db = TempDb::new();
+        db.fill_users(count);
+        db
+    }
+
+    /// Run the commands in a session of the shell on the database, then `.exit`, returning the
+    /// lines it printed without its prompts.
+    pub fn run(&self, commands: &[&str]) -> Vec<String> {
+        let mut input = commands.join("\n");
+        input.push_str("\n.exit\n");
+        let output = Command::cargo_bin("sqlite-rust").unwrap()
+            .arg(&self.path)
+            .write_stdin(input)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).unwrap()
+            .replace("Sqlite-rs >", "\n")
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /// Make the database a SQLite file, written by the sqlite3 shell from the SQL.
+    pub fn create_with_sqlite3(&self, sql: &str) {
+        let status = std::process::Command::new("sqlite3").arg(&self.path).arg(sql).status()
+            .expect("the sqlite3 shell is on the PATH");
+        assert!(status.success());
+    }
+
+    /// The lines the sqlite3 shell prints running the SQL on the database.
+    pub fn query_with_sqlite3(&self, sql: &str) -> Vec<String> {
+        let output = std::process::Command::new("sqlite3").arg(&self.path).arg(sql).output()
+            .expect("the sqlite3 shell is on the PATH");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap().lines().map(String::from).collect()
+    }
+
+    /// Fill the built-in users table with rows 1 to `count`.
+    pub fn fill_users(&self, count: usize) {
+        let inserts: Vec<String> = (1..=count)
+            .map(|i| format!("insert into users values ({}, 'user{}', 'person{}@example.com')", i, i, i))
+            .collect();
+        let inserts: Vec<&str> = inserts.iter().map(String::as_str).collect();
+        self.query(&inserts);
+    }
+
+    /// As `run`, leaving out the `Executed.` every statement that succeeds ends with.
+    pub fn query(&self, commands: &[&str]) -> Vec<String> {
+        self.run(commands).into_iter().filter(|line| line != "Executed.").collect()
+    }
+
+    /// Open the database in this process, as a program embedding the engine does.
+    pub fn connect(&self) -> Connection {
+        db_open(self.path.to_str().unwrap())
+    }
+}
+
+/// Run a statement on an open connection.
+pub fn execute(connection: &mut Connection, sql: &str) -> ExecuteResult {
+    execute_statement(prepare_statement(sql).unwrap(), connection)
+}
+
+/// Run statements on an open connection, every one of which must succeed.
+pub fn execute_all(connection: &mut Connection, statements: &[&str]) {
+    for sql in statements {
+        assert_eq!(execute(connection, sql), ExecuteResult::ExecuteSuccess, "{}", sql);
+    }
+}
+
+/// A text value, as a test expects one.
+pub fn text(value: &str) -> Value {
+    Value::Text(String::from(value))
+}
+
+/// Register the function `record` on the connection, which keeps the arguments of every call
+/// as a row and returns NULL, so that a test can read back the values a query computed.
+pub fn record_calls(connection: &mut Connection) -> Rc<RefCell<Vec<Vec<Value>>>> {
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let recorded = calls.clone();
+    connection.create_scalar_function("record", -1, move |args| {
+        recorded.borrow_mut().push(args.to_vec());
+        Ok(Value::Null)
+    });
+    calls
+}
+
+/// Write an open connection's pages back to its file.
+pub fn close(mut connection: Connection) {
+    db_close(&mut connection);
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A table of values of every type in columns of every affinity.
+pub fn typed_db() -> TempDb {
+    TempDb::with(&[
+        "create table t (i integer, r real, n numeric, x text, b blob)",
+        "insert into t values ('12', '3', '4.0', 5, '7'), ('a1', 2, '1e2', 1.5, x'00ff'), (3.0, 'x', '0x10', null, 1)",
+    ])
+}
+
+/// Pay by department: `a` has a tie for the most, `b` a NULL and `c` a single row.
+pub fn pay_db() -> TempDb {
+    TempDb::with(&[
+        "create table s (dept text, name text, pay int)",
+        "insert into s values ('a', 'x', 10), ('a', 'y', 30), ('a', 'q', 30), ('b', 'z', 5), ('b', 'w', null), \
+         ('b', 'u', 8), ('c', 'v', 7)",
+    ])
+}
+
+/// Users and their orders, one of which belongs to no user.
+pub fn orders_db() -> TempDb {
+    TempDb::with(&[
+        "create table u (id integer primary key, name text)",
+        "create table o (oid integer primary key, uid int, item text, amount int)",
+        "insert into u values (1, 'ann'), (2, 'bo'), (3, 'cy')",
+        "insert into o values (10, 1, 'pen', 5), (11, 1, 'ink', 7), (12, 3, 'cup', 2), (13, 9, 'hat', 4)",
+    ])
+}
+
+/// Numbers and names, with a NULL in each column.
+pub fn fruit_db() -> TempDb {
+    TempDb::with(&[
+        "create table t (a int, b text)",
+        "insert into t values (1, 'apple'), (2, 'Banana'), (3, null), (null, 'cherry'), (5, 'date')",
+    ])
+}
+
+/// A file of several tables, one deep enough for interior pages, with an index of its own, the
+/// indexes SQLite makes for UNIQUE and PRIMARY KEY constraints, and a row longer than a page.
+pub fn sqlite_db() -> TempDb {
+    let db = TempDb::new();
+    db.create_with_sqlite3("
+        create table people (id integer primary key, name text, email text unique, age int);
+        create index people_name on people (name);
+        create table notes (code text primary key, body text);
+        create table plain (a text, b real);
+        with recursive n(i) as (select 1 union all select i + 1 from n where i < 3000)
+            insert into people select i, 'name' || (i % 100), 'e' || i || '@x', i % 90 from n;
+        insert into notes values ('long', printf('%.*c', 10000, 'x')), ('short', 'body');
+        insert into plain values ('one', 1), ('two', 2.5);
+    ");
+    db
+}
+
+/// WITHOUT ROWID tables keyed by text and by two columns, each holding rows out of key order.
+pub fn keyed_db() -> TempDb {
+    TempDb::with(&[
+        "create table kv (k text primary key, v int) without rowid",
+        "create table pair (a int, b text, c int, primary key (a, b)) without rowid",
+        "insert into kv values ('b', 2), ('a', 1), ('c', 3)",
+        "insert into pair values (1, 'y', 1), (1, 'x', 2), (0, 'z', 3)",
+    ])
+}
+
+/// A table and an index whose text is ordered by the collation `reverse`, which a connection
+/// must register to use them.
+pub fn reverse_db() -> TempDb {
+    let db = TempDb::new();
+    let mut connection = db.connect();
+    connection.create_collation("reverse", |a, b| b.cmp(a));
+    execute_all(&mut connection, &[
+        "create table t (id integer primary key, s text collate reverse)",
+        "insert into t (s) values ('a'), ('b')",
+        "create index t_s on t (s)",
+    ]);
+    close(connection);
+    db
+}
+
+/// JSON documents, one with nested arrays and objects and one with an empty array.
+pub fn docs_db() -> TempDb {
+    TempDb::with(&[
+        "create table d (id integer primary key, doc text)",
+        "insert into d values (1, '{\"a\": 1, \"b\": [1, 2, {\"c\": \"x\"}]}'), (2, '{\"a\": 2, \"b\": []}')",
+    ])
+}
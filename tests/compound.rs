@@ -0,0 +1,37 @@
+mod common;
+
+use common::TempDb;
+
+#[test]
+fn operators_compare_by_column_collation() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (a int, s text collate nocase)",
+        "insert into t values (1, 'Alice'), (2, 'alice'), (3, 'Bob'), (3, 'Bob'), (null, 'x')",
+        "select s from t union select 'ALICE'",
+        "select s from t intersect select 'BOB'",
+        "select s from t except select 'alice'",
+        "select a from t union all select 3 except select 1",
+    ]);
+    assert_eq!(lines, ["ALICE", "Bob", "x", "Bob", "Bob", "x", "2", "3"]);
+}
+
+#[test]
+fn large_sets_spill_to_temporary_files() {
+    let db = TempDb::with_users(200);
+    let lines = db.query(&[
+        "select a.id * 1000 + b.id from users a cross join users b union select 7 except select 1001 order by 1 limit 3",
+        "select a.id from users a cross join users b intersect select id from users where id > 198",
+    ]);
+    assert_eq!(lines, ["7", "1002", "1003", "199", "200"]);
+    let output = assert_cmd::Command::cargo_bin("sqlite-rust").unwrap()
+        .arg(&db.path)
+        .env("TMPDIR", "/nonexistent-directory")
+        .write_stdin("select a.id, b.id from users a cross join users b union select 1, 1\n.exit\n")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    assert!(String::from_utf8(output).unwrap().contains("Error: cannot create temporary file"));
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::TempDb;
+
+#[test]
+fn not_null_check_and_default_apply_on_insert_and_update() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (a integer primary key, b text not null, c int default 7, d real default (1.5 * 2), \
+         e int check (e > 0), constraint pos check (c >= 0))",
+        "insert into t (a, b) values (1, 'x')",
+        "insert into t (a, c) values (2, 1)",
+        "insert into t values (3, 'y', 1, 1, 0)",
+        "insert into t values (3, 'y', -1, 1, 1)",
+        "insert into t values (3, 'y', 1, 1, null)",
+        "update t set b = null where a = 1",
+        "update t set e = -5 where a = 3",
+        "select * from t",
+    ]);
+    assert_eq!(lines, [
+        "Error: NOT NULL constraint failed: t.b",
+        "Error: CHECK constraint failed: e > 0",
+        "Error: CHECK constraint failed: pos",
+        "Error: NOT NULL constraint failed: t.b",
+        "Error: CHECK constraint failed: e > 0",
+        "1, x, 7, 3.0, ",
+        "3, y, 1, 1.0, ",
+    ]);
+}
+
+#[test]
+fn constraints_survive_reopening() {
+    let db = TempDb::with(&["create table t (a int not null, b int default 4 check (b < 10))"]);
+    let lines = db.query(&[
+        "insert into t (b) values (1)",
+        "insert into t (a, b) values (1, 10)",
+        "insert into t (a) values (2)",
+        "select a, b from t",
+    ]);
+    assert_eq!(lines, [
+        "Error: NOT NULL constraint failed: t.a",
+        "Error: CHECK constraint failed: b < 10",
+        "2, 4",
+    ]);
+}
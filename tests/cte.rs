@@ -0,0 +1,30 @@
+mod common;
+
+use common::TempDb;
+
+#[test]
+fn outer_limit_stops_a_recursive_table() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "with recursive r(n) as (select 1 union all select n + 1 from r) select n from r limit 5",
+        "with recursive r(n) as (select 1 union all select n + 1 from r) select n from r where n % 100 = 0 limit 2",
+        "with recursive r(n) as (select 1 union all select n + 1 from r limit 2) select a.n, b.n from r a cross join r b",
+    ]);
+    assert_eq!(lines, ["1", "2", "3", "4", "5", "100", "200", "1, 1", "1, 2", "2, 1", "2, 2"]);
+}
+
+#[test]
+fn queue_order_by_walks_depth_first() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table org (name text, boss text)",
+        "insert into org values ('a', null), ('b', 'a'), ('c', 'a'), ('d', 'b'), ('e', 'c')",
+        "with recursive t(name, level) as (select name, 0 from org where boss is null \
+         union all select org.name, t.level + 1 from org join t on org.boss = t.name order by 2 desc) \
+         select name, level from t",
+        "with recursive t(name, level) as (select name, 0 from org where boss is null \
+         union all select org.name, t.level + 1 from org join t on org.boss = t.name) \
+         select name, level from t",
+    ]);
+    assert_eq!(lines, ["a, 0", "b, 1", "d, 2", "c, 1", "e, 2", "a, 0", "b, 1", "c, 1", "d, 2", "e, 2"]);
+}
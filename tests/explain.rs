@@ -0,0 +1,75 @@
+mod common;
+
+use common::TempDb;
+
+#[test]
+fn explain_query_plan_reports_scan_and_seek() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "explain query plan select * from users",
+        "explain query plan select * from users where id = 1",
+    ]);
+    assert_eq!(lines, ["QUERY PLAN", "`--SCAN users", "QUERY PLAN", "`--SEARCH users USING PRIMARY KEY (id=?)"]);
+}
+
+#[test]
+fn explain_lists_operations() {
+    let db = TempDb::new();
+    let lines = db.query(&["explain select * from users"]);
+    assert_eq!(lines[0], "addr  opcode            p1        comment");
+    assert!(lines.iter().any(|line| line.contains("ResultRow")));
+    assert!(lines.last().unwrap().contains("Halt"));
+}
+
+#[test]
+fn explain_keywords_ignore_case_and_spacing() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "EXPLAIN QUERY PLAN select * from users",
+        "explain  query\tplan select * from users",
+        "Explain select * from users",
+    ]);
+    assert_eq!(&lines[..4], ["QUERY PLAN", "`--SCAN users", "QUERY PLAN", "`--SCAN users"]);
+    assert_eq!(lines[4], "addr  opcode            p1        comment");
+}
+
+#[test]
+fn explain_without_statement_is_syntax_error() {
+    let db = TempDb::new();
+    assert_eq!(db.query(&["explain"]), ["Syntax error. Could not parse statement."]);
+}
+
+#[test]
+fn explain_insert_seeks_the_rowids_it_would_generate() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (a int, b text)",
+        "insert into t values (1, 'x'), (2, 'y')",
+        "explain insert into t values (3, 'z')",
+        "explain insert into users values (null, 'ann', 'ann@example.com'), (7, 'bo', 'bo@example.com'), (null, 'cy', 'cy@example.com')",
+        "explain insert into users values ('seven', 'bo', 'bo@example.com')",
+    ]);
+    assert_eq!(lines, [
+        "addr  opcode            p1        comment",
+        "0     Find              3         seek key=3",
+        "1     CheckDuplicateKey 3         fail if key=3 exists",
+        "2     LeafNodeInsert    3         insert cell key=3",
+        "3     Halt",
+        "addr  opcode            p1        comment",
+        "0     Find              1         seek key=1",
+        "1     CheckDuplicateKey 1         fail if key=1 exists",
+        "2     LeafNodeInsert    1         insert cell key=1",
+        "3     Find              7         seek key=7",
+        "4     CheckDuplicateKey 7         fail if key=7 exists",
+        "5     LeafNodeInsert    7         insert cell key=7",
+        "6     Find              8         seek key=8",
+        "7     CheckDuplicateKey 8         fail if key=8 exists",
+        "8     LeafNodeInsert    8         insert cell key=8",
+        "9     Halt",
+        "addr  opcode            p1        comment",
+        "0     Find                        seek key",
+        "1     CheckDuplicateKey           fail if key exists",
+        "2     LeafNodeInsert              insert cell key",
+        "3     Halt",
+    ]);
+}
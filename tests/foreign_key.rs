@@ -0,0 +1,54 @@
+mod common;
+
+use common::TempDb;
+
+const SCHEMA: [&str; 5] = [
+    "create table p (id integer primary key, name text)",
+    "create table c (id integer primary key, pid int references p(id) on delete cascade)",
+    "create table n (id integer primary key, pid int references p(id) on delete set null)",
+    "create table r (id integer primary key, pid int references p(id) on delete restrict)",
+    "insert into p values (1, 'a'), (2, 'b'), (3, 'c')",
+];
+
+#[test]
+fn on_delete_actions_cascade_set_null_and_restrict() {
+    let db = TempDb::new();
+    db.query(&SCHEMA);
+    let lines = db.query(&[
+        "pragma foreign_keys = on",
+        "insert into c values (1, 1), (2, 1), (3, 2)",
+        "insert into c values (4, 9)",
+        "insert into n values (1, 1), (2, 2)",
+        "insert into r values (1, 3)",
+        "delete from p where id = 3",
+        "update r set pid = 8 where id = 1",
+        "delete from p where id = 1",
+        "select * from c",
+        "select * from n",
+        "update p set id = 5 where id = 2",
+    ]);
+    assert_eq!(lines, [
+        "Error: FOREIGN KEY constraint failed: c.pid -> p.id",
+        "Error: FOREIGN KEY constraint failed: r.pid -> p.id",
+        "Error: FOREIGN KEY constraint failed: r.pid -> p.id",
+        "3, 2",
+        "1, ",
+        "2, 2",
+        "Error: FOREIGN KEY constraint failed: c.pid -> p.id",
+    ]);
+}
+
+#[test]
+fn foreign_keys_are_only_enforced_when_the_pragma_is_on() {
+    let db = TempDb::new();
+    db.query(&SCHEMA);
+    let lines = db.query(&[
+        "insert into c values (1, 9)",
+        "pragma foreign_keys = on",
+        "insert into c values (2, 9)",
+        "pragma foreign_keys = off",
+        "delete from p",
+        "select id, pid from c",
+    ]);
+    assert_eq!(lines, ["Error: FOREIGN KEY constraint failed: c.pid -> p.id", "1, 9"]);
+}
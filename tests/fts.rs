@@ -0,0 +1,57 @@
+mod common;
+
+use common::TempDb;
+
+#[test]
+fn match_finds_terms_prefixes_phrases_and_columns() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create virtual table f using fts5(title, body)",
+        "insert into f (rowid, title, body) values (1, 'Rust book', 'learn the rust language'), \
+         (2, 'Sqlite notes', 'the sqlite file format'), (3, 'Cooking', 'rusty pans and sqlite')",
+        "select rowid from f where f match 'rust'",
+        "select rowid from f where f match 'rust*' order by rowid",
+        "select rowid from f where f match '\"file format\"'",
+        "select rowid from f where f match 'title:sqlite'",
+        "select rowid from f where f match 'sqlite NOT pans'",
+        "select rowid from f where f match 'AND'",
+        "delete from f where rowid = 2",
+        "select rowid from f where f match 'sqlite'",
+        "create index fi on f (title)",
+    ]);
+    assert_eq!(lines, [
+        "1",
+        "1", "3",
+        "2",
+        "2",
+        "2",
+        "Error: fts5: syntax error near \"AND\"",
+        "3",
+        "Error: virtual tables may not be indexed",
+    ]);
+}
+
+#[test]
+fn tokenizers_fold_case_diacritics_and_stems() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create virtual table p using fts5(body, tokenize = 'porter')",
+        "create virtual table u using fts5(body, tokenize = 'unicode61')",
+        "insert into p values ('running runners ran'), ('he runs')",
+        "insert into u values ('Äpfel café'), ('plain')",
+        "select rowid from p where p match 'run'",
+        "select rowid from u where u match 'apfel OR cafe'",
+    ]);
+    assert_eq!(lines, ["1", "2", "1"]);
+}
+
+#[test]
+fn bm25_ranks_documents_by_relevance() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create virtual table f using fts5(body)",
+        "insert into f values ('apple apple apple'), ('apple pear'), ('pear plum'), ('plum fig'), ('fig kiwi')",
+        "select rowid, round(bm25(f), 4) from f where f match 'apple' order by bm25(f)",
+    ]);
+    assert_eq!(lines, ["1, -0.4905", "2, -0.3495"]);
+}
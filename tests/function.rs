@@ -0,0 +1,80 @@
+mod common;
+
+use sqlite_rust::result::ExecuteResult::*;
+use sqlite_rust::value::Value;
+use common::{close, execute, execute_all, record_calls, text, TempDb};
+
+#[test]
+fn functions_are_registered_per_connection() {
+    let (a, b) = (TempDb::new(), TempDb::new());
+    let (mut a, mut b) = (a.connect(), b.connect());
+    a.create_scalar_function("twice", 1, |args| Ok(Value::Integer(args[0].to_integer().unwrap_or(0) * 2)));
+    a.create_aggregate_function("total_len", 1, |total: &mut i64, args| {
+        *total += args[0].to_string().len() as i64;
+        Ok(())
+    }, |total| Ok(Value::Integer(*total)));
+    let calls = record_calls(&mut a);
+    execute_all(&mut a, &[
+        "insert into users values (1, 'ann', 'ann@example.com'), (2, 'bo', 'bo@example.com')",
+        "select record(id, twice(id), twice('x')) from users",
+        "select record(total_len(username), total_len(email)) from users",
+        "select record(total_len(username)) from users where id > 5",
+    ]);
+    assert_eq!(*calls.borrow(), [
+        vec![Value::Integer(1), Value::Integer(2), Value::Integer(0)],
+        vec![Value::Integer(2), Value::Integer(4), Value::Integer(0)],
+        vec![Value::Integer(5), Value::Integer(29)],
+        vec![Value::Integer(0)],
+    ]);
+    assert_eq!(execute(&mut a, "select twice(1, 2)"), ExecuteWrongArgumentCount(String::from("twice")));
+    assert_eq!(execute(&mut b, "select twice(1)"), ExecuteNoSuchFunction(String::from("twice")));
+    execute_all(&mut b, &["insert into users values (1, 'ann', 'ann@example.com')"]);
+    assert_eq!(execute(&mut b, "select total_len(username) from users"), ExecuteNoSuchFunction(String::from("total_len")));
+}
+
+#[test]
+fn errors_of_registered_functions_fail_the_statement() {
+    let db = TempDb::new();
+    let mut connection = db.connect();
+    connection.create_scalar_function("checked", 1, |args| match &args[0] {
+        Value::Null => Err(String::from("checked() got NULL")),
+        value => Ok(value.clone()),
+    });
+    connection.create_aggregate_function("small_sum", 1, |total: &mut i64, args| {
+        match args[0].to_integer() {
+            Some(n) if n >= 0 => *total += n,
+            _ => return Err(String::from("small_sum() takes counts")),
+        }
+        Ok(())
+    }, |total| if *total > 10 { Err(format!("small_sum() of {} is not small", total)) } else { Ok(Value::Integer(*total)) });
+    let calls = record_calls(&mut connection);
+    execute_all(&mut connection, &["insert into users values (1, 'ann', 'ann@example.com'), (2, 'bo', null)"]);
+    assert_eq!(execute(&mut connection, "select checked(null)"), ExecuteFunctionError(String::from("checked() got NULL")));
+    assert_eq!(execute(&mut connection, "select id from users where checked(email) like 'a%'"), ExecuteFunctionError(String::from("checked() got NULL")));
+    assert_eq!(execute(&mut connection, "update users set username = checked(email) where id = 2"), ExecuteFunctionError(String::from("checked() got NULL")));
+    assert_eq!(execute(&mut connection, "select small_sum(id - 2) from users"), ExecuteFunctionError(String::from("small_sum() takes counts")));
+    assert_eq!(execute(&mut connection, "select small_sum(id * 5) from users"), ExecuteFunctionError(String::from("small_sum() of 15 is not small")));
+    // The failed UPDATE left its row as it was.
+    execute_all(&mut connection, &["select record(username, small_sum(id)) from users where id = 2"]);
+    assert_eq!(*calls.borrow(), [vec![text("bo"), Value::Integer(2)]]);
+}
+
+#[test]
+fn stored_expressions_call_the_functions_of_the_connection() {
+    let db = TempDb::new();
+    let mut connection = db.connect();
+    connection.create_scalar_function("is_even", 1, |args| Ok(Value::Integer((args[0].to_integer().unwrap_or(1) % 2 == 0) as i64)));
+    execute_all(&mut connection, &["create table t (n int check (is_even(n)))", "insert into t values (2)"]);
+    assert_eq!(execute(&mut connection, "insert into t values (3)"), ExecuteCheckViolation(String::from("is_even(n)")));
+    close(connection);
+
+    let mut connection = db.connect();
+    assert_eq!(execute(&mut connection, "insert into t values (4)"), ExecuteNoSuchFunction(String::from("is_even")));
+    connection.create_scalar_function("is_even", 1, |args| Ok(Value::Integer((args[0].to_integer().unwrap_or(1) % 2 == 0) as i64)));
+    let calls = record_calls(&mut connection);
+    execute_all(&mut connection, &["insert into t values (4)", "select record(n, is_even(n + 1)) from t"]);
+    assert_eq!(*calls.borrow(), [
+        vec![Value::Integer(2), Value::Integer(0)],
+        vec![Value::Integer(4), Value::Integer(0)],
+    ]);
+}
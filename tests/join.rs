@@ -0,0 +1,49 @@
+mod common;
+
+use common::orders_db;
+
+#[test]
+fn inner_left_and_cross_joins() {
+    let db = orders_db();
+    let lines = db.query(&[
+        "select u.name, o.item from u join o on o.uid = u.id order by o.oid",
+        "select u.name, o.item from u left join o on o.uid = u.id order by u.id, o.oid",
+        "select count(*) from u cross join o",
+        "select name, item from u, o where uid = id and item > 'd'",
+        "select a.name, b.name from u a join u b on a.id < b.id",
+        "select name from u left join o on uid = id where o.oid is null",
+    ]);
+    assert_eq!(lines, [
+        "ann, pen", "ann, ink", "cy, cup",
+        "ann, pen", "ann, ink", "bo, ", "cy, cup",
+        "12",
+        "ann, pen", "ann, ink",
+        "ann, bo", "ann, cy", "bo, cy",
+        "bo",
+    ]);
+}
+
+#[test]
+fn joins_search_by_key_index_or_hash_table() {
+    let db = orders_db();
+    let lines = db.query(&[
+        "explain query plan select u.name, o.item from o join u on o.uid = u.id",
+        "explain query plan select u.name, o.item from u join o on o.item = u.name",
+        "create index o_uid on o (uid)",
+        "explain query plan select u.name, o.item from u join o on o.uid = u.id",
+        "select u.name, o.item from u join o on o.uid = u.id order by o.oid",
+    ]);
+    assert_eq!(lines, [
+        "QUERY PLAN", "|--SCAN o", "`--SEARCH u USING PRIMARY KEY (id=?)",
+        "QUERY PLAN", "|--SCAN u", "`--SEARCH o USING HASH TABLE (item=?)",
+        "QUERY PLAN", "|--SCAN u", "`--SEARCH o USING INDEX o_uid (uid=?)",
+        "ann, pen", "ann, ink", "cy, cup",
+    ]);
+}
+
+#[test]
+fn ambiguous_columns_are_rejected() {
+    let db = orders_db();
+    let lines = db.query(&["select name from u a join u b on a.id = b.id"]);
+    assert_eq!(lines, ["Error: ambiguous column name: name"]);
+}
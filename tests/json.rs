@@ -0,0 +1,51 @@
+mod common;
+
+use common::docs_db;
+
+#[test]
+fn json_scalar_functions_and_operators() {
+    let db = docs_db();
+    let lines = db.query(&[
+        "select json(' [1, 2 ,3] '), json_array(1, 'x', null, 2.5), json_object('k', 1, 'l', json('[true]'))",
+        "select json_extract(doc, '$.a'), json_extract(doc, '$.b[2].c'), doc -> '$.b', doc ->> '$.a' from d order by id",
+        "select json_set(doc, '$.a', 10, '$.z', 'new') from d where id = 2",
+        "select json('{bad')",
+        "select json_extract('[1]', 'x')",
+    ]);
+    assert_eq!(lines, [
+        "[1,2,3], [1,\"x\",null,2.5], {\"k\":1,\"l\":[true]}",
+        "1, x, [1,2,{\"c\":\"x\"}], 1",
+        "2, , [], 2",
+        "{\"a\":10,\"b\":[],\"z\":\"new\"}",
+        "Error: malformed JSON",
+        "Error: bad JSON path: 'x'",
+    ]);
+}
+
+#[test]
+fn json_each_and_json_tree_walk_documents() {
+    let db = docs_db();
+    let lines = db.query(&[
+        "select key, value, type from json_each('{\"x\": 1, \"y\": [2]}')",
+        "select fullkey, type from json_tree('{\"x\": [1, {\"y\": null}]}')",
+        "select d.id, e.value from d, json_each(d.doc, '$.b') e order by 1, 2",
+    ]);
+    assert_eq!(lines, [
+        "x, 1, integer", "y, [2], array",
+        "$, object", "$.x, array", "$.x[0], integer", "$.x[1], object", "$.x[1].y, null",
+        "1, 1", "1, 2", "1, {\"c\":\"x\"}",
+    ]);
+}
+
+#[test]
+fn indexes_on_extracted_paths_are_searched() {
+    let db = docs_db();
+    let lines = db.query(&[
+        "create index d_a on d (json_extract(doc, '$.a'))",
+        "explain query plan select id from d where json_extract(doc, '$.a') = 2",
+        "select id from d where json_extract(doc, '$.a') = 2",
+        "update d set doc = json_set(doc, '$.a', 3) where id = 2",
+        "select id from d where json_extract(doc, '$.a') = 3",
+    ]);
+    assert_eq!(lines, ["QUERY PLAN", "`--SEARCH d USING INDEX d_a (<expr>=?)", "2", "2"]);
+}
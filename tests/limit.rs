@@ -0,0 +1,36 @@
+mod common;
+
+use common::TempDb;
+
+#[test]
+fn limit_and_offset_select_a_window_of_rows() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (a int)",
+        "insert into t values (1), (2), (3), (4), (5)",
+        "select a from t limit 2",
+        "select a from t limit 2 offset 3",
+        "select a from t limit 3, 1",
+        "select a from t order by a desc limit 2 offset 1",
+        "select a from t limit -1 offset 4",
+        "select a from t limit 0",
+        "select a from t where a > 1 limit 1 + 1",
+        "select a from t limit 10 offset 10",
+        "select a from t limit 'x'",
+    ]);
+    assert_eq!(lines, ["1", "2", "4", "5", "4", "4", "3", "5", "2", "3", "Error: datatype mismatch"]);
+}
+
+#[test]
+fn scan_stops_once_the_limit_is_met() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table j (b text)",
+        "insert into j values ('[1]'), ('{bad')",
+        "select json(b) from j limit 1",
+        "select json(b) from j limit 1 offset 1",
+        "explain select b from j limit 2",
+    ]);
+    assert_eq!(lines[..2], ["[1]", "Error: malformed JSON"]);
+    assert!(lines.iter().any(|line| line.contains("DecrJumpZero") && line.contains("if LIMIT reached goto")));
+}
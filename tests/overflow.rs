@@ -0,0 +1,121 @@
+mod common;
+
+use std::fs;
+use common::TempDb;
+
+fn long_text(len: usize) -> String {
+    (0..len).map(|i| (b'a' + (i % 26) as u8) as char).collect()
+}
+
+#[test]
+fn long_rows_overflow_and_survive_reopen() {
+    let text = long_text(5000);
+    let db = TempDb::with(&[
+        "create table t (id integer primary key, body text)",
+        &format!("insert into t values (1, '{}')", text),
+    ]);
+    let lines = db.query(&["select id, length(body), substr(body, 4990) from t"]);
+    assert_eq!(lines, [format!("1, 5000, {}", &text[4989..])]);
+}
+
+#[test]
+fn long_index_keys_overflow() {
+    let db = TempDb::new();
+    let keys: Vec<String> = (0..20).map(|i| format!("{}{:02}", long_text(1000), i)).collect();
+    let mut commands = vec![String::from("create table t (id integer primary key, body text)"), String::from("create index t_body on t (body)")];
+    commands.extend(keys.iter().enumerate().rev().map(|(i, key)| format!("insert into t values ({}, '{}')", i, key)));
+    commands.push(format!("select id from t where body = '{}'", keys[7]));
+    let commands: Vec<&str> = commands.iter().map(String::as_str).collect();
+    assert_eq!(db.query(&commands), ["7"]);
+    let lines = db.query(&["select id from t order by body limit 3"]);
+    assert_eq!(lines, ["0", "1", "2"]);
+}
+
+#[test]
+fn long_json_and_fts_bodies_are_stored() {
+    let db = TempDb::new();
+    let json = format!("[{}]", (0..200).map(|i| i.to_string()).collect::<Vec<_>>().join(","));
+    let body = format!("{} needle", long_text(2000));
+    let lines = db.query(&[
+        "create table j (id integer primary key, doc text)",
+        &format!("insert into j values (1, '{}')", json),
+        "select json_extract(doc, '$[199]') from j",
+        "create virtual table d using fts5(body)",
+        &format!("insert into d values ('{}')", body),
+        "select rowid from d where d match 'needle'",
+    ]);
+    assert_eq!(lines, ["199", "1"]);
+}
+
+#[test]
+fn overflow_pages_are_reused() {
+    let text = long_text(20000);
+    let db = TempDb::with(&[
+        "create table t (id integer primary key, body text)",
+        &format!("insert into t values (1, '{}')", text),
+    ]);
+    let size = fs::metadata(&db.path).unwrap().len();
+    db.query(&[
+        "delete from t where id = 1",
+        &format!("insert into t values (2, '{}')", text),
+        &format!("update t set body = '{}x' where id = 2", text),
+    ]);
+    assert_eq!(fs::metadata(&db.path).unwrap().len(), size);
+    assert_eq!(db.query(&["select id, length(body) from t"]), ["2, 20001"]);
+}
+
+#[test]
+fn many_rows_split_internal_nodes_and_survive_reopen() {
+    // Rows in a scattered order, each too long for more than a few dozen to share a leaf.
+    let rows: Vec<String> = (1..=3000).map(|i| format!("({}, '{}')", i * 7919 % 3001, format!("{:04}", i).repeat(50))).collect();
+    let db = TempDb::with(&[
+        "create table t (id integer primary key, body text)",
+        "create index t_body on t (body)",
+        &format!("insert into t values {}", rows.join(", ")),
+    ]);
+    let lines = db.query(&[
+        "select count(*), sum(id), min(id), max(id) from t",
+        &format!("select id from t where body = '{}'", "0007".repeat(50)),
+        "select count(*) from t where id between 100 and 199",
+    ]);
+    assert_eq!(lines, ["3000, 4501500, 1, 3000", "1415", "100"]);
+}
+
+#[test]
+fn rows_that_grow_move_to_a_new_leaf() {
+    let db = TempDb::new();
+    let rows: Vec<String> = (1..=12).map(|i| format!("({}, '{}')", i, long_text(300))).collect();
+    let lines = db.query(&[
+        "create table t (id integer primary key, body text)",
+        &format!("insert into t values {}", rows.join(", ")),
+        "update t set body = printf('%s%s', body, body)",
+        "select count(*), sum(length(body)), group_concat(id) from t",
+    ]);
+    assert_eq!(lines, ["12, 7200, 1,2,3,4,5,6,7,8,9,10,11,12"]);
+}
+
+#[test]
+fn writes_past_max_page_count_fail() {
+    let db = TempDb::new();
+    let mut commands = vec![
+        String::from("create table t (id integer primary key, body text)"),
+        String::from("pragma max_page_count = 1"),
+        String::from("pragma max_page_count = 6"),
+    ];
+    commands.extend((0..4).map(|i| {
+        let rows: Vec<String> = (0..10).map(|j| format!("({}, '{}')", i * 10 + j, long_text(300))).collect();
+        format!("insert into t values {}", rows.join(", "))
+    }));
+    commands.push(String::from("create table u (id integer primary key)"));
+    let commands: Vec<&str> = commands.iter().map(String::as_str).collect();
+    assert_eq!(db.query(&commands), [
+        "3",
+        "6",
+        "Error: database or disk is full",
+        "Error: database or disk is full",
+        "Error: database or disk is full",
+    ]);
+    // The limit holds for the connection that set it alone.
+    let lines = db.query(&["select count(*) from t", "pragma max_page_count", "insert into t values (100, 'x')"]);
+    assert_eq!(lines, ["20", "1048576"]);
+}
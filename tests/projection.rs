@@ -0,0 +1,41 @@
+mod common;
+
+use common::TempDb;
+
+#[test]
+fn select_lists_columns_expressions_aliases_and_star() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "insert 1 alice alice@x",
+        "insert 2 bob bob@x",
+        "select username, id * 10 as tens, * from users",
+        "select upper(username), length(email) from users where id = 2",
+        "select id, id + 0.5 half, 'k' from users order by half desc",
+        "select nope from users",
+    ]);
+    assert_eq!(lines, [
+        "alice, 10, 1, alice, alice@x",
+        "bob, 20, 2, bob, bob@x",
+        "BOB, 5",
+        "2, 2.5, k",
+        "1, 1.5, k",
+        "Error: no such column: nope",
+    ]);
+}
+
+#[test]
+fn headers_name_the_result_columns() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "insert 1 alice alice@x",
+        ".headers on",
+        "select username, id * 10 as tens, * from users",
+        "select id + 0.5, count(*) n from users",
+    ]);
+    assert_eq!(lines, [
+        "username, tens, id, username, email",
+        "alice, 10, 1, alice, alice@x",
+        "id + 0.5, n",
+        "1.5, 1",
+    ]);
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::TempDb;
+
+#[test]
+fn table_without_integer_primary_key_gets_hidden_rowid() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table x (a text)",
+        "insert into x values ('one')",
+        "insert into x (a) values ('two')",
+        "select * from x",
+        "select rowid, a from x where rowid = 2",
+    ]);
+    assert_eq!(lines, ["one", "two", "2, two"]);
+    assert_eq!(db.query(&["select rowid, * from x"]), ["1, one", "2, two"]);
+}
+
+#[test]
+fn primary_key_on_other_type_is_unique() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table z (code text primary key, n int)",
+        "insert into z values ('a', 1)",
+        "insert into z values ('a', 2)",
+        "select * from z",
+    ]);
+    assert_eq!(lines, ["Error: UNIQUE constraint failed: z.code (index sqlite_autoindex_z_1)", "a, 1"]);
+}
+
+#[test]
+fn minimum_integer_literal_is_an_integer() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table y (id integer primary key, b text)",
+        "insert into y values (-9223372036854775808, 'min')",
+        "select id, b, typeof(id) from y",
+        "select -9223372036854775808.0",
+    ]);
+    assert_eq!(lines, ["-9223372036854775808, min, integer", "-9.22337203685478e+18"]);
+}
+
+#[test]
+fn generated_rowid_follows_largest_after_deletes() {
+    let db = TempDb::new();
+    let mut commands = vec![String::from("create table t (id integer primary key, v text)")];
+    commands.extend((1..=40).map(|i| format!("insert into t values ({}, 'v{}')", i, i)));
+    // Empty the rightmost leaves, leaving 10 as the largest rowid.
+    commands.push(String::from("delete from t where id > 10"));
+    commands.push(String::from("insert into t (v) values ('next')"));
+    commands.push(String::from("select id from t where v = 'next'"));
+    let commands: Vec<&str> = commands.iter().map(String::as_str).collect();
+    assert_eq!(db.query(&commands), ["11"]);
+}
+
+#[test]
+fn generated_rowids_count_those_given_in_the_same_insert() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (id integer primary key, v text)",
+        "insert into t values (null, 'a'), (7, 'b'), (null, 'c')",
+        "select id, v from t",
+    ]);
+    assert_eq!(lines, ["1, a", "7, b", "8, c"]);
+}
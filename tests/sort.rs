@@ -0,0 +1,65 @@
+mod common;
+
+use std::fs;
+use std::process::Command;
+use common::{close, execute, TempDb};
+use sqlite_rust::result::ExecuteResult::ExecuteSuccess;
+
+/// Over 300 users, enough that sorting the cross join spills many runs.
+const CROSS_JOIN_SORT: &str = "select a.id, b.email from users a cross join users b order by b.email, a.id limit 3";
+
+#[test]
+fn sort_of_many_runs_is_ordered_and_stable() {
+    let db = TempDb::with_users(300);
+    assert_eq!(db.query(&[CROSS_JOIN_SORT]), ["1, person100@example.com", "2, person100@example.com", "3, person100@example.com"]);
+    // Rows of equal keys come out in the order the scan reached them.
+    let lines = db.query(&["select a.id, b.id from users a cross join users b order by a.username desc limit 2 offset 299"]);
+    assert_eq!(lines, ["99, 300", "98, 1"]);
+}
+
+#[test]
+fn sort_of_many_runs_fits_few_open_files() {
+    let db = TempDb::with_users(300);
+    let binary = assert_cmd::cargo::cargo_bin("sqlite-rust");
+    let script = format!("ulimit -n 64 && echo \"{}\" | \"{}\" \"{}\"", CROSS_JOIN_SORT, binary.display(), db.path.display());
+    let output = Command::new("sh").arg("-c").arg(script).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("1, person100@example.com\n2, person100@example.com\n3, person100@example.com"), "{}", stdout);
+}
+
+#[test]
+fn temporary_file_error_is_reported() {
+    let db = TempDb::with_users(300);
+    let output = assert_cmd::Command::cargo_bin("sqlite-rust").unwrap()
+        .arg(&db.path)
+        .env("TMPDIR", "/nonexistent-directory")
+        .write_stdin(format!("{}\nselect count(*) from users\n.exit\n", CROSS_JOIN_SORT))
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let stdout = String::from_utf8(output).unwrap();
+    assert!(stdout.contains("Error: cannot create temporary file: No such file or directory"), "{}", stdout);
+    assert!(stdout.contains("300\n"), "{}", stdout);
+}
+
+#[test]
+fn temporary_files_of_taken_names_are_left_alone() {
+    let db = TempDb::with_users(300);
+    // Files under the names this process would give its first spilled runs.
+    let taken: Vec<_> = (0..8)
+        .map(|id| std::env::temp_dir().join(format!("sqlite-rs-{}-{}", std::process::id(), id)))
+        .collect();
+    for path in &taken {
+        fs::write(path, "not a run").unwrap();
+    }
+    let mut connection = db.connect();
+    let result = execute(&mut connection, CROSS_JOIN_SORT);
+    close(connection);
+    for path in &taken {
+        assert_eq!(fs::read_to_string(path).unwrap(), "not a run");
+        fs::remove_file(path).unwrap();
+    }
+    assert_eq!(result, ExecuteSuccess);
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use std::fs;
+use common::{sqlite_db, TempDb};
+
+#[test]
+fn tables_are_read_and_sought_by_rowid() {
+    let db = sqlite_db();
+    let lines = db.query(&[
+        "select count(*) from people",
+        "select id, name, email, age from people where id = 2500",
+        "select id from people where id > 2997",
+        "select rowid, a, b from plain",
+    ]);
+    assert_eq!(lines, ["3000", "2500, name0, e2500@x, 70", "2998", "2999", "3000", "1, one, 1.0", "2, two, 2.5"]);
+}
+
+#[test]
+fn indexes_of_the_file_are_used() {
+    let db = sqlite_db();
+    let lines = db.query(&[
+        "explain query plan select id from people where name = 'name7'",
+        "select id from people where name = 'name7' limit 3",
+        "explain query plan select id from people where email = 'e77@x'",
+        "select id from people where email = 'e77@x'",
+        "explain query plan select body from notes where code = 'short'",
+        "select body from notes where code = 'short'",
+    ]);
+    assert_eq!(lines, [
+        "QUERY PLAN", "`--SEARCH people USING INDEX people_name (name=?)", "7", "107", "207",
+        "QUERY PLAN", "`--SEARCH people USING INDEX sqlite_autoindex_people_1 (email=?)", "77",
+        "QUERY PLAN", "`--SEARCH notes USING INDEX sqlite_autoindex_notes_1 (code=?)", "body",
+    ]);
+}
+
+#[test]
+fn long_rows_are_read_through_overflow_pages() {
+    let db = sqlite_db();
+    let lines = db.query(&["select code, length(body), substr(body, -2) from notes"]);
+    assert_eq!(lines, ["long, 10000, xx", "short, 4, dy"]);
+}
+
+#[test]
+fn writes_are_refused() {
+    let db = sqlite_db();
+    let lines = db.query(&["insert into plain values ('three', 3)", "select count(*) from plain"]);
+    assert_eq!(lines, ["Error: attempt to write a readonly database", "2"]);
+}
+
+#[test]
+fn truncated_file_is_an_error() {
+    let db = sqlite_db();
+    let bytes = fs::read(&db.path).unwrap();
+    fs::write(&db.path, &bytes[..3 * 4096]).unwrap();
+    let lines = db.query(&["select count(*) from people", "select * from plain"]);
+    assert_eq!(lines.len(), 2);
+    assert!(lines.iter().all(|line| line.starts_with("Error: database disk image is malformed")), "{:?}", lines);
+}
+
+#[test]
+fn malformed_page_is_an_error() {
+    let db = sqlite_db();
+    let mut bytes = fs::read(&db.path).unwrap();
+    // Claim more cells on the root page of people, page 2, than its pointer array can hold.
+    bytes[4096 + 3..4096 + 5].copy_from_slice(&0xffffu16.to_be_bytes());
+    fs::write(&db.path, bytes).unwrap();
+    let lines = db.query(&["select count(*) from people", "select a from plain"]);
+    assert!(lines[0].starts_with("Error: database disk image is malformed"), "{:?}", lines);
+    assert_eq!(lines[1..], ["one", "two"]);
+}
+
+#[test]
+fn without_rowid_tables_are_read_in_key_order() {
+    let db = TempDb::new();
+    db.create_with_sqlite3("
+        create table kv (k text primary key, v real) without rowid;
+        create table pair (a int, b text collate nocase, c, primary key (a desc, b)) without rowid;
+        with recursive n(i) as (select 1 union all select i + 1 from n where i < 2000)
+            insert into kv select 'k' || i, i from n;
+        insert into pair values (1, 'x', 1), (2, 'Y', 2), (2, 'a', 3);
+    ");
+    let lines = db.query(&[
+        "select count(*) from kv",
+        "select * from kv where k = 'k1500'",
+        "select * from pair",
+        "select c from pair where a = 2 and b = 'y'",
+    ]);
+    assert_eq!(lines, ["2000", "k1500, 1500.0", "2, a, 3", "2, Y, 2", "1, x, 1", "2"]);
+}
+
+#[test]
+fn file_with_a_view_is_refused() {
+    let db = TempDb::new();
+    db.create_with_sqlite3("create table t (a); create view v as select a from t;");
+    assert_eq!(db.query(&["select * from t"]), ["Error: views are not supported: v"]);
+}
+
+#[test]
+fn databases_are_saved_as_sqlite_files() {
+    let (db, saved) = (TempDb::new(), TempDb::new());
+    let save = format!(".save {}", saved.path.display());
+    db.query(&[
+        "insert into users values (1, 'ann', 'ann@example.com'), (2, 'bob', 'bob@example.com')",
+        "create table kv (k text primary key, v int) without rowid",
+        "insert into kv values ('b', 2), ('a', 1)",
+        "create table t (a int, b text)",
+        "insert into t values (1, 'x'), (2, 'y')",
+        "create index tb on t (b)",
+        &save,
+    ]);
+    let lines = saved.query_with_sqlite3("
+        pragma integrity_check;
+        select * from users;
+        select * from kv;
+        select rowid, a from t where b = 'y';
+    ");
+    assert_eq!(lines, ["ok", "1|ann|ann@example.com", "2|bob|bob@example.com", "a|1", "b|2", "2|2"]);
+    let lines = saved.query(&["select * from kv", "select a from t where b = 'x'", "insert into t values (3, 'z')"]);
+    assert_eq!(lines, ["a, 1", "b, 2", "1", "Error: attempt to write a readonly database"]);
+}
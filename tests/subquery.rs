@@ -0,0 +1,87 @@
+mod common;
+
+use common::orders_db;
+
+#[test]
+fn uncorrelated_scalar_in_and_exists_subqueries() {
+    let db = orders_db();
+    let lines = db.query(&[
+        "select name from u where id in (select uid from o)",
+        "select name from u where id not in (select uid from o)",
+        "select (select max(amount) from o), (select name from u order by id desc)",
+        "select name from u where id = (select uid from o where amount = 2)",
+        "select name from u where 2 in (select amount from o) and id > (select min(uid) from o)",
+        "select count(*) from u where id not in (select null)",
+        "select name from u where id in (select uid, amount from o)",
+    ]);
+    assert_eq!(lines, [
+        "ann", "cy",
+        "bo",
+        "7, cy",
+        "cy",
+        "bo", "cy",
+        "0",
+        "Error: sub-select returns 2 columns - expected 1",
+    ]);
+}
+
+#[test]
+fn correlated_subqueries_run_for_each_row() {
+    let db = orders_db();
+    let lines = db.query(&[
+        "select name from u where exists (select 1 from o where o.uid = u.id and amount > 6)",
+        "select name, (select sum(amount) from o where uid = u.id) from u",
+        "select name from u where not exists (select * from o where uid = id)",
+        "explain query plan select name from u where id in (select uid from o) and exists (select 1 from o where o.uid = u.id)",
+    ]);
+    assert_eq!(lines, [
+        "ann",
+        "ann, 12", "bo, ", "cy, 2",
+        "bo",
+        "QUERY PLAN",
+        "|--SCAN u",
+        "|--LIST SUBQUERY 1",
+        "|  `--SCAN o",
+        "`--CORRELATED SCALAR SUBQUERY 2",
+        "   `--SCAN o",
+    ]);
+}
+
+#[test]
+fn subqueries_read_columns_of_every_enclosing_query() {
+    let db = orders_db();
+    let lines = db.query(&[
+        "select name from u where exists (select 1 from o where o.uid = u.id and exists (select 1 from u u2 where u2.id = u.id and u2.name > 'b'))",
+        "select name, (select count(*) from o where o.uid = u.id and o.amount > (select min(amount) from o o2 where o2.uid = u.id)) from u",
+        "select name from u where exists (select 1 from o where exists (select 1 from u u2 where u2.id = u.id and o.uid = u2.id))",
+    ]);
+    assert_eq!(lines, [
+        "cy",
+        "ann, 1", "bo, 0", "cy, 0",
+        "ann", "cy",
+    ]);
+}
+
+#[test]
+fn in_subquery_looks_its_operand_up() {
+    let db = orders_db();
+    let values: Vec<String> = (1..=500).map(|i| format!("({})", i * 2)).collect();
+    let lines = db.query(&[
+        "create table n (v)",
+        "insert into n values (1), (null), (2.0)",
+        "create table evens (v int)",
+        &format!("insert into evens values {}", values.join(", ")),
+        "select name from u where id in (select v from n)",
+        "select count(*) from u where id not in (select v from n)",
+        "select 5 in (select v from n), null in (select uid from o where 0), null in (select uid from o)",
+        "select 'ANN' collate nocase in (select name from u), 'ANN' in (select name from u)",
+        "select (select count(*) from evens where v + 1 in (select v from evens)), (select count(*) from evens where v / 2 in (select v from evens))",
+    ]);
+    assert_eq!(lines, [
+        "ann", "bo",
+        "0",
+        ", 0, ",
+        "1, 0",
+        "0, 250",
+    ]);
+}
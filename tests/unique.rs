@@ -0,0 +1,59 @@
+mod common;
+
+use common::TempDb;
+
+#[test]
+fn unique_columns_reject_duplicates_on_insert_and_update() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (a integer primary key, b text unique, c int)",
+        "insert into t values (1, 'x', 1), (2, 'y', 2)",
+        "insert into t values (3, 'x', 3)",
+        "insert into t values (3, null, 3), (4, null, 4)",
+        "update t set b = 'y' where a = 1",
+        "select a, b from t order by a",
+    ]);
+    assert_eq!(lines, [
+        "Error: UNIQUE constraint failed: t.b (index sqlite_autoindex_t_1)",
+        "Error: UNIQUE constraint failed: t.b (index sqlite_autoindex_t_1)",
+        "1, x",
+        "2, y",
+        "3, ",
+        "4, ",
+    ]);
+}
+
+#[test]
+fn unique_index_on_users_keeps_emails_distinct() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "insert 1 a a@x",
+        "insert 2 b b@x",
+        "create unique index users_email on users (email)",
+        "insert 3 c a@x",
+        "update users set email = 'b@x' where id = 1",
+        "update users set email = 'z@x' where id = 1",
+        "insert 3 c a@x",
+        "select id, email from users",
+    ]);
+    assert_eq!(lines, [
+        "Error: UNIQUE constraint failed: users.email (index users_email)",
+        "Error: UNIQUE constraint failed: users.email (index users_email)",
+        "1, z@x",
+        "2, b@x",
+        "3, a@x",
+    ]);
+}
+
+#[test]
+fn unique_index_is_not_created_over_duplicates() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (c int)",
+        "insert into t values (1), (1)",
+        "create unique index tc on t (c)",
+        "insert into t values (1)",
+        "select count(*) from t",
+    ]);
+    assert_eq!(lines, ["Error: UNIQUE constraint failed: t.c (index tc)", "3"]);
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::{fruit_db, TempDb};
+
+#[test]
+fn where_combines_comparisons_in_like_glob_and_between() {
+    let db = fruit_db();
+    let lines = db.query(&[
+        "select a from t where a > 1 and b is not null",
+        "select a from t where a in (1, 5) or b like 'b%'",
+        "select a from t where b glob '*a*' and not a between 2 and 4",
+        "select a from t where (a + 1) * 2 = 12 or a % 2 = 0",
+        "select a from t where b not like '%e%'",
+    ]);
+    assert_eq!(lines, ["2", "5", "1", "2", "5", "1", "5", "2", "5", "2"]);
+}
+
+#[test]
+fn null_makes_conditions_unknown() {
+    let db = fruit_db();
+    let lines = db.query(&[
+        "select b from t where a is null",
+        "select a from t where b in ('apple', null)",
+        "select count(*) from t where not (b in ('apple', null))",
+        "select count(*) from t where a = null or a <> null",
+        "select count(*) from t where a is not 5",
+    ]);
+    assert_eq!(lines, ["cherry", "1", "0", "0", "4"]);
+}
+
+#[test]
+fn conditions_that_fail_end_the_statement_with_their_error() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table t (id integer primary key, j text)",
+        "insert into t values (1, '{\"a\":1}'), (2, 'bad')",
+        "select id from t where twice(id) > 0",
+        "select id from t where abs(-9223372036854775807 - id) > 0",
+        "update t set j = 'x' where twice(id) > 0",
+        "delete from t where json_extract(j, '$.a') = 1",
+        "select a.id from t a join t b on json_extract(b.j, '$.a') = a.id",
+        "select a.id from t a join t b on a.id = b.id where twice(a.id)",
+        "select id, j from t",
+    ]);
+    assert_eq!(lines, [
+        "Error: no such function: twice",
+        "Error: integer overflow",
+        "Error: no such function: twice",
+        "Error: malformed JSON",
+        "1",
+        "Error: malformed JSON",
+        "Error: no such function: twice",
+        "1, {\"a\":1}",
+        "2, bad",
+    ]);
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::pay_db;
+
+#[test]
+fn ranking_and_offset_functions() {
+    let db = pay_db();
+    let lines = db.query(&[
+        "select name, row_number() over (order by pay, name), rank() over (order by pay), \
+         dense_rank() over (order by pay) from s order by pay, name",
+        "select name, lag(pay) over (partition by dept order by pay, name), \
+         lead(pay, 1, -1) over (partition by dept order by pay, name) from s order by dept, pay, name",
+        "select name, first_value(name) over (partition by dept order by pay desc, name) from s order by name",
+    ]);
+    assert_eq!(lines, [
+        "w, 1, 1, 1", "z, 2, 2, 2", "v, 3, 3, 3", "u, 4, 4, 4", "x, 5, 5, 5", "q, 6, 6, 6", "y, 7, 6, 6",
+        "x, , 30", "q, 10, 30", "y, 30, -1", "w, , 5", "z, , 8", "u, 5, -1", "v, , -1",
+        "q, q", "u, u", "v, v", "w, u", "x, q", "y, q", "z, u",
+    ]);
+}
+
+#[test]
+fn aggregate_windows_over_partitions_and_frames() {
+    let db = pay_db();
+    let lines = db.query(&[
+        "select name, sum(pay) over (partition by dept order by pay, name \
+         rows between unbounded preceding and current row) from s order by dept, pay, name",
+        "select name, sum(pay) over (order by name rows between 1 preceding and 1 following) from s order by name",
+        "select name, sum(pay) over (partition by dept) from s order by name",
+        "select name, avg(pay) over (order by pay), count(*) over () from s order by name",
+    ]);
+    assert_eq!(lines, [
+        "x, 10", "q, 40", "y, 70", "w, ", "z, 5", "u, 13", "v, 7",
+        "q, 38", "u, 45", "v, 15", "w, 17", "x, 40", "y, 45", "z, 35",
+        "q, 70", "u, 13", "v, 7", "w, 13", "x, 70", "y, 70", "z, 13",
+        "q, 15.0, 7", "u, 6.66666666666667, 7", "v, 6.0, 7", "w, , 7", "x, 7.5, 7", "y, 15.0, 7", "z, 5.0, 7",
+    ]);
+}
+
+#[test]
+fn window_functions_need_an_over_clause() {
+    let db = pay_db();
+    let lines = db.query(&["select row_number() from s"]);
+    assert_eq!(lines, ["Error: misuse of window function row_number()"]);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::{keyed_db, TempDb};
+
+#[test]
+fn text_and_composite_keys_order_and_find_rows() {
+    let db = keyed_db();
+    let lines = db.query(&[
+        "insert into kv values ('a', 9)",
+        "select * from kv",
+        "select v from kv where k = 'b'",
+        "insert into pair values (1, 'x', 4)",
+        "select * from pair",
+        "select c from pair where a = 1 and b = 'y'",
+        "explain query plan select * from pair where a = 1",
+    ]);
+    assert_eq!(lines, [
+        "Error: Duplicate key.",
+        "a, 1", "b, 2", "c, 3",
+        "2",
+        "Error: Duplicate key.",
+        "0, z, 3", "1, x, 2", "1, y, 1",
+        "1",
+        "QUERY PLAN",
+        "`--SEARCH pair USING PRIMARY KEY (a=?)",
+    ]);
+}
+
+#[test]
+fn keyed_rows_survive_updates_deletes_and_reopening() {
+    let db = keyed_db();
+    db.query(&[
+        "update kv set k = 'd' where k = 'a'",
+        "delete from pair where a = 1",
+    ]);
+    let lines = db.query(&["select * from kv", "select * from pair"]);
+    assert_eq!(lines, ["b, 2", "c, 3", "d, 1", "0, z, 3"]);
+}
+
+#[test]
+fn key_declarations_are_checked() {
+    let db = TempDb::new();
+    let lines = db.query(&[
+        "create table bad (a int, b int, primary key (a, b))",
+        "create table nopk (a int) without rowid",
+        "create table kv (k text primary key, v int) without rowid",
+        "create index kvi on kv (v)",
+    ]);
+    assert_eq!(lines, [
+        "Error: table bad needs WITHOUT ROWID for a PRIMARY KEY of several columns",
+        "Error: PRIMARY KEY missing on table nopk",
+        "Error: WITHOUT ROWID table kv cannot have secondary or UNIQUE indexes",
+    ]);
+}
+
+#[test]
+fn updating_values_across_leaves_keeps_keys_unique() {
+    let db = TempDb::new();
+    let rows: Vec<String> = (0..60).map(|i| format!("({}, 'k{}', {})", i % 3, 43 + i, 236 + i)).collect();
+    let lines = db.query(&[
+        "create table kv (t int, u text, v int, primary key (t, u)) without rowid",
+        &format!("insert into kv values {}", rows.join(", ")),
+        "update kv set v = v + 1000 where t = 2",
+        "update kv set v = v + 1000 where t = 2",
+        "delete from kv where t = 1 and v % 2 = 0",
+        "select count(*), count(distinct u), sum(v) from kv",
+        "create virtual table f using fts5(body)",
+        "insert into f values ('red fox'), ('blue fox'), ('red hen')",
+        "update f set body = 'green fox' where rowid = 1",
+        "delete from f where rowid = 2",
+        "select rowid from f where f match 'fox OR red'",
+    ]);
+    assert_eq!(lines, ["50, 50, 53260", "1", "3"]);
+}